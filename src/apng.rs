@@ -0,0 +1,172 @@
+//! Assembling animated PNG files from ordinary ones.
+//!
+//! An animated PNG is a PNG file whose image is the first frame, so programs
+//! that don't understand animation still show something. After the header
+//! comes an `acTL` chunk giving the number of frames. Each frame's pixels are
+//! preceded by an `fcTL` chunk giving its size, position, and how long to show
+//! it; the first frame's pixels are the file's usual `IDAT` chunks, and later
+//! frames' are `fdAT` chunks, which are the same but for a sequence number.
+//! The `fcTL` and `fdAT` chunks share a single sequence, so readers can tell
+//! if any have gone astray.
+//!
+//! We encode each frame as a PNG file of its own, and then move its chunks
+//! into the animation, writing each frame out as soon as it's rendered.
+
+use bytes::{be16, be32};
+use chunks;
+use std::io::{self, ErrorKind, Write};
+
+/// An animated PNG file being written.
+pub struct Animation<W: Write> {
+    output: W,
+
+    /// The number of frames the animation will have.
+    frames: u32,
+
+    /// How long to show each frame, in milliseconds.
+    delay: u16,
+
+    /// The number of frames added so far.
+    added: u32,
+
+    /// The data of the first frame's `IHDR` chunk, which every later frame's
+    /// must match.
+    header: Option<Vec<u8>>,
+
+    /// The next number in the sequence of `fcTL` and `fdAT` chunks.
+    sequence: u32
+}
+
+impl<W: Write> Animation<W> {
+    /// Start writing an animation of `frames` frames to `output`, each shown
+    /// for `delay` milliseconds, repeating forever.
+    pub fn new(output: W, frames: u32, delay: u16) -> Animation<W> {
+        Animation {
+            output: output,
+            frames: frames,
+            delay: delay,
+            added: 0,
+            header: None,
+            sequence: 0
+        }
+    }
+
+    /// Add `png`, a complete PNG file, as the animation's next frame. Every
+    /// frame must have the same size and color type as the first; the rest of
+    /// the first frame's header chunks, like its gamma or color profile, apply
+    /// to them all.
+    pub fn add(&mut self, png: &[u8]) -> io::Result<()> {
+        if self.added == self.frames {
+            return Err(io::Error::new(ErrorKind::InvalidInput,
+                                      "animation already has all its frames"));
+        }
+        let first = self.added == 0;
+        if first {
+            try!(self.output.write_all(&png[..8])); // the signature
+        }
+        let mut size = None;
+        for (kind, data) in chunks::split(png) {
+            match &kind {
+                b"IHDR" => {
+                    if first {
+                        try!(self.output.write_all(&chunks::chunk(b"IHDR", data)));
+                        self.header = Some(data.to_vec());
+                    } else if self.header.as_ref().map(|header| &header[..]) != Some(data) {
+                        return Err(io::Error::new(ErrorKind::InvalidInput,
+                                                  "animation frames must all have the \
+                                                   same size and color type"));
+                    }
+                    size = Some(data[..8].to_vec());
+                }
+                b"IDAT" if first => try!(self.output.write_all(&chunks::chunk(b"IDAT", data))),
+                b"IDAT" => {
+                    let mut frame_data = be32(self.next_sequence()).to_vec();
+                    frame_data.extend_from_slice(data);
+                    try!(self.output.write_all(&chunks::chunk(b"fdAT", &frame_data)));
+                }
+                b"IEND" => (),
+                _ if first => try!(self.output.write_all(&chunks::chunk(&kind, data))),
+                _ => ()
+            }
+
+            // Once we have the frame's size, and before its pixels, control
+            // the frame, and in the first, the animation.
+            if let Some(size) = size.take() {
+                if first {
+                    let control = self.animation_control();
+                    try!(self.output.write_all(&chunks::chunk(b"acTL", &control)));
+                }
+                let mut control = be32(self.next_sequence()).to_vec();
+                control.extend_from_slice(&size);
+                control.extend_from_slice(&[0; 8]); // the frame's offset
+                control.extend_from_slice(&be16(self.delay));
+                control.extend_from_slice(&be16(1000)); // the delay's in thousandths
+                // Leave each frame in place when the next comes, and replace
+                // it, rather than blending the next over it.
+                control.extend_from_slice(&[0, 0]);
+                try!(self.output.write_all(&chunks::chunk(b"fcTL", &control)));
+            }
+        }
+        self.added += 1;
+        Ok(())
+    }
+
+    /// Finish the animation, which must have all the frames promised.
+    pub fn finish(mut self) -> io::Result<()> {
+        if self.added != self.frames {
+            return Err(io::Error::new(ErrorKind::InvalidInput,
+                                      format!("animation has {} of its {} frames",
+                                              self.added, self.frames)));
+        }
+        try!(self.output.write_all(&chunks::chunk(b"IEND", &[])));
+        self.output.flush()
+    }
+
+    /// Return the data of the `acTL` chunk: the number of frames, and of times
+    /// to play them, where zero means forever.
+    fn animation_control(&self) -> Vec<u8> {
+        let mut control = be32(self.frames).to_vec();
+        control.extend_from_slice(&be32(0));
+        control
+    }
+
+    fn next_sequence(&mut self) -> u32 {
+        self.sequence += 1;
+        self.sequence - 1
+    }
+}
+
+#[test]
+fn test_animation() {
+    let frame = |color: u8, pixels: &[u8]| {
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png.extend(chunks::chunk(b"IHDR", &[0, 0, 0, 2, 0, 0, 0, 1, 8, color, 0, 0, 0]));
+        png.extend(chunks::chunk(b"gAMA", &[0, 0, 0xb1, 0x8f]));
+        png.extend(chunks::chunk(b"IDAT", pixels));
+        png.extend(chunks::chunk(b"IEND", &[]));
+        png
+    };
+    let mut apng = Vec::new();
+    {
+        let mut animation = Animation::new(&mut apng, 2, 40);
+        animation.add(&frame(0, b"first")).unwrap();
+        animation.add(&frame(0, b"second")).unwrap();
+        animation.finish().unwrap();
+    }
+    let kinds: Vec<[u8; 4]> = chunks::split(&apng).iter().map(|&(kind, _)| kind).collect();
+    assert_eq!(kinds, vec![*b"IHDR", *b"acTL", *b"fcTL", *b"gAMA", *b"IDAT", *b"fcTL",
+                           *b"fdAT", *b"IEND"]);
+    let chunks = chunks::split(&apng);
+    assert_eq!(chunks[1].1, &[0, 0, 0, 2, 0, 0, 0, 0][..]);
+    assert_eq!(chunks[2].1, &[0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0,
+                              0, 40, 3, 232, 0, 0][..]);
+    assert_eq!(chunks[4].1, b"first");
+    assert_eq!(&chunks[5].1[..4], &[0, 0, 0, 1]);
+    assert_eq!(chunks[6].1, b"\0\0\0\x02second");
+
+    // Later frames must match the first's header, and all must be present.
+    let mut animation = Animation::new(Vec::new(), 2, 40);
+    animation.add(&frame(0, b"first")).unwrap();
+    assert!(animation.add(&frame(2, b"second")).is_err());
+    assert!(animation.finish().is_err());
+}
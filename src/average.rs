@@ -0,0 +1,198 @@
+//! Coloring escaping points by averages taken along their orbits.
+//!
+//! Escape times alone give an image bands of flat color. These colorings
+//! instead compute some quantity at each step of a point's orbit, average it
+//! over the whole orbit, and color the point by the average, which varies
+//! smoothly across each band and brings out the structure within it.
+//!
+//! Averaging over a whole number of steps would still leave seams where the
+//! escape time changes, so we blend between the averages with and without the
+//! final step, by how far past the escape radius that step went. If it only
+//! just crossed the radius, the point's neighbors on the far side of the band
+//! boundary took one more step, and we use the full average; if it went as
+//! far past as it could, its neighbors took one step fewer, and we leave the
+//! last step out.
+//!
+//! The triangle inequality average, or TIA, measures each step `z^d + c` by
+//! where its magnitude falls between the least and greatest values the
+//! triangle inequality allows: `|z^d| - |c|` and `|z^d| + |c|`.
+//!
+//! The stripe average takes `(1 + sin(k arg z)) / 2` at each point, for some
+//! stripe density `k`, and so follows the direction the orbit turns as it
+//! winds outward, drawing curving stripes that flow around the set.
+//!
+//! The curvature average measures how sharply the orbit turns at each point:
+//! the angle between the step that arrived there and the step before it, as a
+//! fraction of a half turn. Orbits that pass near the set's filaments turn
+//! sharply, so this picks out the filaments.
+
+use num::Complex;
+use std::f64;
+
+/// The quantity to average at each step of an orbit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Addend {
+    /// The triangle inequality ratio.
+    Triangle,
+
+    /// The stripe function, with the given density.
+    Stripes(f64),
+
+    /// The angle the orbit turns through.
+    Curvature
+}
+
+/// An average being taken along an orbit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Average {
+    addend: Addend,
+
+    /// The exponent of the orbit's iteration.
+    power: f64,
+
+    /// The point the orbit most recently visited, if any, and the one before
+    /// that.
+    last: Option<Complex<f64>>,
+    before: Option<Complex<f64>>,
+
+    /// The sum of the terms so far, the last of them, and how many there were.
+    sum: f64,
+    term: f64,
+    terms: u32
+}
+
+impl Average {
+    /// Start averaging `addend` along an orbit under an iteration whose
+    /// exponent is `power`.
+    pub fn new(addend: Addend, power: f64) -> Average {
+        Average {
+            addend: addend,
+            power: power,
+            last: None,
+            before: None,
+            sum: 0.0,
+            term: 0.0,
+            terms: 0
+        }
+    }
+
+    /// Add the orbit's step to `z` to the average, where `c` is the orbit's
+    /// constant. The first point visited only starts the orbit off.
+    pub fn visit(&mut self, z: Complex<f64>, c: Complex<f64>) {
+        let last = match self.last {
+            Some(last) => last,
+            None => {
+                self.last = Some(z);
+                return;
+            }
+        };
+        let term = match self.addend {
+            Addend::Triangle => {
+                let raised = last.norm().powf(self.power);
+                let c = c.norm();
+                let (low, high) = ((raised - c).abs(), raised + c);
+                if high > low {
+                    Some((z.norm() - low) / (high - low))
+                } else {
+                    None
+                }
+            }
+            Addend::Stripes(density) => Some((1.0 + (density * z.arg()).sin()) / 2.0),
+            Addend::Curvature => self.before.and_then(|before| {
+                let previous = last - before;
+                if previous.norm_sqr() > 0.0 {
+                    Some(((z - last) / previous).arg().abs() / f64::consts::PI)
+                } else {
+                    None
+                }
+            })
+        };
+        if let Some(term) = term {
+            self.sum += term;
+            self.term = term;
+            self.terms += 1;
+        }
+        self.before = self.last;
+        self.last = Some(z);
+    }
+
+    /// Return the smoothed average for an orbit that has just escaped the
+    /// circle of the given `radius`, as a fraction from 0 to 1.
+    pub fn value(&self, radius: f64) -> f64 {
+        if self.terms < 2 {
+            return if self.terms == 1 { self.sum } else { 0.0 };
+        }
+        let with = self.sum / self.terms as f64;
+        let without = (self.sum - self.term) / (self.terms - 1) as f64;
+
+        // How far the final step carried the orbit beyond the radius: 0 if it
+        // only just left, and 1 if it left from as far inside as it could.
+        let last = self.last.map_or(0.0, |last| last.norm());
+        let past = (last.ln() / radius.ln()).ln() / self.power.ln();
+        let weight = (1.0 - past).max(0.0).min(1.0);
+        (without + (with - without) * weight).max(0.0).min(1.0)
+    }
+}
+
+#[test]
+fn test_triangle() {
+    let c = Complex { re: 0.0, im: 0.5 };
+    let mut average = Average::new(Addend::Triangle, 2.0);
+
+    // The first step, from zero, has no room between its bounds.
+    average.visit(Complex { re: 0.0, im: 0.0 }, c);
+    average.visit(c, c);
+    assert_eq!(average.terms, 0);
+
+    // From 0.5i, the next step is -0.25 + 0.5i, whose magnitude lies
+    // (sqrt(5) - 1) / 2 of the way from 0.25 to 0.75.
+    average.visit(Complex { re: -0.25, im: 0.5 }, c);
+    assert_eq!(average.terms, 1);
+    assert!((average.sum - (5.0_f64.sqrt() - 1.0) / 2.0).abs() < 1e-12);
+}
+
+#[test]
+fn test_value() {
+    let average = |last| Average {
+        addend: Addend::Triangle,
+        power: 2.0,
+        last: Some(Complex { re: last, im: 0.0 }),
+        before: None,
+        sum: 1.0,
+        term: 0.2,
+        terms: 2
+    };
+    // The average with the last term is 0.5, and without it, 0.8.
+    assert!((average(2.0).value(2.0) - 0.5).abs() < 1e-12);
+    assert!((average(4.0).value(2.0) - 0.8).abs() < 1e-12);
+    assert!((average(2.0_f64.powf(2.0_f64.sqrt())).value(2.0) - 0.65).abs() < 1e-12);
+}
+
+#[test]
+fn test_stripes() {
+    let mut average = Average::new(Addend::Stripes(2.0), 2.0);
+    let c = Complex { re: 0.0, im: 0.0 };
+    average.visit(Complex { re: 1.0, im: 0.0 }, c);
+    average.visit(Complex { re: 1.0, im: 1.0 }, c);
+    average.visit(Complex { re: -1.0, im: 1.0 }, c);
+    average.visit(Complex { re: 1.0, im: 0.0 }, c);
+    assert_eq!(average.terms, 3);
+    assert!((average.sum - 1.5).abs() < 1e-12);
+}
+
+#[test]
+fn test_curvature() {
+    let mut average = Average::new(Addend::Curvature, 2.0);
+    let c = Complex { re: 0.0, im: 0.0 };
+    let visit = |average: &mut Average, re, im| average.visit(Complex { re: re, im: im }, c);
+    visit(&mut average, 0.0, 0.0);
+    visit(&mut average, 1.0, 0.0);
+    assert_eq!(average.terms, 0);
+
+    // Straight on, a right turn, and then straight back.
+    visit(&mut average, 2.0, 0.0);
+    visit(&mut average, 2.0, -1.0);
+    visit(&mut average, 2.0, 0.0);
+    assert_eq!(average.terms, 3);
+    assert!((average.sum - 1.5).abs() < 1e-12);
+}
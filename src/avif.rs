@@ -0,0 +1,132 @@
+//! Writing AVIF files, with the help of libavif's `avifenc` program.
+//!
+//! AVIF is an AV1 video frame in a HEIF container. Unlike the other formats we
+//! write, there's no simple way to produce it ourselves: even the simplest AV1
+//! encoder is a large piece of work. So we encode the image as a PNG file,
+//! which `avifenc` reads, and run `avifenc` on that, which must be installed
+//! somewhere on the `PATH`.
+//!
+//! AVIF can store ten bits per channel, which is enough to keep the gentle
+//! gradients of a fractal's exterior from breaking into bands the way they do
+//! in eight-bit JPEG files. Images drawn with sixteen bits per channel become
+//! ten-bit AVIF files.
+
+use std::env;
+use std::ffi::OsString;
+use std::fs::{self, DirBuilder, File, OpenOptions};
+use std::io::{self, ErrorKind, Read, Write};
+#[cfg(unix)]
+use std::os::unix::fs::DirBuilderExt;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The program we run to do the encoding.
+const AVIFENC: &'static str = "avifenc";
+
+/// Return `png`, a PNG image with `depth` bits per channel, encoded as an AVIF
+/// file at `quality`, from 1 to 100.
+pub fn encode(png: &[u8], depth: u8, quality: u8) -> io::Result<Vec<u8>> {
+    encode_with(AVIFENC, png, depth, quality)
+}
+
+/// Encode `png` as `encode` does, but with `program` in place of `avifenc`.
+fn encode_with(program: &str, png: &[u8], depth: u8, quality: u8) -> io::Result<Vec<u8>> {
+    let directory = try!(private_directory());
+    let result = run(program, png, &directory, depth, quality);
+    let _ = fs::remove_dir_all(&directory);
+    result
+}
+
+fn run(program: &str, png: &[u8], directory: &Path, depth: u8, quality: u8)
+    -> io::Result<Vec<u8>>
+{
+    let input = directory.join("image.png");
+    let output = directory.join("image.avif");
+    let mut file = try!(OpenOptions::new().write(true).create_new(true).open(&input));
+    try!(file.write_all(png));
+    drop(file);
+    let finished = try!(Command::new(program)
+                        .args(&arguments(&input, &output, depth, quality))
+                        .output()
+                        .map_err(|e| {
+                            io::Error::new(e.kind(),
+                                           format!("couldn't run '{}', which writing AVIF \
+                                                    files requires: {}", program, e))
+                        }));
+    if !finished.status.success() {
+        let message = String::from_utf8_lossy(&finished.stderr);
+        return Err(io::Error::new(ErrorKind::Other,
+                                  format!("'{}' failed: {}", program, message.trim())));
+    }
+
+    let mut avif = Vec::new();
+    try!(try!(File::open(output)).read_to_end(&mut avif));
+    Ok(avif)
+}
+
+/// Return the arguments asking `avifenc` to encode the PNG file `input` as the
+/// AVIF file `output`, with `depth` bits per channel at `quality`.
+fn arguments(input: &Path, output: &Path, depth: u8, quality: u8) -> Vec<OsString> {
+    vec![OsString::from("--depth"),
+         OsString::from(if depth > 8 { "10" } else { "8" }),
+         OsString::from("-q"),
+         OsString::from(quality.to_string()),
+         input.as_os_str().to_owned(),
+         output.as_os_str().to_owned()]
+}
+
+/// Create a new directory in the system's temporary directory, which only we
+/// can use, and return its path. Files inside it can't be anticipated by
+/// other users, the way files directly in the shared directory could: they
+/// might plant a link where we're about to write.
+fn private_directory() -> io::Result<PathBuf> {
+    let mut builder = DirBuilder::new();
+    #[cfg(unix)]
+    builder.mode(0o700);
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    for attempt in 0..100 {
+        let path = env::temp_dir().join(format!("mandelbrot-{}-{}{:09}-{}", process::id(),
+                                                now.as_secs(), now.subsec_nanos(), attempt));
+        match builder.create(&path) {
+            Ok(()) => return Ok(path),
+            Err(ref e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e)
+        }
+    }
+    Err(io::Error::new(ErrorKind::AlreadyExists,
+                       "couldn't find an unused name for a temporary directory"))
+}
+
+#[test]
+fn test_arguments() {
+    let (input, output) = (Path::new("in.png"), Path::new("out.avif"));
+    assert_eq!(arguments(input, output, 8, 60),
+               vec!["--depth", "8", "-q", "60", "in.png", "out.avif"]);
+    assert_eq!(arguments(input, output, 16, 100)[..2], ["--depth", "10"]);
+}
+
+#[test]
+fn test_private_directory() {
+    let (first, second) = (private_directory().unwrap(), private_directory().unwrap());
+    assert!(first != second);
+    assert!(first.is_dir() && second.is_dir());
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(fs::metadata(&first).unwrap().permissions().mode() & 0o777, 0o700);
+    }
+    fs::remove_dir(first).unwrap();
+    fs::remove_dir(second).unwrap();
+}
+
+#[test]
+fn test_errors() {
+    let missing = encode_with("mandelbrot-no-such-encoder", b"png", 8, 60).unwrap_err();
+    assert!(missing.to_string().starts_with("couldn't run 'mandelbrot-no-such-encoder'"));
+    #[cfg(unix)]
+    {
+        let failed = encode_with("false", b"png", 8, 60).unwrap_err();
+        assert!(failed.to_string().starts_with("'false' failed"));
+    }
+}
@@ -0,0 +1,97 @@
+//! Job files for the `batch` subcommand.
+//!
+//! A job file lists images to render. It may take either of two forms:
+//!
+//! - A text file with one job per line, where each line holds arguments for
+//!   the `render` subcommand, separated by whitespace:
+//!
+//!   ```text
+//!   # The whole set, and a closer look at the seahorse valley.
+//!   -o whole.png -s 1000x750 --center -0.5,0 --zoom 0.8
+//!   -o seahorse.png -s 1000x750 --center -0.743,0.131 --zoom 1e3
+//!   ```
+//!
+//!   Blank lines and lines starting with `#` are ignored.
+//!
+//! - A JSON array of objects, each of which has the same keys as a
+//!   configuration file:
+//!
+//!   ```json
+//!   [ { "output": "whole.png", "size": [1000, 750],
+//!       "center": [-0.5, 0.0], "zoom": 0.8 } ]
+//!   ```
+
+use std::fs::File;
+use std::io::{self, ErrorKind, Read};
+use std::path::Path;
+
+use serde_json;
+
+use config::Config;
+
+/// A single job from a job file.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Job {
+    /// Arguments for the `render` subcommand.
+    Arguments(Vec<String>),
+
+    /// Settings from a JSON job file.
+    Settings(Config)
+}
+
+/// Parse `text` as the contents of a job file. Return a vector of jobs, each
+/// paired with a label identifying it in status messages.
+pub fn parse(text: &str) -> Result<Vec<(String, Job)>, String> {
+    if text.trim_left().starts_with('[') {
+        let configs: Vec<Config> = try!(serde_json::from_str(text).map_err(|e| {
+            format!("error parsing JSON job file: {}", e)
+        }));
+        return Ok(configs.into_iter()
+                  .enumerate()
+                  .map(|(i, config)| (format!("job {}", i + 1), Job::Settings(config)))
+                  .collect());
+    }
+
+    Ok(text.lines()
+       .enumerate()
+       .map(|(i, line)| (i, line.trim()))
+       .filter(|&(_, line)| !line.is_empty() && !line.starts_with('#'))
+       .map(|(i, line)| {
+           let args = line.split_whitespace().map(str::to_string).collect();
+           (format!("line {}", i + 1), Job::Arguments(args))
+       })
+       .collect())
+}
+
+/// Read the job file at `path`.
+pub fn load(path: &Path) -> io::Result<Vec<(String, Job)>> {
+    let mut text = String::new();
+    try!(try!(File::open(path)).read_to_string(&mut text));
+    parse(&text).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+}
+
+#[test]
+fn test_parse() {
+    assert_eq!(parse("# comment\n\
+                      -o a.png -s 10x10 --center 0,0\n\
+                      \n\
+                      \t-o b.png  -s 20x20 -u -1,1 -l 1,-1  \n").unwrap(),
+               vec![("line 2".to_string(),
+                     Job::Arguments(vec!["-o", "a.png", "-s", "10x10", "--center", "0,0"]
+                                    .into_iter().map(str::to_string).collect())),
+                    ("line 4".to_string(),
+                     Job::Arguments(vec!["-o", "b.png", "-s", "20x20", "-u", "-1,1", "-l", "1,-1"]
+                                    .into_iter().map(str::to_string).collect()))]);
+
+    assert_eq!(parse("  [ { \"output\": \"a.png\", \"zoom\": 2.0 }, {} ]").unwrap(),
+               vec![("job 1".to_string(),
+                     Job::Settings(Config {
+                         output: Some("a.png".to_string()),
+                         zoom: Some(2.0),
+                         .. Config::default()
+                     })),
+                    ("job 2".to_string(), Job::Settings(Config::default()))]);
+
+    assert!(parse("[ { \"output\": 7 } ]").is_err());
+    assert_eq!(parse("").unwrap(), vec![]);
+}
@@ -0,0 +1,105 @@
+//! Writing uncompressed BMP files.
+//!
+//! BMP is Windows's native image format, which its tools read without fuss.
+//! We write it uncompressed, so the file's pixels are exactly the ones we drew,
+//! laid out in a way easily checked with a hex dump: that makes it handy for
+//! telling whether a problem lies in the drawing or in another format's
+//! encoder. Rows run from the bottom of the image up, each pixel is blue,
+//! green, and red, and each row is padded to a multiple of four bytes.
+//! Transparent images are written with 32-bit pixels and a version 4 header,
+//! which says where the alpha channel is.
+
+use bytes::le32;
+use std::io::{self, ErrorKind};
+
+/// The size of the file header, which precedes the bitmap header.
+const FILE_HEADER: usize = 14;
+
+/// The sizes of the original bitmap header, and of the version 4 header.
+const INFO_HEADER: usize = 40;
+const V4_HEADER: usize = 108;
+
+/// Compression methods: none, or none with the channels' positions given by
+/// masks.
+const BI_RGB: u32 = 0;
+const BI_BITFIELDS: u32 = 3;
+
+/// Pixels per meter, for 72 pixels per inch.
+const RESOLUTION: u32 = 2835;
+
+/// Return `pixels`, an image whose dimensions are given by `bounds`, encoded as
+/// a BMP file. Each pixel is red, green, blue, and alpha; if `alpha` is false,
+/// alpha is ignored, and the file has 24-bit pixels.
+pub fn encode(pixels: &[[u8; 4]], bounds: (usize, usize), alpha: bool) -> io::Result<Vec<u8>> {
+    let (header, bits, compression) = if alpha {
+        (V4_HEADER, 32, BI_BITFIELDS)
+    } else {
+        (INFO_HEADER, 24, BI_RGB)
+    };
+    let row = (bounds.0 * bits / 8 + 3) / 4 * 4;
+    let offset = FILE_HEADER + header;
+    let size = offset as u64 + row as u64 * bounds.1 as u64;
+    if size > 0xffff_ffff || bounds.0 > 0x7fff_ffff || bounds.1 > 0x7fff_ffff {
+        return Err(io::Error::new(ErrorKind::InvalidInput,
+                                  "image is too large to write as BMP"));
+    }
+
+    let mut bmp = Vec::with_capacity(size as usize);
+    bmp.extend_from_slice(b"BM");
+    bmp.extend_from_slice(&le32(size as u32));
+    bmp.extend_from_slice(&le32(0));
+    bmp.extend_from_slice(&le32(offset as u32));
+
+    bmp.extend_from_slice(&le32(header as u32));
+    bmp.extend_from_slice(&le32(bounds.0 as u32));
+    bmp.extend_from_slice(&le32(bounds.1 as u32)); // positive, so bottom-up
+    bmp.extend_from_slice(&[1, 0, bits as u8, 0]); // planes, bits per pixel
+    bmp.extend_from_slice(&le32(compression));
+    bmp.extend_from_slice(&le32((row * bounds.1) as u32));
+    bmp.extend_from_slice(&le32(RESOLUTION));
+    bmp.extend_from_slice(&le32(RESOLUTION));
+    bmp.extend_from_slice(&le32(0)); // colors in the palette
+    bmp.extend_from_slice(&le32(0)); // important colors
+    if alpha {
+        for &mask in &[0x00ff_0000, 0x0000_ff00, 0x0000_00ff, 0xff00_0000] {
+            bmp.extend_from_slice(&le32(mask));
+        }
+        bmp.extend_from_slice(b"BGRs"); // LCS_sRGB, backwards
+        bmp.extend_from_slice(&[0; 48]); // endpoints and gammas, unused for sRGB
+    }
+    assert_eq!(bmp.len(), offset);
+
+    for line in pixels.chunks(bounds.0).rev() {
+        let start = bmp.len();
+        for pixel in line {
+            bmp.extend_from_slice(&[pixel[2], pixel[1], pixel[0]]);
+            if alpha {
+                bmp.push(pixel[3]);
+            }
+        }
+        bmp.resize(start + row, 0);
+    }
+    Ok(bmp)
+}
+
+#[test]
+fn test_encode() {
+    let pixels = [[1, 2, 3, 255], [4, 5, 6, 255], [7, 8, 9, 128], [10, 11, 12, 0]];
+    let bmp = encode(&pixels, (2, 2), false).unwrap();
+    assert_eq!(bmp.len(), 54 + 8 * 2);
+    assert_eq!(&bmp[..2], b"BM");
+    assert_eq!(&bmp[2..6], &le32(70));
+    assert_eq!(&bmp[10..14], &le32(54));
+    assert_eq!(&bmp[28..30], &[24, 0]);
+    assert_eq!(&bmp[54..],
+               &[9, 8, 7, 12, 11, 10, 0, 0, // the bottom row comes first
+                 3, 2, 1, 6, 5, 4, 0, 0][..]);
+
+    let bmp = encode(&pixels, (2, 2), true).unwrap();
+    assert_eq!(bmp.len(), 122 + 8 * 2);
+    assert_eq!(&bmp[14..18], &le32(108));
+    assert_eq!(&bmp[28..30], &[32, 0]);
+    assert_eq!(&bmp[122..],
+               &[9, 8, 7, 128, 12, 11, 10, 0,
+                 3, 2, 1, 255, 6, 5, 4, 255][..]);
+}
@@ -0,0 +1,103 @@
+//! Named locations on the complex plane, stored in a TOML file.
+//!
+//! A bookmarks file is a series of tables, one per location:
+//!
+//! ```toml
+//! [seahorse-valley]
+//! center = [-0.743, 0.131]
+//! zoom = 1000.0
+//! iterations = 1000
+//! ```
+//!
+//! The `iterations` key is optional.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, ErrorKind, Read, Write};
+use std::path::Path;
+
+use toml;
+
+/// The file we read bookmarks from, if the user doesn't say otherwise.
+pub const DEFAULT_PATH: &'static str = "locations.toml";
+
+/// A bookmarked location: a center point and magnification, along with the
+/// iteration limit that brings out its detail.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Location {
+    pub center: (f64, f64),
+    pub zoom: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iterations: Option<u32>
+}
+
+/// A collection of locations, indexed by name.
+pub type Bookmarks = BTreeMap<String, Location>;
+
+/// Parse `text` as the contents of a bookmarks file.
+pub fn parse(text: &str) -> Result<Bookmarks, toml::de::Error> {
+    toml::from_str(text)
+}
+
+/// Read the bookmarks file at `path`. If there is no such file, return an empty
+/// collection, so that the first `bookmark add` can create it.
+pub fn load(path: &Path) -> io::Result<Bookmarks> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(Bookmarks::new()),
+        Err(e) => return Err(e)
+    };
+
+    let mut text = String::new();
+    try!(file.read_to_string(&mut text));
+    parse(&text).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+}
+
+/// Write `bookmarks` to the file at `path`, replacing its prior contents.
+pub fn save(path: &Path, bookmarks: &Bookmarks) -> io::Result<()> {
+    let text = try!(toml::to_string(bookmarks)
+                    .map_err(|e| io::Error::new(ErrorKind::InvalidData, e)));
+    let mut file = try!(File::create(path));
+    file.write_all(text.as_bytes())
+}
+
+/// Look up the location named `name` in the bookmarks file at `path`.
+pub fn find(path: &Path, name: &str) -> io::Result<Location> {
+    let mut bookmarks = try!(load(path));
+    bookmarks.remove(name)
+        .ok_or_else(|| io::Error::new(ErrorKind::NotFound,
+                                      format!("no location named '{}' in {}",
+                                              name, path.display())))
+}
+
+#[test]
+fn test_parse() {
+    let bookmarks = parse("[seahorse-valley]\n\
+                           center = [-0.743, 0.131]\n\
+                           zoom = 1000.0\n\
+                           iterations = 1000\n\
+                           \n\
+                           [whole]\n\
+                           center = [-0.5, 0.0]\n\
+                           zoom = 1.0\n")
+        .expect("bookmarks should parse");
+    assert_eq!(bookmarks.len(), 2);
+    assert_eq!(bookmarks["seahorse-valley"],
+               Location { center: (-0.743, 0.131), zoom: 1000.0, iterations: Some(1000) });
+    assert_eq!(bookmarks["whole"],
+               Location { center: (-0.5, 0.0), zoom: 1.0, iterations: None });
+
+    assert!(parse("[broken]\ncenter = [1.0]\nzoom = 1.0\n").is_err());
+}
+
+#[test]
+fn test_round_trip() {
+    let mut bookmarks = Bookmarks::new();
+    bookmarks.insert("spiral".to_string(),
+                     Location { center: (-0.761574, -0.0847596), zoom: 2.5e4,
+                                iterations: Some(2000) });
+    bookmarks.insert("elephants".to_string(),
+                     Location { center: (0.275, 0.0), zoom: 40.0, iterations: None });
+    let text = toml::to_string(&bookmarks).unwrap();
+    assert_eq!(parse(&text).unwrap(), bookmarks);
+}
@@ -0,0 +1,96 @@
+//! Buddhabrot and Nebulabrot images.
+//!
+//! Rather than shading each pixel by the fate of its own point, a Buddhabrot
+//! picks points `c` at random from all over the plane, and for each whose orbit
+//! escapes, adds one to the count of every pixel the orbit passed through on
+//! its way out. The counts, scaled to the brightest pixel, give the image.
+//!
+//! A Nebulabrot does the same for three different iteration limits at once,
+//! one for each of red, green, and blue. Orbits that take a long time to
+//! escape trace out fine detail that short ones don't, so each channel shows
+//! different structure.
+
+/// A count of how many orbits passed through each pixel of an image.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Histogram {
+    counts: Vec<u32>
+}
+
+impl Histogram {
+    /// Return an empty histogram for an image of `pixels` pixels.
+    pub fn new(pixels: usize) -> Histogram {
+        Histogram { counts: vec![0; pixels] }
+    }
+
+    /// Count an orbit passing through the pixel at `index`, in row-major order.
+    pub fn add(&mut self, index: usize) {
+        self.counts[index] = self.counts[index].saturating_add(1);
+    }
+
+    /// Add the counts in `other`, which must be for an image of the same size,
+    /// to this histogram.
+    pub fn merge(&mut self, other: &Histogram) {
+        assert_eq!(self.counts.len(), other.counts.len());
+        for (count, &more) in self.counts.iter_mut().zip(other.counts.iter()) {
+            *count = count.saturating_add(more);
+        }
+    }
+
+    /// Return this histogram's counts as shades from 0 to 255, with the
+    /// busiest pixel at full brightness. We take the square root of each
+    /// count's share of the maximum, so that the faint outer orbits still show.
+    fn shades(&self) -> Vec<u8> {
+        let max = self.counts.iter().cloned().max().unwrap_or(0);
+        if max == 0 {
+            return vec![0; self.counts.len()];
+        }
+        self.counts.iter()
+            .map(|&count| ((count as f64 / max as f64).sqrt() * 255.0).round() as u8)
+            .collect()
+    }
+}
+
+/// Convert a single histogram to grayscale pixels.
+pub fn grayscale(histogram: &Histogram) -> Vec<u8> {
+    histogram.shades()
+}
+
+/// Convert three histograms, for the red, green, and blue channels, to RGB
+/// pixels. Each channel is scaled to its own brightest pixel.
+pub fn colorize(red: &Histogram, green: &Histogram, blue: &Histogram) -> Vec<u8> {
+    let (red, green, blue) = (red.shades(), green.shades(), blue.shades());
+    let mut pixels = Vec::with_capacity(red.len() * 3);
+    for i in 0..red.len() {
+        pixels.extend_from_slice(&[red[i], green[i], blue[i]]);
+    }
+    pixels
+}
+
+#[test]
+fn test_histogram() {
+    let mut a = Histogram::new(4);
+    a.add(0);
+    a.add(0);
+    a.add(0);
+    a.add(0);
+    a.add(1);
+    let mut b = Histogram::new(4);
+    b.add(0);
+    b.add(0);
+    b.add(0);
+    b.add(0);
+    b.add(2);
+    a.merge(&b);
+    assert_eq!(a.counts, vec![8, 1, 1, 0]);
+    assert_eq!(grayscale(&a), vec![255, 90, 90, 0]);
+    assert_eq!(grayscale(&Histogram::new(2)), vec![0, 0]);
+}
+
+#[test]
+fn test_colorize() {
+    let mut red = Histogram::new(2);
+    red.add(0);
+    let mut blue = Histogram::new(2);
+    blue.add(1);
+    assert_eq!(colorize(&red, &Histogram::new(2), &blue), vec![255, 0, 0, 0, 0, 255]);
+}
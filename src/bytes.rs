@@ -0,0 +1,65 @@
+//! Fixed-size integers as bytes, for the file formats we read and write.
+//!
+//! Every format here spells out its own byte order, and they don't agree: PNG,
+//! QOI, and farbfeld are big-endian, while BMP, GIF, OpenEXR, WebP, NumPy, and
+//! our own sample and count files are little-endian.
+
+use std::io::{self, ErrorKind, Read};
+
+/// Return `n` as two big-endian bytes.
+pub fn be16(n: u16) -> [u8; 2] {
+    [(n >> 8) as u8, n as u8]
+}
+
+/// Return `n` as four big-endian bytes.
+pub fn be32(n: u32) -> [u8; 4] {
+    [(n >> 24) as u8, (n >> 16) as u8, (n >> 8) as u8, n as u8]
+}
+
+/// Return `n` as two little-endian bytes.
+pub fn le16(n: u16) -> [u8; 2] {
+    [n as u8, (n >> 8) as u8]
+}
+
+/// Return `n` as four little-endian bytes.
+pub fn le32(n: u32) -> [u8; 4] {
+    [n as u8, (n >> 8) as u8, (n >> 16) as u8, (n >> 24) as u8]
+}
+
+/// Return `n` as eight little-endian bytes.
+pub fn le64(n: u64) -> [u8; 8] {
+    let (low, high) = (le32(n as u32), le32((n >> 32) as u32));
+    [low[0], low[1], low[2], low[3], high[0], high[1], high[2], high[3]]
+}
+
+/// Read a four-byte little-endian integer from `input`.
+pub fn read32<R: Read>(input: &mut R) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    try!(input.read_exact(&mut bytes));
+    Ok(bytes.iter().rev().fold(0, |n, &byte| n << 8 | byte as u32))
+}
+
+/// Read an eight-byte little-endian integer from `input`.
+pub fn read64<R: Read>(input: &mut R) -> io::Result<u64> {
+    let low = try!(read32(input)) as u64;
+    let high = try!(read32(input)) as u64;
+    Ok(high << 32 | low)
+}
+
+/// Return an error saying that a file's contents are damaged, and `why`.
+pub fn invalid(why: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, why)
+}
+
+#[test]
+fn test_bytes() {
+    assert_eq!(be16(0x0102), [1, 2]);
+    assert_eq!(be32(0x01020304), [1, 2, 3, 4]);
+    assert_eq!(le16(0x0102), [2, 1]);
+    assert_eq!(le32(0x01020304), [4, 3, 2, 1]);
+    assert_eq!(le64(0x0102030405060708), [8, 7, 6, 5, 4, 3, 2, 1]);
+    let mut input = &[4, 3, 2, 1, 8, 7, 6, 5, 4, 3, 2, 1, 0][..];
+    assert_eq!(read32(&mut input).unwrap(), 0x01020304);
+    assert_eq!(read64(&mut input).unwrap(), 0x0102030405060708);
+    assert!(read32(&mut input).is_err());
+}
@@ -0,0 +1,138 @@
+//! Adding ancillary chunks to PNG files, and taking them apart.
+//!
+//! A PNG file is an eight-byte signature followed by a series of chunks, each
+//! a four-byte big-endian length, a four-byte type, the data, and a CRC-32 of
+//! the type and data. The first chunk is always the `IHDR` header. Our PNG
+//! encoder only writes the chunks it needs for the pixels themselves, so to
+//! say how the colors should be interpreted, we add chunks like `gAMA` and
+//! `sRGB` to its output afterwards, right after the header, where the format
+//! requires them to be.
+//!
+//! Animated PNG files are assembled from the chunks of ordinary ones, so we
+//! can also split a PNG file into its chunks.
+//!
+//! Some chunks, like `iCCP`, hold zlib-compressed data. Those are small enough
+//! that we don't bother compressing them: zlib's format allows data to be
+//! stored as it is, in blocks of up to 65535 bytes.
+
+use bytes::be32;
+
+/// The length of the PNG signature plus the `IHDR` chunk, which always holds
+/// thirteen bytes of data.
+const HEADER_LENGTH: usize = 8 + 4 + 4 + 13 + 4;
+
+/// Insert a chunk of type `kind` holding `data` into `png`, a complete PNG
+/// file, just after its header. Panic if `png` is too short to be one.
+pub fn insert(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    assert!(png.len() >= HEADER_LENGTH, "PNG file too short to have a header");
+    let tail = png.split_off(HEADER_LENGTH);
+    png.extend(chunk(kind, data));
+    png.extend(tail);
+}
+
+/// Return a chunk of type `kind` holding `data`, ready to add to a PNG file.
+pub fn chunk(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut chunk = Vec::with_capacity(data.len() + 12);
+    chunk.extend_from_slice(&be32(data.len() as u32));
+    chunk.extend_from_slice(kind);
+    chunk.extend_from_slice(data);
+    let crc = crc32(&chunk[4..]);
+    chunk.extend_from_slice(&be32(crc));
+    chunk
+}
+
+/// Return the chunks of `png`, a complete PNG file, each as its type and
+/// data. Panic if `png` isn't made of whole chunks.
+pub fn split(png: &[u8]) -> Vec<([u8; 4], &[u8])> {
+    let mut chunks = Vec::new();
+    let mut rest = &png[8..];
+    while !rest.is_empty() {
+        let length = rest[..4].iter().fold(0, |n, &byte| n << 8 | byte as usize);
+        let kind = [rest[4], rest[5], rest[6], rest[7]];
+        chunks.push((kind, &rest[8..8 + length]));
+        rest = &rest[12 + length..];
+    }
+    chunks
+}
+
+/// Return the CRC-32 of `bytes`, as PNG computes it.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0_u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// Return `data` in the zlib format, stored without compression.
+pub fn zlib(data: &[u8]) -> Vec<u8> {
+    // The header says: deflate, with a 32k window, and no preset dictionary.
+    let mut zlib = vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xffff).peekable();
+    if blocks.peek().is_none() {
+        zlib.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        // Each block starts with a byte whose low bit marks the final block,
+        // and whose next two bits, zero, mark it stored; then its length and
+        // the length's complement, each two bytes little-endian.
+        let last = if blocks.peek().is_none() { 1 } else { 0 };
+        let length = block.len() as u16;
+        zlib.extend_from_slice(&[last, length as u8, (length >> 8) as u8,
+                                 !length as u8, (!length >> 8) as u8]);
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&be32(adler32(data)));
+    zlib
+}
+
+/// Return the Adler-32 checksum of `bytes`, which ends every zlib stream.
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1_u32, 0_u32);
+    for &byte in bytes {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    b << 16 | a
+}
+
+#[test]
+fn test_crc32() {
+    // Every PNG file ends with an empty IEND chunk, whose CRC never changes.
+    assert_eq!(crc32(b"IEND"), 0xae42_6082);
+}
+
+#[test]
+fn test_insert() {
+    let mut png = vec![0; HEADER_LENGTH];
+    png.extend_from_slice(b"rest");
+    insert(&mut png, b"sRGB", &[0]);
+    assert_eq!(png.len(), HEADER_LENGTH + 13 + 4);
+    assert_eq!(&png[HEADER_LENGTH..HEADER_LENGTH + 9], &[0, 0, 0, 1, b's', b'R', b'G', b'B', 0]);
+    assert_eq!(&png[HEADER_LENGTH + 9..HEADER_LENGTH + 13], &be32(crc32(b"sRGB\0")));
+    assert_eq!(&png[HEADER_LENGTH + 13..], b"rest");
+}
+
+#[test]
+fn test_split() {
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    png.extend(chunk(b"IHDR", &[1; 13]));
+    png.extend(chunk(b"IEND", &[]));
+    assert_eq!(&png[png.len() - 4..], &be32(0xae42_6082));
+    assert_eq!(split(&png), vec![(*b"IHDR", &[1; 13][..]), (*b"IEND", &[][..])]);
+}
+
+#[test]
+fn test_zlib() {
+    assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    assert_eq!(zlib(b"abc"),
+               vec![0x78, 0x01, 1, 3, 0, 0xfc, 0xff, b'a', b'b', b'c', 0x02, 0x4d, 0x01, 0x27]);
+    assert_eq!(zlib(b""), vec![0x78, 0x01, 1, 0, 0, 0xff, 0xff, 0, 0, 0, 1]);
+    let long = zlib(&vec![7; 70000]);
+    assert_eq!(long.len(), 2 + 5 + 65535 + 5 + 4465 + 4);
+    assert_eq!(&long[2..7], &[0, 0xff, 0xff, 0, 0]);
+    assert_eq!(&long[65542..65547], &[1, 0x71, 0x11, 0x8e, 0xee]);
+}
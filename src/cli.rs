@@ -0,0 +1,1008 @@
+//! The definition of our command-line interface.
+
+use clap::{App, AppSettings, Arg, SubCommand};
+
+/// Return the arguments giving the area of the complex plane the image covers:
+/// either the points at its upper left and lower right corners, the ranges it
+/// spans along the real and imaginary axes, its center and magnification, or the
+/// name of a bookmarked location.
+///
+/// None of these are required as far as clap is concerned, since a
+/// configuration file may supply them instead; `main` checks that the settings
+/// are complete once everything has been merged.
+fn region_args() -> Vec<Arg<'static, 'static>> {
+    vec![Arg::with_name("upper-left")
+         .short("u")
+         .long("upper-left")
+         .value_name("POINT")
+         .allow_hyphen_values(true)
+         .conflicts_with_all(&["center", "location"])
+         .requires("lower-right")
+         .help("Point at the image's upper left corner, like -1.20,0.35 \
+                or -1.20+0.35i"),
+         Arg::with_name("lower-right")
+         .short("l")
+         .long("lower-right")
+         .value_name("POINT")
+         .allow_hyphen_values(true)
+         .conflicts_with_all(&["center", "location"])
+         .requires("upper-left")
+         .help("Point at the image's lower right corner, like -1,0.20 \
+                or -1+0.20i"),
+         Arg::with_name("re")
+         .long("re")
+         .value_name("LOW:HIGH")
+         .allow_hyphen_values(true)
+         .conflicts_with_all(&["upper-left", "lower-right", "center", "location"])
+         .requires("im")
+         .help("Range of the real axis the image spans, like -2.0:1.0"),
+         Arg::with_name("im")
+         .long("im")
+         .value_name("LOW:HIGH")
+         .allow_hyphen_values(true)
+         .conflicts_with_all(&["upper-left", "lower-right", "center", "location"])
+         .requires("re")
+         .help("Range of the imaginary axis the image spans, like -1.25:1.25"),
+         Arg::with_name("center")
+         .short("c")
+         .long("center")
+         .value_name("POINT")
+         .allow_hyphen_values(true)
+         .conflicts_with("location")
+         .help("Point at the center of the image, like -0.743,0.131 \
+                or -0.743+0.131i"),
+         Arg::with_name("location")
+         .short("L")
+         .long("location")
+         .value_name("NAME")
+         .help("Name of a bookmarked location to use as the center and zoom"),
+         bookmarks_arg(),
+         Arg::with_name("zoom")
+         .short("z")
+         .long("zoom")
+         .value_name("FACTOR")
+         .requires("center")
+         .help("Magnification around --center; at 1, the image's shorter \
+                side spans four units [default: 1]")]
+}
+
+/// Return the `--rotate` argument, which turns the view about its center.
+fn rotate_arg() -> Arg<'static, 'static> {
+    Arg::with_name("rotate")
+        .long("rotate")
+        .value_name("DEGREES")
+        .allow_hyphen_values(true)
+        .help("Rotate the view counterclockwise about its center")
+}
+
+/// Return the `--bookmarks` argument, naming the file holding bookmarked
+/// locations.
+fn bookmarks_arg() -> Arg<'static, 'static> {
+    Arg::with_name("bookmarks")
+        .long("bookmarks")
+        .value_name("FILE")
+        .help("File of bookmarked locations [default: locations.toml]")
+}
+
+/// Return the arguments that choose which set to draw: the Mandelbrot set, or
+/// a Julia set, and for the latter, the value of `c`; and how to iterate.
+fn set_args() -> Vec<Arg<'static, 'static>> {
+    let mut args = vec![Arg::with_name("set")
+                        .long("set")
+                        .value_name("SET")
+                        .possible_values(&["mandelbrot", "julia"])
+                        .help("Which set to draw [default: mandelbrot]"),
+                        Arg::with_name("c")
+                        .long("c")
+                        .value_name("POINT")
+                        .allow_hyphen_values(true)
+                        .help("The constant c for a Julia set, like -0.8+0.156i"),
+                        Arg::with_name("z0")
+                        .long("z0")
+                        .value_name("POINT")
+                        .allow_hyphen_values(true)
+                        .help("Where each point's orbit starts, when drawing a \
+                               Mandelbrot set; try other critical points, or \
+                               values near them [default: 0.5 for the lambda \
+                               formula, 1 for nova, 0 for others]")];
+    args.extend(formula_args());
+    args.push(Arg::with_name("script")
+              .long("script")
+              .value_name("FILE")
+              .conflicts_with_all(&["formula", "hybrid", "formula-expr"])
+              .help("Iterate with the function iterate(z, c) defined by the Rhai \
+                     script in FILE, and color with its color(n, z) function, if \
+                     it has one"));
+    args
+}
+
+/// Return the arguments that choose the formula and power to iterate with.
+fn formula_args() -> Vec<Arg<'static, 'static>> {
+    vec![Arg::with_name("formula")
+         .long("formula")
+         .value_name("FORMULA")
+         .possible_values(&["standard", "burning-ship", "tricorn", "celtic",
+                            "perpendicular", "perpendicular-burning-ship",
+                            "heart", "phoenix", "magnet1", "magnet2", "lambda",
+                            "nova", "quat-julia", "collatz", "spider", "manowar"])
+         .help("Variation on the iteration to use; burning-ship takes the \
+                absolute values of z's parts before raising it to the power, \
+                tricorn its conjugate, and celtic, perpendicular, \
+                perpendicular-burning-ship, and heart take other absolute \
+                values; phoenix adds p times the previous z; magnet1 and \
+                magnet2 are Magnet type I and II formulas, lambda iterates \
+                z = cz(1 - z), and nova takes relaxed Newton's method steps \
+                for z^D - 1, plus c; quat-julia draws a slice of a quaternion \
+                Julia set, and collatz iterates the complex Collatz map, plus \
+                c; spider replaces c with c/2 + z at each step, and manowar \
+                adds the previous z [default: standard]"),
+         Arg::with_name("hybrid")
+         .long("hybrid")
+         .value_name("FORMULAS")
+         .conflicts_with("formula")
+         .help("Iterate with each of these formulas in turn, separated by \
+                commas, like standard,standard,burning-ship"),
+         Arg::with_name("formula-expr")
+         .long("formula-expr")
+         .value_name("EXPRESSION")
+         .allow_hyphen_values(true)
+         .conflicts_with_all(&["formula", "hybrid"])
+         .help("Iterate z = EXPRESSION, written in terms of z and c, like \
+                'z^3 + sin(z) + c'; see the README for what it may use"),
+         Arg::with_name("morph-to")
+         .long("morph-to")
+         .value_name("FORMULA")
+         .conflicts_with("formula-expr")
+         .help("Blend each step's result with that of this second formula, \
+                to morph one fractal into another"),
+         Arg::with_name("morph")
+         .long("morph")
+         .value_name("T")
+         .requires("morph-to")
+         .help("How far to morph toward --morph-to, from 0 for none of the \
+                way to 1 for all of it [default: 0.5]"),
+         Arg::with_name("phoenix-p")
+         .long("phoenix-p")
+         .value_name("POINT")
+         .allow_hyphen_values(true)
+         .help("The constant p for the phoenix formula [default: -0.5]"),
+         Arg::with_name("relaxation")
+         .long("relaxation")
+         .value_name("R")
+         .allow_hyphen_values(true)
+         .help("Factor scaling each Newton's method step of the nova \
+                formula [default: 1]"),
+         Arg::with_name("slice")
+         .long("slice")
+         .value_name("k=K,l=L")
+         .allow_hyphen_values(true)
+         .help("Which slice of a quat-julia set to draw: the image shows the \
+                quaternions x + yi + Kj + Lk [default: k=0,l=0]"),
+         Arg::with_name("power")
+         .long("power")
+         .value_name("D")
+         .help("Iterate z = z^D + c, drawing a Multibrot set or its Julia \
+                sets; D may be fractional [default: 2]")]
+}
+
+/// Return the arguments choosing the colors for escape times.
+fn palette_args() -> Vec<Arg<'static, 'static>> {
+    vec![Arg::with_name("palette")
+         .long("palette")
+         .value_name("NAME")
+         .help("Color points by escape time with the built-in palette NAME: \
+                gray, fire, ocean, rainbow, viridis, magma, inferno, plasma, \
+                turbo, or cubehelix [default: gray]"),
+         Arg::with_name("palette-file")
+         .long("palette-file")
+         .value_name("FILE")
+         .conflicts_with("palette")
+         .help("Color points by escape time with the palette in FILE, in \
+                FractInt's .map format, or with the gradient NAME from an \
+                Ultra Fractal .ugr file, given as FILE.ugr:NAME"),
+         Arg::with_name("gradient")
+         .long("gradient")
+         .value_name("STOPS")
+         .conflicts_with_all(&["palette", "palette-file"])
+         .help("Color points by escape time with a gradient through STOPS, \
+                like 0:#000764,0.16:#206bcb,0.42:#edffff,1:#000200, each a \
+                position from 0 to 1 and a color"),
+         Arg::with_name("gradient-space")
+         .long("gradient-space")
+         .value_name("SPACE")
+         .possible_values(&["rgb", "hsv", "lab"])
+         .requires("gradient")
+         .help("Blend the gradient's colors in SPACE [default: rgb]"),
+         Arg::with_name("transfer")
+         .long("transfer")
+         .value_name("FUNCTION")
+         .help("Reshape escape times before looking them up in the palette: \
+                linear, sqrt, log, or power:K; sqrt and log spread out the \
+                points escaping soonest, and power:K with K above 1 the \
+                slowest [default: linear]"),
+         Arg::with_name("gamma")
+         .long("gamma")
+         .value_name("GAMMA")
+         .help("Blend the palette's colors in linear light, taking them to be \
+                encoded as sRGB (srgb) or with the exponent GAMMA, and record \
+                the encoding in the PNG file"),
+         Arg::with_name("icc-profile")
+         .long("icc-profile")
+         .value_name("PROFILE")
+         .help("Embed an ICC profile in the image file, saying what colors its \
+                values mean: srgb for a built-in sRGB profile, or the name \
+                of a profile file"),
+         Arg::with_name("depth")
+         .long("depth")
+         .value_name("BITS")
+         .possible_values(&["8", "16"])
+         .help("Write BITS bits per channel; 16 keeps fine gradations of \
+                escape time for tone-mapping elsewhere [default: 8]"),
+         Arg::with_name("coloring")
+         .long("coloring")
+         .value_name("METHOD")
+         .possible_values(&["escape", "tia", "stripes", "curvature", "binary", "angle",
+                            "distance", "atom"])
+         .help("Color points that escape by their escape time; by the \
+                triangle inequality average (tia), stripe average, or \
+                curvature average over their orbits; by which half of the \
+                plane (binary) or which direction (angle) they escape in; by \
+                their estimated distance from the set; or color every point \
+                by its atom domain [default: escape]"),
+         Arg::with_name("stripe-density")
+         .long("stripe-density")
+         .value_name("K")
+         .help("Draw K stripes around each point of the orbit for \
+                --coloring stripes [default: 5]"),
+         Arg::with_name("distance-thickness")
+         .long("distance-thickness")
+         .value_name("PIXELS")
+         .help("Draw the set's boundary PIXELS wide for --coloring distance \
+                [default: 1]"),
+         Arg::with_name("light-angle")
+         .long("light-angle")
+         .value_name("DEGREES")
+         .allow_hyphen_values(true)
+         .help("Shade the outside of the set as a surface in relief, lit from \
+                DEGREES counterclockwise from the positive real axis"),
+         Arg::with_name("interior")
+         .long("interior")
+         .value_name("METHOD")
+         .possible_values(&["black", "magnitude", "closest", "average", "period",
+                            "transparent"])
+         .help("Color points that never escape black, or from the palette by \
+                the magnitude of their orbit's last point, its closest \
+                approach to the origin, or the magnitude of its average, or \
+                by the period of the cycle their orbit settles into, or leave \
+                them transparent [default: black]"),
+         Arg::with_name("trap")
+         .long("trap")
+         .value_name("SHAPE")
+         .help("Color every point by how close its orbit comes to SHAPE: \
+                point:Z, line:Z,ANGLE for the line through Z at ANGLE \
+                degrees, circle:Z,RADIUS, where Z is a complex number, or \
+                stalks, for Pickover stalks along the axes"),
+         Arg::with_name("trap-blend")
+         .long("trap-blend")
+         .value_name("HOW")
+         .possible_values(&["replace", "multiply", "minimum"])
+         .requires("trap")
+         .help("Combine the trap's colors with escape-time colors: replace \
+                them, multiply the two, or take the darker of each channel \
+                [default: replace]"),
+         Arg::with_name("equalize")
+         .long("equalize")
+         .help("Spread escape times over the palette by histogram \
+                equalization, so that each color covers about as many points, \
+                however deep the zoom"),
+         Arg::with_name("auto-expose")
+         .long("auto-expose")
+         .value_name("LOW,HIGH")
+         .conflicts_with("equalize")
+         .help("Stretch the palette over the escape times between these \
+                percentiles, like 1,99, so that a few extreme points don't \
+                squeeze the rest into a few colors"),
+         Arg::with_name("legend")
+         .long("legend")
+         .value_name("CORNER")
+         .possible_values(&["top-left", "top-right", "bottom-left", "bottom-right"])
+         .help("Draw a legend in this corner of the image, showing the \
+                palette with the escape times its colors stand for"),
+         Arg::with_name("dither")
+         .long("dither")
+         .help("Dither colors down to eight bits per channel, rather than \
+                rounding, so that smooth gradients don't break into bands"),
+         Arg::with_name("palette-period")
+         .long("palette-period")
+         .value_name("N")
+         .conflicts_with("equalize")
+         .help("Cycle through the palette every N iterations, looping from \
+                its last color back to its first"),
+         Arg::with_name("palette-offset")
+         .long("palette-offset")
+         .value_name("N")
+         .requires("palette-period")
+         .help("Start the palette cycle N iterations along [default: 0]")]
+}
+
+/// Return the arguments choosing the file format of the images written.
+fn format_args() -> Vec<Arg<'static, 'static>> {
+    vec![Arg::with_name("format")
+         .long("format")
+         .value_name("FORMAT")
+         .possible_values(&["png", "jpeg", "tiff", "webp", "avif", "qoi", "pgm", "ppm",
+                            "farbfeld", "bmp", "dzi"])
+         .help("Write images in this format [default: chosen by the output \
+                file's extension: .jpg or .jpeg for JPEG, .tif or .tiff for \
+                TIFF, .webp for WebP, .avif for AVIF, .qoi for QOI, .pgm for \
+                PGM, .ppm for PPM, .ff for farbfeld, .bmp for BMP, .dzi for a \
+                Deep Zoom image, and PNG otherwise]"),
+         Arg::with_name("quality")
+         .long("quality")
+         .value_name("N")
+         .help("Quality of JPEG, WebP, or AVIF images, from 1 to 100, where \
+                100 makes WebP lossless [default: 90 for JPEG, 100 for WebP, \
+                80 for AVIF]"),
+         Arg::with_name("tile-format")
+         .long("tile-format")
+         .value_name("FORMAT")
+         .possible_values(&["png", "jpeg"])
+         .help("Format of the tiles of a Deep Zoom image or web map \
+                [default: png]"),
+         Arg::with_name("tile-size")
+         .long("tile-size")
+         .value_name("N")
+         .help("Width and height of the tiles of a Deep Zoom image, not \
+                counting their overlap, or of a web map [default: 254 for Deep \
+                Zoom, 256 for web maps]"),
+         Arg::with_name("tile-overlap")
+         .long("tile-overlap")
+         .value_name("N")
+         .help("Pixels each of a Deep Zoom image's tiles shares with its \
+                neighbors on each side [default: 1]")]
+}
+
+/// Return the arguments choosing how to render the image: by escape time, or as
+/// a Newton fractal, and for the latter, the polynomial to use.
+fn mode_args() -> Vec<Arg<'static, 'static>> {
+    vec![Arg::with_name("mode")
+         .long("mode")
+         .value_name("MODE")
+         .possible_values(&["escape", "newton", "buddhabrot", "inverse"])
+         .help("How to render: escape shades points by how quickly they \
+                escape, newton colors them by the root of --polynomial \
+                that Newton's method finds from there, and buddhabrot by how \
+                many escaping orbits pass through them; inverse outlines a \
+                Julia set by iterating backwards [default: escape]"),
+         Arg::with_name("polynomial")
+         .long("polynomial")
+         .value_name("COEFFICIENTS")
+         .allow_hyphen_values(true)
+         .help("Coefficients of the polynomial for --mode newton, highest \
+                degree first, like 1,0,0,-1 for z^3 - 1 [default: 1,0,0,-1]"),
+         Arg::with_name("samples")
+         .long("samples")
+         .value_name("N")
+         .help("Number of random orbits per pixel to follow for --mode \
+                buddhabrot, or points per pixel to plot for --mode inverse \
+                [default: 20]"),
+         Arg::with_name("channel-limits")
+         .long("channel-limits")
+         .value_name("RED,GREEN,BLUE")
+         .help("Draw a Nebulabrot with --mode buddhabrot, counting orbits \
+                that escape within each of these iteration limits in the \
+                red, green, and blue channels, like 5000,500,50"),
+         Arg::with_name("anti")
+         .long("anti")
+         .help("Draw an anti-Buddhabrot with --mode buddhabrot, following \
+                the orbits that never escape instead")]
+}
+
+/// Return the `--iterations` argument, giving the iteration limit.
+fn iterations_arg() -> Arg<'static, 'static> {
+    Arg::with_name("iterations")
+        .short("i")
+        .long("iterations")
+        .value_name("N")
+        .help("Give up on a point after N iterations, and deem it in the set \
+               [default: 100 for the collatz formula, 255 for others]")
+}
+
+/// Return the `--escape-radius` argument, giving the radius of the circle a
+/// point's orbit must leave for us to consider it outside the set.
+fn escape_radius_arg() -> Arg<'static, 'static> {
+    Arg::with_name("escape-radius")
+        .short("r")
+        .long("escape-radius")
+        .value_name("R")
+        .help("Consider a point escaped once its orbit leaves the circle of \
+               radius R [default: 100 for the collatz formula, 2 for others]")
+}
+
+/// Return the `--threads` argument, giving the number of threads to render
+/// with.
+fn threads_arg() -> Arg<'static, 'static> {
+    Arg::with_name("threads")
+        .short("t")
+        .long("threads")
+        .value_name("N")
+        .help("Render with N threads [default: one per available core]")
+}
+
+/// Return the `--seed` argument, for rendering modes that sample at random.
+fn seed_arg() -> Arg<'static, 'static> {
+    Arg::with_name("seed")
+        .long("seed")
+        .value_name("N")
+        .help("Seed for random sampling, to make renders reproducible \
+               [default: chosen at random, and printed]")
+}
+
+/// Return the `--force` argument, allowing renders zoomed in too deeply for
+/// `f64` to handle.
+fn force_arg() -> Arg<'static, 'static> {
+    Arg::with_name("force")
+        .long("force")
+        .help("Render even when zoomed in too deeply for 64-bit floating \
+               point to distinguish adjacent pixels")
+}
+
+/// Return the `--size` argument, giving the dimensions of the image in pixels.
+fn size_arg() -> Arg<'static, 'static> {
+    Arg::with_name("size")
+        .short("s")
+        .long("size")
+        .value_name("PIXELS")
+        .help("Image dimensions, like 1000x750; give just the width, as 1000 \
+               or 1000x_, or just the height, as _x750, to match the \
+               region's aspect ratio. Presets like 720p, 1080p, 4k, or \
+               a4@300dpi (add -landscape after the paper name to turn it) \
+               are accepted too")
+}
+
+/// Return the `--config` argument, naming a TOML file of settings that the
+/// other arguments override.
+fn config_arg() -> Arg<'static, 'static> {
+    Arg::with_name("config")
+        .short("C")
+        .long("config")
+        .value_name("FILE")
+        .help("TOML file of render settings; other arguments override its values")
+}
+
+/// Return the `--import-par` argument.
+fn import_par_arg() -> Arg<'static, 'static> {
+    Arg::with_name("import-par")
+        .long("import-par")
+        .value_name("FILE:ENTRY")
+        .help("Take settings from the named entry of a FractInt .par file; \
+               other arguments override its values")
+}
+
+/// Return the `--import-kfr` argument.
+fn import_kfr_arg() -> Arg<'static, 'static> {
+    Arg::with_name("import-kfr")
+        .long("import-kfr")
+        .value_name("FILE")
+        .conflicts_with("import-par")
+        .help("Take the location from a Kalles Fraktaler .kfr file; \
+               other arguments override its values")
+}
+
+/// Return the `--import-upr` argument.
+fn import_upr_arg() -> Arg<'static, 'static> {
+    Arg::with_name("import-upr")
+        .long("import-upr")
+        .value_name("FILE:ENTRY")
+        .conflicts_with_all(&["import-par", "import-kfr"])
+        .help("Take settings from the named entry of an Ultra Fractal .upr file; \
+               other arguments override its values")
+}
+
+/// Return the `--share` argument, taking a view from a code made by `share
+/// encode`.
+fn share_arg() -> Arg<'static, 'static> {
+    Arg::with_name("share")
+        .long("share")
+        .value_name("CODE")
+        .help("Take the view, set, and iteration limit from a code made by \
+               'mandelbrot share encode'; other arguments override its values")
+}
+
+/// Return the clap description of our command-line interface.
+pub fn build() -> App<'static, 'static> {
+    App::new("mandelbrot")
+        .version(crate_version!())
+        .author(crate_authors!())
+        .about("Plots the Mandelbrot set in parallel and writes it out as image files.")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .setting(AppSettings::VersionlessSubcommands)
+        .subcommand(SubCommand::with_name("render")
+                    .about("Render a single image, or several crops of one scene")
+                    .arg(Arg::with_name("output")
+                         .short("o")
+                         .long("output")
+                         .value_name("FILE")
+                         .multiple(true)
+                         .number_of_values(1)
+                         .help("Image file to write, or - for standard output; \
+                                repeat with --region to render several crops"))
+                    .arg(Arg::with_name("output-normals")
+                         .long("output-normals")
+                         .value_name("FILE")
+                         .conflicts_with("region")
+                         .help("Also write a normal map of the image to the image \
+                                file FILE, for relighting it elsewhere"))
+                    .arg(Arg::with_name("output-data")
+                         .long("output-data")
+                         .value_name("FILE")
+                         .conflicts_with("region")
+                         .help("Also save what was found about each point to FILE, \
+                                for the colorize subcommand to paint again"))
+                    .arg(Arg::with_name("output-raw")
+                         .long("output-raw")
+                         .value_name("FILE")
+                         .conflicts_with("region")
+                         .help("Also save the number of iterations each point's orbit \
+                                took to escape to FILE, as little-endian 32-bit \
+                                integers after a short header"))
+                    .arg(Arg::with_name("output-npy")
+                         .long("output-npy")
+                         .value_name("FILE")
+                         .conflicts_with("region")
+                         .help("Also save the number of iterations each point's orbit \
+                                took to escape to FILE, as a NumPy array"))
+                    .arg(Arg::with_name("npy-smooth")
+                         .long("npy-smooth")
+                         .requires("output-npy")
+                         .help("Save smooth iteration counts to the --output-npy file, \
+                                as 32-bit floats, rather than whole ones"))
+                    .arg(Arg::with_name("output-csv")
+                         .long("output-csv")
+                         .value_name("FILE")
+                         .conflicts_with("region")
+                         .help("Also write a CSV file to FILE with a row for each pixel: \
+                                its column and row, the real and imaginary parts of its \
+                                point, and how many iterations its orbit took to escape"))
+                    .arg(Arg::with_name("csv-step")
+                         .long("csv-step")
+                         .value_name("N")
+                         .requires("output-csv")
+                         .help("Only write every Nth pixel each way to the --output-csv \
+                                file [default: 1]"))
+                    .arg(Arg::with_name("output-exr")
+                         .long("output-exr")
+                         .value_name("FILE")
+                         .conflicts_with("region")
+                         .help("Also write each point's smooth iteration count to FILE \
+                                as a 32-bit floating-point OpenEXR image"))
+                    .arg(Arg::with_name("exr-channels")
+                         .long("exr-channels")
+                         .value_name("CHANNELS")
+                         .requires("output-exr")
+                         .help("Add these channels to the --output-exr image, separated \
+                                by commas: distance, each point's estimated distance \
+                                from the set, and magnitude, the magnitude of the last \
+                                point of its orbit"))
+                    .arg(Arg::with_name("output-hdr")
+                         .long("output-hdr")
+                         .value_name("FILE")
+                         .conflicts_with("region")
+                         .help("Also write each point's smooth iteration count to FILE \
+                                as a Radiance HDR image"))
+                    .arg(Arg::with_name("region")
+                         .long("region")
+                         .value_name("UPPER_LEFT:LOWER_RIGHT")
+                         .multiple(true)
+                         .number_of_values(1)
+                         .allow_hyphen_values(true)
+                         .conflicts_with_all(&["upper-left", "lower-right", "re", "im",
+                                               "center", "location", "watch",
+                                               "stdin-json"])
+                         .help("Corners of a region to render, like -1.2,0.35:-1,0.2; \
+                                may be repeated, each with its own --output"))
+                    .arg(config_arg())
+                    .arg(import_par_arg())
+                    .arg(import_kfr_arg())
+                    .arg(import_upr_arg())
+                    .arg(share_arg())
+                    .arg(Arg::with_name("stdin-json")
+                         .long("stdin-json")
+                         .conflicts_with("config")
+                         .help("Read settings from standard input as a JSON object; \
+                                if no output file is given, write the image to \
+                                standard output"))
+                    .arg(Arg::with_name("watch")
+                         .long("watch")
+                         .value_name("FILE")
+                         .conflicts_with_all(&["config", "stdin-json", "dry-run"])
+                         .help("Like --config, but render again each time FILE \
+                                changes, until interrupted"))
+                    .arg(Arg::with_name("dry-run")
+                         .long("dry-run")
+                         .help("Check the settings and describe the render, with \
+                                estimates of its memory use and running time, \
+                                but don't carry it out"))
+                    .arg(size_arg())
+                    .args(&region_args())
+                    .arg(rotate_arg())
+                    .args(&mode_args())
+                    .args(&palette_args())
+                    .args(&format_args())
+                    .args(&set_args())
+                    .arg(iterations_arg())
+                    .arg(escape_radius_arg())
+                    .arg(threads_arg())
+                    .arg(seed_arg())
+                    .arg(force_arg())
+                    .after_help("EXAMPLE:\n    \
+                                 mandelbrot render -o mandel.png -s 1000x750 \
+                                 -u -1.20,0.35 -l -1,0.20"))
+        .subcommand(SubCommand::with_name("animate")
+                    .about("Render a sequence of frames zooming from one view to another")
+                    .arg(Arg::with_name("output")
+                         .short("o")
+                         .long("output")
+                         .value_name("PREFIX")
+                         .required_unless_one(&["apng", "gif", "ffmpeg"])
+                         .help("Prefix for frame file names; frames are written \
+                                to PREFIX0000.png, PREFIX0001.png, and so on, \
+                                or with the extension --format calls for"))
+                    .arg(Arg::with_name("apng")
+                         .long("apng")
+                         .value_name("FILE")
+                         .conflicts_with_all(&["output", "gif", "ffmpeg"])
+                         .help("Assemble the frames into an animated PNG file, FILE, \
+                                rather than writing each to a file of its own"))
+                    .arg(Arg::with_name("gif")
+                         .long("gif")
+                         .value_name("FILE")
+                         .conflicts_with_all(&["output", "ffmpeg"])
+                         .help("Assemble the frames into an animated GIF file, FILE, \
+                                with up to 256 colors in each frame"))
+                    .arg(Arg::with_name("gif-dither")
+                         .long("gif-dither")
+                         .requires("gif")
+                         .help("Dither each frame of the --gif file to its palette, \
+                                trading banding for grain"))
+                    .arg(Arg::with_name("gif-global-palette")
+                         .long("gif-global-palette")
+                         .requires("gif")
+                         .help("Give every frame of the --gif file the palette \
+                                chosen for the first, rather than one of its own"))
+                    .arg(Arg::with_name("ffmpeg")
+                         .long("ffmpeg")
+                         .value_name("ARGS")
+                         .conflicts_with("output")
+                         .help("Pipe the frames to ffmpeg as they're rendered, passing \
+                                it ARGS to say how to encode them and where, as in \
+                                \"-c:v libx264 -crf 18 out.mp4\""))
+                    .arg(Arg::with_name("frame-delay")
+                         .long("frame-delay")
+                         .value_name("MS")
+                         .help("Show each frame of the --apng, --gif, or --ffmpeg \
+                                animation for MS milliseconds [default: 40]; GIF \
+                                rounds this to hundredths of a second"))
+                    .arg(Arg::with_name("frames")
+                         .short("n")
+                         .long("frames")
+                         .value_name("COUNT")
+                         .default_value("100")
+                         .help("Number of frames to render"))
+                    .arg(size_arg())
+                    .args(&region_args())
+                    .arg(rotate_arg())
+                    .args(&mode_args())
+                    .args(&palette_args())
+                    .args(&format_args())
+                    .args(&set_args())
+                    .arg(iterations_arg())
+                    .arg(escape_radius_arg())
+                    .arg(threads_arg())
+                    .arg(seed_arg())
+                    .arg(force_arg())
+                    .arg(Arg::with_name("to-upper-left")
+                         .long("to-upper-left")
+                         .value_name("POINT")
+                         .allow_hyphen_values(true)
+                         .conflicts_with_all(&["to-center", "to-location"])
+                         .requires("to-lower-right")
+                         .help("Upper left corner of the final frame"))
+                    .arg(Arg::with_name("to-lower-right")
+                         .long("to-lower-right")
+                         .value_name("POINT")
+                         .allow_hyphen_values(true)
+                         .conflicts_with_all(&["to-center", "to-location"])
+                         .requires("to-upper-left")
+                         .help("Lower right corner of the final frame"))
+                    .arg(Arg::with_name("to-re")
+                         .long("to-re")
+                         .value_name("LOW:HIGH")
+                         .allow_hyphen_values(true)
+                         .conflicts_with_all(&["to-upper-left", "to-lower-right",
+                                               "to-center", "to-location"])
+                         .requires("to-im")
+                         .help("Range of the real axis the final frame spans"))
+                    .arg(Arg::with_name("to-im")
+                         .long("to-im")
+                         .value_name("LOW:HIGH")
+                         .allow_hyphen_values(true)
+                         .conflicts_with_all(&["to-upper-left", "to-lower-right",
+                                               "to-center", "to-location"])
+                         .requires("to-re")
+                         .help("Range of the imaginary axis the final frame spans"))
+                    .arg(Arg::with_name("to-center")
+                         .long("to-center")
+                         .value_name("POINT")
+                         .allow_hyphen_values(true)
+                         .help("Center of the final frame"))
+                    .arg(Arg::with_name("to-zoom")
+                         .long("to-zoom")
+                         .value_name("FACTOR")
+                         .requires("to-center")
+                         .help("Magnification of the final frame [default: 1]"))
+                    .arg(Arg::with_name("to-location")
+                         .long("to-location")
+                         .value_name("NAME")
+                         .conflicts_with("to-center")
+                         .help("Bookmarked location of the final frame"))
+                    .arg(Arg::with_name("to-morph")
+                         .long("to-morph")
+                         .value_name("T")
+                         .requires("morph-to")
+                         .help("How far toward --morph-to the final frame is \
+                                morphed [default: same as the first]"))
+                    .arg(Arg::with_name("to-rotate")
+                         .long("to-rotate")
+                         .value_name("DEGREES")
+                         .allow_hyphen_values(true)
+                         .help("Rotation of the final frame [default: same as the first]")))
+        .subcommand(SubCommand::with_name("atlas")
+                    .about("Render a grid of Julia sets, for values of c across a region")
+                    .arg(Arg::with_name("output")
+                         .short("o")
+                         .long("output")
+                         .value_name("FILE")
+                         .help("Image file to write, or - for standard output"))
+                    .arg(Arg::with_name("grid")
+                         .long("grid")
+                         .value_name("COLUMNSxROWS")
+                         .default_value("8x8")
+                         .help("Number of Julia sets across and down"))
+                    .arg(Arg::with_name("cell-size")
+                         .long("cell-size")
+                         .value_name("PIXELS")
+                         .default_value("128")
+                         .help("Width and height of each Julia set's image"))
+                    .arg(config_arg())
+                    .args(&region_args())
+                    .arg(rotate_arg())
+                    .args(&formula_args())
+                    .args(&palette_args())
+                    .args(&format_args())
+                    .arg(iterations_arg())
+                    .arg(escape_radius_arg())
+                    .arg(threads_arg())
+                    .after_help("The region is of the parameter plane: each cell \
+                                 shows the Julia set for the value of c at its \
+                                 center, from -2 to 2 on both axes. The region \
+                                 defaults to one around the Mandelbrot set.\n\n\
+                                 EXAMPLE:\n    \
+                                 mandelbrot atlas -o atlas.png --grid 16x12 \
+                                 --cell-size 100 --re -2:1 --im -1.125:1.125"))
+        .subcommand(SubCommand::with_name("tiles")
+                    .about("Render a tree of web map tiles, for a slippy map viewer")
+                    .arg(Arg::with_name("output")
+                         .short("o")
+                         .long("output")
+                         .value_name("DIR")
+                         .help("Directory to write the tiles under, as \
+                                DIR/LEVEL/COLUMN/ROW.png"))
+                    .arg(Arg::with_name("levels")
+                         .long("levels")
+                         .value_name("LOW:HIGH")
+                         .default_value("0:4")
+                         .help("Zoom levels to render, or a single level; level 0 \
+                                is a single tile showing the whole region, and \
+                                each level above has twice as many tiles across \
+                                and down"))
+                    .arg(config_arg())
+                    .args(&region_args())
+                    .arg(rotate_arg())
+                    .args(&set_args())
+                    .args(&palette_args())
+                    .args(&format_args())
+                    .arg(iterations_arg())
+                    .arg(escape_radius_arg())
+                    .arg(threads_arg())
+                    .after_help("The region is the whole map, which should be \
+                                 square; it defaults to one around the Mandelbrot \
+                                 set. Tiles are 256 pixels square, unless \
+                                 --tile-size says otherwise, and are rendered \
+                                 independently, many at once.\n\n\
+                                 EXAMPLE:\n    \
+                                 mandelbrot tiles -o map --levels 0:6 \
+                                 --iterations 2000 --tile-format jpeg"))
+        .subcommand(SubCommand::with_name("colorize")
+                    .about("Paint the samples saved by 'render --output-data' or \
+                            'render --output-raw'")
+                    .arg(Arg::with_name("input")
+                         .value_name("FILE")
+                         .required(true)
+                         .help("Sample file written by 'render --output-data', or \
+                                iteration count file written by 'render --output-raw'"))
+                    .arg(Arg::with_name("output")
+                         .short("o")
+                         .long("output")
+                         .value_name("FILE")
+                         .help("Image file to write, or - for standard output"))
+                    .arg(config_arg())
+                    .args(&palette_args())
+                    .args(&format_args())
+                    .after_help("The samples record how each point's orbit behaved, \
+                                 so any palette, transfer function, or cycling \
+                                 can be applied to them without following the \
+                                 orbits again.\n\n\
+                                 EXAMPLE:\n    \
+                                 mandelbrot render -o mandel.png \
+                                 --output-data mandel.smp -s 4000x3000 \
+                                 --iterations 100000\n    \
+                                 mandelbrot colorize mandel.smp -o fire.png \
+                                 --palette fire --transfer log"))
+        .subcommand(SubCommand::with_name("lyapunov")
+                    .about("Render a Markus-Lyapunov fractal")
+                    .arg(Arg::with_name("output")
+                         .short("o")
+                         .long("output")
+                         .value_name("FILE")
+                         .help("Image file to write, or - for standard output"))
+                    .arg(Arg::with_name("sequence")
+                         .long("sequence")
+                         .value_name("LETTERS")
+                         .default_value("AB")
+                         .help("Sequence of the letters A and B, saying which \
+                                growth rate to use at each step"))
+                    .arg(config_arg())
+                    .arg(size_arg())
+                    .args(&region_args())
+                    .arg(rotate_arg())
+                    .args(&format_args())
+                    .arg(iterations_arg())
+                    .arg(threads_arg())
+                    .after_help("The real axis gives the growth rate a, and the \
+                                 imaginary axis b; the region defaults to \
+                                 2 <= a, b <= 4.\n\n\
+                                 EXAMPLE:\n    \
+                                 mandelbrot lyapunov -o zircon.png -s 800x800 \
+                                 --sequence BBBBBBAAAAAA --re 2.5:3.4 --im 3.4:4"))
+        .subcommand(SubCommand::with_name("info")
+                    .about("Describe a view without rendering it")
+                    .arg(config_arg())
+                    .arg(import_par_arg())
+                    .arg(import_kfr_arg())
+                    .arg(import_upr_arg())
+                    .arg(share_arg())
+                    .arg(size_arg())
+                    .args(&region_args())
+                    .arg(rotate_arg()))
+        .subcommand(SubCommand::with_name("locate")
+                    .about("Convert between pixels of an image and points on the \
+                            complex plane")
+                    .arg(Arg::with_name("pixel")
+                         .short("p")
+                         .long("pixel")
+                         .value_name("COLUMN,ROW")
+                         .multiple(true)
+                         .number_of_values(1)
+                         .required_unless("point")
+                         .help("Print the point at the upper left corner of this \
+                                pixel; may be repeated"))
+                    .arg(Arg::with_name("point")
+                         .short("P")
+                         .long("point")
+                         .value_name("POINT")
+                         .multiple(true)
+                         .number_of_values(1)
+                         .allow_hyphen_values(true)
+                         .help("Print the pixel containing this point; may be \
+                                repeated"))
+                    .arg(config_arg())
+                    .arg(import_par_arg())
+                    .arg(import_kfr_arg())
+                    .arg(import_upr_arg())
+                    .arg(share_arg())
+                    .arg(size_arg())
+                    .args(&region_args())
+                    .arg(rotate_arg())
+                    .after_help("EXAMPLE:\n    \
+                                 mandelbrot locate -s 1000x750 -u -1.20,0.35 \
+                                 -l -1,0.20 --pixel 640,200"))
+        .subcommand(SubCommand::with_name("share")
+                    .about("Make or read compact codes for exchanging views")
+                    .setting(AppSettings::SubcommandRequiredElseHelp)
+                    .subcommand(SubCommand::with_name("encode")
+                                .about("Print a code recording a view, to give \
+                                        to --share")
+                                .arg(config_arg())
+                                .arg(import_par_arg())
+                                .arg(import_kfr_arg())
+                                .arg(import_upr_arg())
+                                .args(&region_args())
+                                .arg(rotate_arg())
+                                .args(&set_args())
+                                .arg(iterations_arg()))
+                    .subcommand(SubCommand::with_name("decode")
+                                .about("Print the settings a code records")
+                                .arg(Arg::with_name("code")
+                                     .value_name("CODE")
+                                     .required(true)
+                                     .help("Code made by 'share encode'"))))
+        .subcommand(SubCommand::with_name("palette")
+                    .about("Work with palettes")
+                    .setting(AppSettings::SubcommandRequiredElseHelp)
+                    .subcommand(SubCommand::with_name("preview")
+                                .about("Draw a palette as a strip, running from \
+                                        the fastest escape time to the slowest")
+                                .arg(Arg::with_name("output")
+                                     .short("o")
+                                     .long("output")
+                                     .value_name("FILE")
+                                     .help("Image file to write, or - for \
+                                            standard output"))
+                                .arg(Arg::with_name("strip")
+                                     .long("strip")
+                                     .value_name("WIDTHxHEIGHT")
+                                     .default_value("512x32")
+                                     .help("Dimensions of the strip"))
+                                .arg(config_arg())
+                                .args(&palette_args())
+                                .args(&format_args())
+                                .arg(iterations_arg())
+                                .after_help("Each column of the strip has the color \
+                                             that an escape time of the same \
+                                             fraction of the iteration limit would \
+                                             get, after the transfer function and \
+                                             any cycling.\n\n\
+                                             EXAMPLE:\n    \
+                                             mandelbrot palette preview -o fire.png \
+                                             --palette fire --transfer sqrt")))
+        .subcommand(SubCommand::with_name("bookmark")
+                    .about("Manage the file of bookmarked locations")
+                    .setting(AppSettings::SubcommandRequiredElseHelp)
+                    .subcommand(SubCommand::with_name("add")
+                                .about("Save a view under a name")
+                                .arg(Arg::with_name("name")
+                                     .value_name("NAME")
+                                     .required(true)
+                                     .help("Name for the new location"))
+                                .args(&region_args())
+                                .arg(iterations_arg()))
+                    .subcommand(SubCommand::with_name("list")
+                                .about("List the bookmarked locations")
+                                .arg(bookmarks_arg())))
+        .subcommand(SubCommand::with_name("batch")
+                    .about("Render every job listed in a job file")
+                    .arg(Arg::with_name("jobs")
+                         .value_name("JOBFILE")
+                         .required(true)
+                         .help("File listing the jobs: either one line of `render` \
+                                arguments per job, or a JSON array of settings"))
+                    .arg(Arg::with_name("parallel")
+                         .short("p")
+                         .long("parallel")
+                         .value_name("COUNT")
+                         .default_value("1")
+                         .help("Number of jobs to render at once"))
+                    .arg(bookmarks_arg()))
+}
+
+#[test]
+fn test_build() {
+    // clap checks for duplicate argument names as each one is added, in
+    // debug builds, so building the whole tree catches them.
+    let matches = build().get_matches_from_safe(vec!["mandelbrot", "tiles", "-o", "map",
+                                                     "--formula", "burning-ship"])
+        .expect("tiles arguments should parse");
+    let tiles = matches.subcommand_matches("tiles").unwrap();
+    assert_eq!(tiles.value_of("formula"), Some("burning-ship"));
+}
@@ -0,0 +1,818 @@
+//! Coloring escape-time renders.
+//!
+//! Rendering produces an iteration count for each pixel; coloring maps each
+//! count to a color, through a palette. A palette is a gradient running
+//! through a list of colors, evenly spaced: points whose orbits escape at once
+//! get the first color, those that take nearly the whole iteration limit get
+//! the last, and those in between are interpolated along the way. Points that
+//! never escape are black, whatever the palette.
+//!
+//! The default palette, `gray`, runs from white to black, and gives the same
+//! image the program has always drawn. The others include the perceptually
+//! uniform colormaps `viridis`, `magma`, `inferno`, `plasma`, `turbo`, and
+//! `cubehelix`.
+//!
+//! Palettes can also be read from FractInt `.map` files, which list colors one
+//! per line, usually 256 of them, as red, green, and blue values from 0 to
+//! 255, optionally followed by a comment:
+//!
+//! ```text
+//! 0 0 0     the first color
+//! 0 0 168
+//! ```
+//!
+//! The colors become the stops of the gradient, in order.
+//!
+//! Spreading escape times evenly over the palette wastes most of it when
+//! zoomed in: the counts bunch up in a narrow band, and the image comes out
+//! nearly one color. Histogram equalization spreads them by rank instead,
+//! placing each point along the gradient by the fraction of the image's
+//! escaping points that escaped sooner. This needs every count in the image
+//! before any can be colored, so rendering and coloring are separate passes.
+//!
+//! A transfer function can stretch one end of the gradient instead. Each
+//! point's position along the gradient, a fraction from 0 to 1, passes through
+//! it on the way: `sqrt` and `log` give more of the palette to the points that
+//! escape soonest, while `power:K` with `K` above 1 favors the slowest.
+//!
+//! Deep zooms can span tens of thousands of iterations, far more than one pass
+//! through a gradient can show. A palette can cycle instead, repeating every
+//! so many iterations: then the gradient runs from its last color back around
+//! to its first, so that the repetitions join without a seam.
+
+use config::{Blend, Corner};
+use std::fs::File;
+use std::io::{self, ErrorKind, Read};
+use std::path::Path;
+
+/// The color of points whose orbits never escape.
+pub const INTERIOR: [u8; 3] = [0, 0, 0];
+
+/// The colors for points whose orbits settle into cycles, by period: the first
+/// for period 1, the second for period 2, and so on, starting over after the
+/// last. They are chosen to be easy to tell apart, rather than to blend.
+pub const PERIODS: &'static [[u8; 3]] = &[
+    [78, 121, 167], [242, 142, 43], [225, 87, 89], [118, 183, 178], [89, 161, 79],
+    [237, 201, 72], [176, 122, 161], [255, 157, 167], [156, 117, 95], [186, 176, 172]
+];
+
+/// The built-in palettes, by name, each a list of colors from the fastest
+/// escaping points to the slowest.
+pub const PALETTES: &'static [(&'static str, &'static [[u8; 3]])] = &[
+    ("gray", &[[255, 255, 255], [0, 0, 0]]),
+    ("fire", &[[0, 0, 0], [128, 0, 0], [255, 64, 0], [255, 200, 0], [255, 255, 224]]),
+    ("ocean", &[[0, 7, 100], [32, 107, 203], [237, 255, 255], [255, 170, 0], [0, 2, 0]]),
+    ("rainbow", &[[255, 0, 0], [255, 255, 0], [0, 255, 0], [0, 255, 255], [0, 0, 255],
+                  [255, 0, 255]]),
+
+    // Colormaps designed for scientific visualization, which brighten evenly
+    // and read well in grayscale and to colorblind eyes. Each is sampled at
+    // evenly spaced points, which the gradient interpolates between. Viridis,
+    // magma, inferno, and plasma come from matplotlib, turbo from Google, and
+    // cubehelix from Dave Green's formula, with matplotlib's parameters.
+    ("viridis", &[[68, 1, 84], [72, 40, 120], [62, 73, 137], [49, 104, 142], [38, 130, 142],
+                  [31, 158, 137], [53, 183, 121], [110, 206, 88], [181, 222, 43], [253, 231, 37]]),
+    ("magma", &[[0, 0, 4], [24, 15, 61], [68, 15, 118], [114, 31, 129], [158, 47, 127],
+                [205, 64, 113], [241, 96, 93], [253, 150, 104], [254, 202, 141], [252, 253, 191]]),
+    ("inferno", &[[0, 0, 4], [27, 12, 65], [74, 12, 107], [120, 28, 109], [165, 44, 96],
+                  [207, 68, 70], [237, 105, 37], [251, 155, 6], [247, 209, 61], [252, 255, 164]]),
+    ("plasma", &[[13, 8, 135], [70, 3, 159], [114, 1, 168], [156, 23, 158], [189, 55, 134],
+                 [216, 87, 107], [237, 121, 83], [251, 159, 58], [253, 202, 38], [240, 249, 33]]),
+    ("turbo", &[[48, 18, 59], [65, 69, 171], [70, 117, 237], [57, 162, 252], [27, 207, 212],
+                [36, 236, 166], [97, 252, 108], [164, 252, 59], [209, 232, 52], [243, 198, 58],
+                [254, 155, 45], [243, 99, 21], [217, 56, 6], [177, 25, 1], [122, 4, 2]]),
+    ("cubehelix", &[[0, 0, 0], [22, 12, 31], [26, 33, 62], [22, 61, 78], [23, 90, 73],
+                    [43, 111, 57], [84, 121, 47], [135, 122, 58], [181, 121, 94], [208, 126, 147],
+                    [212, 144, 198], [202, 171, 232], [193, 202, 243], [200, 228, 240],
+                    [224, 245, 240], [255, 255, 255]]),
+];
+
+/// The palette to use if none is given.
+pub const DEFAULT_PALETTE: &'static str = "gray";
+
+/// A gradient through a list of colors.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Palette {
+    colors: Vec<[u8; 3]>
+}
+
+impl Palette {
+    /// Return a palette running through `colors`, of which there must be at
+    /// least one.
+    pub fn new(colors: Vec<[u8; 3]>) -> Palette {
+        assert!(!colors.is_empty());
+        Palette { colors: colors }
+    }
+
+    /// Return the built-in palette called `name`, if there is one.
+    pub fn named(name: &str) -> Option<Palette> {
+        PALETTES.iter()
+            .find(|&&(palette, _)| palette == name)
+            .map(|&(_, colors)| Palette::new(colors.to_vec()))
+    }
+
+    /// Return true if every color in this palette is a shade of gray, so that
+    /// images drawn with it can be written as grayscale.
+    pub fn is_gray(&self) -> bool {
+        self.colors.iter().all(|color| color[0] == color[1] && color[1] == color[2])
+    }
+
+    /// Return the color for a point whose orbit escaped after `count`
+    /// iterations, out of a limit of `limit`. Points that never escaped should
+    /// have a count of `limit`.
+    pub fn shade(&self, count: u32, limit: u32) -> [u8; 3] {
+        if count >= limit {
+            return INTERIOR;
+        }
+        self.at(count as u64, limit as u64)
+    }
+
+    /// Return the color `along / total` of the way through this palette,
+    /// where `along` is less than `total`.
+    pub fn at(&self, along: u64, total: u64) -> [u8; 3] {
+        narrow(self.interpolate(along, total, false, 1, None))
+    }
+
+    /// Like `at`, but treat the palette as a loop, running from its last
+    /// color back to its first.
+    pub fn around(&self, along: u64, total: u64) -> [u8; 3] {
+        narrow(self.interpolate(along, total, true, 1, None))
+    }
+
+    /// Return the color `along / total` of the way through the gradient,
+    /// looping back from the last color to the first if `cyclic` is true. The
+    /// colors' channels are multiplied by `scale` first: 1 gives eight-bit
+    /// channels, and 257 stretches them to sixteen bits. If `gamma` is given,
+    /// blend the colors in linear light, re-encoding the result.
+    fn interpolate(&self, along: u64, total: u64, cyclic: bool, scale: i64,
+                   gamma: Option<Gamma>)
+        -> [u16; 3]
+    {
+        let scaled = |color: [u8; 3]| {
+            [color[0] as i64 * scale, color[1] as i64 * scale, color[2] as i64 * scale]
+        };
+        if self.colors.len() == 1 {
+            let color = scaled(self.colors[0]);
+            return [color[0] as u16, color[1] as u16, color[2] as u16];
+        }
+        let segments = (if cyclic { self.colors.len() } else { self.colors.len() - 1 }) as u64;
+        // Find the segment of the gradient `along` falls in, and how far
+        // along it, as the fraction `offset / total`. Integer arithmetic keeps
+        // the gray palette exactly as it always was.
+        let position = along * segments;
+        let index = (position / total) as usize;
+        let offset = (position % total) as i64;
+        let from = scaled(self.colors[index]);
+        let to = scaled(self.colors[(index + 1) % self.colors.len()]);
+        let mut color = [0; 3];
+        for channel in 0..3 {
+            let (from, to) = (from[channel], to[channel]);
+            color[channel] = match gamma {
+                None => (from + (to - from) * offset / total as i64) as u16,
+                Some(gamma) => {
+                    let full = (255 * scale) as f64;
+                    let from = gamma.decode(from as f64 / full);
+                    let to = gamma.decode(to as f64 / full);
+                    let linear = from + (to - from) * (offset as f64 / total as f64);
+                    (gamma.encode(linear) * full).round() as u16
+                }
+            };
+        }
+        color
+    }
+}
+
+/// Reduce `colors`, the sixteen-bit colors of an image `width` pixels wide,
+/// to eight bits per channel, by ordered dithering.
+///
+/// Rounding every pixel the same way turns a smooth gradient into bands of
+/// flat color, with visible edges between them. Instead, we add to each pixel
+/// a different fraction of an eight-bit step before truncating, following an
+/// 8x8 Bayer matrix, so a color between two eight-bit values comes out as a
+/// fine pattern of both, in the right proportion. Colors that are exactly
+/// eight-bit values to begin with are unchanged.
+pub fn dither(colors: &mut [[u16; 3]], width: usize) {
+    for (i, color) in colors.iter_mut().enumerate() {
+        let threshold = (bayer(i % width, i / width) as f64 + 0.5) / 64.0;
+        for channel in color.iter_mut() {
+            *channel = (*channel as f64 / 257.0 + threshold).min(255.0) as u16;
+        }
+    }
+}
+
+/// Return the entry of the 8x8 Bayer matrix for the pixel at `x`, `y`, from 0
+/// to 63: the bits of `x ^ y` and `y`, interleaved and reversed.
+fn bayer(x: usize, y: usize) -> u32 {
+    let (x, y) = ((x % 8) as u32, (y % 8) as u32);
+    let mut entry = 0;
+    for bit in 0..3 {
+        entry = (entry << 2) | (((x ^ y) >> bit) & 1) << 1 | ((y >> bit) & 1);
+    }
+    entry
+}
+
+/// How color values are encoded: the curve relating a channel's value, from 0
+/// to 1, to the intensity of light it stands for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Gamma {
+    /// The sRGB curve, a power of 2.4 with a short linear segment near black.
+    Srgb,
+
+    /// A plain power law: the value raised to this power gives the intensity.
+    Power(f64)
+}
+
+impl Gamma {
+    /// Return the intensity the encoded `value` stands for.
+    pub fn decode(&self, value: f64) -> f64 {
+        match *self {
+            Gamma::Srgb if value <= 0.04045 => value / 12.92,
+            Gamma::Srgb => ((value + 0.055) / 1.055).powf(2.4),
+            Gamma::Power(gamma) => value.powf(gamma)
+        }
+    }
+
+    /// Return the encoded value for the intensity `linear`.
+    pub fn encode(&self, linear: f64) -> f64 {
+        match *self {
+            Gamma::Srgb if linear <= 0.0031308 => linear * 12.92,
+            Gamma::Srgb => 1.055 * linear.powf(1.0 / 2.4) - 0.055,
+            Gamma::Power(gamma) => linear.powf(1.0 / gamma)
+        }
+    }
+}
+
+/// A function reshaping positions along the gradient.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transfer {
+    Linear,
+    Sqrt,
+
+    /// Position `along / total` becomes `ln(1 + along) / ln(1 + total)`.
+    Log,
+
+    /// Raise the position to the given power, which must be positive.
+    Power(f64)
+}
+
+/// The denominator of positions along the gradient computed in floating
+/// point: fine enough that no palette could show the difference.
+const RESOLUTION: u64 = 1 << 24;
+
+/// Return `fraction`, which should be from 0 to 1, as a position along the
+/// gradient: a numerator less than `RESOLUTION`, and `RESOLUTION`.
+fn position(fraction: f64) -> (u64, u64) {
+    let scaled = (fraction.max(0.0) * RESOLUTION as f64) as u64;
+    (scaled.min(RESOLUTION - 1), RESOLUTION)
+}
+
+impl Transfer {
+    /// Apply this function to the position `along / total` of the way through
+    /// a gradient, where `along` is less than `total`, and return the new
+    /// position as a numerator and denominator in the same way.
+    pub fn apply(&self, along: u64, total: u64) -> (u64, u64) {
+        let fraction = along as f64 / total as f64;
+        position(match *self {
+            Transfer::Linear => return (along, total),
+            Transfer::Sqrt => fraction.sqrt(),
+            Transfer::Log => (along as f64).ln_1p() / (total as f64).ln_1p(),
+            Transfer::Power(exponent) => fraction.powf(exponent)
+        })
+    }
+}
+
+/// Return `color`, whose channels must fit in eight bits, as bytes.
+fn narrow(color: [u16; 3]) -> [u8; 3] {
+    [color[0] as u8, color[1] as u8, color[2] as u8]
+}
+
+/// What rendering found out about a point, for coloring.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sample {
+    /// The number of iterations the point's orbit took to escape, or the
+    /// iteration limit if it never did.
+    Count(u32),
+
+    /// The point's position along the palette, from 0 to 1, as chosen by some
+    /// other coloring method.
+    Fraction(f64),
+
+    /// Both of the above, to be combined as the `Blend` says.
+    Blend(u32, f64, Blend),
+
+    /// An iteration count, whose color is to be darkened to the given
+    /// brightness, from 0 to 1, by lighting.
+    Lit(u32, f64),
+
+    /// The period of the cycle a point's orbit settled into, to be colored
+    /// from `PERIODS` rather than the palette, or 0 if it never settled.
+    Period(u32)
+}
+
+impl Default for Sample {
+    fn default() -> Sample {
+        Sample::Count(0)
+    }
+}
+
+/// Return `counts` as samples.
+pub fn counts(counts: &[u32]) -> Vec<Sample> {
+    counts.iter().map(|&count| Sample::Count(count)).collect()
+}
+
+/// How to turn a whole image's escape times into colors.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Coloring {
+    pub palette: Palette,
+    pub transfer: Transfer,
+
+    /// If true, spread the escape times over the palette by histogram
+    /// equalization, rather than evenly.
+    pub equalize: bool,
+
+    /// If present, the percentiles of the escape times, among the points that
+    /// escape, to put at the start and the end of the palette. Points that
+    /// escape sooner or later than those take the palette's ends.
+    pub exposure: Option<(f64, f64)>,
+
+    /// If present, the period and offset with which to cycle through the
+    /// palette: points that escape after `count` iterations get the color
+    /// `(count + offset) % period / period` of the way around it.
+    pub cycle: Option<(u32, u32)>,
+
+    /// The number of bits per channel the colors should have: 8 or 16.
+    pub depth: u8,
+
+    /// If present, the encoding of the palette's colors, so that we can
+    /// interpolate between them in linear light. Otherwise, we interpolate
+    /// between the encoded values directly.
+    pub gamma: Option<Gamma>,
+
+    /// If true, compute eight-bit colors to sixteen bits, for `dither` to
+    /// reduce to eight.
+    pub dither: bool,
+
+    /// If true, points whose orbits never escape should be transparent,
+    /// rather than black.
+    pub transparent: bool,
+
+    /// The ICC profile to embed in the image, if any.
+    pub profile: Option<Vec<u8>>,
+
+    /// The corner of the image in which to draw a legend, if any.
+    pub legend: Option<Corner>
+}
+
+impl Coloring {
+    /// Return the first and last escape times that get distinct colors in an
+    /// image whose samples are `samples`: those that start and end the cycle,
+    /// if cycling; the least and greatest of those in the image, if
+    /// equalizing; the exposure's percentiles; or else all of them.
+    pub fn range(&self, samples: &[Sample], limit: u32) -> (u32, u32) {
+        if let Some((period, _)) = self.cycle {
+            return (0, period - 1);
+        }
+        if self.equalize {
+            return percentiles(samples, limit, 0.0, 100.0);
+        }
+        match self.exposure {
+            Some((low, high)) => percentiles(samples, limit, low, high),
+            None => (0, limit.saturating_sub(1))
+        }
+    }
+
+    /// Return the opacity of each of `samples`, as `colors` would color them:
+    /// false for the points that `transparent` leaves out, and true for all
+    /// the others.
+    pub fn opacity(&self, samples: &[Sample], limit: u32) -> Vec<bool> {
+        samples.iter().map(|&sample| {
+            !self.transparent || match sample {
+                Sample::Count(count) | Sample::Lit(count, _) => count < limit,
+                Sample::Period(_) => false,
+                Sample::Fraction(_) | Sample::Blend(_, _, _) => true
+            }
+        }).collect()
+    }
+
+    /// Return true if every color this coloring would give `samples` is a
+    /// gray.
+    pub fn is_gray(&self, samples: &[Sample]) -> bool {
+        self.palette.is_gray() && !samples.iter().any(|sample| match *sample {
+            Sample::Period(period) => period > 0,
+            _ => false
+        })
+    }
+
+    /// Return the color for each of `samples`, the results of rendering an
+    /// image's points, with an iteration limit of `limit`. Each channel is a
+    /// sample of `depth` bits, or sixteen if `dither` is set, held in a `u16`
+    /// regardless.
+    ///
+    /// Counts pass through equalization, exposure, or cycling, if requested,
+    /// to find their positions along the palette; cycling takes precedence,
+    /// and then equalization. Fractions give their positions directly. Either
+    /// way, the position then passes through the transfer function.
+    pub fn colors(&self, samples: &[Sample], limit: u32) -> Vec<[u16; 3]> {
+        self.colors_among(samples, samples, limit)
+    }
+
+    /// Return the colors `samples` would have in an image whose samples are
+    /// `image`. Equalization and exposure depend on the whole image.
+    pub fn colors_among(&self, samples: &[Sample], image: &[Sample], limit: u32)
+        -> Vec<[u16; 3]>
+    {
+        let scale = if self.depth == 16 || self.dither { 257 } else { 1 };
+        let ranks = if self.equalize && self.cycle.is_none() {
+            Some(ranks(image, limit))
+        } else {
+            None
+        };
+        let exposure = match self.exposure {
+            Some((low, high)) => Some(percentiles(image, limit, low, high)),
+            None => None
+        };
+        let black = [INTERIOR[0] as u16, INTERIOR[1] as u16, INTERIOR[2] as u16];
+        let lookup = |along, total, cyclic| {
+            let (along, total) = self.transfer.apply(along, total);
+            self.palette.interpolate(along, total, cyclic, scale, self.gamma)
+        };
+        let escape = |count: u32| {
+            if count >= limit {
+                return black;
+            }
+            match (self.cycle, &ranks) {
+                (Some((period, offset)), _) => {
+                    let period = period as u64;
+                    lookup((count as u64 + offset as u64) % period, period, true)
+                }
+                (None, &Some((ref sooner, escaped))) => {
+                    lookup(rank(sooner, escaped, count), escaped, false)
+                }
+                (None, &None) => match exposure {
+                    Some((low, high)) => {
+                        let count = count.max(low).min(high);
+                        lookup((count - low) as u64, (high - low) as u64 + 1, false)
+                    }
+                    None => lookup(count as u64, limit as u64, false)
+                }
+            }
+        };
+        let fraction = |fraction| {
+            let (along, total) = position(fraction);
+            lookup(along, total, false)
+        };
+        let full = 255 * scale as u32;
+        samples.iter().map(|&sample| {
+            match sample {
+                Sample::Count(count) => escape(count),
+                Sample::Fraction(f) => fraction(f),
+                Sample::Blend(count, f, blend) => {
+                    let (escape, trap) = (escape(count), fraction(f));
+                    let mut color = [0; 3];
+                    for channel in 0..3 {
+                        let (a, b) = (escape[channel] as u32, trap[channel] as u32);
+                        color[channel] = match blend {
+                            Blend::Replace => b,
+                            Blend::Multiply => a * b / full,
+                            Blend::Minimum => a.min(b)
+                        } as u16;
+                    }
+                    color
+                }
+                Sample::Lit(count, light) => {
+                    let escape = escape(count);
+                    let mut color = [0; 3];
+                    for channel in 0..3 {
+                        color[channel] = (escape[channel] as f64 * light).round() as u16;
+                    }
+                    color
+                }
+                Sample::Period(0) => black,
+                Sample::Period(period) => {
+                    let color = PERIODS[(period - 1) as usize % PERIODS.len()];
+                    let channel = |value: u8| (value as i64 * scale) as u16;
+                    [channel(color[0]), channel(color[1]), channel(color[2])]
+                }
+            }
+        }).collect()
+    }
+}
+
+/// Return, for each distinct iteration count below `limit` among `samples`,
+/// the number of `samples` that escaped sooner, sorted by count, along with
+/// the total number that escaped at all.
+///
+/// This is sized by the samples, not by `limit`, which comes from a file
+/// header and may be enormous.
+fn ranks(samples: &[Sample], limit: u32) -> (Vec<(u32, u64)>, u64) {
+    let mut counts: Vec<u32> = samples.iter().filter_map(|sample| match *sample {
+        Sample::Count(count) | Sample::Blend(count, _, _) | Sample::Lit(count, _)
+            if count < limit => Some(count),
+        _ => None
+    }).collect();
+    counts.sort();
+    let mut sooner: Vec<(u32, u64)> = Vec::new();
+    for (escaped, &count) in counts.iter().enumerate() {
+        if sooner.last().map_or(true, |&(last, _)| last != count) {
+            sooner.push((count, escaped as u64));
+        }
+    }
+    (sooner, counts.len() as u64)
+}
+
+/// Return the number of samples that escaped before `count`, given the
+/// result of `ranks`. `count` need not appear in the ranked samples.
+fn rank(sooner: &[(u32, u64)], escaped: u64, count: u32) -> u64 {
+    match sooner.binary_search_by_key(&count, |&(count, _)| count) {
+        Ok(index) => sooner[index].1,
+        Err(index) => sooner.get(index).map_or(escaped, |&(_, rank)| rank)
+    }
+}
+
+/// Return the escape times at the `low` and `high` percentiles of those of
+/// `samples` that escaped before `limit`. If none did, return `(0, limit - 1)`.
+fn percentiles(samples: &[Sample], limit: u32, low: f64, high: f64) -> (u32, u32) {
+    let mut counts: Vec<u32> = samples.iter().filter_map(|sample| match *sample {
+        Sample::Count(count) | Sample::Blend(count, _, _) | Sample::Lit(count, _)
+            if count < limit => Some(count),
+        _ => None
+    }).collect();
+    if counts.is_empty() {
+        return (0, limit.saturating_sub(1));
+    }
+    counts.sort();
+    let at = |percentile: f64| {
+        let index = (percentile / 100.0 * (counts.len() - 1) as f64).round() as usize;
+        counts[index.min(counts.len() - 1)]
+    };
+    (at(low), at(high))
+}
+
+/// Parse `text` as a FractInt `.map` file, and return its palette.
+pub fn parse_map(text: &str) -> Result<Palette, String> {
+    let mut colors = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut fields = line.split_whitespace();
+        let mut color = [0; 3];
+        for channel in color.iter_mut() {
+            let field = try!(fields.next().ok_or_else(|| {
+                format!("line {}: expected red, green, and blue values", number + 1)
+            }));
+            *channel = try!(field.parse().map_err(|_| {
+                format!("line {}: expected a number from 0 to 255, not '{}'", number + 1, field)
+            }));
+        }
+        colors.push(color);
+    }
+    if colors.is_empty() {
+        return Err("the file has no colors in it".to_string());
+    }
+    Ok(Palette::new(colors))
+}
+
+/// Read the FractInt `.map` file at `path`.
+pub fn load_map(path: &Path) -> io::Result<Palette> {
+    let mut text = String::new();
+    try!(try!(File::open(path)).read_to_string(&mut text));
+    parse_map(&text).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+}
+
+/// Return the names of the built-in palettes, separated by commas, for use in
+/// messages.
+pub fn palette_names() -> String {
+    PALETTES.iter().map(|&(name, _)| name).collect::<Vec<_>>().join(", ")
+}
+
+#[test]
+fn test_gray() {
+    let gray = Palette::named("gray").unwrap();
+    assert!(gray.is_gray());
+    let shades = |counts: &[u32], limit| {
+        counts.iter().map(|&count| gray.shade(count, limit)[0]).collect::<Vec<_>>()
+    };
+    assert_eq!(shades(&[0, 1, 254, 255], 255), vec![255, 254, 1, 0]);
+    assert_eq!(shades(&[0, 500, 999, 1000], 1000), vec![255, 128, 1, 0]);
+    assert_eq!(shades(&[0, 100000, 200000], 200000), vec![255, 128, 0]);
+}
+
+#[test]
+fn test_parse_map() {
+    let palette = parse_map("0 0 0 black\n\n  255 128 7\t orange, more or less\n0 0 255\n")
+        .unwrap();
+    assert_eq!(palette, Palette::new(vec![[0, 0, 0], [255, 128, 7], [0, 0, 255]]));
+    assert!(parse_map("").is_err());
+    assert!(parse_map("0 0 0\n1 2\n").is_err());
+    assert!(parse_map("0 0 256\n").is_err());
+    assert!(parse_map("0 zero 0\n").is_err());
+}
+
+#[test]
+fn test_shade() {
+    let palette = Palette::new(vec![[0, 0, 0], [200, 100, 0], [0, 100, 200]]);
+    assert!(!palette.is_gray());
+    assert_eq!(palette.shade(0, 100), [0, 0, 0]);
+    assert_eq!(palette.shade(25, 100), [100, 50, 0]);
+    assert_eq!(palette.shade(50, 100), [200, 100, 0]);
+    assert_eq!(palette.shade(75, 100), [100, 100, 100]);
+    assert_eq!(palette.shade(100, 100), INTERIOR);
+    assert_eq!(Palette::new(vec![[1, 2, 3]]).shade(5, 100), [1, 2, 3]);
+    assert!(Palette::named("no-such-palette").is_none());
+    for &(name, _) in PALETTES {
+        assert!(Palette::named(name).is_some());
+    }
+    let viridis = Palette::named("viridis").unwrap();
+    assert_eq!(viridis.shade(0, 90), [68, 1, 84]);
+    assert_eq!(viridis.shade(5, 90), [70, 20, 102]);
+    assert_eq!(viridis.shade(89, 90), [245, 230, 38]);
+}
+
+/// Return a coloring with the gray palette, spread evenly, and nothing else,
+/// for tests to adjust.
+#[cfg(test)]
+pub fn plain() -> Coloring {
+    Coloring {
+        palette: Palette::named("gray").unwrap(),
+        transfer: Transfer::Linear,
+        equalize: false,
+        exposure: None,
+        cycle: None,
+        depth: 8,
+        gamma: None,
+        dither: false,
+        transparent: false,
+        profile: None,
+        legend: None
+    }
+}
+
+#[test]
+fn test_equalize() {
+    let even = plain();
+    let equalized = Coloring { equalize: true, .. even.clone() };
+    let shades = |coloring: &Coloring, escapes: &[u32]| {
+        coloring.colors(&counts(escapes), 1000).iter().map(|rgb| rgb[0]).collect::<Vec<_>>()
+    };
+
+    // Counts bunched together near the limit come out nearly black when spread
+    // evenly, but run through the whole palette when equalized, in order.
+    let counts = [990, 991, 991, 992, 993, 1000];
+    assert_eq!(shades(&even, &counts), vec![3, 3, 3, 3, 2, 0]);
+    assert_eq!(shades(&equalized, &counts), vec![255, 204, 204, 102, 51, 0]);
+
+    // Only the counts' order matters.
+    assert_eq!(shades(&equalized, &[3, 700, 700, 701, 999, 1000]),
+               shades(&equalized, &counts));
+    assert_eq!(shades(&equalized, &[1000, 1000]), vec![0, 0]);
+
+    // Counts absent from the image rank among those present, and a huge limit
+    // costs nothing.
+    let image = self::counts(&[10, 20, 20, 30]);
+    let among = equalized.colors_among(&self::counts(&[5, 10, 15, 25, 35]), &image, 1000);
+    assert_eq!(among.iter().map(|rgb| rgb[0]).collect::<Vec<_>>(), vec![255, 255, 192, 64, 0]);
+    let huge = equalized.colors(&self::counts(&[1, 2]), u32::max_value());
+    assert_eq!(huge.iter().map(|rgb| rgb[0]).collect::<Vec<_>>(), shades(&equalized, &[1, 2]));
+}
+
+#[test]
+fn test_transfer() {
+    assert_eq!(Transfer::Linear.apply(3, 10), (3, 10));
+    let fraction = |transfer: Transfer, along, total| {
+        let (along, total) = transfer.apply(along, total);
+        along as f64 / total as f64
+    };
+    let close = |a: f64, b: f64| (a - b).abs() < 1e-6;
+    assert!(close(fraction(Transfer::Sqrt, 1, 4), 0.5));
+    assert!(close(fraction(Transfer::Log, 9, 99), 0.5));
+    assert!(close(fraction(Transfer::Power(2.0), 1, 2), 0.25));
+    assert_eq!(Transfer::Sqrt.apply(0, 10), (0, RESOLUTION));
+    assert!(fraction(Transfer::Power(0.001), 9, 10) < 1.0);
+
+    // The gray palette runs from white at 0 to black at 1.
+    let sqrt = Coloring { transfer: Transfer::Sqrt, .. plain() };
+    assert_eq!(sqrt.colors(&counts(&[0, 25, 100]), 100), vec![[255; 3], [128; 3], [0; 3]]);
+}
+
+#[test]
+fn test_exposure() {
+    let even = plain();
+    let exposed = Coloring { exposure: Some((10.0, 90.0)), .. even.clone() };
+    let shades = |coloring: &Coloring, escapes: &[u32]| {
+        coloring.colors(&counts(escapes), 1000).iter().map(|rgb| rgb[0]).collect::<Vec<_>>()
+    };
+
+    // One stray slow point leaves the rest near white, until the range is
+    // clipped to the bulk of the escape times.
+    let mut escapes: Vec<u32> = (0..11).map(|i| 10 + i).collect();
+    escapes.push(900);
+    escapes.push(1000);
+    assert_eq!(percentiles(&counts(&escapes), 1000, 10.0, 90.0), (11, 20));
+    assert_eq!(shades(&even, &escapes)[..3], [253, 253, 252]);
+    assert_eq!(shades(&exposed, &escapes)[..3], [255, 255, 230]);
+    assert_eq!(shades(&exposed, &escapes)[10..], [26, 26, 0]);
+    assert_eq!(percentiles(&counts(&[1000]), 1000, 1.0, 99.0), (0, 999));
+    assert_eq!(exposed.range(&counts(&escapes), 1000), (11, 20));
+    assert_eq!(even.range(&counts(&escapes), 1000), (0, 999));
+}
+
+#[test]
+fn test_cycle() {
+    let palette = Palette::new(vec![[0, 0, 0], [200, 100, 0]]);
+    assert_eq!(palette.around(0, 4), [0, 0, 0]);
+    assert_eq!(palette.around(1, 4), [100, 50, 0]);
+    assert_eq!(palette.around(2, 4), [200, 100, 0]);
+    assert_eq!(palette.around(3, 4), [100, 50, 0]);
+
+    let cycling = Coloring { palette: palette, cycle: Some((4, 1)), .. plain() };
+    assert_eq!(cycling.colors(&counts(&[0, 1, 2, 3, 4, 5, 10]), 10),
+               vec![[100, 50, 0], [200, 100, 0], [100, 50, 0], [0, 0, 0],
+                    [100, 50, 0], [200, 100, 0], [0, 0, 0]]);
+}
+
+#[test]
+fn test_depth() {
+    let deep = Coloring { depth: 16, .. plain() };
+    let shades = deep.colors(&counts(&[0, 1, 999, 1000]), 1000).iter().map(|rgb| rgb[0])
+        .collect::<Vec<_>>();
+    assert_eq!(shades, vec![65535, 65470, 66, 0]);
+}
+
+#[test]
+fn test_fractions() {
+    let coloring = Coloring {
+        palette: Palette::new(vec![[0, 0, 0], [200, 100, 0]]),
+        cycle: Some((10, 0)),
+        .. plain()
+    };
+    // Fractions go along the palette, rather than around it.
+    let samples = [Sample::Count(5), Sample::Count(10), Sample::Fraction(0.5),
+                   Sample::Fraction(0.0), Sample::Fraction(1.0)];
+    assert_eq!(coloring.colors(&samples, 10),
+               vec![[200, 100, 0], [0, 0, 0], [100, 50, 0], [0, 0, 0], [199, 99, 0]]);
+}
+
+#[test]
+fn test_blend() {
+    let coloring = Coloring {
+        palette: Palette::new(vec![[0, 0, 0], [200, 100, 0], [0, 200, 250]]),
+        .. plain()
+    };
+    // The count gives [200, 100, 0], and the fraction [1, 199, 249], as close
+    // to the end of the palette as it gets.
+    let samples = [Sample::Blend(5, 1.0, Blend::Replace),
+                   Sample::Blend(5, 1.0, Blend::Multiply),
+                   Sample::Blend(5, 1.0, Blend::Minimum),
+                   Sample::Blend(10, 1.0, Blend::Multiply)];
+    assert_eq!(coloring.colors(&samples, 10),
+               vec![[1, 199, 249], [0, 78, 0], [1, 100, 0], [0, 0, 0]]);
+
+    let samples = [Sample::Lit(5, 1.0), Sample::Lit(5, 0.25), Sample::Lit(5, 0.0)];
+    assert_eq!(coloring.colors(&samples, 10), vec![[200, 100, 0], [50, 25, 0], [0, 0, 0]]);
+}
+
+#[test]
+fn test_periods() {
+    let coloring = Coloring { depth: 16, .. plain() };
+    assert!(coloring.is_gray(&[Sample::Count(3), Sample::Period(0)]));
+    assert!(!coloring.is_gray(&[Sample::Count(3), Sample::Period(2)]));
+    let samples = [Sample::Period(0), Sample::Period(1), Sample::Period(12)];
+    assert_eq!(coloring.colors(&samples, 10),
+               vec![[0, 0, 0], [78 * 257, 121 * 257, 167 * 257],
+                    [242 * 257, 142 * 257, 43 * 257]]);
+}
+
+#[test]
+fn test_gamma() {
+    let linear = plain();
+    let square = Coloring { gamma: Some(Gamma::Power(2.0)), .. plain() };
+    let srgb = Coloring { gamma: Some(Gamma::Srgb), .. plain() };
+    let middle = |coloring: &Coloring| coloring.colors(&counts(&[0, 50]), 100)[1];
+
+    // Halfway between white and black is half the light, which takes a
+    // value well above half to encode.
+    assert_eq!(middle(&linear), [128, 128, 128]);
+    assert_eq!(middle(&square), [180, 180, 180]);
+    assert_eq!(middle(&srgb), [188, 188, 188]);
+
+    for &value in &[0.0, 0.01, 0.5, 1.0] {
+        assert!((Gamma::Srgb.encode(Gamma::Srgb.decode(value)) - value).abs() < 1e-12);
+    }
+}
+
+#[test]
+fn test_dither() {
+    // Every entry of the matrix appears once.
+    let mut entries: Vec<u32> = (0..64).map(|i| bayer(i % 8, i / 8)).collect();
+    assert_eq!(&entries[..4], &[0, 32, 8, 40]);
+    entries.sort();
+    assert_eq!(entries, (0..64).collect::<Vec<_>>());
+
+    // Halfway between two eight-bit values, half the pixels get each.
+    let mut colors = vec![[128 * 257 + 128, 200 * 257, 65535]; 64];
+    dither(&mut colors, 8);
+    assert_eq!(colors.iter().filter(|color| color[0] == 129).count(), 32);
+    assert!(colors.iter().all(|color| color[0] == 128 || color[0] == 129));
+    assert!(colors.iter().all(|color| color[1] == 200 && color[2] == 255));
+}
@@ -0,0 +1,872 @@
+//! Render settings, gathered from configuration files and the command line.
+//!
+//! A configuration file is TOML, with any of the following keys:
+//!
+//! ```toml
+//! output = "mandel.png"
+//! output_dir = "renders"
+//! output_normals = "normals.png"
+//! output_data = "mandel.smp"
+//! output_raw = "mandel.iter"
+//! output_npy = "mandel.npy"
+//! npy_smooth = false
+//! output_csv = "mandel.csv"
+//! csv_step = 4
+//! output_exr = "mandel.exr"
+//! exr_channels = ["distance", "magnitude"]
+//! output_hdr = "mandel.hdr"
+//! format = "jpeg"
+//! quality = 90
+//! tile_format = "jpeg"
+//! tile_size = 254
+//! tile_overlap = 1
+//! size = [1000, 750]
+//! upper_left = [-1.20, 0.35]
+//! lower_right = [-1.0, 0.20]
+//! rotate = 30.0
+//! set = "julia"
+//! c = [-0.8, 0.156]
+//! mode = "escape"
+//! polynomial = [[1.0, 0.0], [0.0, 0.0], [0.0, 0.0], [-1.0, 0.0]]
+//! formula = "burning-ship"
+//! hybrid = ["standard", "standard", "burning-ship"]
+//! formula_expr = "z^3 + sin(z) + c"
+//! script = "cubic.rhai"
+//! power = 3.0
+//! phoenix_p = [-0.5, 0.0]
+//! relaxation = [1.0, 0.0]
+//! slice = [0.1, 0.0]
+//! morph_to = "burning-ship"
+//! morph = 0.5
+//! palette = "fire"
+//! palette_file = "classic.map"
+//! gradient = "0:#000764,0.16:#206bcb,0.42:#edffff,1:#000200"
+//! gradient_space = "lab"
+//! transfer = "power:0.5"
+//! gamma = "srgb"
+//! icc_profile = "srgb"
+//! depth = 16
+//! coloring = "stripes"
+//! stripe_density = 5.0
+//! distance_thickness = 1.0
+//! light_angle = 45.0
+//! interior = "closest"
+//! trap = "circle:0,0,0.5"
+//! trap_blend = "multiply"
+//! equalize = false
+//! auto_expose = [1.0, 99.0]
+//! legend = "bottom-right"
+//! dither = true
+//! palette_period = 64
+//! palette_offset = 17
+//! z0 = [0.5, 0.0]
+//! samples = 20
+//! channel_limits = [5000, 500, 50]
+//! anti = false
+//! iterations = 1000
+//! escape_radius = 2.0
+//! threads = 4
+//! seed = 1234
+//! force = false
+//! ```
+//!
+//! Instead of `size`, you can give just one of `width` or `height`, and the
+//! other will be chosen to match the region's aspect ratio.
+//!
+//! Instead of `upper_left` and `lower_right`, the region may be given as
+//! `center` and `zoom`, or as the name of a bookmarked `location`, looked up in
+//! the file named by `bookmarks`.
+//!
+//! A relative `output`, `output_normals`, `output_data`, `output_raw`,
+//! `output_npy`, `output_csv`, `output_exr`, or `output_hdr` path is taken to
+//! be relative to `output_dir`, if given.
+//!
+//! If `format` is absent, the output file's name chooses it: names ending in
+//! `.jpg` or `.jpeg` get JPEG, `.tif` or `.tiff` get TIFF, `.webp` gets WebP,
+//! `.avif` gets AVIF, `.qoi` gets QOI, `.pgm` gets PGM, `.ppm` gets PPM, `.ff`
+//! gets farbfeld, `.bmp` gets BMP, `.dzi` gets a Deep Zoom image, and all
+//! others PNG. `quality` applies only to JPEG, WebP, and AVIF images, and JPEG
+//! tiles. A Deep Zoom image's tiles are `tile_format`, either PNG or JPEG,
+//! `tile_size` pixels square, and overlap their neighbors by `tile_overlap`
+//! pixels; the `tiles` subcommand's web map tiles use the first two.
+//!
+//! The same settings can also be given as a JSON object with the same keys,
+//! for programs that would rather not generate TOML.
+
+use std::fs::File;
+use std::io::{self, ErrorKind, Read};
+use std::path::Path;
+use std::thread;
+
+use serde_json;
+use toml;
+
+/// The kinds of sets we can draw.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SetKind {
+    /// The Mandelbrot set, where each point is a value for `c`.
+    Mandelbrot,
+
+    /// The Julia set for the value of `c` given by the `c` setting, where each
+    /// point is a starting value for the iteration.
+    Julia
+}
+
+/// The ways we have of rendering an image.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    /// Shade each point by how quickly its orbit escapes, drawing the
+    /// Mandelbrot set, a Julia set, or one of their variations.
+    Escape,
+
+    /// Color each point by the root of `polynomial` that Newton's method
+    /// carries it to.
+    Newton,
+
+    /// Brighten each point by how many escaping orbits pass through it,
+    /// drawing a Buddhabrot, or a Nebulabrot if `channel_limits` is given. If
+    /// `anti` is set, count the orbits that never escape instead.
+    Buddhabrot,
+
+    /// Draw the outline of a Julia set by inverse iteration, plotting
+    /// `samples` points per pixel.
+    Inverse
+}
+
+/// How to color the points of an escape-time render whose orbits escape.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Exterior {
+    /// By how many iterations they took to escape.
+    Escape,
+
+    /// By the triangle inequality average over their orbits.
+    Tia,
+
+    /// By the stripe average over their orbits, with `stripe_density`
+    /// stripes.
+    Stripes,
+
+    /// By the curvature average over their orbits.
+    Curvature,
+
+    /// By which side of the real axis their orbits escape on, splitting each
+    /// escape-time band in two: binary decomposition.
+    Binary,
+
+    /// By the direction in which their orbits escape, approximating their
+    /// external angles.
+    Angle,
+
+    /// By their estimated distance from the set, in units of
+    /// `distance_thickness` pixels, drawing the set's boundary.
+    Distance,
+
+    /// By their atom domains: the step at which their orbits came closest to
+    /// the origin. This colors the points that never escape, too.
+    Atom
+}
+
+/// How to color the points of an escape-time render whose orbits never escape.
+/// Other than `Black`, `Period`, and `Transparent`, each picks a color from the palette by
+/// some property of the orbit, measured against the escape radius.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Interior {
+    /// Color them all black.
+    Black,
+
+    /// By the magnitude of the last point of the orbit.
+    Magnitude,
+
+    /// By how close the orbit came to the origin.
+    Closest,
+
+    /// By the magnitude of the average of the orbit's points.
+    Average,
+
+    /// By the period of the cycle the orbit settles into, from a fixed set of
+    /// colors rather than the palette.
+    Period,
+
+    /// Leave them out of the image, as fully transparent pixels.
+    Transparent
+}
+
+/// How to combine the color an orbit trap gives a point with the color its
+/// escape time gives it.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Blend {
+    /// Use the trap's color alone.
+    Replace,
+
+    /// Multiply the two colors, channel by channel, as fractions of full
+    /// brightness.
+    Multiply,
+
+    /// Take the darker of the two colors in each channel.
+    Minimum
+}
+
+/// The color space in which to blend a gradient's stops.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorSpace {
+    /// Mix red, green, and blue directly.
+    Rgb,
+
+    /// Mix hue, saturation, and value, turning the short way around the
+    /// color wheel.
+    Hsv,
+
+    /// Mix in CIE L*a*b*.
+    Lab
+}
+
+/// The file formats we can write images in.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Png,
+    Jpeg,
+    Tiff,
+    Webp,
+    Avif,
+    Qoi,
+    Pgm,
+    Ppm,
+    Farbfeld,
+    Bmp,
+
+    /// A Deep Zoom image: an XML descriptor, and a pyramid of tiles in a
+    /// directory beside it.
+    Dzi
+}
+
+impl Format {
+    /// Return the usual file name extension for this format.
+    pub fn extension(&self) -> &'static str {
+        match *self {
+            Format::Png => "png",
+            Format::Jpeg => "jpg",
+            Format::Tiff => "tif",
+            Format::Webp => "webp",
+            Format::Avif => "avif",
+            Format::Qoi => "qoi",
+            Format::Pgm => "pgm",
+            Format::Ppm => "ppm",
+            Format::Farbfeld => "ff",
+            Format::Bmp => "bmp",
+            Format::Dzi => "dzi"
+        }
+    }
+}
+
+/// Channels an OpenEXR file can hold besides the smooth iteration count.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExrChannel {
+    /// The estimated distance from each point to the set.
+    Distance,
+
+    /// The magnitude of the last point of each point's orbit.
+    Magnitude
+}
+
+/// A corner of the image, in which to draw the legend.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight
+}
+
+/// Variations on the iteration `z = z^d + c`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Formula {
+    /// The usual iteration.
+    Standard,
+
+    /// The Burning Ship: take the absolute values of `z`'s real and imaginary
+    /// parts before raising it to the power.
+    BurningShip,
+
+    /// The Tricorn, or Mandelbar: take the complex conjugate of `z` before
+    /// raising it to the power.
+    Tricorn,
+
+    /// The Celtic: take the absolute value of the real part of `z^d`.
+    Celtic,
+
+    /// The Perpendicular Mandelbrot: take the absolute value of `z`'s real
+    /// part, and negate its imaginary part, before raising it to the power.
+    Perpendicular,
+
+    /// The Perpendicular Burning Ship: keep `z`'s real part, but replace its
+    /// imaginary part with minus its absolute value, before raising it to the
+    /// power.
+    PerpendicularBurningShip,
+
+    /// The Heart: take the absolute value of `z`'s real part before raising
+    /// it to the power.
+    Heart,
+
+    /// The Phoenix: add `phoenix_p` times the previous point in the orbit,
+    /// as in `z = z^d + c + p * z_previous`.
+    Phoenix,
+
+    /// Magnet type I, `z = ((z^2 + c - 1) / (2z + c - 2))^2`, from the theory
+    /// of magnetic phase transitions. Orbits may also settle at 1.
+    Magnet1,
+
+    /// Magnet type II, a higher-degree rational function of `z` and `c` in the
+    /// same family.
+    Magnet2,
+
+    /// The lambda, or logistic, family: `z = c * z * (1 - z)`.
+    Lambda,
+
+    /// The Nova: a step of Newton's method for `z^d - 1`, scaled by
+    /// `relaxation`, plus `c`. Orbits run until they converge.
+    Nova,
+
+    /// A quaternion Julia set, `q = q^2 + c` over the quaternions, drawn as the
+    /// slice through four-dimensional space that `slice` picks out.
+    QuatJulia,
+
+    /// The complex Collatz map, `z = (2 + 7z - (2 + 5z) cos(pi z)) / 4 + c`,
+    /// which takes odd integers `n` to `3n + 1` and even ones to `n / 2`. Its
+    /// Julia set for `c = 0` is the classic Collatz fractal.
+    Collatz,
+
+    /// The Spider: `z = z^d + c`, but `c` changes as the orbit goes, becoming
+    /// `c / 2 + z` after each step.
+    Spider,
+
+    /// The Manowar: `z = z^d + z_previous + c`, where the point before the
+    /// first is taken to be the first itself.
+    Manowar
+}
+
+impl Formula {
+    /// Return the point from which this formula's orbits start when drawing its
+    /// Mandelbrot set. This is a critical point of the iteration: one where
+    /// its derivative is zero.
+    pub fn critical_point(&self) -> (f64, f64) {
+        match *self {
+            Formula::Lambda => (0.5, 0.0),
+            Formula::Nova => (1.0, 0.0),
+            _ => (0.0, 0.0)
+        }
+    }
+
+    /// Return the iteration limit to use with this formula if none is given.
+    pub fn default_iterations(&self) -> u32 {
+        match *self {
+            Formula::Collatz => DEFAULT_COLLATZ_ITERATIONS,
+            _ => DEFAULT_ITERATIONS
+        }
+    }
+
+    /// Return the escape radius to use with this formula if none is given.
+    pub fn default_escape_radius(&self) -> f64 {
+        match *self {
+            Formula::Collatz => DEFAULT_COLLATZ_ESCAPE_RADIUS,
+            _ => DEFAULT_ESCAPE_RADIUS
+        }
+    }
+}
+
+/// Settings for a render, any of which may be absent.
+///
+/// Settings come from several sources: the command line, and optionally a
+/// configuration file. Each source produces a `Config`, and `merge` layers them
+/// to produce the settings we actually use.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub output: Option<String>,
+    pub output_dir: Option<String>,
+    pub output_normals: Option<String>,
+    pub output_data: Option<String>,
+    pub output_raw: Option<String>,
+    pub output_npy: Option<String>,
+    pub npy_smooth: Option<bool>,
+    pub output_csv: Option<String>,
+    pub csv_step: Option<usize>,
+    pub output_exr: Option<String>,
+    pub exr_channels: Option<Vec<ExrChannel>>,
+    pub output_hdr: Option<String>,
+    pub format: Option<Format>,
+    pub quality: Option<u8>,
+    pub tile_format: Option<Format>,
+    pub tile_size: Option<usize>,
+    pub tile_overlap: Option<usize>,
+    pub size: Option<(usize, usize)>,
+    pub width: Option<usize>,
+    pub height: Option<usize>,
+    pub upper_left: Option<(f64, f64)>,
+    pub lower_right: Option<(f64, f64)>,
+    pub center: Option<(f64, f64)>,
+    pub zoom: Option<f64>,
+    pub rotate: Option<f64>,
+    pub location: Option<String>,
+    pub bookmarks: Option<String>,
+    pub set: Option<SetKind>,
+    pub c: Option<(f64, f64)>,
+    pub mode: Option<Mode>,
+    pub polynomial: Option<Vec<(f64, f64)>>,
+    pub formula: Option<Formula>,
+    pub hybrid: Option<Vec<Formula>>,
+    pub formula_expr: Option<String>,
+    pub script: Option<String>,
+    pub power: Option<f64>,
+    pub phoenix_p: Option<(f64, f64)>,
+    pub relaxation: Option<(f64, f64)>,
+    pub slice: Option<(f64, f64)>,
+    pub morph_to: Option<Formula>,
+    pub morph: Option<f64>,
+    pub palette: Option<String>,
+    pub palette_file: Option<String>,
+    pub gradient: Option<String>,
+    pub gradient_space: Option<ColorSpace>,
+    pub transfer: Option<String>,
+    pub gamma: Option<String>,
+    pub icc_profile: Option<String>,
+    pub depth: Option<u8>,
+    pub coloring: Option<Exterior>,
+    pub stripe_density: Option<f64>,
+    pub distance_thickness: Option<f64>,
+    pub light_angle: Option<f64>,
+    pub interior: Option<Interior>,
+    pub trap: Option<String>,
+    pub trap_blend: Option<Blend>,
+    pub equalize: Option<bool>,
+    pub auto_expose: Option<(f64, f64)>,
+    pub legend: Option<Corner>,
+    pub dither: Option<bool>,
+    pub palette_period: Option<u32>,
+    pub palette_offset: Option<u32>,
+    pub z0: Option<(f64, f64)>,
+    pub samples: Option<u32>,
+    pub channel_limits: Option<(u32, u32, u32)>,
+    pub anti: Option<bool>,
+    pub iterations: Option<u32>,
+    pub escape_radius: Option<f64>,
+    pub threads: Option<usize>,
+    pub seed: Option<u64>,
+    pub force: Option<bool>
+}
+
+/// The iteration limit to use if none is given.
+pub const DEFAULT_ITERATIONS: u32 = 255;
+
+/// The iteration limit for the Collatz formula if none is given. Its orbits
+/// either escape quickly or not at all, so a long limit only costs time.
+pub const DEFAULT_COLLATZ_ITERATIONS: u32 = 100;
+
+/// The polynomial whose Newton fractal we draw if none is given: z^3 - 1.
+pub const DEFAULT_POLYNOMIAL: &'static [(f64, f64)] =
+    &[(1.0, 0.0), (0.0, 0.0), (0.0, 0.0), (-1.0, 0.0)];
+
+/// The power to iterate with if none is given: squaring, for the Mandelbrot set
+/// proper.
+pub const DEFAULT_POWER: f64 = 2.0;
+
+/// The value of `p` for the Phoenix formula if none is given.
+pub const DEFAULT_PHOENIX_P: (f64, f64) = (-0.5, 0.0);
+
+/// How many orbits per pixel a Buddhabrot render follows if not told otherwise.
+pub const DEFAULT_SAMPLES: u32 = 20;
+
+/// The relaxation for the Nova formula if none is given: plain Newton's method.
+pub const DEFAULT_RELAXATION: (f64, f64) = (1.0, 0.0);
+
+/// How far to morph toward `morph_to` if it is given, but `morph` isn't:
+/// halfway.
+pub const DEFAULT_MORPH: f64 = 0.5;
+
+/// The number of stripes to draw for stripe average coloring if none is
+/// given.
+pub const DEFAULT_STRIPE_DENSITY: f64 = 5.0;
+
+/// How many pixels wide to draw the set's boundary for distance estimation
+/// coloring if no thickness is given.
+pub const DEFAULT_DISTANCE_THICKNESS: f64 = 1.0;
+
+/// The slice of a quaternion Julia set to draw if none is given: the one
+/// through the complex plane.
+pub const DEFAULT_SLICE: (f64, f64) = (0.0, 0.0);
+
+/// The escape radius to use if none is given.
+pub const DEFAULT_ESCAPE_RADIUS: f64 = 2.0;
+
+/// The escape radius for the Collatz formula if none is given. Its orbits
+/// wander well beyond 2 before settling into cycles like 1, 4, 2, 1, but once
+/// they pass 100, they grow without bound.
+pub const DEFAULT_COLLATZ_ESCAPE_RADIUS: f64 = 100.0;
+
+impl Config {
+    /// Return the iteration limit these settings call for. If none is given,
+    /// the first formula decides.
+    pub fn iterations(&self) -> u32 {
+        self.iterations.unwrap_or_else(|| self.first_formula().default_iterations())
+    }
+
+    /// Return the power these settings call for.
+    pub fn power(&self) -> f64 {
+        self.power.unwrap_or(DEFAULT_POWER)
+    }
+
+    /// Return the number of Buddhabrot samples, or inverse iteration points,
+    /// per pixel these settings call for.
+    pub fn samples(&self) -> u32 {
+        self.samples.unwrap_or(DEFAULT_SAMPLES)
+    }
+
+    /// Return the formulas these settings call for: those of the hybrid, if
+    /// one is given, or else just the one formula.
+    pub fn formulas(&self) -> Vec<Formula> {
+        match self.hybrid {
+            Some(ref hybrid) => hybrid.clone(),
+            None => vec![self.formula.unwrap_or(Formula::Standard)]
+        }
+    }
+
+    /// Return the formula to blend each step with, and how far toward it to
+    /// go, if these settings call for a morph.
+    pub fn morph(&self) -> Option<(Formula, f64)> {
+        self.morph_to.map(|formula| (formula, self.morph.unwrap_or(DEFAULT_MORPH)))
+    }
+
+    /// Return the first formula these settings call for, which decides the
+    /// defaults for those that have their own.
+    fn first_formula(&self) -> Formula {
+        self.formulas().first().cloned().unwrap_or(Formula::Standard)
+    }
+
+    /// Return the escape radius these settings call for. If none is given, the
+    /// first formula decides.
+    pub fn escape_radius(&self) -> f64 {
+        self.escape_radius.unwrap_or_else(|| self.first_formula().default_escape_radius())
+    }
+
+    /// Return the number of threads these settings call for. If none is given,
+    /// use one per core the system makes available to us.
+    pub fn threads(&self) -> usize {
+        self.threads.unwrap_or_else(|| {
+            thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+        })
+    }
+
+    /// Return the path to which we should write the image, taking `output_dir`
+    /// into account. An output of `-`, meaning standard output, is left alone.
+    pub fn output_path(&self) -> Option<String> {
+        self.output.as_ref().map(|output| self.in_output_dir(output))
+    }
+
+    /// Return the path to which we should write the normal map, if any,
+    /// taking `output_dir` into account as `output_path` does.
+    pub fn normals_path(&self) -> Option<String> {
+        self.output_normals.as_ref().map(|output| self.in_output_dir(output))
+    }
+
+    /// Return the path to which we should write the image's samples, if
+    /// anywhere, taking `output_dir` into account as `output_path` does.
+    pub fn data_path(&self) -> Option<String> {
+        self.output_data.as_ref().map(|output| self.in_output_dir(output))
+    }
+
+    /// Return the path to which we should write the image's iteration counts,
+    /// if anywhere, taking `output_dir` into account as `output_path` does.
+    pub fn raw_path(&self) -> Option<String> {
+        self.output_raw.as_ref().map(|output| self.in_output_dir(output))
+    }
+
+    /// Return the path to which we should write the NumPy array, if any,
+    /// taking `output_dir` into account as `output_path` does.
+    pub fn npy_path(&self) -> Option<String> {
+        self.output_npy.as_ref().map(|output| self.in_output_dir(output))
+    }
+
+    /// Return the path to which we should write the CSV file of iteration
+    /// counts, if any, taking `output_dir` into account as `output_path` does.
+    pub fn csv_path(&self) -> Option<String> {
+        self.output_csv.as_ref().map(|output| self.in_output_dir(output))
+    }
+
+    /// Return the path to which we should write the OpenEXR image, if any,
+    /// taking `output_dir` into account as `output_path` does.
+    pub fn exr_path(&self) -> Option<String> {
+        self.output_exr.as_ref().map(|output| self.in_output_dir(output))
+    }
+
+    /// Return the path to which we should write the Radiance HDR image, if
+    /// any, taking `output_dir` into account as `output_path` does.
+    pub fn hdr_path(&self) -> Option<String> {
+        self.output_hdr.as_ref().map(|output| self.in_output_dir(output))
+    }
+
+    /// Return `output` taken relative to `output_dir`.
+    fn in_output_dir(&self, output: &str) -> String {
+        match self.output_dir {
+            Some(ref dir) if output != "-" => {
+                Path::new(dir).join(output).to_string_lossy().into_owned()
+            }
+            _ => output.to_string()
+        }
+    }
+
+    /// Return true if this `Config` says anything at all about which region of
+    /// the complex plane to show.
+    pub fn has_region(&self) -> bool {
+        self.upper_left.is_some() || self.lower_right.is_some() ||
+            self.center.is_some() || self.zoom.is_some() ||
+            self.location.is_some()
+    }
+
+    /// Return true if this `Config` says anything about the image's size.
+    pub fn has_size(&self) -> bool {
+        self.size.is_some() || self.width.is_some() || self.height.is_some()
+    }
+
+    /// Return a `Config` whose settings are taken from `self` where present,
+    /// and from `under` otherwise.
+    ///
+    /// The region settings are treated as a unit: if `self` specifies a region
+    /// in any way, `under`'s region settings are ignored entirely. Otherwise,
+    /// giving `--center` on the command line would leave a file's corners in
+    /// place to conflict with it. The size settings are a unit in the same way,
+    /// as are `formula`, `hybrid`, `formula_expr`, and `script`, and `palette`,
+    /// `palette_file`, and `gradient`.
+    pub fn merge(self, under: Config) -> Config {
+        let region = if self.has_region() { self.clone() } else { under.clone() };
+        let size = if self.has_size() { self.clone() } else { under.clone() };
+        let formula = if self.formula.is_some() || self.hybrid.is_some() ||
+            self.formula_expr.is_some() || self.script.is_some()
+        {
+            self.clone()
+        } else {
+            under.clone()
+        };
+        let palette = if self.palette.is_some() || self.palette_file.is_some() ||
+            self.gradient.is_some()
+        {
+            self.clone()
+        } else {
+            under.clone()
+        };
+        Config {
+            output: self.output.or(under.output),
+            output_dir: self.output_dir.or(under.output_dir),
+            output_normals: self.output_normals.or(under.output_normals),
+            output_data: self.output_data.or(under.output_data),
+            output_raw: self.output_raw.or(under.output_raw),
+            output_npy: self.output_npy.or(under.output_npy),
+            npy_smooth: self.npy_smooth.or(under.npy_smooth),
+            output_csv: self.output_csv.or(under.output_csv),
+            csv_step: self.csv_step.or(under.csv_step),
+            output_exr: self.output_exr.or(under.output_exr),
+            exr_channels: self.exr_channels.or(under.exr_channels),
+            output_hdr: self.output_hdr.or(under.output_hdr),
+            format: self.format.or(under.format),
+            quality: self.quality.or(under.quality),
+            tile_format: self.tile_format.or(under.tile_format),
+            tile_size: self.tile_size.or(under.tile_size),
+            tile_overlap: self.tile_overlap.or(under.tile_overlap),
+            size: size.size,
+            width: size.width,
+            height: size.height,
+            upper_left: region.upper_left,
+            lower_right: region.lower_right,
+            center: region.center,
+            zoom: region.zoom,
+            rotate: self.rotate.or(under.rotate),
+            location: region.location,
+            bookmarks: self.bookmarks.or(under.bookmarks),
+            set: self.set.or(under.set),
+            c: self.c.or(under.c),
+            mode: self.mode.or(under.mode),
+            polynomial: self.polynomial.or(under.polynomial),
+            formula: formula.formula,
+            hybrid: formula.hybrid,
+            formula_expr: formula.formula_expr,
+            script: formula.script,
+            power: self.power.or(under.power),
+            phoenix_p: self.phoenix_p.or(under.phoenix_p),
+            relaxation: self.relaxation.or(under.relaxation),
+            slice: self.slice.or(under.slice),
+            morph_to: self.morph_to.or(under.morph_to),
+            morph: self.morph.or(under.morph),
+            palette: palette.palette,
+            palette_file: palette.palette_file,
+            gradient: palette.gradient,
+            gradient_space: self.gradient_space.or(under.gradient_space),
+            transfer: self.transfer.or(under.transfer),
+            gamma: self.gamma.or(under.gamma),
+            icc_profile: self.icc_profile.or(under.icc_profile),
+            depth: self.depth.or(under.depth),
+            coloring: self.coloring.or(under.coloring),
+            stripe_density: self.stripe_density.or(under.stripe_density),
+            distance_thickness: self.distance_thickness.or(under.distance_thickness),
+            light_angle: self.light_angle.or(under.light_angle),
+            interior: self.interior.or(under.interior),
+            trap: self.trap.or(under.trap),
+            trap_blend: self.trap_blend.or(under.trap_blend),
+            equalize: self.equalize.or(under.equalize),
+            auto_expose: self.auto_expose.or(under.auto_expose),
+            legend: self.legend.or(under.legend),
+            dither: self.dither.or(under.dither),
+            palette_period: self.palette_period.or(under.palette_period),
+            palette_offset: self.palette_offset.or(under.palette_offset),
+            z0: self.z0.or(under.z0),
+            samples: self.samples.or(under.samples),
+            channel_limits: self.channel_limits.or(under.channel_limits),
+            anti: self.anti.or(under.anti),
+            iterations: self.iterations.or(under.iterations),
+            escape_radius: self.escape_radius.or(under.escape_radius),
+            threads: self.threads.or(under.threads),
+            seed: self.seed.or(under.seed),
+            force: self.force.or(under.force)
+        }
+    }
+}
+
+/// Parse `text` as the contents of a configuration file.
+pub fn parse(text: &str) -> Result<Config, toml::de::Error> {
+    toml::from_str(text)
+}
+
+/// Read the configuration file at `path`.
+pub fn load(path: &Path) -> io::Result<Config> {
+    let mut text = String::new();
+    try!(try!(File::open(path)).read_to_string(&mut text));
+    parse(&text).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+}
+
+/// Read settings from `reader`, as a JSON object.
+pub fn read_json<R: Read>(reader: R) -> io::Result<Config> {
+    serde_json::from_reader(reader).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+}
+
+#[test]
+fn test_parse() {
+    assert_eq!(parse("").unwrap(), Config::default());
+    assert_eq!(parse("output = \"julia.png\"\n\
+                      size = [1000, 750]\n\
+                      center = [-0.5, 0.0]\n\
+                      zoom = 2.0\n\
+                      set = \"julia\"\n\
+                      c = [-0.8, 0.156]\n\
+                      iterations = 1000\n").unwrap(),
+               Config {
+                   output: Some("julia.png".to_string()),
+                   size: Some((1000, 750)),
+                   center: Some((-0.5, 0.0)),
+                   zoom: Some(2.0),
+                   set: Some(SetKind::Julia),
+                   c: Some((-0.8, 0.156)),
+                   iterations: Some(1000),
+                   .. Config::default()
+               });
+    assert!(parse("iterations = \"lots\"\n").is_err());
+    assert!(parse("colour = \"blue\"\n").is_err());
+}
+
+#[test]
+fn test_merge() {
+    let file = Config {
+        output: Some("file.png".to_string()),
+        size: Some((100, 100)),
+        upper_left: Some((-2.0, 1.0)),
+        lower_right: Some((1.0, -1.0)),
+        iterations: Some(500),
+        .. Config::default()
+    };
+    let flags = Config {
+        size: Some((200, 200)),
+        center: Some((0.0, 0.0)),
+        .. Config::default()
+    };
+    assert_eq!(flags.clone().merge(file.clone()),
+               Config {
+                   output: Some("file.png".to_string()),
+                   size: Some((200, 200)),
+                   center: Some((0.0, 0.0)),
+                   iterations: Some(500),
+                   .. Config::default()
+               });
+    assert_eq!(Config::default().merge(file.clone()), file);
+
+    let file = Config { palette_file: Some("classic.map".to_string()), .. Config::default() };
+    let flags = Config { palette: Some("fire".to_string()), .. Config::default() };
+    assert_eq!(flags.clone().merge(file.clone()), flags);
+    let flags = Config { gradient: Some("0:#000000,1:#ffffff".to_string()),
+                         .. Config::default() };
+    assert_eq!(flags.clone().merge(file), flags);
+
+    // The gradient's color space isn't part of the palette unit, so it can be
+    // changed without repeating the gradient.
+    let file = Config { gradient: Some("0:#000000,1:#ffffff".to_string()),
+                        .. Config::default() };
+    let flags = Config { gradient_space: Some(ColorSpace::Lab), .. Config::default() };
+    assert_eq!(flags.merge(file.clone()),
+               Config { gradient_space: Some(ColorSpace::Lab), .. file });
+}
+
+#[test]
+fn test_formula_defaults() {
+    let config = Config::default();
+    assert_eq!((config.iterations(), config.escape_radius()),
+               (DEFAULT_ITERATIONS, DEFAULT_ESCAPE_RADIUS));
+    let collatz = Config { formula: Some(Formula::Collatz), .. Config::default() };
+    assert_eq!((collatz.iterations(), collatz.escape_radius()),
+               (DEFAULT_COLLATZ_ITERATIONS, DEFAULT_COLLATZ_ESCAPE_RADIUS));
+    let given = Config { iterations: Some(50), escape_radius: Some(1e3), .. collatz };
+    assert_eq!((given.iterations(), given.escape_radius()), (50, 1e3));
+}
+
+#[test]
+fn test_output_path() {
+    let mut config = Config { output: Some("a.png".to_string()), .. Config::default() };
+    assert_eq!(config.output_path(), Some("a.png".to_string()));
+    config.output_dir = Some("out".to_string());
+    assert_eq!(config.output_path(), Some(Path::new("out").join("a.png")
+                                          .to_string_lossy().into_owned()));
+    config.output = Some("/tmp/a.png".to_string());
+    assert_eq!(config.output_path(), Some("/tmp/a.png".to_string()));
+    config.output = Some("-".to_string());
+    assert_eq!(config.output_path(), Some("-".to_string()));
+    config.output = None;
+    assert_eq!(config.output_path(), None);
+    assert_eq!(config.normals_path(), None);
+    config.output_normals = Some("n.png".to_string());
+    assert_eq!(config.normals_path(), Some(Path::new("out").join("n.png")
+                                           .to_string_lossy().into_owned()));
+    assert_eq!(config.data_path(), None);
+    config.output_data = Some("/tmp/d.smp".to_string());
+    assert_eq!(config.data_path(), Some("/tmp/d.smp".to_string()));
+    assert_eq!(config.exr_path(), None);
+    config.output_exr = Some("f.exr".to_string());
+    assert_eq!(config.exr_path(), Some(Path::new("out").join("f.exr")
+                                       .to_string_lossy().into_owned()));
+}
+
+#[test]
+fn test_read_json() {
+    let json = "{ \"size\": [640, 480], \"center\": [-0.5, 0.0], \"iterations\": 300 }";
+    assert_eq!(read_json(json.as_bytes()).unwrap(),
+               Config {
+                   size: Some((640, 480)),
+                   center: Some((-0.5, 0.0)),
+                   iterations: Some(300),
+                   .. Config::default()
+               });
+    assert!(read_json("{ \"size\": \"big\" }".as_bytes()).is_err());
+}
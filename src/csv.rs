@@ -0,0 +1,52 @@
+//! Writing iteration counts as CSV, for spreadsheets and statistics packages.
+//!
+//! Each row gives a pixel's column and row in the image, the real and
+//! imaginary parts of the point it stands for, and the number of iterations
+//! that point's orbit took to escape, under a header row naming the columns.
+//! A large image makes for an enormous file, so the rows are streamed out as
+//! they're formatted, rather than gathered into one string first.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Write a CSV row to `output` for each of `counts`, the counts for the
+/// pixels of a grid whose dimensions are given by `bounds`, row by row from
+/// the top. `pixel` maps each position in the grid to the column and row of
+/// the image pixel it stands for, and `point` maps those to the point on the
+/// complex plane.
+pub fn write<W, P, Q>(output: W, bounds: (usize, usize), counts: &[u32], pixel: P, point: Q)
+    -> io::Result<()>
+    where W: Write, P: Fn(usize, usize) -> (usize, usize), Q: Fn((usize, usize)) -> (f64, f64)
+{
+    let mut output = BufWriter::new(output);
+    try!(writeln!(output, "x,y,re,im,iterations"));
+    for (i, &count) in counts.iter().enumerate() {
+        let (x, y) = pixel(i % bounds.0, i / bounds.0);
+        let (re, im) = point((x, y));
+        try!(writeln!(output, "{},{},{},{},{}", x, y, re, im, count));
+    }
+    output.flush()
+}
+
+/// Write a CSV file to `path`, as `write` does.
+pub fn save<P, Q>(path: &Path, bounds: (usize, usize), counts: &[u32], pixel: P, point: Q)
+    -> io::Result<()>
+    where P: Fn(usize, usize) -> (usize, usize), Q: Fn((usize, usize)) -> (f64, f64)
+{
+    write(try!(File::create(path)), bounds, counts, pixel, point)
+}
+
+#[test]
+fn test_write() {
+    let mut csv = Vec::new();
+    write(&mut csv, (2, 2), &[1, 2, 3, 1000],
+          |column, row| (column * 10, row * 10),
+          |(x, y)| (x as f64 * 0.5 - 1.0, 1.0 - y as f64 * 0.25)).unwrap();
+    assert_eq!(String::from_utf8(csv).unwrap(),
+               "x,y,re,im,iterations\n\
+                0,0,-1,1,1\n\
+                10,0,4,1,2\n\
+                0,10,-1,-1.5,3\n\
+                10,10,4,-1.5,1000\n");
+}
@@ -0,0 +1,216 @@
+//! Laying out Deep Zoom images.
+//!
+//! A Deep Zoom image, which viewers like OpenSeadragon can pan and zoom
+//! smoothly however large it is, is a pyramid of levels, each half the width
+//! and height of the one above, rounding up, down to a single pixel. Level
+//! zero is that single pixel, and the highest level is the full image. Each
+//! level is cut into square tiles, numbered by column and row from the upper
+//! left; tiles on the right and bottom edges may be smaller. Each tile also
+//! includes a few pixels of its neighbors on every side it has one, so that
+//! viewers can blend the seams.
+//!
+//! For an image `NAME.dzi`, the tiles go in a directory `NAME_files`, with a
+//! subdirectory for each level holding files named `COLUMN_ROW.png` or
+//! `COLUMN_ROW.jpg`. `NAME.dzi` itself is a short XML file giving the image's
+//! size and how it is tiled.
+
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// The usual width and height of a tile: with one pixel of overlap on each
+/// side, a tile in the interior is 256 pixels square.
+pub const DEFAULT_TILE_SIZE: usize = 254;
+
+/// The usual number of pixels each tile shares with its neighbors.
+pub const DEFAULT_OVERLAP: usize = 1;
+
+/// How a Deep Zoom image is cut into tiles.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pyramid {
+    /// The width and height of each tile, not counting the overlap.
+    pub tile_size: usize,
+
+    /// The number of pixels each tile shares with its neighbor on each side.
+    pub overlap: usize
+}
+
+/// A tile of some level of a pyramid.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tile {
+    pub column: usize,
+    pub row: usize,
+
+    /// The columns and rows of the level's pixels the tile covers, including
+    /// the overlap.
+    pub x: Range<usize>,
+    pub y: Range<usize>
+}
+
+impl Pyramid {
+    /// Return the number of the highest level of a pyramid for an image whose
+    /// dimensions are given by `bounds`: the level at full size.
+    pub fn top_level(&self, bounds: (usize, usize)) -> u32 {
+        let mut level = 0;
+        while (1 << level) < bounds.0.max(bounds.1) {
+            level += 1;
+        }
+        level
+    }
+
+    /// Return the tiles of a level whose dimensions are given by `bounds`.
+    pub fn tiles(&self, bounds: (usize, usize)) -> Vec<Tile> {
+        let covered = |index: usize, length: usize| -> Range<usize> {
+            let start = index * self.tile_size;
+            let end = (start + self.tile_size + self.overlap).min(length);
+            start.saturating_sub(self.overlap)..end
+        };
+        let columns = (bounds.0 + self.tile_size - 1) / self.tile_size;
+        let rows = (bounds.1 + self.tile_size - 1) / self.tile_size;
+        let mut tiles = Vec::with_capacity(columns * rows);
+        for row in 0..rows {
+            for column in 0..columns {
+                tiles.push(Tile {
+                    column: column,
+                    row: row,
+                    x: covered(column, bounds.0),
+                    y: covered(row, bounds.1)
+                });
+            }
+        }
+        tiles
+    }
+
+    /// Return the XML descriptor of a pyramid for an image whose dimensions
+    /// are given by `bounds`, with tiles in files with the extension
+    /// `extension`.
+    pub fn descriptor(&self, bounds: (usize, usize), extension: &str) -> String {
+        format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                 <Image xmlns=\"http://schemas.microsoft.com/deepzoom/2008\" \
+                 Format=\"{}\" Overlap=\"{}\" TileSize=\"{}\">\n  \
+                 <Size Width=\"{}\" Height=\"{}\"/>\n\
+                 </Image>\n",
+                extension, self.overlap, self.tile_size, bounds.0, bounds.1)
+    }
+}
+
+/// Return the directory holding the tiles of the Deep Zoom image whose
+/// descriptor is at `path`.
+pub fn tile_directory(path: &Path) -> PathBuf {
+    let stem = path.file_stem().map_or(String::new(), |stem| stem.to_string_lossy().into_owned());
+    path.with_file_name(format!("{}_files", stem))
+}
+
+/// Return the pixels of `pixels`, an image whose dimensions are given by
+/// `bounds` with `pixel_bytes` bytes per pixel, that lie in the columns `x`
+/// and rows `y`.
+pub fn crop(pixels: &[u8], bounds: (usize, usize), pixel_bytes: usize, x: Range<usize>,
+            y: Range<usize>)
+    -> Vec<u8>
+{
+    let line = bounds.0 * pixel_bytes;
+    let mut cropped = Vec::with_capacity(x.len() * y.len() * pixel_bytes);
+    for row in y {
+        let start = row * line;
+        cropped.extend_from_slice(&pixels[start + x.start * pixel_bytes..
+                                          start + x.end * pixel_bytes]);
+    }
+    cropped
+}
+
+/// Return `pixels`, an image whose dimensions are given by `bounds`, shrunk to
+/// half its width and height, rounding up, and those dimensions. Each pixel
+/// has `channels` samples of `depth` bits, big-endian, and each pixel of the
+/// result averages the two-by-two block of pixels it covers, or as much of
+/// the block as there is along the right and bottom edges.
+pub fn halve(pixels: &[u8], bounds: (usize, usize), channels: usize, depth: u8)
+    -> (Vec<u8>, (usize, usize))
+{
+    let size = depth as usize / 8;
+    let sample = |x: usize, y: usize, channel: usize| -> u32 {
+        let at = ((y * bounds.0 + x) * channels + channel) * size;
+        if size == 2 {
+            (pixels[at] as u32) << 8 | pixels[at + 1] as u32
+        } else {
+            pixels[at] as u32
+        }
+    };
+    let halved = ((bounds.0 + 1) / 2, (bounds.1 + 1) / 2);
+    let mut result = Vec::with_capacity(halved.0 * halved.1 * channels * size);
+    for y in 0..halved.1 {
+        let rows = (y * 2..(y * 2 + 2).min(bounds.1)).collect::<Vec<_>>();
+        for x in 0..halved.0 {
+            let columns = x * 2..(x * 2 + 2).min(bounds.0);
+            let count = (rows.len() * columns.len()) as u32;
+            for channel in 0..channels {
+                let mut sum = 0;
+                for &row in &rows {
+                    for column in columns.clone() {
+                        sum += sample(column, row, channel);
+                    }
+                }
+                let average = (sum + count / 2) / count;
+                if size == 2 {
+                    result.push((average >> 8) as u8);
+                }
+                result.push(average as u8);
+            }
+        }
+    }
+    (result, halved)
+}
+
+#[test]
+fn test_top_level() {
+    let pyramid = Pyramid { tile_size: 254, overlap: 1 };
+    assert_eq!(pyramid.top_level((1, 1)), 0);
+    assert_eq!(pyramid.top_level((2, 1)), 1);
+    assert_eq!(pyramid.top_level((1000, 750)), 10);
+    assert_eq!(pyramid.top_level((1024, 1024)), 10);
+    assert_eq!(pyramid.top_level((600, 1025)), 11);
+}
+
+#[test]
+fn test_tiles() {
+    let pyramid = Pyramid { tile_size: 4, overlap: 1 };
+    let tiles = pyramid.tiles((10, 3));
+    assert_eq!(tiles, vec![
+        Tile { column: 0, row: 0, x: 0..5, y: 0..3 },
+        Tile { column: 1, row: 0, x: 3..9, y: 0..3 },
+        Tile { column: 2, row: 0, x: 7..10, y: 0..3 }
+    ]);
+    assert_eq!(pyramid.tiles((1, 1)), vec![Tile { column: 0, row: 0, x: 0..1, y: 0..1 }]);
+    assert_eq!(Pyramid { tile_size: 4, overlap: 0 }.tiles((8, 5)).len(), 4);
+}
+
+#[test]
+fn test_descriptor() {
+    let pyramid = Pyramid { tile_size: 254, overlap: 1 };
+    assert_eq!(pyramid.descriptor((1000, 750), "jpg"),
+               "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                <Image xmlns=\"http://schemas.microsoft.com/deepzoom/2008\" \
+                Format=\"jpg\" Overlap=\"1\" TileSize=\"254\">\n  \
+                <Size Width=\"1000\" Height=\"750\"/>\n\
+                </Image>\n");
+    assert_eq!(tile_directory(Path::new("out/world.dzi")), Path::new("out/world_files"));
+}
+
+#[test]
+fn test_crop() {
+    let pixels: Vec<u8> = (0..12).collect();
+    assert_eq!(crop(&pixels, (3, 2), 2, 1..3, 0..2), vec![2, 3, 4, 5, 8, 9, 10, 11]);
+    assert_eq!(crop(&pixels, (3, 2), 2, 0..1, 1..2), vec![6, 7]);
+}
+
+#[test]
+fn test_halve() {
+    // Three by three gray: the right column and bottom row average alone.
+    let (halved, bounds) = halve(&[0, 2, 9, 4, 6, 9, 1, 1, 1], (3, 3), 1, 8);
+    assert_eq!(bounds, (2, 2));
+    assert_eq!(halved, vec![3, 9, 1, 1]);
+
+    // One sixteen-bit gray and alpha pixel pair.
+    let (halved, bounds) = halve(&[0x01, 0x00, 0xff, 0xff, 0x02, 0x00, 0x00, 0x01], (2, 1),
+                                 2, 16);
+    assert_eq!(bounds, (1, 1));
+    assert_eq!(halved, vec![0x01, 0x80, 0x80, 0x00]);
+}
@@ -0,0 +1,82 @@
+//! Errors that end the program, and the exit statuses they call for.
+
+use std::fmt;
+use std::io;
+
+/// The broad kinds of failure. Each exits with its own status, following the
+/// BSD `sysexits.h` conventions, so that scripts can tell them apart.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    /// The command was used incorrectly: settings are missing, or conflict.
+    Usage,
+
+    /// A value on the command line or in a file couldn't be understood.
+    Parse,
+
+    /// Reading or writing a file failed.
+    Io
+}
+
+impl Kind {
+    /// Return the status with which the program should exit.
+    pub fn exit_status(&self) -> i32 {
+        match *self {
+            Kind::Usage => 64,
+            Kind::Parse => 65,
+            Kind::Io => 74
+        }
+    }
+}
+
+/// An error message, along with the kind of failure it reports.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Error {
+    pub kind: Kind,
+    pub message: String
+}
+
+impl Error {
+    /// Return a usage error with the given message.
+    pub fn usage<S: Into<String>>(message: S) -> Error {
+        Error { kind: Kind::Usage, message: message.into() }
+    }
+
+    /// Return a parse error with the given message.
+    pub fn parse<S: Into<String>>(message: S) -> Error {
+        Error { kind: Kind::Parse, message: message.into() }
+    }
+
+    /// Return an I/O error with the given message.
+    pub fn io<S: Into<String>>(message: S) -> Error {
+        Error { kind: Kind::Io, message: message.into() }
+    }
+
+    /// Return an error reporting `error`, which occurred in the course of
+    /// `context`, like "error reading configuration file 'deep.toml'". Our
+    /// modules report data they can't parse as `InvalidData` errors, so those
+    /// are parse errors; anything else is an I/O error.
+    pub fn from_io(context: &str, error: io::Error) -> Error {
+        Error {
+            kind: match error.kind() {
+                io::ErrorKind::InvalidData => Kind::Parse,
+                _ => Kind::Io
+            },
+            message: format!("{}: {}", context, error)
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+#[test]
+fn test_from_io() {
+    let bad_data = io::Error::new(io::ErrorKind::InvalidData, "expected a number");
+    assert_eq!(Error::from_io("error reading 'a.toml'", bad_data),
+               Error::parse("error reading 'a.toml': expected a number"));
+    let missing = io::Error::new(io::ErrorKind::NotFound, "no such file");
+    assert_eq!(Error::from_io("error reading 'a.toml'", missing).kind, Kind::Io);
+}
@@ -0,0 +1,378 @@
+//! Iteration formulas written as expressions, like `z^3 + sin(z) + c`.
+//!
+//! `--formula-expr` takes an expression in `z`, the orbit's current point, and
+//! `c`, the constant; each step of the iteration replaces `z` with its value.
+//! We parse the expression once, into a tree, and evaluate the tree at each
+//! step.
+//!
+//! Expressions may use:
+//!
+//! - numbers like `2`, `0.5`, and `1e-3`, and imaginary numbers like `0.5i`;
+//! - the variables `z` and `c`, and the constants `i`, `pi`, and `e`;
+//! - the operators `+`, `-`, `*`, `/`, and `^`, with the usual precedence, and
+//!   parentheses;
+//! - the functions `sin`, `cos`, `tan`, `sinh`, `cosh`, `tanh`, `exp`, `log`,
+//!   `sqrt`, and `conj`.
+//!
+//! Raising to a whole number power multiplies; other powers go by way of
+//! logarithms, and so have a branch cut along the negative real axis.
+
+use num::Complex;
+use std::f64::consts;
+
+/// A function that an expression can call.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Function {
+    Sin, Cos, Tan, Sinh, Cosh, Tanh, Exp, Log, Sqrt, Conj
+}
+
+/// The functions expressions can call, by name.
+const FUNCTIONS: &'static [(&'static str, Function)] = &[
+    ("sin", Function::Sin),
+    ("cos", Function::Cos),
+    ("tan", Function::Tan),
+    ("sinh", Function::Sinh),
+    ("cosh", Function::Cosh),
+    ("tanh", Function::Tanh),
+    ("exp", Function::Exp),
+    ("log", Function::Log),
+    ("sqrt", Function::Sqrt),
+    ("conj", Function::Conj),
+];
+
+/// A parsed expression.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Constant(Complex<f64>),
+    Z,
+    C,
+    Negate(Box<Expr>),
+    Add(Box<Expr>, Box<Expr>),
+    Subtract(Box<Expr>, Box<Expr>),
+    Multiply(Box<Expr>, Box<Expr>),
+    Divide(Box<Expr>, Box<Expr>),
+
+    /// Raising to a whole number power, which we can do by multiplying.
+    PowerInteger(Box<Expr>, i32),
+
+    /// Raising to any other power.
+    Power(Box<Expr>, Box<Expr>),
+
+    Call(Function, Box<Expr>)
+}
+
+impl Expr {
+    /// Return the value of this expression, for the given `z` and `c`.
+    pub fn evaluate(&self, z: Complex<f64>, c: Complex<f64>) -> Complex<f64> {
+        match *self {
+            Expr::Constant(value) => value,
+            Expr::Z => z,
+            Expr::C => c,
+            Expr::Negate(ref a) => -a.evaluate(z, c),
+            Expr::Add(ref a, ref b) => a.evaluate(z, c) + b.evaluate(z, c),
+            Expr::Subtract(ref a, ref b) => a.evaluate(z, c) - b.evaluate(z, c),
+            Expr::Multiply(ref a, ref b) => a.evaluate(z, c) * b.evaluate(z, c),
+            Expr::Divide(ref a, ref b) => a.evaluate(z, c) / b.evaluate(z, c),
+            Expr::PowerInteger(ref a, n) => {
+                let raised = power_integer(a.evaluate(z, c), n.abs() as u32);
+                if n < 0 { Complex { re: 1.0, im: 0.0 } / raised } else { raised }
+            }
+            Expr::Power(ref a, ref b) => {
+                let (base, exponent) = (a.evaluate(z, c), b.evaluate(z, c));
+                if base.norm_sqr() == 0.0 {
+                    return base;
+                }
+                (exponent * base.ln()).exp()
+            }
+            Expr::Call(function, ref a) => {
+                let a = a.evaluate(z, c);
+                match function {
+                    Function::Sin => a.sin(),
+                    Function::Cos => a.cos(),
+                    Function::Tan => a.tan(),
+                    Function::Sinh => a.sinh(),
+                    Function::Cosh => a.cosh(),
+                    Function::Tanh => a.tanh(),
+                    Function::Exp => a.exp(),
+                    Function::Log => a.ln(),
+                    Function::Sqrt => a.sqrt(),
+                    Function::Conj => a.conj()
+                }
+            }
+        }
+    }
+}
+
+/// Return `z` raised to the `n`'th power, by repeated squaring.
+fn power_integer(z: Complex<f64>, n: u32) -> Complex<f64> {
+    let mut result = Complex { re: 1.0, im: 0.0 };
+    let (mut square, mut n) = (z, n);
+    while n > 0 {
+        if n & 1 != 0 {
+            result = result * square;
+        }
+        square = square * square;
+        n >>= 1;
+    }
+    result
+}
+
+/// A token of an expression's text.
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    Imaginary(f64),
+    Name(String),
+    Symbol(char)
+}
+
+/// Split `text` into tokens, each paired with the text it came from, for
+/// error messages.
+fn tokenize(text: &str) -> Result<Vec<(Token, String)>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some(&(start, ch)) = chars.peek() {
+        let token = if ch.is_whitespace() {
+            chars.next();
+            continue;
+        } else if ch.is_digit(10) || ch == '.' {
+            let mut number = String::new();
+            while let Some(&(_, ch)) = chars.peek() {
+                // Take a sign only just after an exponent's 'e'.
+                let sign = (ch == '+' || ch == '-') && number.ends_with('e');
+                if !(ch.is_digit(10) || ch == '.' || ch == 'e' || sign) {
+                    break;
+                }
+                number.push(ch);
+                chars.next();
+            }
+            let value = try!(number.parse().map_err(|_| {
+                format!("malformed number '{}'", number)
+            }));
+            if chars.peek().map(|&(_, ch)| ch) == Some('i') {
+                chars.next();
+                Token::Imaginary(value)
+            } else {
+                Token::Number(value)
+            }
+        } else if ch.is_alphabetic() {
+            let mut name = String::new();
+            while let Some(&(_, ch)) = chars.peek() {
+                if !ch.is_alphanumeric() {
+                    break;
+                }
+                name.push(ch);
+                chars.next();
+            }
+            Token::Name(name)
+        } else if "+-*/^()".contains(ch) {
+            chars.next();
+            Token::Symbol(ch)
+        } else {
+            return Err(format!("unexpected character '{}'", ch));
+        };
+        let end = chars.peek().map_or(text.len(), |&(end, _)| end);
+        tokens.push((token, text[start..end].to_string()));
+    }
+    Ok(tokens)
+}
+
+/// How deeply parentheses, function calls, negations, and powers may nest.
+/// Parsing recurses at each level, so without a limit a long enough run of
+/// `(` would overflow the stack.
+const MAX_DEPTH: usize = 256;
+
+/// A recursive-descent parser over a list of tokens.
+struct Parser {
+    tokens: Vec<(Token, String)>,
+    position: usize,
+
+    /// How many factors we are currently parsing, one inside another.
+    depth: usize
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|&(ref token, _)| token)
+    }
+
+    /// If the next token is the symbol `symbol`, consume it and return true.
+    fn eat(&mut self, symbol: char) -> bool {
+        if self.peek() == Some(&Token::Symbol(symbol)) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Parse a sum or difference of terms.
+    fn sum(&mut self) -> Result<Expr, String> {
+        let mut left = try!(self.term());
+        loop {
+            if self.eat('+') {
+                left = Expr::Add(Box::new(left), Box::new(try!(self.term())));
+            } else if self.eat('-') {
+                left = Expr::Subtract(Box::new(left), Box::new(try!(self.term())));
+            } else {
+                return Ok(left);
+            }
+        }
+    }
+
+    /// Parse a product or quotient of factors.
+    fn term(&mut self) -> Result<Expr, String> {
+        let mut left = try!(self.factor());
+        loop {
+            if self.eat('*') {
+                left = Expr::Multiply(Box::new(left), Box::new(try!(self.factor())));
+            } else if self.eat('/') {
+                left = Expr::Divide(Box::new(left), Box::new(try!(self.factor())));
+            } else {
+                return Ok(left);
+            }
+        }
+    }
+
+    /// Parse a factor, unless we are already nested too deeply.
+    fn factor(&mut self) -> Result<Expr, String> {
+        if self.depth == MAX_DEPTH {
+            return Err("expression is nested too deeply".to_string());
+        }
+        self.depth += 1;
+        let factor = self.power();
+        self.depth -= 1;
+        factor
+    }
+
+    /// Parse a possibly negated power.
+    fn power(&mut self) -> Result<Expr, String> {
+        if self.eat('-') {
+            return Ok(match try!(self.factor()) {
+                Expr::Constant(value) => Expr::Constant(-value),
+                operand => Expr::Negate(Box::new(operand))
+            });
+        }
+        let base = try!(self.atom());
+        if !self.eat('^') {
+            return Ok(base);
+        }
+        // Exponentiation groups to the right, and binds more tightly than
+        // negation on its left, but not on its right: -z^-2 is -(z^(-2)).
+        Ok(match try!(self.factor()) {
+            Expr::Constant(Complex { re, im })
+                if im == 0.0 && re.fract() == 0.0 && re.abs() <= i32::max_value() as f64 =>
+            {
+                Expr::PowerInteger(Box::new(base), re as i32)
+            }
+            exponent => Expr::Power(Box::new(base), Box::new(exponent))
+        })
+    }
+
+    /// Parse a number, variable, function call, or parenthesized expression.
+    fn atom(&mut self) -> Result<Expr, String> {
+        let token = match self.peek() {
+            Some(token) => token.clone(),
+            None => return Err("expression ends too soon".to_string())
+        };
+        self.position += 1;
+        match token {
+            Token::Number(value) => Ok(Expr::Constant(Complex { re: value, im: 0.0 })),
+            Token::Imaginary(value) => Ok(Expr::Constant(Complex { re: 0.0, im: value })),
+            Token::Symbol('(') => {
+                let inner = try!(self.sum());
+                if !self.eat(')') {
+                    return Err("missing ')'".to_string());
+                }
+                Ok(inner)
+            }
+            Token::Symbol(symbol) => Err(format!("unexpected '{}'", symbol)),
+            Token::Name(name) => {
+                match &name[..] {
+                    "z" => return Ok(Expr::Z),
+                    "c" => return Ok(Expr::C),
+                    "i" => return Ok(Expr::Constant(Complex { re: 0.0, im: 1.0 })),
+                    "pi" => return Ok(Expr::Constant(Complex { re: consts::PI, im: 0.0 })),
+                    "e" => return Ok(Expr::Constant(Complex { re: consts::E, im: 0.0 })),
+                    _ => ()
+                }
+                let function = match FUNCTIONS.iter().find(|&&(n, _)| n == name) {
+                    Some(&(_, function)) => function,
+                    None => return Err(format!("unknown name '{}'", name))
+                };
+                if !self.eat('(') {
+                    return Err(format!("expected '(' after '{}'", name));
+                }
+                let argument = try!(self.sum());
+                if !self.eat(')') {
+                    return Err("missing ')'".to_string());
+                }
+                Ok(Expr::Call(function, Box::new(argument)))
+            }
+        }
+    }
+}
+
+/// Parse `text` as an expression.
+pub fn parse(text: &str) -> Result<Expr, String> {
+    let mut parser = Parser { tokens: try!(tokenize(text)), position: 0, depth: 0 };
+    let expr = try!(parser.sum());
+    match parser.tokens.get(parser.position) {
+        None => Ok(expr),
+        Some(&(_, ref source)) => {
+            Err(format!("unexpected '{}' after the end of the expression", source))
+        }
+    }
+}
+
+#[test]
+fn test_parse() {
+    let z = || Box::new(Expr::Z);
+    assert_eq!(parse("z^2 + c"),
+               Ok(Expr::Add(Box::new(Expr::PowerInteger(z(), 2)), Box::new(Expr::C))));
+    assert_eq!(parse("-z^-2"),
+               Ok(Expr::Negate(Box::new(Expr::PowerInteger(z(), -2)))));
+    assert_eq!(parse("z^0.5"),
+               Ok(Expr::Power(z(), Box::new(Expr::Constant(Complex { re: 0.5, im: 0.0 })))));
+    assert_eq!(parse("sin(z) * 1e-3i"),
+               Ok(Expr::Multiply(Box::new(Expr::Call(Function::Sin, z())),
+                                 Box::new(Expr::Constant(Complex { re: 0.0, im: 1e-3 })))));
+    assert!(parse("").is_err());
+    assert!(parse("z^").is_err());
+    assert!(parse("(z + c").is_err());
+    assert!(parse("z c").is_err());
+    assert!(parse("frob(z)").is_err());
+    assert!(parse("sin z").is_err());
+    assert!(parse("z % 2").is_err());
+    assert_eq!(parse("z c"), Err("unexpected 'c' after the end of the expression".to_string()));
+    assert_eq!(parse("z 1e3i"),
+               Err("unexpected '1e3i' after the end of the expression".to_string()));
+
+    // Deep nesting is refused, not allowed to overflow the stack.
+    let nested = |depth: usize, inner: &str| {
+        format!("{}{}{}", "(".repeat(depth), inner, ")".repeat(depth))
+    };
+    assert_eq!(parse(&nested(MAX_DEPTH - 1, "z")), Ok(Expr::Z));
+    assert!(parse(&nested(MAX_DEPTH, "z")).is_err());
+    assert_eq!(parse(&"(".repeat(100000)),
+               Err("expression is nested too deeply".to_string()));
+    assert!(parse(&format!("{}z", "-".repeat(100000))).is_err());
+    assert!(parse(&"z^".repeat(100000)).is_err());
+    assert!(parse(&format!("{}z{}", "sin(".repeat(100000), ")".repeat(100000))).is_err());
+}
+
+#[test]
+fn test_evaluate() {
+    let z = Complex { re: 1.0, im: 2.0 };
+    let c = Complex { re: -0.5, im: 0.25 };
+    let close = |text: &str, expected: Complex<f64>| {
+        (parse(text).unwrap().evaluate(z, c) - expected).norm_sqr() < 1e-20
+    };
+    assert!(close("z^2 + c", z * z + c));
+    assert!(close("z^3 - z/c", z * z * z - z / c));
+    assert!(close("z^-1", Complex { re: 1.0, im: 0.0 } / z));
+    assert!(close("z^2.5", (z.ln() * Complex { re: 2.5, im: 0.0 }).exp()));
+    assert!(close("exp(log(z))", z));
+    assert!(close("conj(z) + 2i*c", z.conj() + Complex { re: 0.0, im: 2.0 } * c));
+    assert!(close("(1 + 2) * (3 - 4)", Complex { re: -3.0, im: 0.0 }));
+}
@@ -0,0 +1,118 @@
+//! Writing OpenEXR files of 32-bit floating-point channels.
+//!
+//! The other formats we write hold colors; an OpenEXR file can hold the
+//! numbers rendering found before any palette touched them, at full
+//! precision, for tone-mapping and compositing programs to work with. We write
+//! the simplest kind of file those programs accept: a single part of
+//! uncompressed scanlines, each a block of its own.
+//!
+//! The file starts with a magic number and version, followed by a header of
+//! named, typed attributes, ended by an empty name. Then comes a table giving
+//! the offset of each scanline's block in the file, and then the blocks: each
+//! one's row number and size, followed by the row's samples for each channel
+//! in turn, in the order of the channels' names. All numbers are
+//! little-endian.
+
+use bytes::{le32, le64};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// The magic number every OpenEXR file starts with, followed by the version,
+/// 2, with no flags set.
+const MAGIC: &'static [u8; 8] = b"\x76\x2f\x31\x01\x02\0\0\0";
+
+/// The pixel type of 32-bit floating-point channels.
+const FLOAT: u32 = 2;
+
+/// Write an image whose dimensions are given by `bounds` to `output`. Each of
+/// `channels` is a name and that channel's samples, row by row from the top.
+pub fn write<W: Write>(output: W, bounds: (usize, usize), channels: &[(&str, &[f32])])
+    -> io::Result<()>
+{
+    let mut channels = channels.to_vec();
+    channels.sort_by(|a, b| a.0.cmp(b.0));
+
+    let mut header = MAGIC.to_vec();
+    let mut list = Vec::new();
+    for &(name, _) in &channels {
+        list.extend_from_slice(name.as_bytes());
+        list.push(0);
+        list.extend_from_slice(&le32(FLOAT));
+        list.extend_from_slice(&[0, 0, 0, 0]); // not perceptually linear, reserved
+        list.extend_from_slice(&le32(1)); // x sampling
+        list.extend_from_slice(&le32(1)); // y sampling
+    }
+    list.push(0);
+    let mut window = Vec::new();
+    for &n in &[0, 0, bounds.0 as u32 - 1, bounds.1 as u32 - 1] {
+        window.extend_from_slice(&le32(n));
+    }
+    attribute(&mut header, "channels", "chlist", &list);
+    attribute(&mut header, "compression", "compression", &[0]); // none
+    attribute(&mut header, "dataWindow", "box2i", &window);
+    attribute(&mut header, "displayWindow", "box2i", &window);
+    attribute(&mut header, "lineOrder", "lineOrder", &[0]); // increasing y
+    attribute(&mut header, "pixelAspectRatio", "float", &le32(1.0_f32.to_bits()));
+    attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    attribute(&mut header, "screenWindowWidth", "float", &le32(1.0_f32.to_bits()));
+    header.push(0);
+
+    let row = bounds.0 * channels.len() * 4;
+    let block = 8 + row as u64;
+    let first = (header.len() + bounds.1 * 8) as u64;
+    let mut output = BufWriter::new(output);
+    try!(output.write_all(&header));
+    for y in 0..bounds.1 as u64 {
+        try!(output.write_all(&le64(first + y * block)));
+    }
+    for y in 0..bounds.1 {
+        try!(output.write_all(&le32(y as u32)));
+        try!(output.write_all(&le32(row as u32)));
+        for &(_, samples) in &channels {
+            for &sample in &samples[y * bounds.0..(y + 1) * bounds.0] {
+                try!(output.write_all(&le32(sample.to_bits())));
+            }
+        }
+    }
+    output.flush()
+}
+
+/// Write an image to the file at `path`, as `write` does.
+pub fn save(path: &Path, bounds: (usize, usize), channels: &[(&str, &[f32])])
+    -> io::Result<()>
+{
+    write(try!(File::create(path)), bounds, channels)
+}
+
+/// Add an attribute named `name` of type `kind` to `header`, with `value`.
+fn attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&le32(value.len() as u32));
+    header.extend_from_slice(value);
+}
+
+#[test]
+fn test_write() {
+    let mut exr = Vec::new();
+    write(&mut exr, (2, 2), &[("b", &[1.0, 2.0, 3.0, 4.0]), ("a", &[5.0, 6.0, 7.0, 8.0])])
+        .unwrap();
+    assert_eq!(&exr[..8], MAGIC);
+    assert_eq!(&exr[8..35], &b"channels\0chlist\0\x25\0\0\0a\0\x02\0\0\0\0"[..]);
+
+    // Two rows, each eight bytes of row number and size, and then two floats
+    // of each channel, sorted by name.
+    let blocks = exr.len() - 2 * 24;
+    assert_eq!(&exr[blocks - 16..blocks - 8], &le64(blocks as u64));
+    assert_eq!(&exr[blocks - 8..blocks], &le64(blocks as u64 + 24));
+    let floats = |values: &[f32]| -> Vec<u8> {
+        values.iter().flat_map(|value| le32(value.to_bits()).to_vec()).collect()
+    };
+    assert_eq!(&exr[blocks..blocks + 8], b"\0\0\0\0\x10\0\0\0");
+    assert_eq!(exr[blocks + 8..blocks + 24], floats(&[5.0, 6.0, 1.0, 2.0])[..]);
+    assert_eq!(&exr[blocks + 24..blocks + 32], b"\x01\0\0\0\x10\0\0\0");
+    assert_eq!(exr[blocks + 32..], floats(&[7.0, 8.0, 3.0, 4.0])[..]);
+}
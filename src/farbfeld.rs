@@ -0,0 +1,61 @@
+//! Writing farbfeld files.
+//!
+//! farbfeld is the suckless project's image format: the magic string
+//! "farbfeld", the width and height as 32-bit big-endian numbers, and then the
+//! pixels, each sixteen-bit red, green, blue, and alpha samples, big-endian.
+//! Its tools read images from standard input and write them to standard
+//! output, so a picture can go straight from `mandelbrot` into a pipeline of
+//! filters and only be compressed at the end, if ever. Since every sample has
+//! sixteen bits, images drawn with `--depth 16` lose nothing on the way.
+
+use bytes::be32;
+use std::io::{self, BufWriter, Write};
+
+/// Write `pixels`, an image whose dimensions are given by `bounds`, to `output`
+/// as a farbfeld file. Each pixel has `channels` samples of `depth` bits: gray,
+/// gray and alpha, RGB, or RGBA. Eight-bit samples are widened, grays are
+/// repeated for red, green, and blue, and pixels without alpha are opaque.
+pub fn write<W: Write>(output: W, pixels: &[u8], bounds: (usize, usize), channels: usize,
+                       depth: u8)
+    -> io::Result<()>
+{
+    let mut output = BufWriter::new(output);
+    try!(output.write_all(b"farbfeld"));
+    try!(output.write_all(&be32(bounds.0 as u32)));
+    try!(output.write_all(&be32(bounds.1 as u32)));
+
+    let size = depth as usize / 8;
+    let sample = |bytes: &[u8]| -> [u8; 2] {
+        if size == 2 { [bytes[0], bytes[1]] } else { [bytes[0], bytes[0]] }
+    };
+    for pixel in pixels.chunks(channels * size) {
+        let samples: Vec<[u8; 2]> = pixel.chunks(size).map(&sample).collect();
+        let (color, alpha) = if channels % 2 == 0 {
+            (&samples[..channels - 1], samples[channels - 1])
+        } else {
+            (&samples[..], [0xff, 0xff])
+        };
+        for i in 0..3 {
+            try!(output.write_all(&color[if color.len() == 1 { 0 } else { i }]));
+        }
+        try!(output.write_all(&alpha));
+    }
+    output.flush()
+}
+
+#[test]
+fn test_write() {
+    let mut gray = Vec::new();
+    write(&mut gray, &[0x80, 0xff], (2, 1), 1, 8).unwrap();
+    assert_eq!(gray, b"farbfeld\0\0\0\x02\0\0\0\x01\
+                       \x80\x80\x80\x80\x80\x80\xff\xff\
+                       \xff\xff\xff\xff\xff\xff\xff\xff".to_vec());
+
+    let mut rgba = Vec::new();
+    write(&mut rgba, &[1, 2, 3, 4, 5, 6, 7, 8], (1, 1), 4, 16).unwrap();
+    assert_eq!(&rgba[16..], &[1, 2, 3, 4, 5, 6, 7, 8]);
+
+    let mut gray_alpha = Vec::new();
+    write(&mut gray_alpha, &[0x12, 0], (1, 1), 2, 8).unwrap();
+    assert_eq!(&gray_alpha[16..], &[0x12, 0x12, 0x12, 0x12, 0x12, 0x12, 0, 0]);
+}
@@ -0,0 +1,115 @@
+//! Turning animation frames into video, with the help of `ffmpeg`.
+//!
+//! Writing each frame of a long zoom to a PNG file, only to have `ffmpeg`
+//! read them all back, spends a lot of time and disk on files nobody wants.
+//! Instead, we can run `ffmpeg` ourselves, telling it to read raw frames from
+//! its standard input, and write each frame's pixels to it as soon as the
+//! frame is rendered: eight-bit red, green, and blue, row by row from the top.
+//! The arguments the user gives say what to do with them: which codec to use,
+//! and where to write the video. `ffmpeg` must be installed somewhere on the
+//! `PATH`.
+
+use std::io::{self, ErrorKind, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+
+/// The program we run to do the encoding.
+const FFMPEG: &'static str = "ffmpeg";
+
+/// A running `ffmpeg` process, reading frames from us.
+pub struct Pipe {
+    child: Child,
+    input: Option<ChildStdin>
+}
+
+impl Pipe {
+    /// Start `ffmpeg` reading frames whose dimensions are given by `bounds`,
+    /// each shown for `delay` milliseconds, and handling them as `arguments`
+    /// says. `arguments` are separated by spaces, and may be quoted with
+    /// single or double quotes to include spaces.
+    pub fn start(arguments: &str, bounds: (usize, usize), delay: u16) -> io::Result<Pipe> {
+        let arguments = try!(split(arguments).map_err(|why| {
+            io::Error::new(ErrorKind::InvalidInput, why)
+        }));
+        let mut child = try!(Command::new(FFMPEG)
+                             .arg("-nostdin")
+                             .args(&["-f", "rawvideo", "-pix_fmt", "rgb24"])
+                             .arg("-video_size").arg(format!("{}x{}", bounds.0, bounds.1))
+                             .arg("-framerate").arg(format!("1000/{}", delay))
+                             .args(&["-i", "-"])
+                             .args(&arguments)
+                             .stdin(Stdio::piped())
+                             .spawn()
+                             .map_err(|e| {
+                                 io::Error::new(e.kind(),
+                                                format!("couldn't run '{}', which the --ffmpeg \
+                                                         option requires: {}", FFMPEG, e))
+                             }));
+        let input = child.stdin.take();
+        Ok(Pipe { child: child, input: input })
+    }
+
+    /// Pass `rgb`, a frame's pixels, to `ffmpeg`.
+    pub fn add(&mut self, rgb: &[u8]) -> io::Result<()> {
+        let written = self.input.as_mut().unwrap().write_all(rgb);
+        match written {
+            // If `ffmpeg` has quit, what it had to say about why is more
+            // interesting than the broken pipe.
+            Err(ref e) if e.kind() == ErrorKind::BrokenPipe => {
+                self.input = None;
+                try!(self.wait());
+                written
+            }
+            _ => written
+        }
+    }
+
+    /// Tell `ffmpeg` there are no more frames, and wait for it to finish.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.input = None;
+        self.wait()
+    }
+
+    fn wait(&mut self) -> io::Result<()> {
+        let status = try!(self.child.wait());
+        if !status.success() {
+            return Err(io::Error::new(ErrorKind::Other, format!("'{}' failed: {}", FFMPEG,
+                                                                status)));
+        }
+        Ok(())
+    }
+}
+
+/// Split `arguments` at spaces, except within quotes.
+fn split(arguments: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word = None;
+    let mut quote = None;
+    for ch in arguments.chars() {
+        match (quote, ch) {
+            (Some(q), _) if ch == q => quote = None,
+            (Some(_), _) => word.get_or_insert(String::new()).push(ch),
+            (None, '\'') | (None, '"') => {
+                quote = Some(ch);
+                word.get_or_insert(String::new());
+            }
+            (None, _) if ch.is_whitespace() => words.extend(word.take()),
+            (None, _) => word.get_or_insert(String::new()).push(ch)
+        }
+    }
+    if quote.is_some() {
+        return Err(format!("unterminated quotation in ffmpeg arguments: {}", arguments));
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+#[test]
+fn test_split() {
+    assert_eq!(split("-c:v libx264  -crf 18 out.mp4").unwrap(),
+               vec!["-c:v", "libx264", "-crf", "18", "out.mp4"]);
+    assert_eq!(split(" -vf 'scale=640:-1' \"my zoom.mp4\" ''").unwrap(),
+               vec!["-vf", "scale=640:-1", "my zoom.mp4", ""]);
+    assert_eq!(split("a'b c'd").unwrap(), vec!["ab cd"]);
+    assert_eq!(split("").unwrap(), Vec::<String>::new());
+    assert!(split("'out.mp4").is_err());
+}
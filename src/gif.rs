@@ -0,0 +1,495 @@
+//! Writing animated GIF files.
+//!
+//! GIF is an old format, but it's still the one most sure to animate when
+//! dropped into a chat message or a README. Its images can have no more than
+//! 256 colors each, so we choose a palette for each frame by median cut:
+//! starting with a box around all the frame's colors, we repeatedly split the
+//! box with the most pixels spread along some channel in two at the median of
+//! that channel, until there are as many boxes as colors, and then take the
+//! average color of each box. Each pixel then gets the nearest color in the
+//! palette, optionally with Floyd-Steinberg dithering, which spreads the
+//! difference over the neighboring pixels not yet colored, trading banding
+//! for grain.
+//!
+//! Each frame can have its own palette, or all can share one, chosen from the
+//! first frame. A shared palette keeps colors from flickering as each frame
+//! picks slightly different ones, and makes the file a bit smaller.
+//!
+//! The pixels are compressed with LZW, with codes of up to twelve bits, packed
+//! least significant bit first into blocks of up to 255 bytes.
+
+use bytes::le16;
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+/// The largest number of colors a GIF palette can hold.
+const MAX_COLORS: usize = 256;
+
+/// The number of bits in the LZW codes for our pixels at first: enough for
+/// every palette index, plus the clear and end codes.
+const MIN_CODE_SIZE: u8 = 8;
+
+/// The largest LZW code.
+const MAX_CODE: u16 = 4095;
+
+/// A GIF palette, of at most `MAX_COLORS` colors.
+pub type Palette = Vec<[u8; 3]>;
+
+/// An animated GIF file being written.
+pub struct Animation<W: Write> {
+    output: W,
+
+    /// The width and height of every frame.
+    bounds: (usize, usize),
+
+    /// How long to show each frame, in hundredths of a second.
+    delay: u16,
+
+    /// Whether to dither each frame's colors to its palette.
+    dither: bool,
+
+    /// If every frame is to share one palette, that palette, once the first
+    /// frame has chosen it.
+    global: Option<Palette>,
+
+    /// Whether to share one palette among all the frames.
+    share: bool,
+
+    /// The number of frames added so far.
+    added: u32
+}
+
+impl<W: Write> Animation<W> {
+    /// Start writing an animation whose frames' dimensions are given by
+    /// `bounds` to `output`, each shown for `delay` hundredths of a second,
+    /// repeating forever. If `dither` is true, dither each frame's colors. If
+    /// `share` is true, use the first frame's palette for every frame.
+    pub fn new(output: W, bounds: (usize, usize), delay: u16, dither: bool, share: bool)
+        -> Animation<W>
+    {
+        Animation {
+            output: output,
+            bounds: bounds,
+            delay: delay,
+            dither: dither,
+            global: None,
+            share: share,
+            added: 0
+        }
+    }
+
+    /// Add `pixels`, the red, green, and blue of each pixel, row by row from
+    /// the top, as the animation's next frame.
+    pub fn add(&mut self, pixels: &[[u8; 3]]) -> io::Result<()> {
+        if self.bounds.0 > 0xffff || self.bounds.1 > 0xffff {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "image is too large to write as GIF"));
+        }
+        if self.added == 0 {
+            if self.share {
+                self.global = Some(median_cut(pixels));
+            }
+            let global = self.global.take();
+            try!(self.write_header(global.as_ref()));
+            self.global = global;
+        }
+        let palette = match self.global {
+            Some(ref palette) => palette.clone(),
+            None => median_cut(pixels)
+        };
+        let indices = map_to_palette(pixels, self.bounds.0, &palette, self.dither);
+
+        // The graphic control extension: no transparency, and the delay.
+        let delay = self.delay;
+        try!(self.output.write_all(&[0x21, 0xf9, 4, 0, delay as u8, (delay >> 8) as u8, 0, 0]));
+
+        // The image descriptor: the frame covers the whole screen, and has a
+        // palette of its own unless it shares the global one.
+        try!(self.output.write_all(&[0x2c, 0, 0, 0, 0]));
+        try!(self.output.write_all(&le16(self.bounds.0 as u16)));
+        try!(self.output.write_all(&le16(self.bounds.1 as u16)));
+        if self.share {
+            try!(self.output.write_all(&[0]));
+        } else {
+            try!(self.output.write_all(&[0x80 | 7]));
+            try!(write_palette(&mut self.output, &palette));
+        }
+
+        try!(self.output.write_all(&[MIN_CODE_SIZE]));
+        for block in lzw(&indices).chunks(255) {
+            try!(self.output.write_all(&[block.len() as u8]));
+            try!(self.output.write_all(block));
+        }
+        try!(self.output.write_all(&[0]));
+        self.added += 1;
+        Ok(())
+    }
+
+    /// Finish the animation.
+    pub fn finish(mut self) -> io::Result<()> {
+        if self.added == 0 {
+            try!(self.write_header(None));
+        }
+        try!(self.output.write_all(&[0x3b]));
+        self.output.flush()
+    }
+
+    /// Write the file's header, with `global` as its global palette, if any,
+    /// and an extension asking that the animation repeat forever.
+    fn write_header(&mut self, global: Option<&Palette>) -> io::Result<()> {
+        try!(self.output.write_all(b"GIF89a"));
+        try!(self.output.write_all(&le16(self.bounds.0 as u16)));
+        try!(self.output.write_all(&le16(self.bounds.1 as u16)));
+        // Whether there's a global palette, eight bits per channel, and the
+        // palette's size; then the background color and pixel aspect ratio.
+        let flags = if global.is_some() { 0x80 | 7 << 4 | 7 } else { 7 << 4 };
+        try!(self.output.write_all(&[flags, 0, 0]));
+        if let Some(palette) = global {
+            try!(write_palette(&mut self.output, palette));
+        }
+        try!(self.output.write_all(b"\x21\xff\x0bNETSCAPE2.0\x03\x01\0\0\0"));
+        Ok(())
+    }
+}
+
+/// Write `palette`, padded to `MAX_COLORS` colors, to `output`.
+fn write_palette<W: Write>(output: &mut W, palette: &Palette) -> io::Result<()> {
+    for i in 0..MAX_COLORS {
+        try!(output.write_all(palette.get(i).unwrap_or(&[0, 0, 0])));
+    }
+    Ok(())
+}
+
+/// Return a palette of at most `MAX_COLORS` colors for `pixels`, by median
+/// cut.
+fn median_cut(pixels: &[[u8; 3]]) -> Palette {
+    let mut histogram = HashMap::new();
+    for &pixel in pixels {
+        *histogram.entry(pixel).or_insert(0_u64) += 1;
+    }
+    let mut colors: Vec<([u8; 3], u64)> = histogram.into_iter().collect();
+    colors.sort();
+
+    // Each box is a range of `colors`, which we keep sorted within each box
+    // along whichever channel it was last split on.
+    let mut boxes = vec![0..colors.len()];
+    while boxes.len() < MAX_COLORS {
+        // Split the box whose widest channel, times its pixel count, is
+        // greatest.
+        let mut best = None;
+        for (i, range) in boxes.iter().enumerate() {
+            if range.len() < 2 {
+                continue;
+            }
+            let members = &colors[range.clone()];
+            let (channel, spread) = widest_channel(members);
+            let pixels: u64 = members.iter().map(|&(_, count)| count).sum();
+            let score = spread as u64 * pixels;
+            if best.map_or(true, |(_, _, best_score)| score > best_score) {
+                best = Some((i, channel, score));
+            }
+        }
+        let (i, channel) = match best {
+            Some((i, channel, _)) => (i, channel),
+            None => break
+        };
+        let range = boxes[i].clone();
+        colors[range.clone()].sort_by_key(|&(color, _)| color[channel]);
+
+        // Split at the median pixel, but leave at least one color each side.
+        let half: u64 = colors[range.clone()].iter().map(|&(_, count)| count).sum::<u64>() / 2;
+        let mut seen = 0;
+        let mut split = range.start + 1;
+        for j in range.clone() {
+            seen += colors[j].1;
+            if seen > half {
+                split = j.max(range.start + 1).min(range.end - 1);
+                break;
+            }
+        }
+        boxes[i] = range.start..split;
+        boxes.push(split..range.end);
+    }
+
+    boxes.iter().map(|range| {
+        let mut sums = [0_u64; 3];
+        let mut total = 0;
+        for &(color, count) in &colors[range.clone()] {
+            for c in 0..3 {
+                sums[c] += color[c] as u64 * count;
+            }
+            total += count;
+        }
+        [((sums[0] + total / 2) / total) as u8, ((sums[1] + total / 2) / total) as u8,
+         ((sums[2] + total / 2) / total) as u8]
+    }).collect()
+}
+
+/// Return the channel along which `colors` are most spread out, and by how
+/// much.
+fn widest_channel(colors: &[([u8; 3], u64)]) -> (usize, u8) {
+    let mut widest = (0, 0);
+    for channel in 0..3 {
+        let low = colors.iter().map(|&(color, _)| color[channel]).min().unwrap();
+        let high = colors.iter().map(|&(color, _)| color[channel]).max().unwrap();
+        if high - low > widest.1 {
+            widest = (channel, high - low);
+        }
+    }
+    widest
+}
+
+/// Return the index in `palette` of the color to use for each of `pixels`, an
+/// image `width` pixels wide, dithering if `dither` is true.
+fn map_to_palette(pixels: &[[u8; 3]], width: usize, palette: &Palette, dither: bool)
+    -> Vec<u8>
+{
+    let mut nearest = HashMap::new();
+    let mut lookup = |color: [u8; 3]| -> u8 {
+        *nearest.entry(color).or_insert_with(|| closest(palette, color))
+    };
+    if !dither {
+        return pixels.iter().map(|&pixel| lookup(pixel)).collect();
+    }
+
+    // The error carried to each pixel of this row and the next, in each
+    // channel, with a pixel of padding at each end.
+    let mut errors = vec![[0_i32; 3]; width + 2];
+    let mut next = vec![[0_i32; 3]; width + 2];
+    let mut indices = Vec::with_capacity(pixels.len());
+    for row in pixels.chunks(width) {
+        for (x, &pixel) in row.iter().enumerate() {
+            let mut wanted = [0; 3];
+            for c in 0..3 {
+                let value = pixel[c] as i32 + errors[x + 1][c] / 16;
+                wanted[c] = value.max(0).min(255) as u8;
+            }
+            let index = lookup(wanted);
+            let chosen = palette[index as usize];
+            for c in 0..3 {
+                let error = wanted[c] as i32 - chosen[c] as i32;
+                errors[x + 2][c] += error * 7;
+                next[x][c] += error * 3;
+                next[x + 1][c] += error * 5;
+                next[x + 2][c] += error;
+            }
+            indices.push(index);
+        }
+        errors = next;
+        next = vec![[0; 3]; width + 2];
+    }
+    indices
+}
+
+/// Return the index of the color in `palette` nearest `color`.
+fn closest(palette: &Palette, color: [u8; 3]) -> u8 {
+    let distance = |entry: &[u8; 3]| -> i32 {
+        (0..3).map(|c| (entry[c] as i32 - color[c] as i32).pow(2)).sum()
+    };
+    let mut best = 0;
+    for (i, entry) in palette.iter().enumerate() {
+        if distance(entry) < distance(&palette[best]) {
+            best = i;
+        }
+    }
+    best as u8
+}
+
+/// Return `indices` compressed with GIF's variant of LZW.
+fn lzw(indices: &[u8]) -> Vec<u8> {
+    let clear = 1_u16 << MIN_CODE_SIZE;
+    let end = clear + 1;
+    let mut bits = Bits::new();
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut next_code = end + 1;
+    let mut size = MIN_CODE_SIZE + 1;
+    bits.write(clear, size);
+
+    let mut iter = indices.iter();
+    let mut prefix = match iter.next() {
+        Some(&index) => index as u16,
+        None => {
+            bits.write(end, size);
+            return bits.finish();
+        }
+    };
+    for &index in iter {
+        if let Some(&code) = table.get(&(prefix, index)) {
+            prefix = code;
+            continue;
+        }
+        bits.write(prefix, size);
+        if next_code > MAX_CODE {
+            // The table is full; start afresh.
+            bits.write(clear, size);
+            table.clear();
+            next_code = end + 1;
+            size = MIN_CODE_SIZE + 1;
+        } else {
+            table.insert((prefix, index), next_code);
+            // The decoder adds each code a step behind us, so it widens its
+            // codes just after reading the one that fills the current size.
+            if next_code == 1 << size && size < 12 {
+                size += 1;
+            }
+            next_code += 1;
+        }
+        prefix = index as u16;
+    }
+    bits.write(prefix, size);
+    bits.write(end, size);
+    bits.finish()
+}
+
+/// A stream of bits, packed least significant bit first.
+struct Bits {
+    bytes: Vec<u8>,
+    buffer: u32,
+    count: u8
+}
+
+impl Bits {
+    fn new() -> Bits {
+        Bits { bytes: Vec::new(), buffer: 0, count: 0 }
+    }
+
+    /// Add the low `size` bits of `code` to the stream.
+    fn write(&mut self, code: u16, size: u8) {
+        self.buffer |= (code as u32) << self.count;
+        self.count += size;
+        while self.count >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Return the stream, padded to a whole byte.
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+#[test]
+fn test_median_cut() {
+    let black = [0, 0, 0];
+    let white = [255, 255, 255];
+    assert_eq!(median_cut(&[black, white, black]), vec![black, white]);
+    assert_eq!(median_cut(&[black; 4]), vec![black]);
+
+    // With more colors than fit, each box averages its members.
+    let grays: Vec<[u8; 3]> = (0..1024).map(|i| [(i / 2) as u8, 0, (i % 2) as u8]).collect();
+    let palette = median_cut(&grays);
+    assert_eq!(palette.len(), 256);
+    assert!(palette.iter().all(|color| color[1] == 0 && color[2] <= 1));
+}
+
+#[test]
+fn test_map_to_palette() {
+    let palette = vec![[0, 0, 0], [255, 255, 255]];
+    let gray = [128, 128, 128];
+    assert_eq!(map_to_palette(&[gray; 4], 4, &palette, false), vec![1, 1, 1, 1]);
+    // Dithering alternates black and white for a middle gray.
+    assert_eq!(map_to_palette(&[gray; 4], 4, &palette, true), vec![1, 0, 1, 0]);
+}
+
+#[test]
+fn test_lzw() {
+    // Clear, 1, then the code for 1, 1, which is 258, then 1, and the end.
+    let mut bits = Bits::new();
+    for &code in &[256, 1, 258, 1, 257] {
+        bits.write(code, 9);
+    }
+    assert_eq!(lzw(&[1, 1, 1, 1]), bits.finish());
+
+    // Enough noise to widen the codes to twelve bits and fill the table.
+    let mut seed = 1_u32;
+    let indices: Vec<u8> = (0..20000).map(|_| {
+        seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+        (seed >> 16) as u8
+    }).collect();
+    assert_eq!(decode(&lzw(&indices)), indices);
+    assert_eq!(decode(&lzw(&[])), Vec::<u8>::new());
+}
+
+/// Decode `compressed`, LZW data with eight-bit indices, for testing.
+#[cfg(test)]
+fn decode(compressed: &[u8]) -> Vec<u8> {
+    let (clear, end) = (256_usize, 257_usize);
+    let mut position = 0;
+    let mut read = |size: u8| -> usize {
+        let mut code = 0;
+        for i in 0..size as usize {
+            let bit = compressed[(position + i) / 8] >> ((position + i) % 8) & 1;
+            code |= (bit as usize) << i;
+        }
+        position += size as usize;
+        code
+    };
+    let mut table: Vec<Vec<u8>> = Vec::new();
+    let mut size = 9;
+    let mut output = Vec::new();
+    let mut previous: Option<Vec<u8>> = None;
+    loop {
+        let code = read(size);
+        if code == clear {
+            table = (0..256).map(|i| vec![i as u8]).collect();
+            table.push(vec![]);
+            table.push(vec![]);
+            size = 9;
+            previous = None;
+            continue;
+        }
+        if code == end {
+            return output;
+        }
+        let entry = if code < table.len() {
+            table[code].clone()
+        } else {
+            let mut entry = previous.clone().unwrap();
+            entry.push(entry[0]);
+            entry
+        };
+        if let Some(mut previous) = previous {
+            previous.push(entry[0]);
+            table.push(previous);
+            if table.len() == 1 << size && size < 12 {
+                size += 1;
+            }
+        }
+        output.extend_from_slice(&entry);
+        previous = Some(entry);
+    }
+}
+
+#[test]
+fn test_animation() {
+    let (black, white) = ([0, 0, 0], [255, 255, 255]);
+    let mut gif = Vec::new();
+    {
+        let mut animation = Animation::new(&mut gif, (2, 1), 4, false, true);
+        animation.add(&[black, white]).unwrap();
+        animation.add(&[white, white]).unwrap();
+        animation.finish().unwrap();
+    }
+    assert_eq!(&gif[..13], b"GIF89a\x02\0\x01\0\xf7\0\0");
+    assert_eq!(&gif[13..19], &[0, 0, 0, 255, 255, 255]);
+    let rest = &gif[13 + 768..];
+    assert_eq!(&rest[..19], b"\x21\xff\x0bNETSCAPE2.0\x03\x01\0\0\0");
+    // Each frame: its control extension, descriptor, and pixels.
+    let frame = |indices: &[u8]| {
+        let mut frame = b"\x21\xf9\x04\0\x04\0\0\0\x2c\0\0\0\0\x02\0\x01\0\0\x08".to_vec();
+        let compressed = lzw(indices);
+        frame.push(compressed.len() as u8);
+        frame.extend(compressed);
+        frame.push(0);
+        frame
+    };
+    let mut expected = frame(&[0, 1]);
+    expected.extend(frame(&[1, 1]));
+    expected.push(0x3b);
+    assert_eq!(&rest[19..], &expected[..]);
+}
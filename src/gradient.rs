@@ -0,0 +1,230 @@
+//! Gradients given as colors at chosen positions.
+//!
+//! A palette's colors are evenly spaced along it. A gradient instead places
+//! each of its stops wherever it likes, from 0 at the start to 1 at the end,
+//! written on the command line like this:
+//!
+//! ```text
+//! 0:#000764,0.16:#206bcb,0.42:#edffff,1:#000200
+//! ```
+//!
+//! Before the first stop and after the last, the gradient holds their colors.
+//! Between stops, it blends them in the chosen color space: `rgb` mixes the
+//! channels directly; `hsv` turns the hue the short way around the color
+//! wheel, which keeps blends between saturated colors saturated; and `lab`
+//! mixes in CIE L*a*b*, which changes lightness evenly.
+//!
+//! We sample the gradient finely enough to hide the difference, and use the
+//! samples as an ordinary palette.
+
+use coloring::Palette;
+use config::ColorSpace;
+
+/// How many evenly spaced colors to sample a gradient at.
+const SAMPLES: usize = 256;
+
+/// A color at a position along a gradient, from 0 to 1.
+pub type Stop = (f64, [u8; 3]);
+
+/// Parse `text` as a list of stops, like `0:#000000,0.5:#ff8000,1:#ffffff`.
+/// The positions must run from 0 to 1, never decreasing.
+pub fn parse(text: &str) -> Result<Vec<Stop>, String> {
+    let mut stops: Vec<Stop> = Vec::new();
+    for stop in text.split(',') {
+        let stop = stop.trim();
+        let colon = try!(stop.find(':').ok_or_else(|| {
+            format!("expected POSITION:COLOR, like 0.5:#ff8000, not '{}'", stop)
+        }));
+        let position: f64 = try!(stop[..colon].trim().parse().map_err(|_| {
+            format!("bad position '{}'", &stop[..colon])
+        }));
+        if !(position >= 0.0 && position <= 1.0) {
+            return Err(format!("position {} is not between 0 and 1", position));
+        }
+        if stops.last().map_or(false, |&(last, _)| position < last) {
+            return Err(format!("position {} comes before the stop preceding it", position));
+        }
+        stops.push((position, try!(parse_hex(stop[colon + 1..].trim()))));
+    }
+    Ok(stops)
+}
+
+/// Parse `text` as a color in hex, like `#ff8000`; the `#` is optional.
+fn parse_hex(text: &str) -> Result<[u8; 3], String> {
+    let digits = if text.starts_with('#') { &text[1..] } else { text };
+    if digits.len() != 6 || !digits.chars().all(|c| c.is_digit(16)) {
+        return Err(format!("expected a color like #ff8000, not '{}'", text));
+    }
+    let channel = |i: usize| u8::from_str_radix(&digits[i..i + 2], 16).unwrap();
+    Ok([channel(0), channel(2), channel(4)])
+}
+
+/// Return a palette following the gradient through `stops`, blended in
+/// `space`. There must be at least one stop.
+pub fn palette(stops: &[Stop], space: ColorSpace) -> Palette {
+    assert!(!stops.is_empty());
+    Palette::new((0..SAMPLES).map(|i| {
+        at(stops, space, i as f64 / (SAMPLES - 1) as f64)
+    }).collect())
+}
+
+/// Return the color at `position` along the gradient through `stops`.
+fn at(stops: &[Stop], space: ColorSpace, position: f64) -> [u8; 3] {
+    let next = match stops.iter().position(|&(at, _)| at > position) {
+        Some(0) => return stops[0].1,
+        Some(next) => next,
+        None => return stops[stops.len() - 1].1
+    };
+    let ((start, from), (end, to)) = (stops[next - 1], stops[next]);
+    let t = (position - start) / (end - start);
+    match space {
+        ColorSpace::Rgb => {
+            let mut color = [0; 3];
+            for channel in 0..3 {
+                let (from, to) = (from[channel] as f64, to[channel] as f64);
+                color[channel] = (from + (to - from) * t).round() as u8;
+            }
+            color
+        }
+        ColorSpace::Hsv => {
+            let (from, to) = (to_hsv(from), to_hsv(to));
+            // Turn the short way around the color wheel.
+            let mut turn = to[0] - from[0];
+            if turn > 180.0 {
+                turn -= 360.0;
+            } else if turn < -180.0 {
+                turn += 360.0;
+            }
+            let hue = (from[0] + turn * t + 360.0) % 360.0;
+            from_hsv([hue, from[1] + (to[1] - from[1]) * t, from[2] + (to[2] - from[2]) * t])
+        }
+        ColorSpace::Lab => {
+            let (from, to) = (to_lab(from), to_lab(to));
+            let mut lab = [0.0; 3];
+            for channel in 0..3 {
+                lab[channel] = from[channel] + (to[channel] - from[channel]) * t;
+            }
+            from_lab(lab)
+        }
+    }
+}
+
+/// Return `channel`, from 0 to 1, as a byte.
+fn byte(channel: f64) -> u8 {
+    (channel.max(0.0).min(1.0) * 255.0).round() as u8
+}
+
+/// Return `color` as hue, in degrees, and saturation and value, from 0 to 1.
+fn to_hsv(color: [u8; 3]) -> [f64; 3] {
+    let (r, g, b) = (color[0] as f64 / 255.0, color[1] as f64 / 255.0, color[2] as f64 / 255.0);
+    let max = r.max(g).max(b);
+    let range = max - r.min(g).min(b);
+    let hue = if range == 0.0 {
+        0.0
+    } else if max == r {
+        60.0 * ((g - b) / range)
+    } else if max == g {
+        60.0 * ((b - r) / range + 2.0)
+    } else {
+        60.0 * ((r - g) / range + 4.0)
+    };
+    let saturation = if max == 0.0 { 0.0 } else { range / max };
+    [(hue + 360.0) % 360.0, saturation, max]
+}
+
+/// Return the color with the hue, saturation, and value in `hsv`.
+fn from_hsv(hsv: [f64; 3]) -> [u8; 3] {
+    let (hue, saturation, value) = (hsv[0] / 60.0, hsv[1], hsv[2]);
+    let chroma = value * saturation;
+    let x = chroma * (1.0 - (hue % 2.0 - 1.0).abs());
+    let (r, g, b) = match hue as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x)
+    };
+    let low = value - chroma;
+    [byte(r + low), byte(g + low), byte(b + low)]
+}
+
+/// The CIE XYZ coordinates of the D65 white point, which sRGB white maps to.
+const WHITE: [f64; 3] = [0.95047, 1.0, 1.08883];
+
+/// Return the sRGB `color` in CIE L*a*b*.
+fn to_lab(color: [u8; 3]) -> [f64; 3] {
+    let linear = |channel: u8| {
+        let value = channel as f64 / 255.0;
+        if value <= 0.04045 { value / 12.92 } else { ((value + 0.055) / 1.055).powf(2.4) }
+    };
+    let (r, g, b) = (linear(color[0]), linear(color[1]), linear(color[2]));
+    let xyz = [0.4124 * r + 0.3576 * g + 0.1805 * b,
+               0.2126 * r + 0.7152 * g + 0.0722 * b,
+               0.0193 * r + 0.1192 * g + 0.9505 * b];
+    let f = |i: usize| {
+        let t = xyz[i] / WHITE[i];
+        if t > 216.0 / 24389.0 { t.cbrt() } else { (24389.0 / 27.0 * t + 16.0) / 116.0 }
+    };
+    let (fx, fy, fz) = (f(0), f(1), f(2));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+/// Return the sRGB color nearest to `lab`, in CIE L*a*b*.
+fn from_lab(lab: [f64; 3]) -> [u8; 3] {
+    let fy = (lab[0] + 16.0) / 116.0;
+    let f = [fy + lab[1] / 500.0, fy, fy - lab[2] / 200.0];
+    let mut xyz = [0.0; 3];
+    for i in 0..3 {
+        let t = if f[i].powi(3) > 216.0 / 24389.0 {
+            f[i].powi(3)
+        } else {
+            (116.0 * f[i] - 16.0) * 27.0 / 24389.0
+        };
+        xyz[i] = t * WHITE[i];
+    }
+    let (x, y, z) = (xyz[0], xyz[1], xyz[2]);
+    let encode = |linear: f64| {
+        byte(if linear <= 0.0031308 {
+            linear * 12.92
+        } else {
+            1.055 * linear.powf(1.0 / 2.4) - 0.055
+        })
+    };
+    [encode(3.2406 * x - 1.5372 * y - 0.4986 * z),
+     encode(-0.9689 * x + 1.8758 * y + 0.0415 * z),
+     encode(0.0557 * x - 0.2040 * y + 1.0570 * z)]
+}
+
+#[test]
+fn test_parse() {
+    assert_eq!(parse("0:#000764, 0.16:206bcb,1:#FFFFFF"),
+               Ok(vec![(0.0, [0, 7, 100]), (0.16, [32, 107, 203]), (1.0, [255, 255, 255])]));
+    assert!(parse("0:#000764,0.5").is_err());
+    assert!(parse("0:#00076").is_err());
+    assert!(parse("1.5:#000764").is_err());
+    assert!(parse("0.5:#000000,0.25:#ffffff").is_err());
+}
+
+#[test]
+fn test_blend() {
+    let stops = [(0.25, [255, 0, 0]), (0.75, [0, 0, 255])];
+    assert_eq!(at(&stops, ColorSpace::Rgb, 0.0), [255, 0, 0]);
+    assert_eq!(at(&stops, ColorSpace::Rgb, 0.5), [128, 0, 128]);
+    assert_eq!(at(&stops, ColorSpace::Rgb, 1.0), [0, 0, 255]);
+
+    // From red to blue, the short way around the wheel passes magenta.
+    assert_eq!(at(&stops, ColorSpace::Hsv, 0.5), [255, 0, 255]);
+
+    // Lab's lightness runs evenly from black to white, so its middle gray is
+    // lighter than RGB's.
+    let grays = [(0.0, [0, 0, 0]), (1.0, [255, 255, 255])];
+    assert_eq!(at(&grays, ColorSpace::Lab, 0.5), [119, 119, 119]);
+    for &color in &[[0, 7, 100], [237, 255, 255], [255, 170, 0]] {
+        assert_eq!(from_lab(to_lab(color)), color);
+        assert_eq!(from_hsv(to_hsv(color)), color);
+    }
+
+    let palette = palette(&stops, ColorSpace::Rgb);
+    assert_eq!(palette.at(0, 1), [255, 0, 0]);
+}
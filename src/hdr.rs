@@ -0,0 +1,71 @@
+//! Writing Radiance HDR files.
+//!
+//! Radiance's format, often called RGBE after its pixels, stores high dynamic
+//! range images compactly: each pixel is three eight-bit mantissas for red,
+//! green, and blue, sharing an eight-bit exponent. HDR viewers can then
+//! adjust the exposure of a render's raw values after the fact, rather than
+//! living with whatever a palette made of them.
+//!
+//! The file starts with a short text header, ended by a blank line, and a
+//! line giving the image's dimensions. Then come the pixels, row by row from
+//! the top. Radiance files usually compress each row by run-length encoding
+//! each channel separately, but readers also accept uncompressed rows, which
+//! is what we write: a row is only mistaken for a compressed one if it starts
+//! with mantissas of 1 or 2, and no mantissa of a nonzero pixel is ever less
+//! than 128.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Write `values`, a gray image whose dimensions are given by `bounds`, to
+/// `output` as a Radiance HDR file. Negative values are written as zero.
+pub fn write<W: Write>(output: W, bounds: (usize, usize), values: &[f32]) -> io::Result<()> {
+    let mut output = BufWriter::new(output);
+    try!(write!(output, "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+                bounds.1, bounds.0));
+    for &value in values {
+        let (mantissa, exponent) = rgbe(value);
+        try!(output.write_all(&[mantissa, mantissa, mantissa, exponent]));
+    }
+    output.flush()
+}
+
+/// Write `values` to the file at `path`, as `write` does.
+pub fn save(path: &Path, bounds: (usize, usize), values: &[f32]) -> io::Result<()> {
+    write(try!(File::create(path)), bounds, values)
+}
+
+/// Return the mantissa and biased exponent with which RGBE represents
+/// `value`: `value` is about `mantissa / 256 * 2^(exponent - 128)`.
+fn rgbe(value: f32) -> (u8, u8) {
+    let value = value as f64;
+    if value.is_nan() || value < 1e-32 {
+        return (0, 0);
+    }
+    let mut exponent = value.log2().floor() as i32 + 1;
+    let mut mantissa = value / 2.0_f64.powi(exponent) * 256.0;
+    if mantissa >= 256.0 {
+        exponent += 1;
+        mantissa /= 2.0;
+    }
+    if exponent > 127 {
+        return (255, 255);
+    }
+    (mantissa as u8, (exponent + 128) as u8)
+}
+
+#[test]
+fn test_write() {
+    assert_eq!(rgbe(0.0), (0, 0));
+    assert_eq!(rgbe(-3.0), (0, 0));
+    assert_eq!(rgbe(1.0), (128, 129));
+    assert_eq!(rgbe(0.75), (192, 128));
+    assert_eq!(rgbe(1000.0), (250, 138));
+    assert_eq!(rgbe(1e-30), (162, 29));
+
+    let mut hdr = Vec::new();
+    write(&mut hdr, (2, 1), &[1.0, 0.0]).unwrap();
+    assert_eq!(hdr, b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 2\n\
+                      \x80\x80\x80\x81\0\0\0\0".to_vec());
+}
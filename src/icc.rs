@@ -0,0 +1,201 @@
+//! ICC color profiles, for embedding in images.
+//!
+//! A PNG file's colors are just numbers; how they should look is up to the
+//! program displaying them, which usually guesses sRGB. An embedded ICC profile
+//! says precisely which colors the numbers mean, so that color-managed
+//! viewers, printers, and wide-gamut displays all show the same thing.
+//!
+//! We can embed a profile read from a file, or one describing sRGB that we
+//! build ourselves: a version 2 display profile giving the sRGB primaries,
+//! adapted to the D50 white point that profiles use to connect to each other,
+//! and the sRGB transfer curve as a table.
+
+use bytes::be32;
+use coloring::Gamma;
+use std::fs::File;
+use std::io::{self, ErrorKind, Read};
+use std::path::Path;
+
+/// The D50 white point, in CIE XYZ.
+const D50: [f64; 3] = [0.9642, 1.0, 0.8249];
+
+/// The sRGB red, green, and blue primaries, in CIE XYZ, adapted to D50 with
+/// the Bradford transform.
+const PRIMARIES: [[f64; 3]; 3] = [
+    [0.4360747, 0.2225045, 0.0139322],
+    [0.3850649, 0.7168786, 0.0971045],
+    [0.1430804, 0.0606169, 0.7141733]
+];
+
+/// The number of entries in our sRGB profile's transfer curve table.
+const CURVE_POINTS: usize = 1024;
+
+/// Return an ICC profile describing sRGB.
+pub fn srgb() -> Vec<u8> {
+    let mut curve = Vec::with_capacity(CURVE_POINTS);
+    for i in 0..CURVE_POINTS {
+        let value = Gamma::Srgb.decode(i as f64 / (CURVE_POINTS - 1) as f64);
+        curve.push((value * 65535.0).round() as u16);
+    }
+    let mut curv = tag_type(b"curv");
+    curv.extend_from_slice(&be32(CURVE_POINTS as u32));
+    for value in curve {
+        curv.extend_from_slice(&[(value >> 8) as u8, value as u8]);
+    }
+
+    // The three channels' curves are the same, so their tags share it.
+    let tags: Vec<(&[u8; 4], Vec<u8>)> = vec![
+        (b"desc", text_description("sRGB")),
+        (b"cprt", text("No copyright, use freely")),
+        (b"wtpt", xyz(D50)),
+        (b"rXYZ", xyz(PRIMARIES[0])),
+        (b"gXYZ", xyz(PRIMARIES[1])),
+        (b"bXYZ", xyz(PRIMARIES[2])),
+        (b"rTRC", curv.clone()),
+        (b"gTRC", curv.clone()),
+        (b"bTRC", curv)
+    ];
+    build(&tags)
+}
+
+/// Read the ICC profile at `path`, and check that it looks like one.
+pub fn load(path: &Path) -> io::Result<Vec<u8>> {
+    let mut profile = Vec::new();
+    try!(try!(File::open(path)).read_to_end(&mut profile));
+    try!(check(&profile).map_err(|e| io::Error::new(ErrorKind::InvalidData, e)));
+    Ok(profile)
+}
+
+/// Check that `profile` has an ICC profile's header, and is as long as the
+/// header says.
+pub fn check(profile: &[u8]) -> Result<(), String> {
+    if profile.len() < 128 || &profile[36..40] != b"acsp" {
+        return Err("not an ICC profile".to_string());
+    }
+    let size = profile[..4].iter().fold(0, |n, &byte| n << 8 | byte as usize);
+    if size != profile.len() {
+        return Err(format!("the profile's header says it is {} bytes long, but it is {}",
+                           size, profile.len()));
+    }
+    Ok(())
+}
+
+/// Return a profile holding `tags`, each a signature and its data. Tags with
+/// identical data share a single copy.
+fn build(tags: &[(&[u8; 4], Vec<u8>)]) -> Vec<u8> {
+    let table_end = 128 + 4 + 12 * tags.len();
+    let mut table = be32(tags.len() as u32).to_vec();
+    let mut data: Vec<u8> = Vec::new();
+    let mut placed: Vec<(usize, &Vec<u8>)> = Vec::new();
+    for &(signature, ref contents) in tags {
+        let offset = match placed.iter().find(|&&(_, other)| other == contents) {
+            Some(&(offset, _)) => offset,
+            None => {
+                // Each tag's data must start on a four-byte boundary.
+                while data.len() % 4 != 0 {
+                    data.push(0);
+                }
+                let offset = table_end + data.len();
+                data.extend_from_slice(contents);
+                placed.push((offset, contents));
+                offset
+            }
+        };
+        table.extend_from_slice(signature);
+        table.extend_from_slice(&be32(offset as u32));
+        table.extend_from_slice(&be32(contents.len() as u32));
+    }
+    while data.len() % 4 != 0 {
+        data.push(0);
+    }
+
+    let size = table_end + data.len();
+    let mut profile = Vec::with_capacity(size);
+    profile.extend_from_slice(&be32(size as u32));
+    profile.extend_from_slice(&[0; 4]); // preferred color management module
+    profile.extend_from_slice(&[2, 0x10, 0, 0]); // version 2.1
+    profile.extend_from_slice(b"mntrRGB XYZ ");
+    // The creation date: 2000-01-01 at midnight.
+    profile.extend_from_slice(&[0x07, 0xd0, 0, 1, 0, 1, 0, 0, 0, 0, 0, 0]);
+    profile.extend_from_slice(b"acsp");
+    // Platform, flags, manufacturer, model, attributes, and rendering
+    // intent: none, or perceptual.
+    profile.extend_from_slice(&[0; 28]);
+    profile.extend_from_slice(&xyz(D50)[8..]); // the connection space's illuminant
+    profile.extend_from_slice(&[0; 48]); // creator, and reserved space
+    profile.extend(table);
+    profile.extend(data);
+    profile
+}
+
+/// Return the first eight bytes of a tag's data: its type's signature, and
+/// four reserved bytes.
+fn tag_type(signature: &[u8; 4]) -> Vec<u8> {
+    let mut tag = signature.to_vec();
+    tag.extend_from_slice(&[0; 4]);
+    tag
+}
+
+/// Return an XYZ tag holding the color `color`.
+fn xyz(color: [f64; 3]) -> Vec<u8> {
+    let mut tag = tag_type(b"XYZ ");
+    for &component in &color {
+        // ICC's s15Fixed16Number: a signed count of 65536ths.
+        tag.extend_from_slice(&be32((component * 65536.0).round() as i32 as u32));
+    }
+    tag
+}
+
+/// Return a text tag holding `text`, which must be ASCII.
+fn text(text: &str) -> Vec<u8> {
+    let mut tag = tag_type(b"text");
+    tag.extend_from_slice(text.as_bytes());
+    tag.push(0);
+    tag
+}
+
+/// Return a version 2 text description tag holding `text`, which must be
+/// ASCII. The format has room for Unicode and Macintosh versions of the text
+/// as well, which we leave empty.
+fn text_description(text: &str) -> Vec<u8> {
+    let mut tag = tag_type(b"desc");
+    tag.extend_from_slice(&be32(text.len() as u32 + 1));
+    tag.extend_from_slice(text.as_bytes());
+    tag.push(0);
+    tag.extend_from_slice(&[0; 8]); // Unicode language and length
+    tag.extend_from_slice(&[0; 3]); // ScriptCode code and length
+    tag.extend_from_slice(&[0; 67]); // ScriptCode text
+    tag
+}
+
+#[test]
+fn test_srgb() {
+    let profile = srgb();
+    assert_eq!(check(&profile), Ok(()));
+    assert_eq!(profile.len() % 4, 0);
+    assert_eq!(&profile[8..24], b"\x02\x10\0\0mntrRGB XYZ ");
+    assert_eq!(&profile[68..80], &[0, 0, 0xf6, 0xd6, 0, 1, 0, 0, 0, 0, 0xd3, 0x2d]);
+
+    // Nine tags, the last three sharing their data.
+    assert_eq!(&profile[128..132], &[0, 0, 0, 9]);
+    let entry = |i: usize| &profile[132 + 12 * i..144 + 12 * i];
+    assert_eq!(entry(0), b"desc\0\0\0\xf0\0\0\0\x5f");
+    assert_eq!(&entry(6)[4..], &entry(8)[4..]);
+    assert_eq!(&entry(8)[8..], &be32(12 + 2 * CURVE_POINTS as u32));
+
+    // The curve runs from black to white, and is dark in the middle.
+    let offset = entry(6)[4..8].iter().fold(0, |n, &byte| n << 8 | byte as usize);
+    let curve = &profile[offset..offset + 12 + 2 * CURVE_POINTS];
+    assert_eq!(&curve[..12], b"curv\0\0\0\0\0\0\x04\0");
+    assert_eq!(&curve[12..14], &[0, 0]);
+    assert_eq!(&curve[curve.len() - 2..], &[0xff, 0xff]);
+    assert!(curve[12 + CURVE_POINTS] < 0x40);
+}
+
+#[test]
+fn test_check() {
+    let mut profile = srgb();
+    profile.push(0);
+    assert!(check(&profile).is_err());
+    assert!(check(b"not a profile").is_err());
+}
@@ -0,0 +1,65 @@
+//! Julia set outlines by inverse iteration.
+//!
+//! The Julia set of `z = z^2 + c` repels nearby orbits, so running the
+//! iteration backwards attracts them to it instead. Each point `z` has two
+//! preimages, the square roots of `z - c`; starting from a point already in the
+//! Julia set and picking one of the two at random at each step, we visit points
+//! scattered all over the set. Plotting them draws its outline directly, with
+//! no need to test each pixel, which makes a quick preview of the Julia set at
+//! the same coordinates as an escape-time render.
+//!
+//! The points don't fall evenly: they gather where the set is easy to reach,
+//! and deep cusps fill in slowly. More points help, but only so much.
+
+use num::Complex;
+use random::Rng;
+
+/// Return the repelling fixed point of `z = z^2 + c`, which lies in the Julia
+/// set for `c`. The iteration has two fixed points, `(1 ± sqrt(1 - 4c)) / 2`;
+/// the derivative there is `2z`, so the one farther from the origin repels at
+/// least as strongly as the other.
+pub fn repelling_fixed_point(c: Complex<f64>) -> Complex<f64> {
+    let one = Complex { re: 1.0, im: 0.0 };
+    let root = (one - c * Complex { re: 4.0, im: 0.0 }).sqrt();
+    let half = Complex { re: 0.5, im: 0.0 };
+    let (a, b) = ((one + root) * half, (one - root) * half);
+    if a.norm_sqr() >= b.norm_sqr() { a } else { b }
+}
+
+/// Visit `points` points of the Julia set for `c`, calling `plot` on each. The
+/// preimages are chosen with `rng`.
+pub fn outline<F>(c: Complex<f64>, points: u64, rng: &mut Rng, mut plot: F)
+    where F: FnMut(Complex<f64>)
+{
+    let mut z = repelling_fixed_point(c);
+    for _ in 0..points {
+        z = (z - c).sqrt();
+        if rng.next_u64() & 1 != 0 {
+            z = -z;
+        }
+        plot(z);
+    }
+}
+
+#[test]
+fn test_repelling_fixed_point() {
+    let close = |a: Complex<f64>, b: Complex<f64>| (a - b).norm_sqr() < 1e-20;
+    assert!(close(repelling_fixed_point(Complex { re: 0.0, im: 0.0 }),
+                  Complex { re: 1.0, im: 0.0 }));
+    let c = Complex { re: -0.8, im: 0.156 };
+    let z = repelling_fixed_point(c);
+    assert!(close(z * z + c, z));
+    assert!((z + z).norm() > 1.0);
+}
+
+#[test]
+fn test_outline() {
+    // The Julia set for 0 is the unit circle.
+    let mut rng = Rng::new(0, 0);
+    let mut count = 0;
+    outline(Complex { re: 0.0, im: 0.0 }, 1000, &mut rng, |z| {
+        assert!((z.norm() - 1.0).abs() < 1e-9);
+        count += 1;
+    });
+    assert_eq!(count, 1000);
+}
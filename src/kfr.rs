@@ -0,0 +1,124 @@
+//! Reading Kalles Fraktaler `.kfr` location files.
+//!
+//! A `.kfr` file is a series of `Name: value` lines:
+//!
+//! ```text
+//! Re: -1.7490930077784369461815397541469632
+//! Im: -0.0000000003184530506209616391396127
+//! Zoom: 2.4E22
+//! Iterations: 20000
+//! ```
+//!
+//! Kalles Fraktaler renders with arbitrary precision, so deep-zoom locations
+//! carry far more digits than an `f64` can hold. We keep the coordinates as
+//! the strings the file gives, and only round them to `f64` when producing
+//! render settings. Fields other than these four are ignored.
+
+use std::fs::File;
+use std::io::{self, ErrorKind, Read};
+use std::path::Path;
+
+use config::Config;
+
+/// A location from a `.kfr` file, with its coordinates at full precision.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Location {
+    pub re: String,
+    pub im: String,
+    pub zoom: String,
+    pub iterations: Option<u32>
+}
+
+impl Location {
+    /// Return the zoom as an `f64`.
+    pub fn zoom(&self) -> f64 {
+        // `parse` already accepted this string.
+        self.zoom.parse().unwrap()
+    }
+
+    /// Return render settings for this location, rounding the coordinates to
+    /// `f64`. At zoom 1, Kalles Fraktaler shows four units of the complex
+    /// plane vertically, just as we do, so the zoom carries over unchanged.
+    pub fn config(&self) -> Config {
+        Config {
+            center: Some((self.re.parse().unwrap(), self.im.parse().unwrap())),
+            zoom: Some(self.zoom()),
+            iterations: self.iterations,
+            .. Config::default()
+        }
+    }
+}
+
+/// Check that `value`, the value of the field `name`, is present and is a
+/// decimal number, and return it as an owned string.
+fn number(name: &str, value: Option<&str>) -> Result<String, String> {
+    let value = try!(value.ok_or_else(|| format!("missing '{}' field", name)));
+    try!(value.parse::<f64>().map_err(|_| {
+        format!("'{}' field is not a number: '{}'", name, value)
+    }));
+    Ok(value.to_string())
+}
+
+/// Parse `text` as the contents of a `.kfr` file.
+pub fn parse(text: &str) -> Result<Location, String> {
+    let (mut re, mut im, mut zoom, mut iterations) = (None, None, None, None);
+    for line in text.lines() {
+        let colon = match line.find(':') {
+            Some(i) => i,
+            None => continue
+        };
+        let value = line[colon + 1..].trim();
+        match line[..colon].trim() {
+            "Re" => re = Some(value),
+            "Im" => im = Some(value),
+            "Zoom" => zoom = Some(value),
+            "Iterations" => iterations = Some(value),
+            _ => ()
+        }
+    }
+
+    Ok(Location {
+        re: try!(number("Re", re)),
+        im: try!(number("Im", im)),
+        zoom: try!(number("Zoom", zoom)),
+        iterations: match iterations {
+            Some(n) => Some(try!(n.parse().map_err(|_| {
+                format!("'Iterations' field is not a number: '{}'", n)
+            }))),
+            None => None
+        }
+    })
+}
+
+/// Read the `.kfr` file at `path`.
+pub fn load(path: &Path) -> io::Result<Location> {
+    let mut text = String::new();
+    try!(try!(File::open(path)).read_to_string(&mut text));
+    parse(&text).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+}
+
+#[test]
+fn test_parse() {
+    let location = parse("Re: -1.7490930077784369461815397541469632\r\n\
+                          Im: -0.0000000003184530506209616391396127\r\n\
+                          Zoom: 2.4E22\r\n\
+                          Iterations: 20000\r\n\
+                          IterDiv: 1.000000\r\n").unwrap();
+    assert_eq!(location,
+               Location {
+                   re: "-1.7490930077784369461815397541469632".to_string(),
+                   im: "-0.0000000003184530506209616391396127".to_string(),
+                   zoom: "2.4E22".to_string(),
+                   iterations: Some(20000)
+               });
+    let config = location.config();
+    let center = config.center.unwrap();
+    assert!((center.0 - -1.749093007778437).abs() < 1e-15);
+    assert!((center.1 - -3.1845305062096164e-10).abs() < 1e-24);
+    assert_eq!(config.zoom, Some(2.4e22));
+    assert_eq!(config.iterations, Some(20000));
+
+    assert_eq!(parse("Re: 0\nIm: 0\nZoom: 1\n").unwrap().iterations, None);
+    assert!(parse("Re: 0\nZoom: 1\n").is_err());
+    assert!(parse("Re: 0\nIm: zero\nZoom: 1\n").is_err());
+}
@@ -0,0 +1,158 @@
+//! Drawing a legend onto an image, showing which colors mean which escape
+//! times.
+//!
+//! The legend is a dark box in one corner of the image, holding a strip of the
+//! palette's colors, from the first escape time that gets a color of its own to
+//! the last, with tick marks labelled with the escape times at its ends and
+//! its middle. The labels are drawn with a small built-in font, which has only
+//! the digits; on large images, everything is scaled up to stay legible.
+
+use config::Corner;
+
+/// The font's digits, zero through nine, each seven rows of five pixels, with
+/// the leftmost pixel in each row's fifth bit.
+const DIGITS: [[u8; 7]; 10] = [
+    [0x0e, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0e],
+    [0x04, 0x0c, 0x04, 0x04, 0x04, 0x04, 0x0e],
+    [0x0e, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1f],
+    [0x1f, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0e],
+    [0x02, 0x06, 0x0a, 0x12, 0x1f, 0x02, 0x02],
+    [0x1f, 0x10, 0x1e, 0x01, 0x01, 0x11, 0x0e],
+    [0x06, 0x08, 0x10, 0x1e, 0x11, 0x11, 0x0e],
+    [0x1f, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+    [0x0e, 0x11, 0x11, 0x0e, 0x11, 0x11, 0x0e],
+    [0x0e, 0x11, 0x11, 0x0f, 0x01, 0x02, 0x0c]
+];
+
+/// The dimensions of the legend's parts, in unscaled pixels: the space
+/// between the box and the image's edges, and between the box's edges and its
+/// contents; the greatest length of the strip, and its height; the tick marks'
+/// length; and the height of a digit, and the width it takes up, with the
+/// space after it.
+const MARGIN: usize = 8;
+const PADDING: usize = 4;
+const STRIP_LENGTH: usize = 256;
+const STRIP_HEIGHT: usize = 10;
+const TICK_LENGTH: usize = 3;
+const DIGIT_HEIGHT: usize = 7;
+const DIGIT_WIDTH: usize = 6;
+
+/// Draw a legend in `corner` of `image`, whose dimensions are given by
+/// `bounds`, for the escape times `range.0` through `range.1`. Call
+/// `shade` to find the colors of a list of escape times. The legend's text is
+/// drawn in `white`, the value of a channel at full brightness.
+///
+/// Return the upper left corner and size of the area the legend covers, or
+/// `None` if the image is too small to hold one.
+pub fn draw<F>(image: &mut [[u16; 3]], bounds: (usize, usize), corner: Corner,
+               range: (u32, u32), white: u16, shade: F)
+    -> Option<((usize, usize), (usize, usize))>
+    where F: Fn(&[u32]) -> Vec<[u16; 3]>
+{
+    let scale = (bounds.0.min(bounds.1) / 1000).max(1);
+    let room = bounds.0.saturating_sub(2 * (MARGIN + PADDING) * scale) / scale;
+    let length = STRIP_LENGTH.min(room);
+    let size = ((length + 2 * PADDING) * scale,
+                (PADDING + STRIP_HEIGHT + TICK_LENGTH + 2 + DIGIT_HEIGHT + PADDING) * scale);
+    // The strip must have room for the longest label, ten digits.
+    if length < 10 * DIGIT_WIDTH || size.1 + 2 * MARGIN * scale > bounds.1 {
+        return None;
+    }
+    let left = match corner {
+        Corner::TopLeft | Corner::BottomLeft => MARGIN * scale,
+        Corner::TopRight | Corner::BottomRight => bounds.0 - MARGIN * scale - size.0
+    };
+    let top = match corner {
+        Corner::TopLeft | Corner::TopRight => MARGIN * scale,
+        Corner::BottomLeft | Corner::BottomRight => bounds.1 - MARGIN * scale - size.1
+    };
+
+    // Work in unscaled pixels within the box, and draw each as a block.
+    let mut put = |x: usize, y: usize, color: [u16; 3]| {
+        for row in 0..scale {
+            let start = (top + y * scale + row) * bounds.0 + left + x * scale;
+            for pixel in &mut image[start..start + scale] {
+                *pixel = color;
+            }
+        }
+    };
+    for y in 0..size.1 / scale {
+        for x in 0..size.0 / scale {
+            put(x, y, [0, 0, 0]);
+        }
+    }
+
+    let (first, last) = range;
+    let counts: Vec<u32> = (0..length).map(|x| {
+        first + ((last - first) as u64 * x as u64 / (length - 1) as u64) as u32
+    }).collect();
+    let colors = shade(&counts);
+    for (x, &color) in colors.iter().enumerate() {
+        for y in 0..STRIP_HEIGHT {
+            put(PADDING + x, PADDING + y, color);
+        }
+    }
+
+    let white = [white; 3];
+    let ticks = [(0, first), (length / 2, counts[length / 2]), (length - 1, last)];
+    for &(x, count) in &ticks {
+        for y in 0..TICK_LENGTH {
+            put(PADDING + x, PADDING + STRIP_HEIGHT + y, white);
+        }
+
+        // Center the label under its tick, but keep it inside the box.
+        let label = count.to_string();
+        let width = label.len() * DIGIT_WIDTH - 1;
+        let start = (PADDING + x).saturating_sub(width / 2).max(PADDING)
+            .min(PADDING + length - width);
+        let baseline = PADDING + STRIP_HEIGHT + TICK_LENGTH + 2;
+        for (i, digit) in label.bytes().enumerate() {
+            let glyph = &DIGITS[(digit - b'0') as usize];
+            for (y, &row) in glyph.iter().enumerate() {
+                for column in 0..5 {
+                    if row & (0x10 >> column) != 0 {
+                        put(start + i * DIGIT_WIDTH + column, baseline + y, white);
+                    }
+                }
+            }
+        }
+    }
+
+    Some(((left, top), size))
+}
+
+#[test]
+fn test_draw() {
+    let gray = |counts: &[u32]| -> Vec<[u16; 3]> {
+        counts.iter().map(|&count| [count as u16; 3]).collect()
+    };
+
+    // Too small to hold a legend.
+    let mut image = vec![[7; 3]; 40 * 40];
+    assert_eq!(draw(&mut image, (40, 40), Corner::BottomRight, (0, 99), 255, gray), None);
+    assert!(image.iter().all(|&pixel| pixel == [7; 3]));
+
+    let bounds = (400, 100);
+    let mut image = vec![[7; 3]; bounds.0 * bounds.1];
+    assert_eq!(draw(&mut image, bounds, Corner::BottomRight, (0, 255), 255, gray),
+               Some(((128, 62), (264, 30))));
+    let at = |image: &[[u16; 3]], x: usize, y: usize| image[y * bounds.0 + x][0];
+    assert_eq!(at(&image, 127, 80), 7);
+    assert_eq!(at(&image, 128, 80), 0);
+
+    // The strip runs through the range, and the ticks are white.
+    assert_eq!(at(&image, 132, 66), 0);
+    assert_eq!(at(&image, 132 + 255, 66), 255);
+    assert_eq!(at(&image, 132, 76), 255);
+    assert_eq!(at(&image, 132 + 128, 76), 255);
+    assert_eq!(at(&image, 132 + 127, 76), 0);
+
+    // The first label, a zero, starts at the strip's left end, with the top
+    // of the zero's bowl in its first row.
+    assert_eq!((0..5).map(|x| at(&image, 132 + x, 81)).collect::<Vec<_>>(),
+               vec![0, 255, 255, 255, 0]);
+
+    let mut image = vec![[7; 3]; 2000 * 2000];
+    assert_eq!(draw(&mut image, (2000, 2000), Corner::TopLeft, (0, 9), 255, gray),
+               Some(((16, 16), (528, 60))));
+}
@@ -0,0 +1,99 @@
+//! Markus-Lyapunov fractals.
+//!
+//! Each pixel stands for a pair of growth rates `a` and `b`, taken from its
+//! point's real and imaginary parts. We iterate the logistic map `x = r x (1 -
+//! x)`, choosing `r` at each step from `a` and `b` by cycling through a sequence
+//! of letters like `AB` or `AABAB`, and measure the Lyapunov exponent of the
+//! result: the average rate at which nearby orbits pull apart. Where it is
+//! negative, the iteration settles into a stable cycle, and we draw the pixel
+//! in shades of gold; where it is positive, the iteration is chaotic, and we
+//! draw the pixel in shades of blue.
+
+/// How many steps to take before we start measuring, so that the orbit has
+/// had a chance to settle from its starting point.
+const WARMUP: u32 = 50;
+
+/// The point from which every orbit starts.
+const START: f64 = 0.5;
+
+/// Parse `s` as a sequence of the letters `A` and `B`, returning `false` for
+/// each `A` and `true` for each `B`.
+pub fn parse_sequence(s: &str) -> Result<Vec<bool>, String> {
+    let sequence: Vec<bool> = try!(s.trim().chars().map(|ch| {
+        match ch {
+            'A' | 'a' => Ok(false),
+            'B' | 'b' => Ok(true),
+            _ => Err(format!("expected only the letters A and B, not '{}'", ch))
+        }
+    }).collect());
+    if sequence.is_empty() {
+        return Err("the sequence must not be empty".to_string());
+    }
+    Ok(sequence)
+}
+
+/// Return the Lyapunov exponent of the logistic map with rates `a` and `b`,
+/// chosen according to `sequence`, measured over `iterations` steps.
+pub fn exponent(sequence: &[bool], a: f64, b: f64, iterations: u32) -> f64 {
+    let mut rates = sequence.iter().map(|&is_b| if is_b { b } else { a }).cycle();
+    let mut x = START;
+    for _ in 0..WARMUP {
+        x = rates.next().unwrap() * x * (1.0 - x);
+    }
+
+    let mut sum = 0.0;
+    for _ in 0..iterations {
+        let r = rates.next().unwrap();
+        x = r * x * (1.0 - x);
+        // The derivative of the map at x is r (1 - 2x).
+        sum += (r * (1.0 - 2.0 * x)).abs().ln();
+    }
+    sum / iterations as f64
+}
+
+/// Convert a buffer of Lyapunov exponents, as returned by `exponent`, to RGB
+/// pixels. Stable regions are gold, brighter the more negative the exponent;
+/// chaotic regions are blue, brighter the more positive. An exponent of zero,
+/// on the border between them, is black.
+pub fn colorize(exponents: &[f64]) -> Vec<u8> {
+    let mut pixels = Vec::with_capacity(exponents.len() * 3);
+    for &lambda in exponents {
+        // Map the exponent's magnitude onto [0, 1), saturating gently.
+        let t = 1.0 - (-lambda.abs()).exp();
+        let shade = |scale: f64| (t * scale).round() as u8;
+        if lambda < 0.0 {
+            pixels.extend_from_slice(&[shade(255.0), shade(200.0), shade(0.0)]);
+        } else {
+            pixels.extend_from_slice(&[shade(0.0), shade(60.0), shade(255.0)]);
+        }
+    }
+    pixels
+}
+
+#[test]
+fn test_parse_sequence() {
+    assert_eq!(parse_sequence("AB"), Ok(vec![false, true]));
+    assert_eq!(parse_sequence("aabab"), Ok(vec![false, false, true, false, true]));
+    assert!(parse_sequence("").is_err());
+    assert!(parse_sequence("ABC").is_err());
+}
+
+#[test]
+fn test_exponent() {
+    // With r = 2, the orbit settles on the fixed point 1/2, where the
+    // derivative is zero, so nearby orbits converge as fast as can be.
+    assert_eq!(exponent(&[false], 2.0, 4.0, 100), ::std::f64::NEG_INFINITY);
+
+    // With r = 3.9, the map is chaotic, with an exponent of about 0.49.
+    let chaotic = exponent(&[true], 2.0, 3.9, 100_000);
+    assert!((chaotic - 0.49).abs() < 0.05);
+
+    // With r = 3.2, the orbit settles into a stable two-cycle.
+    assert!(exponent(&[false, true], 3.2, 3.2, 1000) < 0.0);
+}
+
+#[test]
+fn test_colorize() {
+    assert_eq!(colorize(&[0.0, ::std::f64::NEG_INFINITY, ::std::f64::INFINITY]),
+               vec![0, 0, 0, 255, 200, 0, 0, 60, 255]);
+}
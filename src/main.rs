@@ -2,67 +2,377 @@
 
 use std::str::FromStr;
 
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate toml;
+
+extern crate serde_json;
+
+mod apng;
+mod average;
+mod avif;
+mod batch;
+mod bmp;
+mod bookmarks;
+mod buddhabrot;
+mod bytes;
+mod chunks;
+mod coloring;
+mod config;
+mod csv;
+mod dzi;
+mod error;
+mod exr;
+mod expr;
+mod farbfeld;
+mod ffmpeg;
+mod gif;
+mod gradient;
+mod hdr;
+mod icc;
+mod inverse;
+mod kfr;
+mod legend;
+mod lyapunov;
+mod newton;
+mod npy;
+mod par;
+mod pnm;
+mod presets;
+mod qoi;
+mod quaternion;
+mod random;
+mod raw;
+mod samples;
+mod script;
+mod share;
+mod tiff;
+mod trap;
+mod ugr;
+mod upr;
+mod view;
+mod webp;
+mod xyz;
+
+use error::Error;
+use std::path::Path;
+use view::{Region, Transform, View, interpolate_view};
+
+/// Remove surrounding whitespace from `s`, and then a pair of enclosing
+/// parentheses, if present, and any whitespace they enclose.
+fn strip_parens(s: &str) -> Result<&str, String> {
+    let s = s.trim();
+    match (s.starts_with('('), s.ends_with(')')) {
+        (true, true) if s.len() >= 2 => Ok(s[1..s.len() - 1].trim()),
+        (false, false) => Ok(s),
+        (true, _) => Err("missing closing parenthesis".to_string()),
+        (false, true) => Err("missing opening parenthesis".to_string())
+    }
+}
+
+/// Parse the string `s` as a single number of type `T`, explaining the problem
+/// if it isn't one.
+fn parse_number<T: FromStr>(s: &str) -> Result<T, String> {
+    if s.is_empty() {
+        return Err("missing number".to_string());
+    }
+    T::from_str(s).map_err(|_| format!("'{}' is not a valid number", s))
+}
+
 /// Parse the string `s` as a coordinate pair, like `"400x600"` or `"1.0,0.5"`.
 ///
 /// Specifically, `s` should have the form <left><sep><right>, where <sep> is
 /// the character given by the `separator` argument, and <left> and <right> are both
-/// strings that can be parsed by `T::from_str`.
+/// strings that can be parsed by `T::from_str`. The pair may be enclosed in
+/// parentheses, and whitespace is allowed around any of the parts, so
+/// `"(-7.4e-1, 1.3e-1)"` is fine too.
 ///
-/// If `s` has the proper form, return `Some<(x, y)>`. If it doesn't parse
-/// correctly, return `None`.
-fn parse_pair<T: FromStr>(s: &str, separator: char) -> Option<(T, T)> {
+/// If `s` has the proper form, return `Ok((x, y))`. If it doesn't parse
+/// correctly, return an error message explaining why not.
+fn parse_pair<T: FromStr>(s: &str, separator: char) -> Result<(T, T), String> {
+    let s = try!(strip_parens(s));
     match s.find(separator) {
-        None => None,
+        None => Err(format!("expected two numbers separated by '{}'", separator)),
         Some(index) => {
-            match (T::from_str(&s[..index]), T::from_str(&s[index + 1..])) {
-                (Ok(l), Ok(r)) => Some((l, r)),
-                _ => None
-            }
+            let left = try!(parse_number(s[..index].trim()));
+            let right = try!(parse_number(s[index + 1..].trim()));
+            Ok((left, right))
         }
     }
 }
 
 #[test]
 fn test_parse_pair() {
-    assert_eq!(parse_pair::<i32>("",        ','), None);
-    assert_eq!(parse_pair::<i32>("10,",     ','), None);
-    assert_eq!(parse_pair::<i32>(",10",     ','), None);
-    assert_eq!(parse_pair::<i32>("10,20",   ','), Some((10, 20)));
-    assert_eq!(parse_pair::<i32>("10,20xy", ','), None);
-    assert_eq!(parse_pair::<f64>("0.5x",    'x'), None);
-    assert_eq!(parse_pair::<f64>("0.5x1.5", 'x'), Some((0.5, 1.5)));
+    assert!(parse_pair::<i32>("",        ',').is_err());
+    assert!(parse_pair::<i32>("10,",     ',').is_err());
+    assert!(parse_pair::<i32>(",10",     ',').is_err());
+    assert_eq!(parse_pair::<i32>("10,20",   ','), Ok((10, 20)));
+    assert!(parse_pair::<i32>("10,20xy", ',').is_err());
+    assert!(parse_pair::<f64>("0.5x",    'x').is_err());
+    assert_eq!(parse_pair::<f64>("0.5x1.5", 'x'), Ok((0.5, 1.5)));
+
+    assert_eq!(parse_pair::<f64>("(-7.4e-1, 1.3e-1)", ','), Ok((-0.74, 0.13)));
+    assert_eq!(parse_pair::<f64>("  ( 1 ,2 )  ",      ','), Ok((1.0, 2.0)));
+    assert_eq!(parse_pair::<f64>(" 1.5 , -2 ",        ','), Ok((1.5, -2.0)));
+
+    assert_eq!(parse_pair::<f64>("(1, 2", ','),
+               Err("missing closing parenthesis".to_string()));
+    assert_eq!(parse_pair::<f64>("1 2", ','),
+               Err("expected two numbers separated by ','".to_string()));
+    assert_eq!(parse_pair::<f64>("1, two", ','),
+               Err("'two' is not a valid number".to_string()));
+    assert_eq!(parse_pair::<f64>(", 2", ','),
+               Err("missing number".to_string()));
+}
+
+/// Parse the string `s` as a complex number, returning its real and imaginary
+/// parts as a pair.
+///
+/// The number may be written either as a pair of real and imaginary parts
+/// separated by a comma, like `"-0.727,0.189"`, or in the usual mathematical
+/// notation, like `"-0.727+0.189i"`. In the latter form, either part may be
+/// omitted, as in `"0.5"` or `"-0.3i"`, and an imaginary part of `i` or `-i`
+/// means one or minus one. Either form may be enclosed in parentheses and
+/// surrounded by whitespace, as for `parse_pair`.
+///
+/// If `s` has either form, return `Ok((re, im))`. Otherwise, return an error
+/// message explaining the problem.
+fn parse_complex(s: &str) -> Result<(f64, f64), String> {
+    if s.contains(',') {
+        return parse_pair(s, ',');
+    }
+
+    let s = try!(strip_parens(s));
+    if !s.ends_with('i') {
+        return parse_number(s).map(|re| (re, 0.0));
+    }
+
+    // Split the part before the `i` at the last sign that isn't at the very
+    // start, and isn't part of an exponent, as in `1e-3`.
+    let body = &s[..s.len() - 1];
+    let bytes = body.as_bytes();
+    let split = (1..bytes.len()).rev().find(|&i| {
+        (bytes[i] == b'+' || bytes[i] == b'-') &&
+            bytes[i - 1] != b'e' && bytes[i - 1] != b'E'
+    });
+    let (re, im) = match split {
+        Some(index) => (try!(parse_number(body[..index].trim())), body[index..].trim()),
+        None => (0.0, body)
+    };
+
+    // Allow space between the sign and the number, as in `1 - 2i`.
+    let im = match im {
+        "" | "+" => 1.0,
+        "-" => -1.0,
+        im if im.starts_with('+') => try!(parse_number::<f64>(im[1..].trim())),
+        im if im.starts_with('-') => -try!(parse_number::<f64>(im[1..].trim())),
+        im => try!(parse_number(im))
+    };
+
+    Ok((re, im))
+}
+
+#[test]
+fn test_parse_complex() {
+    assert_eq!(parse_complex("-0.727,0.189"),  Ok((-0.727, 0.189)));
+    assert_eq!(parse_complex("-0.727+0.189i"), Ok((-0.727, 0.189)));
+    assert_eq!(parse_complex("0.285-0.01i"),   Ok((0.285, -0.01)));
+    assert_eq!(parse_complex("1e-3-2.5e-4i"),  Ok((1e-3, -2.5e-4)));
+    assert_eq!(parse_complex("1E+2+1E+2i"),    Ok((100.0, 100.0)));
+    assert_eq!(parse_complex("0.5"),           Ok((0.5, 0.0)));
+    assert_eq!(parse_complex("-0.3i"),         Ok((0.0, -0.3)));
+    assert_eq!(parse_complex("i"),             Ok((0.0, 1.0)));
+    assert_eq!(parse_complex("-i"),            Ok((0.0, -1.0)));
+    assert_eq!(parse_complex("2+i"),           Ok((2.0, 1.0)));
+    assert_eq!(parse_complex("2-i"),           Ok((2.0, -1.0)));
+    assert_eq!(parse_complex(" (1 - 2i) "),    Ok((1.0, -2.0)));
+    assert_eq!(parse_complex("(-7.4e-1, 1.3e-1)"), Ok((-0.74, 0.13)));
+    assert!(parse_complex("").is_err());
+    assert!(parse_complex("1+2").is_err());
+    assert!(parse_complex("1+2j").is_err());
+    assert!(parse_complex("x+2i").is_err());
+    assert!(parse_complex("1,").is_err());
+}
+
+/// Parse the string `s` as a region of the complex plane, given as its upper
+/// left and lower right corners separated by a colon, like `-1.2,0.35:-1,0.2`.
+/// Each corner may take any form `parse_complex` accepts.
+fn parse_region(s: &str) -> Result<((f64, f64), (f64, f64)), String> {
+    match s.find(':') {
+        Some(index) => Ok((try!(parse_complex(&s[..index])),
+                           try!(parse_complex(&s[index + 1..])))),
+        None => Err("expected two corner points separated by ':'".to_string())
+    }
+}
+
+#[test]
+fn test_parse_region() {
+    assert_eq!(parse_region("-1.2,0.35:-1,0.2"), Ok(((-1.2, 0.35), (-1.0, 0.2))));
+    assert_eq!(parse_region("-2+1i : 1-1i"), Ok(((-2.0, 1.0), (1.0, -1.0))));
+    assert!(parse_region("-1.2,0.35").is_err());
+    assert!(parse_region("-1.2,0.35:").is_err());
+}
+
+/// Parse the string `s` as a range along one axis of the complex plane, given
+/// as its low and high ends separated by a colon, like `-2.0:1.0`.
+fn parse_range(s: &str) -> Result<(f64, f64), String> {
+    match try!(parse_pair(s, ':')) {
+        (low, high) if low < high => Ok((low, high)),
+        _ => Err("the low end of the range must come first".to_string())
+    }
+}
+
+#[test]
+fn test_parse_range() {
+    assert_eq!(parse_range("-2.0:1.0"), Ok((-2.0, 1.0)));
+    assert_eq!(parse_range(" -1.25 : 1.25 "), Ok((-1.25, 1.25)));
+    assert!(parse_range("1.0:-2.0").is_err());
+    assert!(parse_range("1.0").is_err());
+}
+
+/// Parse the string `s` as a range of web map zoom levels, like `"0:6"`, or a
+/// single level, like `"3"`.
+fn parse_levels(s: &str) -> Result<(u32, u32), String> {
+    let (low, high) = if s.contains(':') {
+        try!(parse_pair(s, ':'))
+    } else {
+        let level = try!(parse_number(s.trim()));
+        (level, level)
+    };
+    if low > high {
+        return Err("the lowest level must come first".to_string());
+    }
+    if high > xyz::MAX_LEVEL {
+        return Err(format!("levels can go no higher than {}", xyz::MAX_LEVEL));
+    }
+    Ok((low, high))
+}
+
+#[test]
+fn test_parse_levels() {
+    assert_eq!(parse_levels("0:6"), Ok((0, 6)));
+    assert_eq!(parse_levels(" 3 "), Ok((3, 3)));
+    assert!(parse_levels("6:0").is_err());
+    assert!(parse_levels("0:31").is_err());
+    assert!(parse_levels("-1:2").is_err());
+}
+
+/// Parse the string `s` as image dimensions, like `"1000x750"`, either of which
+/// may be left unspecified, to be worked out from the aspect ratio of the area
+/// the image covers.
+///
+/// A missing dimension may be written as `_`, as in `"1000x_"` or `"_x750"`;
+/// a lone number, like `"1000"`, gives the width alone. `s` may also name a
+/// preset size, like `"1080p"` or `"a4@300dpi"`; see the `presets` module.
+/// Return an error message if `s` doesn't have one of these forms, or leaves
+/// out both dimensions.
+fn parse_size(s: &str) -> Result<(Option<usize>, Option<usize>), String> {
+    fn dimension(s: &str) -> Result<Option<usize>, String> {
+        match s.trim() {
+            "_" => Ok(None),
+            s => parse_number(s).map(Some)
+        }
+    }
+
+    if let Some((width, height)) = try!(presets::lookup(s)) {
+        return Ok((Some(width), Some(height)));
+    }
+
+    let s = s.trim();
+    let size = match s.find('x') {
+        None => (try!(dimension(s).map_err(|_| {
+            "expected WIDTHxHEIGHT, or just WIDTH".to_string()
+        })), None),
+        Some(index) => (try!(dimension(&s[..index])),
+                        try!(dimension(&s[index + 1..])))
+    };
+
+    match size {
+        (None, None) => Err("at least one dimension must be given".to_string()),
+        size => Ok(size)
+    }
+}
+
+#[test]
+fn test_parse_size() {
+    assert_eq!(parse_size("1000x750"), Ok((Some(1000), Some(750))));
+    assert_eq!(parse_size("1000x_"),   Ok((Some(1000), None)));
+    assert_eq!(parse_size("_x750"),    Ok((None, Some(750))));
+    assert_eq!(parse_size("1000"),     Ok((Some(1000), None)));
+    assert_eq!(parse_size(" 1000 x 750 "), Ok((Some(1000), Some(750))));
+    assert_eq!(parse_size("1080p"),    Ok((Some(1920), Some(1080))));
+    assert_eq!(parse_size("a4-landscape@300dpi"), Ok((Some(3508), Some(2480))));
+    assert!(parse_size("_x_").is_err());
+    assert!(parse_size("_").is_err());
+    assert!(parse_size("1000x").is_err());
+    assert!(parse_size("x750").is_err());
+    assert_eq!(parse_size("1000y750"), Err("expected WIDTHxHEIGHT, or just WIDTH".to_string()));
+    assert!(parse_size("big").is_err());
 }
 
 /// Return the point on the complex plane corresponding to a given pixel in the
 /// bitmap.
 ///
-/// `bounds` is a pair giving the width and height of the bitmap. `pixel` is a
-/// pair indicating a particular pixel in that bitmap. The `upper_left` and
-/// `lower_right` parameters are points on the complex plane designating the
-/// area our bitmap covers.
-fn pixel_to_point(bounds: (usize, usize),
-                  pixel: (usize, usize),
-                  upper_left: (f64, f64),
-                  lower_right: (f64, f64))
-    -> (f64, f64)
-{
+/// `pixel` is a (column, row) pair indicating a particular pixel in the bitmap.
+/// The `transform` parameter maps pixel coordinates to the complex plane; it
+/// may shift, scale, and rotate the bitmap to cover any rectangle at all.
+fn pixel_to_point(transform: &Transform, pixel: (usize, usize)) -> (f64, f64) {
     // It might be nicer to find the position of the *middle* of the pixel,
     // instead of its upper left corner, but this is easier to write tests for.
-    let (width, height) = (lower_right.0 - upper_left.0,
-                           upper_left.1 - lower_right.1);
-    (upper_left.0 + pixel.0 as f64 * width  / bounds.0 as f64,
-     upper_left.1 - pixel.1 as f64 * height / bounds.1 as f64)
+    let (column, row) = (pixel.0 as f64, pixel.1 as f64);
+    (transform.origin.0 + column * transform.column.0 + row * transform.row.0,
+     transform.origin.1 + column * transform.column.1 + row * transform.row.1)
 }
 
 #[test]
 fn test_pixel_to_point() {
-    assert_eq!(pixel_to_point((100, 100), (25, 75),
-                              (-1.0, 1.0), (1.0, -1.0)),
-               (-0.5, -0.5));
+    let view = View { bounds: (100, 100),
+                      upper_left: (-1.0, 1.0), lower_right: (1.0, -1.0),
+                      rotation: 0.0 };
+    assert_eq!(pixel_to_point(&view.transform(), (25, 75)), (-0.5, -0.5));
+}
+
+/// Return the pixel in a bitmap of size `bounds` that contains `point`, or
+/// `None` if the point lies outside the bitmap. This is the inverse of
+/// `pixel_to_point`.
+fn point_to_pixel(transform: &Transform, bounds: (usize, usize), point: (f64, f64))
+    -> Option<(usize, usize)>
+{
+    // Solve `point = origin + column * transform.column + row * transform.row`
+    // for `column` and `row`.
+    let (dx, dy) = (point.0 - transform.origin.0, point.1 - transform.origin.1);
+    let (a, b) = transform.column;
+    let (c, d) = transform.row;
+    let determinant = a * d - b * c;
+    let column = ((dx * d - dy * c) / determinant).floor();
+    let row = ((a * dy - b * dx) / determinant).floor();
+    if column < 0.0 || row < 0.0 || column >= bounds.0 as f64 || row >= bounds.1 as f64 {
+        return None;
+    }
+    Some((column as usize, row as usize))
+}
+
+#[test]
+fn test_point_to_pixel() {
+    let view = View { bounds: (100, 100),
+                      upper_left: (-1.0, 1.0), lower_right: (1.0, -1.0),
+                      rotation: 0.0 };
+    let transform = view.transform();
+    assert_eq!(point_to_pixel(&transform, view.bounds, (-0.49, -0.49)), Some((25, 74)));
+    assert_eq!(point_to_pixel(&transform, view.bounds, (-0.99, 0.99)), Some((0, 0)));
+    assert_eq!(point_to_pixel(&transform, view.bounds, (1.5, 0.0)), None);
+
+    let rotated = View { rotation: 90.0, .. view };
+    assert_eq!(point_to_pixel(&rotated.transform(), view.bounds, (-0.99, -0.99)),
+               Some((0, 0)));
 }
 
 extern crate num;
 use num::Complex;
+use coloring::Sample;
+use quaternion::Quaternion;
+use trap::Trap;
 
 /// Try to determine whether the complex number `c` is in the Mandelbrot set.
 ///
@@ -76,116 +386,4449 @@ use num::Complex;
 ///
 /// If the number does leave the circle before we give up, return `Some(i)`, where
 /// `i` is the number of iterations it took.
-fn escapes(c: Complex<f64>, limit: u32) -> Option<u32> {
-    let mut z = Complex { re: 0.0, im: 0.0 };
+///
+/// More generally, `formula` says how to start the orbit for `point`, and how
+/// to carry out each step of it; see `FractalFormula`. Some formulas have
+/// points that settle on a fixed point, rather than escaping or orbiting; we
+/// return `None` for those as soon as they arrive.
+fn escapes<F: FractalFormula>(formula: &F, point: Complex<f64>, limit: u32) -> Option<u32> {
+    let mut state = formula.init(point);
     for i in 0..limit {
-        z = z*z + c;
-        if z.norm_sqr() > 4.0 {
-            return Some(i);
+        match formula.step(&mut state) {
+            Escape::Orbiting => (),
+            Escape::Escaped => return Some(i),
+            Escape::Settled => return None
         }
     }
 
-    return None;
+    None
+}
+
+/// What became of an orbit after a step.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Escape {
+    /// The orbit is still inside the escape circle.
+    Orbiting,
+
+    /// The orbit has left the circle, and will fly off to infinity.
+    Escaped,
+
+    /// The orbit has arrived at a fixed point, and will never leave.
+    Settled
 }
 
-/// Render a rectangle of the Mandelbrot set into a buffer of pixels.
+/// An escape-time iteration: how to start an orbit for each point of the
+/// plane, and how to carry it forward, one step at a time.
 ///
-/// The `bounds` argument gives the width and height of the buffer `pixels`,
-/// which holds one grayscale pixel per byte. The `upper_left` and `lower_right`
-/// arguments specify points on the complex plane corresponding to the upper
-/// left and lower right corners of the pixel buffer.
-fn render(pixels: &mut [u8], bounds: (usize, usize),
-          upper_left: (f64, f64), lower_right: (f64, f64))
+/// This is all `escapes` and the other functions that follow orbits need to
+/// know about the formula being iterated; the built-in formulas, hybrids, and
+/// formula expressions are all drawn by `Iteration`.
+trait FractalFormula: Sync {
+    /// Everything we need to know about an orbit in progress.
+    type State;
+
+    /// Return the state at the start of the orbit for `point`.
+    fn init(&self, point: Complex<f64>) -> Self::State;
+
+    /// Carry `state` forward one step, and say whether the orbit has escaped.
+    fn step(&self, state: &mut Self::State) -> Escape;
+
+    /// Return the orbit's current point.
+    fn position(&self, state: &Self::State) -> Complex<f64>;
+
+    /// Return the constant the orbit is iterating with, its `c`.
+    fn constant(&self, state: &Self::State) -> Complex<f64>;
+}
+
+/// The iterations of the formulas we know, alone or as hybrids.
+#[derive(Clone, Copy, Debug)]
+struct Iteration<'a> {
+    /// Which set to draw, and so how to start each orbit.
+    set: Set,
+
+    /// How to carry out each step. We cycle through these, using
+    /// `steps[i % steps.len()]` for the `i`'th iteration; a single step gives
+    /// the usual iteration, and several give a hybrid formula.
+    steps: &'a [Step<'a>],
+
+    /// The radius of the escape circle. Any radius of 2 or more gives the same
+    /// set, but larger radii let the orbit settle down before we stop
+    /// following it, which some coloring methods need.
+    radius: f64
+}
+
+/// The state of an orbit being followed by an `Iteration`.
+#[derive(Clone, Copy, Debug)]
+struct Orbit {
+    z: Complex<f64>,
+    previous: Complex<f64>,
+    c: Complex<f64>,
+
+    /// The number of steps taken so far.
+    count: usize
+}
+
+impl<'a> FractalFormula for Iteration<'a> {
+    type State = Orbit;
+
+    fn init(&self, point: Complex<f64>) -> Orbit {
+        let (z, c) = self.set.start(point);
+        // The Manowar's orbits begin as if they had been at their starting
+        // value the step before, too; the Phoenix's, as if they had been at 0.
+        let previous = match self.steps[0].formula {
+            Formula::Manowar => z,
+            _ => Complex { re: 0.0, im: 0.0 }
+        };
+        Orbit { z: z, previous: previous, c: c, count: 0 }
+    }
+
+    fn step(&self, orbit: &mut Orbit) -> Escape {
+        let step = self.steps[orbit.count % self.steps.len()];
+        step.advance(orbit);
+        step.status(orbit.z, orbit.previous, self.radius)
+    }
+
+    fn position(&self, orbit: &Orbit) -> Complex<f64> {
+        orbit.z
+    }
+
+    fn constant(&self, orbit: &Orbit) -> Complex<f64> {
+        orbit.c
+    }
+}
+
+/// A quaternion Julia set, iterating `q = q^2 + c` over the quaternions, and
+/// sliced by a plane through four-dimensional space.
+#[derive(Clone, Copy, Debug)]
+struct QuatJulia {
+    c: Quaternion,
+
+    /// The `j` and `k` parts shared by every point of the slice. The point
+    /// `x + yi` of the image stands for the quaternion `x + yi + kj + lk`,
+    /// where `(k, l)` is the slice.
+    slice: (f64, f64),
+
+    /// The radius of the escape sphere.
+    radius: f64
+}
+
+impl FractalFormula for QuatJulia {
+    type State = Quaternion;
+
+    fn init(&self, point: Complex<f64>) -> Quaternion {
+        Quaternion { r: point.re, i: point.im, j: self.slice.0, k: self.slice.1 }
+    }
+
+    fn step(&self, q: &mut Quaternion) -> Escape {
+        *q = *q * *q + self.c;
+        if q.norm_sqr() > self.radius * self.radius {
+            Escape::Escaped
+        } else {
+            Escape::Orbiting
+        }
+    }
+
+    fn position(&self, q: &Quaternion) -> Complex<f64> {
+        Complex { re: q.r, im: q.i }
+    }
+
+    fn constant(&self, _: &Quaternion) -> Complex<f64> {
+        Complex { re: self.c.r, im: self.c.i }
+    }
+}
+
+#[test]
+fn test_quat_julia() {
+    let origin = Complex { re: 0.0, im: 0.0 };
+    let c = Quaternion { r: -1.0, i: 0.0, j: 0.0, k: 0.0 };
+    let flat = QuatJulia { c: c, slice: (0.0, 0.0), radius: 2.0 };
+
+    // In the slice through the complex plane, we get the complex Julia set.
+    let square = Step { formula: Formula::Standard, power: Power::Integer(2), p: origin,
+                        relaxation: origin, morph: None, expression: None };
+    let iteration = Iteration { set: Set::Julia(Complex { re: -1.0, im: 0.0 }),
+                                steps: &[square], radius: 2.0 };
+    for &(re, im) in &[(0.0, 0.0), (0.5, 0.5), (1.5, 0.0), (0.1, 0.7)] {
+        let point = Complex { re: re, im: im };
+        assert_eq!(escapes(&flat, point, 100), escapes(&iteration, point, 100));
+    }
+
+    // Away from it, orbits start off in the j direction. With c = -1, the
+    // orbit of 0 + 1j goes to -2 and then 3, escaping on the second step,
+    // though 0 never escapes; 0 + 2j goes to -5, escaping at once.
+    let sliced = QuatJulia { slice: (1.0, 0.0), .. flat };
+    assert_eq!(escapes(&sliced, origin, 100), Some(1));
+    let sliced = QuatJulia { slice: (2.0, 0.0), .. flat };
+    assert_eq!(escapes(&sliced, origin, 100), Some(0));
+}
+
+#[test]
+fn test_escapes() {
+    // Follow the orbit of `z` for `c`.
+    fn escapes_from(z: Complex<f64>, c: Complex<f64>, steps: &[Step], limit: u32, radius: f64)
+        -> Option<u32>
+    {
+        escapes(&Iteration { set: Set::Mandelbrot(z), steps: steps, radius: radius }, c, limit)
+    }
+
+    // The orbit of 1 is 1, 2, 5, 26, ...
+    let zero = Complex { re: 0.0, im: 0.0 };
+    let one = Complex { re: 1.0, im: 0.0 };
+    let square = Step { formula: Formula::Standard, power: Power::Integer(2), p: zero,
+                         relaxation: one, morph: None, expression: None };
+    assert_eq!(escapes_from(zero, one, &[square], 10, 2.0), Some(2));
+    assert_eq!(escapes_from(zero, one, &[square], 10, 10.0), Some(3));
+    assert_eq!(escapes_from(zero, one, &[square], 2, 2.0), None);
+    assert_eq!(escapes_from(zero, -one, &[square], 1000, 2.0), None);
+
+    // Starting from 1 skips the first step of the orbit.
+    assert_eq!(escapes_from(one, one, &[square], 10, 2.0), Some(1));
+
+    // Cubing, the orbit of -1 is -1, -2, -9, ...
+    let cube = Step { power: Power::Integer(3), .. square };
+    assert_eq!(escapes_from(zero, -one, &[cube], 1000, 2.0), Some(2));
+    let cube = Step { power: Power::Real(3.0), .. square };
+    assert_eq!(escapes_from(zero, -one, &[cube], 1000, 2.0), Some(2));
+
+    // With p = 1, the Phoenix orbit of 0 for c = 1 is 1, 2, 6, ...
+    let phoenix = Step { formula: Formula::Phoenix, p: one, .. square };
+    assert_eq!(escapes_from(zero, one, &[phoenix], 10, 5.0), Some(2));
+
+    // Magnet orbits may escape, or settle at 1.
+    let magnet = Step { formula: Formula::Magnet1, .. square };
+    assert_eq!(escapes_from(zero, Complex { re: 2.5, im: 0.0 }, &[magnet], 100, 100.0), Some(3));
+    assert_eq!(escapes_from(zero, Complex { re: -2.0, im: 0.0 }, &[magnet], 100, 100.0), None);
+
+    // Alternating with the Burning Ship changes the orbit of -1-0.3i, which
+    // escapes after 34 steps on its own and never under the Burning Ship alone.
+    let ship = Step { formula: Formula::BurningShip, .. square };
+    let c = Complex { re: -1.0, im: -0.3 };
+    assert_eq!(escapes_from(zero, c, &[square], 100, 2.0), Some(34));
+    assert_eq!(escapes_from(zero, c, &[ship], 100, 2.0), None);
+    assert_eq!(escapes_from(zero, c, &[square, ship], 100, 2.0), Some(41));
+    assert_eq!(escapes_from(zero, c, &[ship, square], 100, 2.0), None);
+}
+
+/// Follow the orbit of `point` under `formula`, as `escapes` does, and return
+/// the same result. Leave in `orbit` each point the orbit visited inside the
+/// circle, in order.
+fn trace<F: FractalFormula>(formula: &F, point: Complex<f64>, limit: u32,
+                            orbit: &mut Vec<Complex<f64>>)
+    -> Option<u32>
 {
-    assert!(pixels.len() == bounds.0 * bounds.1);
+    orbit.clear();
+    let mut state = formula.init(point);
+    for i in 0..limit {
+        match formula.step(&mut state) {
+            Escape::Orbiting => orbit.push(formula.position(&state)),
+            Escape::Escaped => return Some(i),
+            Escape::Settled => return None
+        }
+    }
 
-    for r in 0 .. bounds.1 {
-        for c in 0 .. bounds.0 {
-            let point = pixel_to_point(bounds, (c, r),
-                                       upper_left, lower_right);
-            pixels[r * bounds.0 + c] =
-                match escapes(Complex { re: point.0, im: point.1 }, 255) {
-                    None => 0,
-                    Some(count) => 255 - count as u8
-                };
+    None
+}
+
+#[test]
+fn test_fractal_formula() {
+    // A formula that just doubles its starting point until it passes 100, or
+    // settles if it starts at zero.
+    struct Doubling;
+    impl FractalFormula for Doubling {
+        type State = Complex<f64>;
+        fn init(&self, point: Complex<f64>) -> Complex<f64> { point }
+        fn step(&self, z: &mut Complex<f64>) -> Escape {
+            *z = *z + *z;
+            if z.norm_sqr() > 100.0 * 100.0 {
+                Escape::Escaped
+            } else if z.norm_sqr() == 0.0 {
+                Escape::Settled
+            } else {
+                Escape::Orbiting
+            }
         }
+        fn position(&self, z: &Complex<f64>) -> Complex<f64> { *z }
+        fn constant(&self, _: &Complex<f64>) -> Complex<f64> { Complex { re: 0.0, im: 0.0 } }
     }
+
+    let zero = Complex { re: 0.0, im: 0.0 };
+    let one = Complex { re: 1.0, im: 0.0 };
+    assert_eq!(escapes(&Doubling, one, 100), Some(6));
+    assert_eq!(escapes(&Doubling, one, 6), None);
+    assert_eq!(escapes(&Doubling, zero, 100), None);
+
+    let mut orbit = Vec::new();
+    assert_eq!(trace(&Doubling, Complex { re: 0.0, im: 25.0 }, 100, &mut orbit), Some(2));
+    assert_eq!(orbit, vec![Complex { re: 0.0, im: 50.0 }, Complex { re: 0.0, im: 100.0 }]);
 }
 
-extern crate image;
+#[test]
+fn test_trace() {
+    let zero = Complex { re: 0.0, im: 0.0 };
+    let one = Complex { re: 1.0, im: 0.0 };
+    let square = Step { formula: Formula::Standard, power: Power::Integer(2), p: zero,
+                         relaxation: one, morph: None, expression: None };
+    let steps = [square];
+    let mandelbrot = Iteration { set: Set::Mandelbrot(zero), steps: &steps, radius: 2.0 };
+    let mut orbit = vec![zero; 5];
+    assert_eq!(trace(&mandelbrot, one, 10, &mut orbit), Some(2));
+    assert_eq!(orbit, vec![one, one + one]);
+    assert_eq!(trace(&mandelbrot, -one, 4, &mut orbit), None);
+    assert_eq!(orbit, vec![-one, zero, -one, zero]);
+}
 
-use std::fs::File;
-use std::io::Result;
-use image::png::PNGEncoder;
-use image::ColorType;
+/// Follow the orbit of `point` under `formula`, as `escapes` does, and return
+/// the same result. Call `visit` on each point the orbit visits after its
+/// start, including the one outside the circle, if it escapes, and the orbit's
+/// constant `c`.
+fn follow<F, V>(formula: &F, point: Complex<f64>, limit: u32, mut visit: V) -> Option<u32>
+    where F: FractalFormula, V: FnMut(Complex<f64>, Complex<f64>)
+{
+    let mut state = formula.init(point);
+    for i in 0..limit {
+        let escape = formula.step(&mut state);
+        visit(formula.position(&state), formula.constant(&state));
+        match escape {
+            Escape::Orbiting => (),
+            Escape::Escaped => return Some(i),
+            Escape::Settled => return None
+        }
+    }
+
+    None
+}
+
+/// How close an orbit must come to a point it visited before to be taken to
+/// have settled into a cycle through it.
+const PERIOD_TOLERANCE: f64 = 1e-9;
+
+/// A summary of the points an orbit visited, for coloring the points whose
+/// orbits never escape.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct OrbitStats {
+    /// The last point the orbit reached.
+    last: Complex<f64>,
+
+    /// The smallest magnitude of any point the orbit visited, and the step at
+    /// which it visited it, counting from 1: the point's atom domain.
+    closest: f64,
+    nearest: u32,
+
+    /// The sum of the points the orbit visited, and how many there were.
+    sum: Complex<f64>,
+    points: u32,
+
+    /// The point we're watching for the orbit to come back to, and how many
+    /// steps ago it visited it.
+    reference: Complex<f64>,
+    since: u32,
+
+    /// The period of the cycle the orbit settled into, or 0 if it hasn't.
+    period: u32
+}
+
+impl OrbitStats {
+    /// Return the summary of an orbit that hasn't visited any points yet.
+    fn new() -> OrbitStats {
+        let zero = Complex { re: 0.0, im: 0.0 };
+        OrbitStats {
+            last: zero,
+            closest: f64::INFINITY,
+            nearest: 0,
+            sum: zero,
+            points: 0,
+            reference: zero,
+            since: 0,
+            period: 0
+        }
+    }
+
+    /// Add `z` to the points the orbit has visited.
+    ///
+    /// To find the orbit's period, we use Brent's cycle detection: we remember
+    /// the points visited at steps 1, 2, 4, 8, and so on, and watch for the
+    /// orbit to return to the latest of them. Once the orbit has settled and
+    /// we're remembering a point on its cycle, the number of steps it takes to
+    /// come back is the period, as long as a whole period fits before the next
+    /// power of two.
+    fn visit(&mut self, z: Complex<f64>) {
+        self.last = z;
+        self.sum = self.sum + z;
+        self.points += 1;
+        if z.norm() < self.closest {
+            self.closest = z.norm();
+            self.nearest = self.points;
+        }
+        if self.period == 0 {
+            self.since += 1;
+            if self.points > 1 && (z - self.reference).norm() < PERIOD_TOLERANCE {
+                self.period = self.since;
+            } else if self.points.is_power_of_two() {
+                self.reference = z;
+                self.since = 0;
+            }
+        }
+    }
+
+    /// Return the property of the orbit that `interior` asks for, as a
+    /// fraction of `radius`, no greater than 1.
+    fn measure(&self, interior: Interior, radius: f64) -> f64 {
+        let value = match interior {
+            Interior::Black | Interior::Period | Interior::Transparent => 0.0,
+            Interior::Magnitude => self.last.norm(),
+            Interior::Closest => self.closest,
+            Interior::Average if self.points == 0 => self.last.norm(),
+            Interior::Average => {
+                let points = self.points as f64;
+                Complex { re: self.sum.re / points, im: self.sum.im / points }.norm()
+            }
+        };
+        (value / radius).min(1.0)
+    }
+}
 
-/// Write the buffer `pixels`, whose dimensions are given by `bounds`, to the
-/// file named `filename`.
-fn write_bitmap(filename: &str, pixels: &[u8], bounds: (usize, usize))
-    -> Result<()>
+/// Follow the orbit of `point` under `iteration`, as `escapes` does, and if it
+/// escapes, return the number of iterations it took, the point it escaped
+/// to, and the derivative of that point with respect to `point`. Return
+/// `None` if the orbit never escapes.
+///
+/// Alongside the orbit, we carry its derivative `dz`: for the Mandelbrot set,
+/// where `point` is `c`, each step takes it to `d z^(d-1) dz + 1`; for a Julia
+/// set, where `point` is where the orbit starts, to `d z^(d-1) dz`.
+///
+/// This only makes sense for iterations of the standard formula; see
+/// `check_distance`.
+fn follow_derivative(iteration: &Iteration, point: Complex<f64>, limit: u32)
+    -> Option<(u32, Complex<f64>, Complex<f64>)>
 {
-    let output = try!(File::create(filename));
+    match follow_to_end(iteration, point, limit) {
+        (Some(count), z, dz) => Some((count, z, dz)),
+        (None, _, _) => None
+    }
+}
 
-    let encoder = PNGEncoder::new(output);
-    try!(encoder.encode(&pixels[..],
-                        bounds.0 as u32, bounds.1 as u32,
-                        ColorType::Gray(8)));
+/// Follow the orbit of `point` under `iteration` as `follow_derivative` does,
+/// but return the last point the orbit reached and its derivative whether it
+/// escapes or not, along with the number of iterations it took to escape, if
+/// it did.
+fn follow_to_end(iteration: &Iteration, point: Complex<f64>, limit: u32)
+    -> (Option<u32>, Complex<f64>, Complex<f64>)
+{
+    let zero = Complex { re: 0.0, im: 0.0 };
+    let one = Complex { re: 1.0, im: 0.0 };
+    let (mut dz, dc) = match iteration.set {
+        Set::Mandelbrot(_) => (zero, one),
+        Set::Julia(_) => (one, zero)
+    };
+    let mut orbit = iteration.init(point);
+    for i in 0..limit {
+        let step = iteration.steps[orbit.count % iteration.steps.len()];
+        dz = step.power.derivative(orbit.z) * dz + dc;
+        match iteration.step(&mut orbit) {
+            Escape::Orbiting => (),
+            Escape::Escaped => return (Some(i), orbit.z, dz),
+            Escape::Settled => break
+        }
+    }
 
-    Ok(())
+    (None, orbit.z, dz)
 }
 
-extern crate crossbeam;
-extern crate atomic_chunks_mut;
+/// Follow the orbit of `point` under `iteration`, and if it escapes, return an
+/// estimate of the distance from `point` to the set's boundary. Once the orbit
+/// escapes to `z`, `|z| ln |z| / 2 |dz|` estimates the distance, within a
+/// small factor either way.
+fn estimate_distance(iteration: &Iteration, point: Complex<f64>, limit: u32) -> Option<f64> {
+    follow_derivative(iteration, point, limit).map(|(_, z, dz)| {
+        let magnitude = z.norm();
+        magnitude * magnitude.ln() / (2.0 * dz.norm())
+    })
+}
 
-use atomic_chunks_mut::AtomicChunksMut;
+/// How high above the plane the light for `--light-angle` shines from, in
+/// the units of the surface's normals.
+const LIGHT_HEIGHT: f64 = 1.5;
 
-use std::io::Write;
+/// Return how brightly a light shining from the direction of the unit vector
+/// `light` lights the point whose orbit escaped to `z` with derivative `dz`,
+/// from 0 to 1.
+///
+/// We treat the set's potential as the height of a surface over the plane;
+/// `z / dz` points straight downhill, away from the set, and so gives the
+/// direction the surface faces. The light comes from `light`, raised
+/// `LIGHT_HEIGHT` above the plane, and the surface is as bright as it faces
+/// toward it. Since the light is raised well above the plane, no part of the
+/// surface is left entirely dark.
+fn brightness(z: Complex<f64>, dz: Complex<f64>, light: Complex<f64>) -> f64 {
+    let normal = downhill(z, dz);
+    let facing = normal.re * light.re + normal.im * light.im;
+    (facing + LIGHT_HEIGHT) / (1.0 + LIGHT_HEIGHT)
+}
 
-fn main() {
-    let args : Vec<String> = std::env::args().collect();
+/// Return the unit vector pointing downhill, away from the set, at the point
+/// whose orbit escaped to `z` with derivative `dz`.
+fn downhill(z: Complex<f64>, dz: Complex<f64>) -> Complex<f64> {
+    let direction = z / dz;
+    direction.unscale(direction.norm())
+}
 
-    if args.len() != 5 {
-        writeln!(std::io::stderr(),
-                 "Usage: mandelbrot FILE PIXELS UPPERLEFT LOWERRIGHT")
-            .unwrap();
-        writeln!(std::io::stderr(),
-                 "Example: {} mandel.png 1000x750 -1.20,0.35 -1,0.20",
-                 args[0])
-            .unwrap();
-        std::process::exit(1);
+/// Return the color encoding the surface normal at the point whose orbit
+/// escaped to `z` with derivative `dz`, for a normal map, in an image turned
+/// `degrees` counterclockwise from the complex plane.
+///
+/// We take the surface to slope away from the set at 45 degrees everywhere,
+/// so the normal leans as far downhill as it points up. As usual for normal
+/// maps, red, green, and blue encode the normal's rightward, upward, and
+/// outward components, each mapped from -1 to 1 onto 1 to 255, with 128 for
+/// zero.
+fn normal_color(z: Complex<f64>, dz: Complex<f64>, degrees: f64) -> [u8; 3] {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let downhill = downhill(z, dz);
+    let x = downhill.re * cos + downhill.im * sin;
+    let y = downhill.im * cos - downhill.re * sin;
+    let encode = |component: f64| {
+        (128.0 + component * f64::consts::FRAC_1_SQRT_2 * 127.0).round() as u8
+    };
+    [encode(x), encode(y), encode(1.0)]
+}
+
+/// Check that `steps` are all ones `estimate_distance` can follow the
+/// derivatives of.
+fn check_distance(steps: &[Step]) -> Result<(), Error> {
+    if steps.iter().any(|step| {
+        step.formula != Formula::Standard || step.morph.is_some() || step.expression.is_some()
+    }) {
+        return Err(Error::usage("distance estimation only works with the standard \
+                                 formula, without hybrids, morphing, or expressions"));
     }
+    Ok(())
+}
+
+#[test]
+fn test_estimate_distance() {
+    let zero = Complex { re: 0.0, im: 0.0 };
+    let one = Complex { re: 1.0, im: 0.0 };
+    let square = Step { formula: Formula::Standard, power: Power::Integer(2), p: zero,
+                         relaxation: one, morph: None, expression: None };
+    let steps = [square];
+
+    // The orbit of 1 is 0, 1, 2, 5, and its derivative, 0, 1, 3, 13.
+    let mandelbrot = Iteration { set: Set::Mandelbrot(zero), steps: &steps, radius: 2.0 };
+    let estimate = estimate_distance(&mandelbrot, one, 10).unwrap();
+    assert!((estimate - 5.0 * 5.0_f64.ln() / 26.0).abs() < 1e-12);
+    assert_eq!(estimate_distance(&mandelbrot, -one, 100), None);
+
+    // The Julia set for c = 0 is the unit circle. From 2, the orbit is 2, 4,
+    // 16, ..., and the estimate of its distance of 1 is ln 2 at every step.
+    let julia = Iteration { set: Set::Julia(zero), steps: &steps, radius: 1e10 };
+    let estimate = estimate_distance(&julia, Complex { re: 2.0, im: 0.0 }, 100).unwrap();
+    assert!((estimate - 2.0_f64.ln()).abs() < 1e-9);
+
+    let light = Complex { re: 0.0, im: 1.0 };
+    assert_eq!(brightness(Complex { re: 0.0, im: 8.0 }, Complex { re: 2.0, im: 0.0 }, light),
+               1.0);
+    assert_eq!(brightness(Complex { re: 0.0, im: 2.0 }, Complex { re: 0.0, im: -4.0 }, light),
+               0.6);
+    assert_eq!(brightness(Complex { re: 0.0, im: -2.0 }, one, light), 0.2);
+
+    let (z, dz) = (Complex { re: 3.0, im: 0.0 }, one);
+    assert_eq!(normal_color(z, dz, 0.0), [218, 128, 218]);
+    assert_eq!(normal_color(z, dz, 90.0), [128, 38, 218]);
+    assert_eq!(normal_color(z, dz, 180.0), [38, 128, 218]);
+
+    assert!(check_distance(&steps).is_ok());
+    assert!(check_distance(&[Step { formula: Formula::BurningShip, .. square }]).is_err());
+}
+
+/// Return the direction of `z` from the origin, as a fraction of a turn
+/// counterclockwise from the positive real axis, from 0 up to 1.
+///
+/// For an orbit that has just escaped, this approximates the external angle
+/// of the point it started from, rotated by some multiple of the angle at
+/// which it was last doubled. Points along the same field line escape in the
+/// same direction, so coloring by this draws the field lines.
+fn turns(z: Complex<f64>) -> f64 {
+    let turns = z.arg() / (2.0 * f64::consts::PI);
+    if turns < 0.0 { turns + 1.0 } else { turns }
+}
+
+#[test]
+fn test_turns() {
+    assert_eq!(turns(Complex { re: 1.0, im: 0.0 }), 0.0);
+    assert_eq!(turns(Complex { re: 0.0, im: 1.0 }), 0.25);
+    assert_eq!(turns(Complex { re: -1.0, im: 0.0 }), 0.5);
+    assert_eq!(turns(Complex { re: 0.0, im: -1.0 }), 0.75);
+}
+
+#[test]
+fn test_follow() {
+    let zero = Complex { re: 0.0, im: 0.0 };
+    let one = Complex { re: 1.0, im: 0.0 };
+    let square = Step { formula: Formula::Standard, power: Power::Integer(2), p: zero,
+                         relaxation: one, morph: None, expression: None };
+    let steps = [square];
+    let mandelbrot = Iteration { set: Set::Mandelbrot(zero), steps: &steps, radius: 2.0 };
+    let mut visited = Vec::new();
+    assert_eq!(follow(&mandelbrot, one, 10, |z, c| visited.push((z, c))), Some(2));
+    assert_eq!(visited, vec![(one, one), (one + one, one), (Complex { re: 5.0, im: 0.0 }, one)]);
+
+    // The orbit of -1 alternates between -1 and 0.
+    let mut stats = OrbitStats::new();
+    assert_eq!(follow(&mandelbrot, -one, 5, |z, _| stats.visit(z)), None);
+    assert_eq!(stats, OrbitStats { last: -one, closest: 0.0, nearest: 2, sum: -one - one - one,
+                                   points: 5, reference: zero, since: 2, period: 2 });
+    assert_eq!(stats.measure(Interior::Magnitude, 2.0), 0.5);
+    assert_eq!(stats.measure(Interior::Closest, 2.0), 0.0);
+    assert_eq!(stats.measure(Interior::Average, 2.0), 0.3);
+
+    // The orbit of -0.5 settles down at (1 - sqrt(3)) / 2, about -0.366.
+    let mut stats = OrbitStats::new();
+    assert_eq!(follow(&mandelbrot, Complex { re: -0.5, im: 0.0 }, 100, |z, _| stats.visit(z)),
+               None);
+    assert!((stats.last.re + 0.366).abs() < 0.001);
+    assert!((stats.measure(Interior::Magnitude, 1.0) - 0.366).abs() < 0.001);
+    assert_eq!(stats.measure(Interior::Magnitude, 0.1), 1.0);
+    assert_eq!(stats.period, 1);
+
+    // The orbit of -0.12 + 0.75i, in the largest bulb atop the main cardioid,
+    // settles into a cycle of period 3. The orbit of 0.25 + 0.5i, outside the
+    // set, never settles at all.
+    let mut stats = OrbitStats::new();
+    follow(&mandelbrot, Complex { re: -0.12, im: 0.75 }, 1000, |z, _| stats.visit(z));
+    assert_eq!(stats.period, 3);
+    let mut stats = OrbitStats::new();
+    follow(&mandelbrot, Complex { re: 0.25, im: 0.5 }, 1000, |z, _| stats.visit(z));
+    assert_eq!(stats.period, 0);
+}
+
+/// How to carry out one step of the iteration, taking `z` to `z^d + c`, or to
+/// some variation on it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Step<'a> {
+    /// Which variation to use.
+    formula: Formula,
 
-    let bounds = parse_pair(&args[2], 'x')
-        .expect("error parsing image dimensions");
-    let upper_left = parse_pair(&args[3], ',')
-        .expect("error parsing upper left corner point");
-    let lower_right = parse_pair(&args[4], ',')
-        .expect("error parsing lower right corner point");
+    /// The exponent `d`. The magnet and lambda formulas, which have no such
+    /// exponent, ignore this.
+    power: Power,
 
-    let mut pixels = vec![0; bounds.0 * bounds.1];
-    let area = bounds.0 as f64 * bounds.1 as f64;
+    /// For the Phoenix formula, the multiple of the previous point in the orbit
+    /// to add at each step.
+    p: Complex<f64>,
 
+    /// For the Nova formula, the factor by which to scale each Newton's method
+    /// step.
+    relaxation: Complex<f64>,
+
+    /// A second formula to blend with this one, and how far toward it to go:
+    /// at 0, we use only `formula`, and at 1, only the second formula.
+    morph: Option<(Formula, f64)>,
+
+    /// An expression given by the user to evaluate instead of any of the
+    /// formulas.
+    expression: Option<&'a expr::Expr>
+}
+
+impl<'a> Step<'a> {
+    /// Carry `orbit` one step further, updating the point it has reached and
+    /// whatever else the formula keeps track of. Most formulas only need the
+    /// previous point, but the Spider changes `c` as it goes, too.
+    fn advance(&self, orbit: &mut Orbit) {
+        let next = self.apply(orbit.z, orbit.previous, orbit.c);
+        if self.expression.is_none() && self.formula == Formula::Spider {
+            orbit.c = orbit.c * Complex { re: 0.5, im: 0.0 } + next;
+        }
+        orbit.previous = orbit.z;
+        orbit.z = next;
+        orbit.count += 1;
+    }
+
+    /// Return the point following `z` in the orbit for `c`. The point before
+    /// `z` was `previous`; at the start of the orbit, that is zero, or `z`
+    /// itself for the Manowar.
+    fn apply(&self, z: Complex<f64>, previous: Complex<f64>, c: Complex<f64>)
+        -> Complex<f64>
     {
-        let bands = AtomicChunksMut::new(&mut pixels, bounds.0);
-        crossbeam::scope(|scope| {
-            for i in 0..8 {
-                scope.spawn(|| {
-                    let mut count = 0;
-                    for (i, band) in &bands {
-                        count += 1;
-                        let top = i;
-                        let height = band.len() / bounds.0;
-                        let band_bounds = (bounds.0, height);
-                        let band_upper_left = pixel_to_point(bounds, (0, top),
-                                                             upper_left, lower_right);
-                        let band_lower_right = pixel_to_point(bounds, (bounds.0, top + height),
-                                                              upper_left, lower_right);
-                        render(band, band_bounds, band_upper_left, band_lower_right);
-                    }
-                });
+        if let Some(expression) = self.expression {
+            return expression.evaluate(z, c);
+        }
+        let here = self.apply_formula(self.formula, z, previous, c);
+        match self.morph {
+            Some((target, t)) => {
+                let there = self.apply_formula(target, z, previous, c);
+                here + (there - here) * Complex { re: t, im: 0.0 }
             }
-        });
+            None => here
+        }
+    }
+
+    /// Return the point following `z` in the orbit for `c`, by `formula`.
+    fn apply_formula(&self, formula: Formula, z: Complex<f64>, previous: Complex<f64>,
+                     c: Complex<f64>)
+        -> Complex<f64>
+    {
+        match formula {
+            Formula::Standard => self.power.raise(z) + c,
+            Formula::BurningShip => {
+                self.power.raise(Complex { re: z.re.abs(), im: z.im.abs() }) + c
+            }
+            Formula::Tricorn => self.power.raise(z.conj()) + c,
+            Formula::Celtic => {
+                let raised = self.power.raise(z);
+                Complex { re: raised.re.abs(), im: raised.im } + c
+            }
+            Formula::Perpendicular => {
+                self.power.raise(Complex { re: z.re.abs(), im: -z.im }) + c
+            }
+            Formula::PerpendicularBurningShip => {
+                self.power.raise(Complex { re: z.re, im: -z.im.abs() }) + c
+            }
+            Formula::Heart => self.power.raise(Complex { re: z.re.abs(), im: z.im }) + c,
+            Formula::Phoenix => self.power.raise(z) + c + self.p * previous,
+            Formula::Spider => self.power.raise(z) + c,
+            Formula::Manowar => self.power.raise(z) + previous + c,
+            Formula::Magnet1 => {
+                let one = Complex { re: 1.0, im: 0.0 };
+                let two = one + one;
+                let ratio = (z * z + c - one) / (two * z + c - two);
+                ratio * ratio
+            }
+            Formula::Lambda => {
+                let one = Complex { re: 1.0, im: 0.0 };
+                c * z * (one - z)
+            }
+            Formula::QuatJulia => {
+                // Within the complex plane, quaternions square like complex
+                // numbers; `QuatJulia` handles the rest of the slice.
+                z * z + c
+            }
+            Formula::Collatz => {
+                let one = Complex { re: 1.0, im: 0.0 };
+                let (two, four) = (one + one, Complex { re: 4.0, im: 0.0 });
+                let (five, seven) = (Complex { re: 5.0, im: 0.0 }, Complex { re: 7.0, im: 0.0 });
+                let pi_z = Complex { re: f64::consts::PI, im: 0.0 } * z;
+                (two + seven * z - (two + five * z) * pi_z.cos()) / four + c
+            }
+            Formula::Nova => {
+                // A relaxed Newton's method step for z^d - 1, plus c.
+                let one = Complex { re: 1.0, im: 0.0 };
+                let d = Complex { re: self.power.exponent(), im: 0.0 };
+                let raised = self.power.raise(z);
+                z - self.relaxation * (raised - one) / (d * raised / z) + c
+            }
+            Formula::Magnet2 => {
+                let one = Complex { re: 1.0, im: 0.0 };
+                let (two, three) = (one + one, one + one + one);
+                let (c1, c2) = (c - one, c - two);
+                let ratio = (z * z * z + three * c1 * z + c1 * c2) /
+                    (three * z * z + three * c2 * z + c1 * c2 + one);
+                ratio * ratio
+            }
+        }
     }
 
-    write_bitmap(&args[1], &pixels[..], bounds).expect("error writing PNG file");
+    /// Say what has become of an orbit that has just arrived at `z` from
+    /// `previous`, given an escape circle of the given `radius`.
+    ///
+    /// Most orbits stop when they leave the circle. The magnet formulas' orbits
+    /// may also settle on a fixed point at 1, which they can't leave. Nova
+    /// orbits, on the other hand, are only finished when they converge on a
+    /// fixed point; we count that as escaping, so that they are shaded by how
+    /// long it took.
+    fn status(&self, z: Complex<f64>, previous: Complex<f64>, radius: f64) -> Escape {
+        match self.formula {
+            Formula::Nova if (z - previous).norm_sqr() < FIXED_POINT_TOLERANCE => {
+                Escape::Escaped
+            }
+            Formula::Nova => Escape::Orbiting,
+            _ if z.norm_sqr() > radius * radius => Escape::Escaped,
+            Formula::Magnet1 | Formula::Magnet2
+                if (z - Complex { re: 1.0, im: 0.0 }).norm_sqr() < FIXED_POINT_TOLERANCE =>
+            {
+                Escape::Settled
+            }
+            _ => Escape::Orbiting
+        }
+    }
+}
+
+/// How close, squared, a point must come to a formula's fixed point for us to
+/// deem it to have arrived.
+const FIXED_POINT_TOLERANCE: f64 = 1e-18;
+
+#[test]
+fn test_step() {
+    let c = Complex { re: 0.5, im: 0.25 };
+    let z = Complex { re: -1.0, im: -2.0 };
+    let previous = Complex { re: 3.0, im: 1.0 };
+    let square = Power::Integer(2);
+    let one = Complex { re: 1.0, im: 0.0 };
+    let standard = Step { formula: Formula::Standard, power: square, p: c, relaxation: one,
+                          morph: None, expression: None };
+    assert_eq!(standard.apply(z, previous, c), z * z + c);
+    let burning_ship = Step { formula: Formula::BurningShip, .. standard };
+    assert_eq!(burning_ship.apply(z, previous, c), -z * -z + c);
+    assert_eq!(burning_ship.apply(-z, previous, c), -z * -z + c);
+    let tricorn = Step { formula: Formula::Tricorn, .. standard };
+    assert_eq!(tricorn.apply(z, previous, c), z.conj() * z.conj() + c);
+    let phoenix = Step { formula: Formula::Phoenix, .. standard };
+    assert_eq!(phoenix.apply(z, previous, c), z * z + c + c * previous);
+
+    // Both magnet formulas have a fixed point at 1, whatever c is.
+    for &formula in &[Formula::Magnet1, Formula::Magnet2] {
+        let magnet = Step { formula: formula, .. standard };
+        assert!((magnet.apply(one, previous, c) - one).norm_sqr() < 1e-20);
+        assert_eq!(magnet.status(one, previous, 2.0), Escape::Settled);
+        assert_eq!(magnet.status(z, previous, 10.0), Escape::Orbiting);
+        assert_eq!(magnet.status(z, previous, 2.0), Escape::Escaped);
+    }
+    assert_eq!(standard.status(one, previous, 2.0), Escape::Orbiting);
+    assert_eq!(standard.status(z, previous, 2.0), Escape::Escaped);
+
+    // The Spider halves c and adds the new point to it at each step; the
+    // Manowar adds the previous point.
+    let spider = Step { formula: Formula::Spider, .. standard };
+    let mut orbit = Orbit { z: z, previous: previous, c: c, count: 0 };
+    spider.advance(&mut orbit);
+    assert_eq!((orbit.z, orbit.previous, orbit.count), (z * z + c, z, 1));
+    assert_eq!(orbit.c, c * Complex { re: 0.5, im: 0.0 } + z * z + c);
+    let manowar = Step { formula: Formula::Manowar, .. standard };
+    assert_eq!(manowar.apply(z, previous, c), z * z + previous + c);
+    let mut orbit = Orbit { z: z, previous: previous, c: c, count: 0 };
+    manowar.advance(&mut orbit);
+    assert_eq!((orbit.z, orbit.previous, orbit.c), (z * z + previous + c, z, c));
+
+    // A morph blends the results of two formulas.
+    let morph = Step { morph: Some((Formula::Tricorn, 0.25)), .. standard };
+    let blend = (z * z + c) * Complex { re: 0.75, im: 0.0 }
+        + (z.conj() * z.conj() + c) * Complex { re: 0.25, im: 0.0 };
+    assert!((morph.apply(z, previous, c) - blend).norm_sqr() < 1e-20);
+    let all_the_way = Step { morph: Some((Formula::Tricorn, 1.0)), .. standard };
+    assert_eq!(all_the_way.apply(z, previous, c), tricorn.apply(z, previous, c));
+
+    // The Collatz map takes 1 to 4, 4 to 2, and 2 back to 1.
+    let collatz = Step { formula: Formula::Collatz, .. standard };
+    let origin = Complex { re: 0.0, im: 0.0 };
+    let real = |x| Complex { re: x, im: 0.0 };
+    for &(from, to) in &[(1.0, 4.0), (4.0, 2.0), (2.0, 1.0), (3.0, 10.0)] {
+        assert!((collatz.apply(real(from), previous, origin) - real(to)).norm_sqr() < 1e-20);
+    }
+
+    // Nova orbits run until they converge, however far they roam.
+    let nova = Step { formula: Formula::Nova, power: Power::Integer(3), .. standard };
+    let expected = z - (z * z * z - one) / (Complex { re: 3.0, im: 0.0 } * z * z) + c;
+    assert!((nova.apply(z, previous, c) - expected).norm_sqr() < 1e-20);
+    let relaxed = Step { relaxation: Complex { re: 0.5, im: 0.0 }, .. nova };
+    let expected = z - (z * z * z - one) / (Complex { re: 6.0, im: 0.0 } * z * z) + c;
+    assert!((relaxed.apply(z, previous, c) - expected).norm_sqr() < 1e-20);
+    assert_eq!(nova.status(Complex { re: 100.0, im: 100.0 }, previous, 2.0), Escape::Orbiting);
+    assert_eq!(nova.status(z, z, 2.0), Escape::Escaped);
+
+    // The absolute-value variations, written out as they usually are.
+    let (x, y) = (z.re, z.im);
+    let celtic = Step { formula: Formula::Celtic, .. standard };
+    assert_eq!(celtic.apply(z, previous, c),
+               Complex { re: (x * x - y * y).abs(), im: 2.0 * x * y } + c);
+    let perpendicular = Step { formula: Formula::Perpendicular, .. standard };
+    assert_eq!(perpendicular.apply(z, previous, c),
+               Complex { re: x * x - y * y, im: -2.0 * x.abs() * y } + c);
+    let perpendicular_ship = Step { formula: Formula::PerpendicularBurningShip, .. standard };
+    assert_eq!(perpendicular_ship.apply(z, previous, c),
+               Complex { re: x * x - y * y, im: -2.0 * x * y.abs() } + c);
+    let heart = Step { formula: Formula::Heart, .. standard };
+    assert_eq!(heart.apply(z, previous, c),
+               Complex { re: x * x - y * y, im: 2.0 * x.abs() * y } + c);
+
+    let lambda = Step { formula: Formula::Lambda, .. standard };
+    assert_eq!(lambda.apply(z, previous, c), c * z * (one - z));
+}
+
+/// The exponent `d` in the iteration `z = z^d + c`. A power of 2 gives the
+/// Mandelbrot set proper; others give the Multibrot sets.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Power {
+    /// A whole-number exponent, applied by repeated squaring.
+    Integer(u32),
+
+    /// Any other exponent, applied in polar form.
+    Real(f64)
+}
+
+impl Power {
+    /// Return the `Power` for the exponent `d`, using the faster `Integer`
+    /// representation when we can.
+    fn new(d: f64) -> Power {
+        if d.fract() == 0.0 && d >= 0.0 && d <= u32::max_value() as f64 {
+            Power::Integer(d as u32)
+        } else {
+            Power::Real(d)
+        }
+    }
+
+    /// Return the exponent itself.
+    fn exponent(&self) -> f64 {
+        match *self {
+            Power::Integer(n) => n as f64,
+            Power::Real(d) => d
+        }
+    }
+
+    /// Return `z` raised to this power.
+    fn raise(&self, z: Complex<f64>) -> Complex<f64> {
+        match *self {
+            Power::Integer(2) => z * z,
+            Power::Integer(mut n) => {
+                let mut result = Complex { re: 1.0, im: 0.0 };
+                let mut base = z;
+                while n > 0 {
+                    if n & 1 == 1 {
+                        result = result * base;
+                    }
+                    base = base * base;
+                    n >>= 1;
+                }
+                result
+            }
+            Power::Real(d) => {
+                let magnitude = z.norm_sqr().powf(d / 2.0);
+                let angle = z.im.atan2(z.re) * d;
+                Complex { re: magnitude * angle.cos(), im: magnitude * angle.sin() }
+            }
+        }
+    }
+
+    /// Return the derivative of `z` raised to this power: `d z^(d - 1)`.
+    fn derivative(&self, z: Complex<f64>) -> Complex<f64> {
+        let lowered = match *self {
+            Power::Integer(0) => return Complex { re: 0.0, im: 0.0 },
+            Power::Integer(n) => Power::Integer(n - 1),
+            Power::Real(d) => Power::Real(d - 1.0)
+        };
+        Complex { re: self.exponent(), im: 0.0 } * lowered.raise(z)
+    }
+}
+
+#[test]
+fn test_power() {
+    assert_eq!(Power::new(3.0), Power::Integer(3));
+    assert_eq!(Power::new(2.5), Power::Real(2.5));
+
+    let z = Complex { re: 1.0, im: 2.0 };
+    assert_eq!(Power::Integer(2).raise(z), z * z);
+    assert_eq!(Power::Integer(5).raise(z), z * z * z * z * z);
+    let real = Power::Real(5.0).raise(z);
+    assert!((real - z * z * z * z * z).norm_sqr() < 1e-20);
+
+    let three = Complex { re: 3.0, im: 0.0 };
+    assert_eq!(Power::Integer(3).derivative(z), three * z * z);
+    assert_eq!(Power::Integer(0).derivative(z), Complex { re: 0.0, im: 0.0 });
+    let real = Power::Real(3.0).derivative(z);
+    assert!((real - three * z * z).norm_sqr() < 1e-20);
+}
+
+/// Which kind of set to draw.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Set {
+    /// The Mandelbrot set: each pixel's point is used as `c`, and the
+    /// iteration starts from the given value, usually zero.
+    Mandelbrot(Complex<f64>),
+
+    /// The Julia set for the given `c`: each pixel's point is used as the
+    /// iteration's starting value.
+    Julia(Complex<f64>)
+}
+
+impl Set {
+    /// Return the starting value and `c` of the orbit for `point`.
+    fn start(&self, point: Complex<f64>) -> (Complex<f64>, Complex<f64>) {
+        match *self {
+            Set::Mandelbrot(z0) => (z0, point),
+            Set::Julia(c) => (point, c)
+        }
+    }
+}
+
+/// Render a rectangle of the complex plane into `buffer`, which holds one value
+/// per pixel.
+///
+/// The `bounds` argument gives the width and height of `buffer`. The
+/// `transform` argument maps each pixel to its point on the complex plane, as
+/// for `pixel_to_point`, and `pixel` computes the value to store for that
+/// point: for the Mandelbrot set, say, the number of iterations `escapes`
+/// needed to see that the point is outside the set.
+fn render<T, F>(buffer: &mut [T], bounds: (usize, usize), transform: &Transform, pixel: &F)
+    where F: Fn(Complex<f64>) -> T
+{
+    assert!(buffer.len() == bounds.0 * bounds.1);
+
+    for r in 0 .. bounds.1 {
+        for c in 0 .. bounds.0 {
+            let point = pixel_to_point(transform, (c, r));
+            buffer[r * bounds.0 + c] = pixel(Complex { re: point.0, im: point.1 });
+        }
+    }
+}
+
+extern crate image;
+
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, Write};
+use image::jpeg::JPEGEncoder;
+use image::png::PNGEncoder;
+use image::ColorType;
+
+/// A rendered image's pixels, and how to interpret them.
+struct Bitmap {
+    pixels: Vec<u8>,
+    color: ColorType,
+
+    /// How the pixels' values encode light, if we know, to be recorded in the
+    /// image file.
+    gamma: Option<coloring::Gamma>,
+
+    /// An ICC profile saying what colors the pixels' values mean, to be
+    /// embedded in the image file.
+    profile: Option<Vec<u8>>
+}
+
+/// Color a buffer of samples for an image `width` pixels wide, produced by
+/// following orbits with the iteration limit `limit`, as `coloring` directs.
+/// If all the colors are grays, the bitmap is grayscale; otherwise, it is RGB.
+/// If `coloring` makes some points transparent, the bitmap has an alpha
+/// channel as well.
+fn paint(samples: &[Sample], width: usize, limit: u32, coloring: &coloring::Coloring)
+    -> Bitmap
+{
+    paint_as(samples, width, limit, coloring, coloring.is_gray(samples))
+}
+
+/// Color `samples` as `paint` does, but make a grayscale bitmap if `gray` is
+/// true, and an RGB bitmap otherwise, whatever colors they turn out to be.
+fn paint_as(samples: &[Sample], width: usize, limit: u32, coloring: &coloring::Coloring,
+            gray: bool)
+    -> Bitmap
+{
+    let channels = if gray { 1 } else { 3 };
+    let mut colors = coloring.colors(samples, limit);
+    let mut opacity = coloring.opacity(samples, limit);
+    if let Some(corner) = coloring.legend {
+        let bounds = (width, samples.len() / width);
+        let white = if coloring.depth == 16 || coloring.dither { 0xffff } else { 0xff };
+        let range = coloring.range(samples, limit);
+        let area = legend::draw(&mut colors, bounds, corner, range, white, |counts| {
+            coloring.colors_among(&coloring::counts(counts), samples, limit)
+        });
+        // The legend is opaque, even over transparent parts of the image.
+        if let Some(((left, top), (columns, rows))) = area {
+            for row in top..top + rows {
+                for pixel in &mut opacity[row * width + left..row * width + left + columns] {
+                    *pixel = true;
+                }
+            }
+        }
+    }
+    if coloring.dither {
+        coloring::dither(&mut colors, width);
+    }
+    let opaque = if coloring.depth == 16 { 0xffff } else { 0xff };
+    let transparent = coloring.transparent;
+    let samples = colors.into_iter().zip(opacity).flat_map(|(rgb, opacity)| {
+        let mut pixel = rgb[..channels].to_vec();
+        if transparent {
+            pixel.push(if opacity { opaque } else { 0 });
+        }
+        pixel
+    });
+    let pixels = if coloring.depth == 16 {
+        // PNG stores sixteen-bit samples big-endian.
+        samples.flat_map(|sample| vec![(sample >> 8) as u8, sample as u8]).collect()
+    } else {
+        samples.map(|sample| sample as u8).collect()
+    };
+    Bitmap {
+        pixels: pixels,
+        color: color_type(gray, transparent, coloring.depth),
+        gamma: coloring.gamma,
+        profile: coloring.profile.clone()
+    }
+}
+
+/// Return the PNG color type for pixels that are grayscale if `gray` is true,
+/// and RGB otherwise, with an alpha channel if `alpha` is true, and `depth`
+/// bits per channel.
+fn color_type(gray: bool, alpha: bool, depth: u8) -> ColorType {
+    match (gray, alpha) {
+        (true, false) => ColorType::Gray(depth),
+        (true, true) => ColorType::GrayA(depth),
+        (false, false) => ColorType::RGB(depth),
+        (false, true) => ColorType::RGBA(depth)
+    }
+}
+
+#[test]
+fn test_paint() {
+    let gray = coloring::plain();
+    let bitmap = paint(&coloring::counts(&[0, 500, 1000]), 3, 1000, &gray);
+    assert_eq!((bitmap.pixels, bitmap.color), (vec![255, 128, 0], ColorType::Gray(8)));
+    let dithered = coloring::Coloring { dither: true, .. gray.clone() };
+    let bitmap = paint(&coloring::counts(&[0, 500, 999, 1000]), 2, 1000, &dithered);
+    assert_eq!((bitmap.pixels, bitmap.color), (vec![255, 128, 1, 0], ColorType::Gray(8)));
+    let bitmap = paint(&[Sample::Count(0), Sample::Period(1)], 2, 1000, &gray);
+    assert_eq!((bitmap.pixels, bitmap.color),
+               (vec![255, 255, 255, 78, 121, 167], ColorType::RGB(8)));
+    let red = coloring::Coloring {
+        palette: coloring::Palette::new(vec![[255, 0, 0], [0, 0, 0]]),
+        .. coloring::plain()
+    };
+    let bitmap = paint(&coloring::counts(&[0, 1000]), 2, 1000, &red);
+    assert_eq!((bitmap.pixels, bitmap.color), (vec![255, 0, 0, 0, 0, 0], ColorType::RGB(8)));
+    let deep = coloring::Coloring { depth: 16, .. gray.clone() };
+    let bitmap = paint(&coloring::counts(&[0, 500, 1000]), 3, 1000, &deep);
+    assert_eq!((bitmap.pixels, bitmap.color),
+               (vec![255, 255, 128, 0, 0, 0], ColorType::Gray(16)));
+    let clear = coloring::Coloring { transparent: true, .. gray };
+    let bitmap = paint(&[Sample::Count(500), Sample::Count(1000), Sample::Fraction(0.0)], 3,
+                       1000, &clear);
+    assert_eq!((bitmap.pixels, bitmap.color),
+               (vec![128, 255, 0, 0, 255, 255], ColorType::GrayA(8)));
+}
+
+/// How to encode an image file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Encoding {
+    Png,
+
+    /// JPEG, at the given quality, from 1 to 100.
+    Jpeg(u8),
+
+    Tiff,
+
+    /// WebP, at the given quality, from 1 to 100; 100 is lossless.
+    Webp(u8),
+
+    /// AVIF, at the given quality, from 1 to 100.
+    Avif(u8),
+
+    Qoi,
+    Pgm,
+    Ppm,
+    Farbfeld,
+    Bmp,
+
+    /// A Deep Zoom pyramid, cut as given, of JPEG tiles at the given quality,
+    /// or else PNG tiles.
+    Dzi(dzi::Pyramid, Option<u8>)
+}
+
+/// The JPEG and AVIF qualities to use if none is given. WebP images are
+/// lossless unless asked otherwise.
+const JPEG_QUALITY: u8 = 90;
+const AVIF_QUALITY: u8 = 80;
+
+/// Return the format `config` asks for images written to `filename`: the one
+/// it names, or else the one `filename`'s extension suggests.
+fn resolve_format(config: &Config, filename: &str) -> config::Format {
+    config.format.unwrap_or_else(|| {
+        let extension = Path::new(filename).extension()
+            .map_or(String::new(), |extension| extension.to_string_lossy().to_lowercase());
+        match &extension[..] {
+            "jpg" | "jpeg" => config::Format::Jpeg,
+            "tif" | "tiff" => config::Format::Tiff,
+            "webp" => config::Format::Webp,
+            "avif" => config::Format::Avif,
+            "qoi" => config::Format::Qoi,
+            "pgm" => config::Format::Pgm,
+            "ppm" => config::Format::Ppm,
+            "ff" => config::Format::Farbfeld,
+            "bmp" => config::Format::Bmp,
+            "dzi" => config::Format::Dzi,
+            _ => config::Format::Png
+        }
+    })
+}
+
+/// Return the encoding `config` asks for images written to `filename`, or an
+/// error if the image it describes can't be written that way.
+fn resolve_encoding(config: &Config, filename: &str) -> Result<Encoding, Error> {
+    let format = resolve_format(config, filename);
+    match format {
+        config::Format::Png => Ok(Encoding::Png),
+        config::Format::Tiff => Ok(Encoding::Tiff),
+        config::Format::Jpeg => {
+            if config.depth.unwrap_or(8) != 8 {
+                return Err(Error::usage("JPEG images can only have a bit depth of 8"));
+            }
+            if config.interior == Some(Interior::Transparent) {
+                return Err(Error::usage("JPEG images can't be transparent"));
+            }
+            Ok(Encoding::Jpeg(try!(resolve_quality(config, JPEG_QUALITY))))
+        }
+        config::Format::Webp => {
+            if config.depth.unwrap_or(8) != 8 {
+                return Err(Error::usage("WebP images can only have a bit depth of 8"));
+            }
+            Ok(Encoding::Webp(try!(resolve_quality(config, 100))))
+        }
+        config::Format::Avif => Ok(Encoding::Avif(try!(resolve_quality(config, AVIF_QUALITY)))),
+        config::Format::Qoi => {
+            if config.depth.unwrap_or(8) != 8 {
+                return Err(Error::usage("QOI images can only have a bit depth of 8"));
+            }
+            Ok(Encoding::Qoi)
+        }
+        config::Format::Pgm | config::Format::Ppm => {
+            if config.interior == Some(Interior::Transparent) {
+                return Err(Error::usage("PGM and PPM images can't be transparent"));
+            }
+            Ok(if format == config::Format::Pgm { Encoding::Pgm } else { Encoding::Ppm })
+        }
+        config::Format::Farbfeld => Ok(Encoding::Farbfeld),
+        config::Format::Bmp => {
+            if config.depth.unwrap_or(8) != 8 {
+                return Err(Error::usage("BMP images can only have a bit depth of 8"));
+            }
+            Ok(Encoding::Bmp)
+        }
+        config::Format::Dzi => {
+            let pyramid = dzi::Pyramid {
+                tile_size: config.tile_size.unwrap_or(dzi::DEFAULT_TILE_SIZE),
+                overlap: config.tile_overlap.unwrap_or(dzi::DEFAULT_OVERLAP)
+            };
+            if pyramid.tile_size == 0 {
+                return Err(Error::usage("Deep Zoom tiles must be at least one pixel square"));
+            }
+            match try!(resolve_tile_encoding(config)) {
+                Encoding::Jpeg(quality) => Ok(Encoding::Dzi(pyramid, Some(quality))),
+                _ => Ok(Encoding::Dzi(pyramid, None))
+            }
+        }
+    }
+}
+
+/// Return the encoding `config` asks for the tiles of a Deep Zoom image or web
+/// map, which must be PNG or JPEG.
+fn resolve_tile_encoding(config: &Config) -> Result<Encoding, Error> {
+    // Let the tiles' format be checked as any other image's would be.
+    let tiles = Config { format: config.tile_format, .. config.clone() };
+    match try!(resolve_encoding(&tiles, "tile.png")) {
+        encoding @ Encoding::Png | encoding @ Encoding::Jpeg(_) => Ok(encoding),
+        _ => Err(Error::usage("tiles can only be PNG or JPEG images"))
+    }
+}
+
+/// Return the quality `config` asks for, or `default` if it doesn't say.
+fn resolve_quality(config: &Config, default: u8) -> Result<u8, Error> {
+    let quality = config.quality.unwrap_or(default);
+    if quality < 1 || quality > 100 {
+        return Err(Error::usage(format!("bad quality {}: expected 1 to 100", quality)));
+    }
+    Ok(quality)
+}
+
+#[test]
+fn test_resolve_encoding() {
+    let config = Config::default();
+    assert_eq!(resolve_encoding(&config, "mandel.png").ok(), Some(Encoding::Png));
+    assert_eq!(resolve_encoding(&config, "-").ok(), Some(Encoding::Png));
+    assert_eq!(resolve_encoding(&config, "mandel.JPG").ok(), Some(Encoding::Jpeg(90)));
+    assert_eq!(resolve_encoding(&config, "mandel.jpeg").ok(), Some(Encoding::Jpeg(90)));
+    assert_eq!(resolve_encoding(&config, "mandel.tif").ok(), Some(Encoding::Tiff));
+    assert_eq!(resolve_encoding(&config, "mandel.webp").ok(), Some(Encoding::Webp(100)));
+    assert_eq!(resolve_encoding(&config, "mandel.avif").ok(), Some(Encoding::Avif(80)));
+    assert_eq!(resolve_encoding(&config, "mandel.qoi").ok(), Some(Encoding::Qoi));
+    assert_eq!(resolve_encoding(&config, "mandel.pgm").ok(), Some(Encoding::Pgm));
+    assert_eq!(resolve_encoding(&config, "mandel.ff").ok(), Some(Encoding::Farbfeld));
+    assert_eq!(resolve_encoding(&config, "mandel.bmp").ok(), Some(Encoding::Bmp));
+    assert_eq!(resolve_encoding(&config, "mandel.dzi").ok(),
+               Some(Encoding::Dzi(dzi::Pyramid { tile_size: 254, overlap: 1 }, None)));
+    let tiles = Config { tile_format: Some(config::Format::Jpeg), tile_size: Some(510),
+                         .. Config::default() };
+    assert_eq!(resolve_encoding(&tiles, "mandel.dzi").ok(),
+               Some(Encoding::Dzi(dzi::Pyramid { tile_size: 510, overlap: 1 }, Some(90))));
+    assert!(resolve_encoding(&Config { tile_format: Some(config::Format::Tiff), .. tiles },
+                             "mandel.dzi").is_err());
+
+    let config = Config { format: Some(config::Format::Jpeg), quality: Some(75),
+                          .. Config::default() };
+    assert_eq!(resolve_encoding(&config, "-").ok(), Some(Encoding::Jpeg(75)));
+    assert!(resolve_encoding(&Config { quality: Some(0), .. config.clone() }, "-").is_err());
+    assert!(resolve_encoding(&Config { depth: Some(16), .. config.clone() }, "-").is_err());
+    assert_eq!(resolve_encoding(&Config { format: Some(config::Format::Png), .. config },
+                                "mandel.jpg").ok(),
+               Some(Encoding::Png));
+}
+
+/// Write `bitmap`, whose dimensions are given by `bounds`, to the file named
+/// `filename`, as `encoding` says. If `filename` is `-`, write to standard
+/// output.
+fn write_bitmap(filename: &str, bitmap: &Bitmap, bounds: (usize, usize), encoding: Encoding)
+    -> io::Result<()>
+{
+    if filename == "-" {
+        let stdout = io::stdout();
+        return encode_bitmap(stdout.lock(), bitmap, bounds, encoding);
+    }
+    if let Encoding::Dzi(pyramid, jpeg) = encoding {
+        return write_dzi(filename, bitmap, bounds, pyramid, jpeg);
+    }
+
+    let output = try!(File::create(filename));
+    encode_bitmap(output, bitmap, bounds, encoding)
+}
+
+/// Encode `bitmap`, whose dimensions are given by `bounds`, as `encoding` says,
+/// and write it to `output`.
+fn encode_bitmap<W: io::Write>(mut output: W, bitmap: &Bitmap, bounds: (usize, usize),
+                               encoding: Encoding)
+    -> io::Result<()>
+{
+    match encoding {
+        Encoding::Png => encode_png(output, bitmap, bounds),
+        Encoding::Jpeg(quality) => {
+            let jpeg = try!(encode_jpeg(bitmap, bounds, quality));
+            output.write_all(&jpeg)
+        }
+        Encoding::Tiff => {
+            let (channels, depth) = color_layout(bitmap.color);
+            tiff::write(output, &bitmap.pixels, bounds, channels, depth,
+                        bitmap.profile.as_ref().map(|profile| &profile[..]))
+        }
+        Encoding::Webp(quality) => {
+            let pixels = try!(rgba_pixels(bitmap, "WebP"));
+            let webp = try!(webp::encode(&pixels, bounds, quality,
+                                         bitmap.profile.as_ref().map(|profile| &profile[..])));
+            output.write_all(&webp)
+        }
+        Encoding::Avif(quality) => {
+            let mut png = Vec::new();
+            try!(encode_png(&mut png, bitmap, bounds));
+            let avif = try!(avif::encode(&png, color_layout(bitmap.color).1, quality));
+            output.write_all(&avif)
+        }
+        Encoding::Qoi => {
+            let pixels = try!(rgba_pixels(bitmap, "QOI"));
+            let alpha = color_layout(bitmap.color).0 % 2 == 0;
+            output.write_all(&qoi::encode(&pixels, bounds, alpha))
+        }
+        Encoding::Pgm | Encoding::Ppm => {
+            let (channels, depth) = color_layout(bitmap.color);
+            pnm::write(output, &bitmap.pixels, bounds, channels, depth, encoding == Encoding::Pgm)
+        }
+        Encoding::Farbfeld => {
+            let (channels, depth) = color_layout(bitmap.color);
+            farbfeld::write(output, &bitmap.pixels, bounds, channels, depth)
+        }
+        Encoding::Bmp => {
+            let pixels = try!(rgba_pixels(bitmap, "BMP"));
+            let alpha = color_layout(bitmap.color).0 % 2 == 0;
+            output.write_all(&try!(bmp::encode(&pixels, bounds, alpha)))
+        }
+        Encoding::Dzi(..) => {
+            Err(io::Error::new(io::ErrorKind::InvalidInput,
+                               "Deep Zoom images can only be written to files"))
+        }
+    }
+}
+
+/// Write `bitmap`, whose dimensions are given by `bounds`, as a Deep Zoom
+/// image whose descriptor is the file named `filename`, cut into tiles as
+/// `pyramid` says. The tiles are JPEG at the quality `jpeg`, if given, or else
+/// PNG.
+fn write_dzi(filename: &str, bitmap: &Bitmap, bounds: (usize, usize), pyramid: dzi::Pyramid,
+             jpeg: Option<u8>)
+    -> io::Result<()>
+{
+    let (encoding, extension) = match jpeg {
+        Some(quality) => (Encoding::Jpeg(quality), "jpg"),
+        None => (Encoding::Png, "png")
+    };
+    try!(try!(File::create(filename))
+         .write_all(pyramid.descriptor(bounds, extension).as_bytes()));
+
+    // Write the full-size level first, then halve it for each level below.
+    let (channels, depth) = color_layout(bitmap.color);
+    let directory = dzi::tile_directory(Path::new(filename));
+    let mut halved: Option<Vec<u8>> = None;
+    let mut level_bounds = bounds;
+    for level in (0..pyramid.top_level(bounds) + 1).rev() {
+        let (next, next_bounds) = {
+            let pixels = halved.as_ref().unwrap_or(&bitmap.pixels);
+            try!(write_dzi_level(&directory.join(level.to_string()), pixels, level_bounds,
+                                 bitmap, pyramid, encoding, extension));
+            dzi::halve(pixels, level_bounds, channels, depth)
+        };
+        halved = Some(next);
+        level_bounds = next_bounds;
+    }
+    Ok(())
+}
+
+/// Write the tiles of one level of a Deep Zoom image, whose `pixels` have
+/// dimensions given by `bounds` and are otherwise like `bitmap`'s, to
+/// `directory`, cut as `pyramid` says, encoded as `encoding`, in files with the
+/// extension `extension`.
+fn write_dzi_level(directory: &Path, pixels: &[u8], bounds: (usize, usize), bitmap: &Bitmap,
+                   pyramid: dzi::Pyramid, encoding: Encoding, extension: &str)
+    -> io::Result<()>
+{
+    let (channels, depth) = color_layout(bitmap.color);
+    let pixel_bytes = channels * depth as usize / 8;
+    try!(std::fs::create_dir_all(directory));
+    for tile in pyramid.tiles(bounds) {
+        let tile_bitmap = Bitmap {
+            pixels: dzi::crop(pixels, bounds, pixel_bytes, tile.x.clone(), tile.y.clone()),
+            color: bitmap.color,
+            gamma: bitmap.gamma,
+            profile: bitmap.profile.clone()
+        };
+        let path = directory.join(format!("{}_{}.{}", tile.column, tile.row, extension));
+        let output = try!(File::create(path));
+        try!(encode_bitmap(output, &tile_bitmap, (tile.x.len(), tile.y.len()), encoding));
+    }
+    Ok(())
+}
+
+/// Return `bitmap`'s pixels as red, green, blue, and alpha, for writing in the
+/// format named `format`, which must have eight bits per channel.
+fn rgba_pixels(bitmap: &Bitmap, format: &str) -> io::Result<Vec<[u8; 4]>> {
+    let (channels, depth) = color_layout(bitmap.color);
+    if depth != 8 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                  format!("{} images must have eight bits per channel", format)));
+    }
+    Ok(bitmap.pixels.chunks(channels).map(|pixel| {
+        match channels {
+            1 => [pixel[0], pixel[0], pixel[0], 255],
+            2 => [pixel[0], pixel[0], pixel[0], pixel[1]],
+            3 => [pixel[0], pixel[1], pixel[2], 255],
+            _ => [pixel[0], pixel[1], pixel[2], pixel[3]]
+        }
+    }).collect())
+}
+
+/// Return the number of channels in each pixel of a bitmap of type `color`,
+/// and the number of bits in each channel.
+fn color_layout(color: ColorType) -> (usize, u8) {
+    match color {
+        ColorType::Gray(depth) | ColorType::Palette(depth) => (1, depth),
+        ColorType::GrayA(depth) => (2, depth),
+        ColorType::RGB(depth) => (3, depth),
+        ColorType::RGBA(depth) => (4, depth)
+    }
+}
+
+/// Encode `bitmap`, whose dimensions are given by `bounds`, as a PNG image, and
+/// write it to `output`. If we know the bitmap's gamma or ICC profile, record
+/// them in the file.
+fn encode_png<W: io::Write>(mut output: W, bitmap: &Bitmap, bounds: (usize, usize))
+    -> io::Result<()>
+{
+    if bitmap.gamma.is_none() && bitmap.profile.is_none() {
+        let encoder = PNGEncoder::new(output);
+        return encoder.encode(&bitmap.pixels[..], bounds.0 as u32, bounds.1 as u32,
+                              bitmap.color);
+    }
+
+    let mut png = Vec::new();
+    try!(PNGEncoder::new(&mut png).encode(&bitmap.pixels[..],
+                                          bounds.0 as u32, bounds.1 as u32,
+                                          bitmap.color));
+    if let Some(gamma) = bitmap.gamma {
+        // The gAMA chunk holds the exponent that encodes linear light, times
+        // 100000. Readers that understand sRGB use that chunk instead; the
+        // standard asks that we give both, with the gamma sRGB approximates.
+        let exponent = match gamma {
+            coloring::Gamma::Srgb => 1.0 / 2.2,
+            coloring::Gamma::Power(gamma) => 1.0 / gamma
+        };
+        chunks::insert(&mut png, b"gAMA", &bytes::be32((exponent * 100_000.0).round() as u32));
+        // An ICC profile takes the place of the sRGB chunk; the standard
+        // forbids having both.
+        if gamma == coloring::Gamma::Srgb && bitmap.profile.is_none() {
+            // Perceptual rendering intent.
+            chunks::insert(&mut png, b"sRGB", &[0]);
+        }
+    }
+    if let Some(ref profile) = bitmap.profile {
+        // The iCCP chunk holds the profile's name, a null byte, the
+        // compression method (always zero, for zlib), and the profile,
+        // compressed.
+        let mut data = b"ICC profile\0\0".to_vec();
+        data.extend(chunks::zlib(profile));
+        chunks::insert(&mut png, b"iCCP", &data);
+    }
+    output.write_all(&png)
+}
+
+/// Return `bitmap`, whose dimensions are given by `bounds`, encoded as a JPEG
+/// image at `quality`. JPEG has no way to record gamma, but if the bitmap has
+/// an ICC profile, embed it.
+fn encode_jpeg(bitmap: &Bitmap, bounds: (usize, usize), quality: u8) -> io::Result<Vec<u8>> {
+    match bitmap.color {
+        ColorType::Gray(8) | ColorType::RGB(8) => (),
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                       "JPEG images must be eight-bit gray or RGB, \
+                                        without transparency"))
+    }
+    let mut jpeg = Vec::new();
+    try!(JPEGEncoder::new_with_quality(&mut jpeg, quality)
+         .encode(&bitmap.pixels[..], bounds.0 as u32, bounds.1 as u32, bitmap.color));
+    if let Some(ref profile) = bitmap.profile {
+        // A profile goes in APP2 segments right after the start-of-image
+        // marker, split into pieces small enough for a segment, each tagged
+        // with its sequence number and the number of pieces.
+        let pieces: Vec<&[u8]> = profile.chunks(ICC_SEGMENT_DATA).collect();
+        let mut segments = Vec::new();
+        for (i, piece) in pieces.iter().enumerate() {
+            let length = 2 + ICC_SEGMENT_HEADER.len() + 2 + piece.len();
+            segments.extend_from_slice(&[0xff, 0xe2, (length >> 8) as u8, length as u8]);
+            segments.extend_from_slice(ICC_SEGMENT_HEADER);
+            segments.extend_from_slice(&[i as u8 + 1, pieces.len() as u8]);
+            segments.extend_from_slice(piece);
+        }
+        // JFIF asks that its APP0 segment come first, so go after that.
+        let mut start = 2;
+        if jpeg[2..4] == [0xff, 0xe0] {
+            start += 2 + ((jpeg[4] as usize) << 8 | jpeg[5] as usize);
+        }
+        let rest = jpeg.split_off(start);
+        jpeg.extend(segments);
+        jpeg.extend(rest);
+    }
+    Ok(jpeg)
+}
+
+/// The identifier that starts each JPEG segment holding part of an ICC
+/// profile, and the most profile data one such segment can hold, after its
+/// length, that identifier, and the two bytes numbering the pieces.
+const ICC_SEGMENT_HEADER: &'static [u8; 12] = b"ICC_PROFILE\0";
+const ICC_SEGMENT_DATA: usize = 65535 - 2 - 12 - 2;
+
+extern crate crossbeam;
+extern crate atomic_chunks_mut;
+
+use atomic_chunks_mut::AtomicChunksMut;
+
+/// Render the area of the complex plane that `transform` maps to into a freshly
+/// allocated buffer, whose dimensions are given by `bounds`. See `render` for
+/// the meaning of `pixel`.
+///
+/// The work is split into single-row bands, which a pool of `threads` threads
+/// draw from until none remain. With no threads, nothing would be drawn, so
+/// `check_config` makes sure there is at least one.
+fn render_parallel<T, F>(bounds: (usize, usize), transform: &Transform, threads: usize,
+                         pixel: F)
+    -> Vec<T>
+    where T: Clone + Default + Send, F: Fn(Complex<f64>) -> T + Sync
+{
+    assert!(threads > 0, "render_parallel needs at least one thread");
+    let mut buffer = vec![T::default(); bounds.0 * bounds.1];
+
+    {
+        let bands = AtomicChunksMut::new(&mut buffer, bounds.0);
+        crossbeam::scope(|scope| {
+            for i in 0..threads {
+                scope.spawn(|| {
+                    for (i, band) in &bands {
+                        let top = i;
+                        let height = band.len() / bounds.0;
+                        let band_bounds = (bounds.0, height);
+                        render(band, band_bounds, &transform.from_row(top), &pixel);
+                    }
+                });
+            }
+        });
+    }
+
+    buffer
+}
+
+/// How many samples each stream of random numbers provides to a Buddhabrot
+/// render. Threads take whole streams, so that the image doesn't depend on how
+/// the work is divided among them.
+const SAMPLES_PER_STREAM: u64 = 10_000;
+
+/// Accumulate Buddhabrot histograms for `view`, one for each limit in `limits`.
+///
+/// We choose values of `c` at random, as many per pixel as `config` asks, from
+/// the square that just holds the escape circle, and follow each one's orbit
+/// under `formula`. If the orbit escapes within a limit, its points are counted
+/// in that limit's histogram. If `config` asks for an
+/// anti-Buddhabrot, it is the other way around: we count the points of orbits
+/// that don't escape within the limit.
+fn buddhabrot<F: FractalFormula>(view: &View, config: &Config, formula: &F, limits: &[u32])
+    -> Vec<buddhabrot::Histogram>
+{
+    let pixels = view.bounds.0 * view.bounds.1;
+    let samples = pixels as u64 * config.samples() as u64;
+    let radius = config.escape_radius();
+    let seed = config.seed.unwrap_or(0);
+    let anti = config.anti.unwrap_or(false);
+    let transform = view.transform();
+    let limit = limits.iter().cloned().max().unwrap_or(0);
+    let streams = (samples + SAMPLES_PER_STREAM - 1) / SAMPLES_PER_STREAM;
+    let next_stream = AtomicUsize::new(0);
+
+    let partials: Vec<Vec<buddhabrot::Histogram>> = crossbeam::scope(|scope| {
+        let workers: Vec<_> = (0..config.threads()).map(|_| scope.spawn(|| {
+            let mut histograms = vec![buddhabrot::Histogram::new(pixels); limits.len()];
+            let mut orbit = Vec::with_capacity(limit as usize);
+            loop {
+                let stream = next_stream.fetch_add(1, Ordering::SeqCst) as u64;
+                if stream >= streams {
+                    break;
+                }
+                let mut rng = random::Rng::new(seed, stream);
+                let count = cmp::min(SAMPLES_PER_STREAM, samples - stream * SAMPLES_PER_STREAM);
+                for _ in 0..count {
+                    let c = Complex { re: (rng.next_f64() * 2.0 - 1.0) * radius,
+                                      im: (rng.next_f64() * 2.0 - 1.0) * radius };
+                    let escaped = trace(formula, c, limit, &mut orbit);
+                    for (histogram, &channel_limit) in histograms.iter_mut().zip(limits) {
+                        let escaped_in_time = match escaped {
+                            Some(i) => i < channel_limit,
+                            None => false
+                        };
+                        if escaped_in_time == anti {
+                            continue;
+                        }
+                        // A channel with a lower limit than we followed the
+                        // orbit for sees only the start of it.
+                        for z in orbit.iter().take(channel_limit as usize) {
+                            if let Some((column, row)) =
+                                point_to_pixel(&transform, view.bounds, (z.re, z.im))
+                            {
+                                histogram.add(row * view.bounds.0 + column);
+                            }
+                        }
+                    }
+                }
+            }
+            histograms
+        })).collect();
+        workers.into_iter().map(|worker| worker.join()).collect()
+    });
+
+    let mut totals = vec![buddhabrot::Histogram::new(pixels); limits.len()];
+    for partial in &partials {
+        for (total, histogram) in totals.iter_mut().zip(partial) {
+            total.merge(histogram);
+        }
+    }
+    totals
+}
+
+/// Return the palette that `config` asks us to color with.
+fn resolve_palette(config: &Config) -> Result<coloring::Palette, Error> {
+    if let Some(ref stops) = config.gradient {
+        let stops = try!(gradient::parse(stops).map_err(|why| {
+            Error::usage(format!("bad gradient '{}': {}", stops, why))
+        }));
+        let space = config.gradient_space.unwrap_or(ColorSpace::Rgb);
+        return Ok(gradient::palette(&stops, space));
+    }
+    if let Some(ref path) = config.palette_file {
+        // Ultra Fractal gradient files hold many gradients, so those must
+        // name the one to use, as FILE.ugr:NAME.
+        if arg_path_has_extension(path, ".ugr") {
+            let (file, name) = try!(parse_entry_arg(path));
+            return ugr::load(file, name).map_err(|e| {
+                Error::from_io(&format!("error reading gradient file '{}'", file.display()), e)
+            });
+        }
+        return coloring::load_map(Path::new(path)).map_err(|e| {
+            Error::from_io(&format!("error reading palette file '{}'", path), e)
+        });
+    }
+    let name = config.palette.as_ref().map_or(coloring::DEFAULT_PALETTE, |name| &name[..]);
+    coloring::Palette::named(name).ok_or_else(|| {
+        Error::usage(format!("unknown palette '{}'; expected one of {}",
+                             name, coloring::palette_names()))
+    })
+}
+
+/// Return the coloring that `config` asks us to use for escape times.
+fn resolve_coloring(config: &Config) -> Result<coloring::Coloring, Error> {
+    let transfer = match config.transfer {
+        Some(ref transfer) => try!(parse_transfer(transfer).map_err(|why| {
+            Error::usage(format!("bad transfer function '{}': {}", transfer, why))
+        })),
+        None => coloring::Transfer::Linear
+    };
+    let equalize = config.equalize.unwrap_or(false);
+    let cycle = match (config.palette_period, config.palette_offset) {
+        (Some(_), _) if equalize => {
+            return Err(Error::usage("palette cycling can't be combined with \
+                                     histogram equalization"));
+        }
+        (Some(period), offset) => Some((period, offset.unwrap_or(0))),
+        (None, Some(_)) => {
+            return Err(Error::usage("a palette offset needs a palette period"));
+        }
+        (None, None) => None
+    };
+    if config.auto_expose.is_some() && (equalize || cycle.is_some()) {
+        return Err(Error::usage("auto exposure can't be combined with histogram \
+                                 equalization or palette cycling"));
+    }
+    let depth = match config.depth.unwrap_or(8) {
+        depth @ 8 | depth @ 16 => depth,
+        depth => return Err(Error::usage(format!("bad bit depth {}: expected 8 or 16", depth)))
+    };
+    let dither = config.dither.unwrap_or(false);
+    if dither && depth != 8 {
+        return Err(Error::usage("dithering only applies to images with a bit depth of 8"));
+    }
+    let gamma = match config.gamma {
+        Some(ref gamma) => Some(try!(parse_gamma(gamma).map_err(|why| {
+            Error::usage(format!("bad gamma '{}': {}", gamma, why))
+        }))),
+        None => None
+    };
+    Ok(coloring::Coloring {
+        palette: try!(resolve_palette(config)),
+        transfer: transfer,
+        equalize: equalize,
+        exposure: config.auto_expose,
+        cycle: cycle,
+        depth: depth,
+        gamma: gamma,
+        dither: dither,
+        transparent: config.interior == Some(Interior::Transparent),
+        profile: try!(resolve_profile(config)),
+        legend: config.legend
+    })
+}
+
+#[test]
+fn test_resolve_coloring() {
+    assert!(resolve_coloring(&Config::default()).is_ok());
+    let cycled = Config { palette_period: Some(64), .. Config::default() };
+    assert_eq!(resolve_coloring(&cycled).unwrap().cycle, Some((64, 0)));
+    let exposed = Config { auto_expose: Some((1.0, 99.0)), .. Config::default() };
+    assert_eq!(resolve_coloring(&exposed).unwrap().exposure, Some((1.0, 99.0)));
+}
+
+/// Return how `config` asks us to color points whose orbits never escape, as
+/// far as rendering is concerned. Transparent points are rendered as black
+/// ones would be, and left to `paint` to make transparent.
+fn resolve_interior(config: &Config) -> Interior {
+    match config.interior.unwrap_or(Interior::Black) {
+        Interior::Transparent => Interior::Black,
+        interior => interior
+    }
+}
+
+/// Return the ICC profile that `config` asks us to embed in the image, if any:
+/// our own sRGB profile, or one read from a file.
+fn resolve_profile(config: &Config) -> Result<Option<Vec<u8>>, Error> {
+    match config.icc_profile {
+        Some(ref profile) if profile == "srgb" => Ok(Some(icc::srgb())),
+        Some(ref profile) => icc::load(Path::new(profile)).map(Some).map_err(|e| {
+            Error::from_io(&format!("error reading ICC profile '{}'", profile), e)
+        }),
+        None => Ok(None)
+    }
+}
+
+/// Return the orbit trap that `config` asks us to color by, if any.
+fn resolve_trap(config: &Config) -> Result<Option<Trap>, Error> {
+    match config.trap {
+        Some(ref trap) => parse_trap(trap).map(Some).map_err(|why| {
+            Error::usage(format!("bad orbit trap '{}': {}", trap, why))
+        }),
+        None => Ok(None)
+    }
+}
+
+/// Return the set that `config` asks us to draw.
+fn resolve_set(config: &Config) -> Result<Set, Error> {
+    match (config.set.unwrap_or(config::SetKind::Mandelbrot), config.c) {
+        (config::SetKind::Mandelbrot, _) => {
+            let z0 = config.z0.unwrap_or_else(|| {
+                config.formulas().first().cloned().unwrap_or(Formula::Standard)
+                    .critical_point()
+            });
+            Ok(Set::Mandelbrot(Complex { re: z0.0, im: z0.1 }))
+        }
+        (config::SetKind::Julia, _) if config.z0.is_some() => {
+            Err(Error::usage("--z0 only applies to Mandelbrot sets; in a Julia set, \
+                              each pixel's point is its orbit's starting value"))
+        }
+        (config::SetKind::Julia, Some(c)) => Ok(Set::Julia(Complex { re: c.0, im: c.1 })),
+        (config::SetKind::Julia, None) =>
+            Err(Error::usage("drawing a Julia set requires a value for c (use --c)"))
+    }
+}
+
+#[test]
+fn test_resolve_set() {
+    let origin = Complex { re: 0.0, im: 0.0 };
+    assert_eq!(resolve_set(&Config::default()).unwrap(), Set::Mandelbrot(origin));
+    let lambda = Config { formula: Some(Formula::Lambda), .. Config::default() };
+    assert_eq!(resolve_set(&lambda).unwrap(), Set::Mandelbrot(Complex { re: 0.5, im: 0.0 }));
+    let perturbed = Config { z0: Some((0.1, -0.2)), .. lambda };
+    assert_eq!(resolve_set(&perturbed).unwrap(),
+               Set::Mandelbrot(Complex { re: 0.1, im: -0.2 }));
+
+    let julia = Config {
+        set: Some(config::SetKind::Julia),
+        c: Some((-0.8, 0.156)),
+        .. Config::default()
+    };
+    assert_eq!(resolve_set(&julia).unwrap(), Set::Julia(Complex { re: -0.8, im: 0.156 }));
+    assert!(resolve_set(&Config { z0: Some((0.1, 0.0)), .. julia.clone() }).is_err());
+    assert!(resolve_set(&Config { c: None, .. julia }).is_err());
+}
+
+/// Return the `c` of the Julia set whose outline `config` asks us to draw by
+/// inverse iteration. Only the standard squaring iteration can be run
+/// backwards.
+fn resolve_inverse(config: &Config) -> Result<Complex<f64>, Error> {
+    if config.formulas() != [Formula::Standard] || config.formula_expr.is_some()
+        || config.power() != 2.0
+    {
+        return Err(Error::usage("--mode inverse only draws the standard iteration, \
+                                 z = z^2 + c"));
+    }
+    match try!(resolve_set(config)) {
+        Set::Julia(c) => Ok(c),
+        Set::Mandelbrot(_) => {
+            Err(Error::usage("--mode inverse only draws Julia sets; use --set julia and --c"))
+        }
+    }
+}
+
+/// Return the quaternion Julia set that `config` asks us to draw, if any.
+fn resolve_quat_julia(config: &Config) -> Result<Option<QuatJulia>, Error> {
+    if !config.formulas().contains(&Formula::QuatJulia) {
+        return Ok(None);
+    }
+    if config.hybrid.is_some() || config.morph_to.is_some() {
+        return Err(Error::usage("the quat-julia formula can't be part of a hybrid or morph"));
+    }
+    if config.mode.unwrap_or(Mode::Escape) != Mode::Escape {
+        return Err(Error::usage("the quat-julia formula can only be used with --mode escape"));
+    }
+    if config.power() != 2.0 {
+        return Err(Error::usage("the quat-julia formula only squares; it can't use --power"));
+    }
+    let c = match try!(resolve_set(config)) {
+        Set::Julia(c) => c,
+        Set::Mandelbrot(_) => {
+            return Err(Error::usage("the quat-julia formula draws Julia sets; \
+                                     use --set julia and --c"));
+        }
+    };
+    Ok(Some(QuatJulia {
+        c: Quaternion { r: c.re, i: c.im, j: 0.0, k: 0.0 },
+        slice: config.slice.unwrap_or(config::DEFAULT_SLICE),
+        radius: config.escape_radius()
+    }))
+}
+
+/// Return the parsed formula expression that `config` asks us to iterate, if
+/// any.
+fn resolve_expression(config: &Config) -> Result<Option<expr::Expr>, Error> {
+    match config.formula_expr {
+        Some(ref text) => expr::parse(text).map(Some).map_err(|why| {
+            Error::parse(format!("error parsing formula expression '{}': {}", text, why))
+        }),
+        None => Ok(None)
+    }
+}
+
+/// Return the iteration steps, formula, power, and so on, that `config` asks
+/// for. There is one step for each formula in a hybrid, and just one otherwise.
+/// If `config` gives a formula expression, `expression` should be its parsed
+/// form, as returned by `resolve_expression`.
+fn resolve_steps<'a>(config: &Config, expression: Option<&'a expr::Expr>)
+    -> Result<Vec<Step<'a>>, Error>
+{
+    if let Some(expression) = expression {
+        return Ok(vec![Step {
+            formula: Formula::Standard,
+            power: Power::Integer(2),
+            p: Complex { re: 0.0, im: 0.0 },
+            relaxation: Complex { re: 1.0, im: 0.0 },
+            morph: None,
+            expression: Some(expression)
+        }]);
+    }
+    let power = match config.power() {
+        d if d > 1.0 => Power::new(d),
+        d => return Err(Error::usage(format!("the power must be greater than 1, not {}", d)))
+    };
+    let p = config.phoenix_p.unwrap_or(config::DEFAULT_PHOENIX_P);
+    let relaxation = config.relaxation.unwrap_or(config::DEFAULT_RELAXATION);
+    let formulas = config.formulas();
+    if formulas.is_empty() {
+        return Err(Error::usage("a hybrid needs at least one formula"));
+    }
+    let morph = config.morph();
+    match morph {
+        Some((Formula::QuatJulia, _)) => {
+            return Err(Error::usage("can't morph into the quat-julia formula"));
+        }
+        Some((_, t)) if t < 0.0 || t > 1.0 => {
+            return Err(Error::usage(format!("the morph must be from 0 to 1, not {}", t)));
+        }
+        None if config.morph.is_some() => {
+            return Err(Error::usage("--morph needs a formula to morph into (use --morph-to)"));
+        }
+        _ => ()
+    }
+    Ok(formulas.into_iter()
+       .map(|formula| Step {
+           formula: formula,
+           power: power,
+           p: Complex { re: p.0, im: p.1 },
+           relaxation: Complex { re: relaxation.0, im: relaxation.1 },
+           morph: morph,
+           expression: None
+       })
+       .collect())
+}
+
+/// Return the polynomial whose Newton fractal `config` asks us to draw.
+fn resolve_polynomial(config: &Config) -> Result<newton::Polynomial, Error> {
+    let coefficients = config.polynomial.clone()
+        .unwrap_or_else(|| config::DEFAULT_POLYNOMIAL.to_vec());
+    newton::Polynomial::new(&coefficients).map_err(Error::usage)
+}
+
+extern crate rhai;
+
+/// Return the script that `config` asks us to iterate with, if any.
+fn resolve_script(config: &Config) -> Result<Option<script::Script>, Error> {
+    let path = match config.script {
+        Some(ref path) => path,
+        None => return Ok(None)
+    };
+    if config.mode.unwrap_or(Mode::Escape) != Mode::Escape {
+        return Err(Error::usage("scripts can only be used with --mode escape"));
+    }
+    script::Script::load(Path::new(path)).map(Some).map_err(|why| {
+        Error::parse(format!("error loading script '{}': {}", path, why))
+    })
+}
+
+use std::sync::Mutex;
+
+/// Render `view` by running `script`, with the other settings in `config`, and
+/// return its pixels.
+fn render_script(view: &View, config: &Config, script: &script::Script)
+    -> Result<Bitmap, Error>
+{
+    let set = try!(resolve_set(config));
+    let limit = config.iterations();
+    let radius = config.escape_radius();
+    let coloring = try!(resolve_coloring(config));
+    if config.interior.unwrap_or(Interior::Black) != Interior::Black {
+        return Err(Error::usage("scripts can only leave points that never escape black"));
+    }
+    if config.trap.is_some() {
+        return Err(Error::usage("scripts can't be drawn with orbit traps"));
+    }
+    if config.coloring.unwrap_or(Exterior::Escape) != Exterior::Escape {
+        return Err(Error::usage("scripts can only color points by escape time"));
+    }
+    if config.light_angle.is_some() {
+        return Err(Error::usage("scripts can't be drawn with lighting"));
+    }
+    if script.has_color() && coloring.depth != 8 {
+        return Err(Error::usage("scripts that choose their own colors can only be \
+                                 drawn with a bit depth of 8"));
+    }
+
+    // A script can fail at any pixel. Stop running it once one has, and
+    // report the first failure. Pixels the script doesn't color itself get
+    // only a count here, and are colored from the palette afterwards.
+    let failure = Mutex::new(None);
+    let pixels = render_parallel(view.bounds, &view.transform(), config.threads(),
+                                 |point| {
+        if failure.lock().unwrap().is_some() {
+            return (limit, [0, 0, 0]);
+        }
+        let (z, c) = set.start(point);
+        let pixel = script.escapes(z, c, limit, radius).and_then(|(count, z)| {
+            let color = if script.has_color() { try!(script.color(count, z)) } else { [0; 3] };
+            Ok((count.unwrap_or(limit), color))
+        });
+        pixel.unwrap_or_else(|why| {
+            failure.lock().unwrap().get_or_insert(why);
+            (limit, [0, 0, 0])
+        })
+    });
+
+    if let Some(why) = failure.into_inner().unwrap() {
+        return Err(Error::parse(format!("error running script '{}': {}",
+                                        config.script.as_ref().unwrap(), why)));
+    }
+    if !script.has_color() {
+        let samples: Vec<Sample> = pixels.iter().map(|&(count, _)| Sample::Count(count)).collect();
+        return Ok(paint(&samples, view.bounds.0, limit, &coloring));
+    }
+    Ok(Bitmap {
+        pixels: pixels.iter().flat_map(|&(_, rgb)| rgb.to_vec()).collect(),
+        color: ColorType::RGB(8),
+        gamma: None,
+        profile: None
+    })
+}
+
+/// Follow the orbit of each point of `view` under `formula`, and color the
+/// results as `coloring` directs. If `config` asks for an orbit trap, color
+/// every point by its orbit's closest approach to the trap, blended with its
+/// escape time as `trap_blend` says; otherwise, color points that escape as
+/// its `coloring` setting asks, and those that never do, as `interior` asks.
+fn render_escapes<F: FractalFormula>(formula: &F, view: &View, config: &Config)
+    -> Result<Vec<Sample>, Error>
+{
+    if config.coloring == Some(Exterior::Distance) || config.light_angle.is_some() {
+        return Err(Error::usage("distance estimation and lighting only work for \
+                                 escape-time images of complex formulas"));
+    }
+    let limit = config.iterations();
+    let radius = config.escape_radius();
+    let interior = resolve_interior(config);
+    let trap = try!(resolve_trap(config));
+    let blend = config.trap_blend.unwrap_or(Blend::Replace);
+    let exterior = config.coloring.unwrap_or(Exterior::Escape);
+    let power = config.power.unwrap_or(2.0);
+    let stripe_density = config.stripe_density.unwrap_or(config::DEFAULT_STRIPE_DENSITY);
+    let samples = render_parallel(view.bounds, &view.transform(), config.threads(), |point| {
+        if let Some(trap) = trap {
+            let mut closest = f64::INFINITY;
+            let count = follow(formula, point, limit, |z, _| {
+                closest = closest.min(trap.distance(z))
+            });
+            let fraction = (closest / radius).min(1.0);
+            return match blend {
+                Blend::Replace => Sample::Fraction(fraction),
+                blend => Sample::Blend(count.unwrap_or(limit), fraction, blend)
+            };
+        }
+        let addend = match exterior {
+            Exterior::Escape if interior == Interior::Black => {
+                return Sample::Count(escapes(formula, point, limit).unwrap_or(limit));
+            }
+            Exterior::Escape => None,
+            Exterior::Tia => Some(average::Addend::Triangle),
+            Exterior::Stripes => Some(average::Addend::Stripes(stripe_density)),
+            Exterior::Curvature => Some(average::Addend::Curvature),
+            Exterior::Binary | Exterior::Angle | Exterior::Distance | Exterior::Atom => None
+        };
+        let mut stats = OrbitStats::new();
+        let mut average = addend.map(|addend| average::Average::new(addend, power));
+        let count = follow(formula, point, limit, |z, c| {
+            stats.visit(z);
+            if let Some(ref mut average) = average {
+                average.visit(z, c);
+            }
+        });
+        match (count, average) {
+            _ if exterior == Exterior::Atom => Sample::Count(stats.nearest),
+            (Some(_), Some(average)) => Sample::Fraction(average.value(radius)),
+            (Some(_), None) if exterior == Exterior::Binary => {
+                Sample::Fraction(if stats.last.im < 0.0 { 0.0 } else { 1.0 })
+            }
+            (Some(_), None) if exterior == Exterior::Angle => Sample::Fraction(turns(stats.last)),
+            (Some(count), None) => Sample::Count(count),
+            (None, _) if interior == Interior::Black => Sample::Count(limit),
+            (None, _) if interior == Interior::Period => Sample::Period(stats.period),
+            (None, _) => Sample::Fraction(stats.measure(interior, radius))
+        }
+    });
+    Ok(samples)
+}
+
+/// Draw the boundary of the set for `iteration` in `view`, by estimating each
+/// point's distance from it. Points within `distance_thickness` pixels of the
+/// boundary, or inside the set, take the palette's last color, and points
+/// farther out fade toward its first color over that same distance, so the
+/// filaments come out as crisp lines of even width at any zoom.
+fn render_distance(iteration: &Iteration, view: &View, config: &Config)
+    -> Result<Vec<Sample>, Error>
+{
+    try!(check_distance(iteration.steps));
+    if config.trap.is_some() || resolve_interior(config) != Interior::Black {
+        return Err(Error::usage("distance estimation can't be combined with orbit traps \
+                                 or interior coloring"));
+    }
+    let limit = config.iterations();
+    let (width, height) = view.pixel_size();
+    let thickness = config.distance_thickness.unwrap_or(config::DEFAULT_DISTANCE_THICKNESS)
+        * width.abs().max(height.abs());
+    Ok(render_parallel(view.bounds, &view.transform(), config.threads(), |point| {
+        match estimate_distance(iteration, point, limit) {
+            Some(distance) => Sample::Fraction((2.0 - distance / thickness).max(0.0).min(1.0)),
+            None => Sample::Count(limit)
+        }
+    }))
+}
+
+/// Draw the set for `iteration` in `view` as `render_escapes` would, coloring
+/// points by escape time, but lit as if the outside of the set were a surface
+/// raised in relief, with a light shining from `degrees` counterclockwise from
+/// the positive real axis.
+fn render_lit(iteration: &Iteration, view: &View, config: &Config, degrees: f64)
+    -> Result<Vec<Sample>, Error>
+{
+    try!(check_distance(iteration.steps));
+    if config.trap.is_some() || resolve_interior(config) != Interior::Black
+        || config.coloring.unwrap_or(Exterior::Escape) != Exterior::Escape
+    {
+        return Err(Error::usage("lighting only works with escape-time coloring, without \
+                                 orbit traps or interior coloring"));
+    }
+    let limit = config.iterations();
+    let radians = degrees.to_radians();
+    let light = Complex { re: radians.cos(), im: radians.sin() };
+    Ok(render_parallel(view.bounds, &view.transform(), config.threads(), |point| {
+        match follow_derivative(iteration, point, limit) {
+            Some((count, z, dz)) => Sample::Lit(count, brightness(z, dz, light)),
+            None => Sample::Count(limit)
+        }
+    }))
+}
+
+/// Return a normal map for `view`, as `config` would draw it: an image in
+/// which each pixel's color encodes the direction the surface that
+/// `--light-angle` lights would face there, for relighting elsewhere. Points
+/// inside the set face straight out of the image.
+fn render_normals(view: &View, config: &Config) -> Result<Bitmap, Error> {
+    if config.script.is_some() || config.mode.unwrap_or(Mode::Escape) != Mode::Escape
+        || try!(resolve_quat_julia(config)).is_some()
+    {
+        return Err(Error::usage("normal maps can only be drawn for escape-time images \
+                                 of complex formulas"));
+    }
+    let expression = try!(resolve_expression(config));
+    let steps = try!(resolve_steps(config, expression.as_ref()));
+    try!(check_distance(&steps));
+    let iteration = Iteration {
+        set: try!(resolve_set(config)),
+        steps: &steps,
+        radius: config.escape_radius()
+    };
+    let limit = config.iterations();
+    let normals = render_parallel(view.bounds, &view.transform(), config.threads(), |point| {
+        match follow_derivative(&iteration, point, limit) {
+            Some((_, z, dz)) => normal_color(z, dz, view.rotation),
+            None => [128, 128, 255]
+        }
+    });
+    Ok(Bitmap {
+        pixels: normals.iter().flat_map(|rgb| rgb.to_vec()).collect(),
+        color: ColorType::RGB(8),
+        gamma: None,
+        profile: None
+    })
+}
+
+/// Return the iteration count of an orbit that escaped at step `count` of
+/// `z^degree + c` to a point of the given `magnitude`, past the escape circle
+/// of `radius`, smoothed to run continuously from one count to the next.
+///
+/// The further past the circle the orbit got, the sooner it must have been
+/// close to escaping: an orbit that just crosses the circle gets `count + 1`,
+/// and one that reaches `radius^degree`, as if it had crossed the circle a
+/// step earlier, gets `count`. In between, the count is
+/// `count + 1 - log_degree(ln |z| / ln radius)`. Iterations with no degree
+/// greater than one get the plain count.
+fn smooth_count(count: u32, magnitude: f64, radius: f64, degree: f64) -> f64 {
+    if degree <= 1.0 {
+        return count as f64;
+    }
+    let escape = (magnitude.ln() / radius.ln()).max(1.0);
+    count as f64 + 1.0 - escape.ln() / degree.ln()
+}
+
+#[test]
+fn test_smooth_count() {
+    assert_eq!(smooth_count(7, 2.0, 2.0, 2.0), 8.0);
+    assert_eq!(smooth_count(7, 4.0, 2.0, 2.0), 7.0);
+    assert_eq!(smooth_count(7, 8.0, 2.0, 3.0), 7.0);
+    assert!((smooth_count(7, 100.0, 10.0, 2.0) - 7.0).abs() < 1e-12);
+    assert!((smooth_count(2, 5.0, 2.0, 2.0) - 1.784677).abs() < 1e-6);
+    assert_eq!(smooth_count(7, 5.0, 2.0, 1.0), 7.0);
+}
+
+/// Return the channels of a floating-point image of `view`, as `config` would
+/// draw it, each a name and its values: `Y`, each point's smooth iteration
+/// count, and whichever of `distance` and `magnitude` `extra` asks for.
+/// Points whose orbits never escape have a count of the iteration limit and a
+/// distance of zero.
+fn render_field(view: &View, config: &Config, extra: &[ExrChannel])
+    -> Result<Vec<(&'static str, Vec<f32>)>, Error>
+{
+    try!(check_field(config, extra));
+    let expression = try!(resolve_expression(config));
+    let steps = try!(resolve_steps(config, expression.as_ref()));
+    let iteration = Iteration {
+        set: try!(resolve_set(config)),
+        steps: &steps,
+        radius: config.escape_radius()
+    };
+    let limit = config.iterations();
+    let values = render_parallel(view.bounds, &view.transform(), config.threads(), |point| {
+        let (count, z, dz) = follow_to_end(&iteration, point, limit);
+        let magnitude = z.norm();
+        match count {
+            Some(count) => {
+                let degree = steps[count as usize % steps.len()].power.exponent();
+                let smooth = smooth_count(count, magnitude, iteration.radius, degree);
+                let distance = magnitude * magnitude.ln() / (2.0 * dz.norm());
+                [smooth as f32, distance as f32, magnitude as f32]
+            }
+            None => [limit as f32, 0.0, magnitude as f32]
+        }
+    });
+
+    let channel = |i: usize| values.iter().map(|value| value[i]).collect();
+    let mut channels = vec![("Y", channel(0))];
+    if extra.contains(&ExrChannel::Distance) {
+        channels.push(("distance", channel(1)));
+    }
+    if extra.contains(&ExrChannel::Magnitude) {
+        channels.push(("magnitude", channel(2)));
+    }
+    Ok(channels)
+}
+
+/// Check that `render_field` can draw the image `config` describes, with the
+/// channels `extra` asks for.
+fn check_field(config: &Config, extra: &[ExrChannel]) -> Result<(), Error> {
+    if config.script.is_some() || config.mode.unwrap_or(Mode::Escape) != Mode::Escape
+        || try!(resolve_quat_julia(config)).is_some()
+    {
+        return Err(Error::usage("floating-point images can only be drawn for escape-time \
+                                 images of complex formulas"));
+    }
+    if extra.contains(&ExrChannel::Distance) {
+        let expression = try!(resolve_expression(config));
+        try!(check_distance(&try!(resolve_steps(config, expression.as_ref()))));
+    }
+    Ok(())
+}
+
+/// Return the number of iterations the orbit of each pixel's point takes to
+/// escape with the settings in `config`, or the iteration limit if it never
+/// does, whatever coloring `config` asks for. The pixels are those of an image
+/// whose dimensions are given by `bounds`, mapped to the plane by `transform`.
+/// `option` is the command-line option asking for the counts, for errors.
+fn render_counts(bounds: (usize, usize), transform: &Transform, config: &Config,
+                 option: &str)
+    -> Result<Vec<u32>, Error>
+{
+    let limit = config.iterations();
+    try!(check_counts(config, option));
+    if let Some(quat_julia) = try!(resolve_quat_julia(config)) {
+        return Ok(render_parallel(bounds, transform, config.threads(), |point| {
+            escapes(&quat_julia, point, limit).unwrap_or(limit)
+        }));
+    }
+    let expression = try!(resolve_expression(config));
+    let steps = try!(resolve_steps(config, expression.as_ref()));
+    let iteration = Iteration {
+        set: try!(resolve_set(config)),
+        steps: &steps,
+        radius: config.escape_radius()
+    };
+    Ok(render_parallel(bounds, transform, config.threads(), |point| {
+        escapes(&iteration, point, limit).unwrap_or(limit)
+    }))
+}
+
+/// Check that `render_counts` can count the iterations of the image `config`
+/// describes, as `option` asks.
+fn check_counts(config: &Config, option: &str) -> Result<(), Error> {
+    if config.script.is_some() {
+        return Err(Error::usage("iteration counts can't be saved for scripted formulas"));
+    }
+    if try!(resolve_quat_julia(config)).is_none()
+        && config.mode.unwrap_or(Mode::Escape) != Mode::Escape
+    {
+        return Err(Error::usage(format!("only escape-time images can be saved with {}",
+                                        option)));
+    }
+    Ok(())
+}
+
+/// Follow the orbits of the points in `view` with the settings in `config`,
+/// and return what we find out about each, ready to be painted. Return `None`
+/// if `config` asks for a kind of image that isn't drawn from samples.
+fn render_samples(view: &View, config: &Config) -> Result<Option<Vec<Sample>>, Error> {
+    if config.script.is_some() {
+        return Ok(None);
+    }
+    if let Some(quat_julia) = try!(resolve_quat_julia(config)) {
+        return render_escapes(&quat_julia, view, config).map(Some);
+    }
+    if config.mode.unwrap_or(Mode::Escape) != Mode::Escape {
+        return Ok(None);
+    }
+    let expression = try!(resolve_expression(config));
+    let steps = try!(resolve_steps(config, expression.as_ref()));
+    let iteration = Iteration {
+        set: try!(resolve_set(config)),
+        steps: &steps,
+        radius: config.escape_radius()
+    };
+    if let Some(degrees) = config.light_angle {
+        return render_lit(&iteration, view, config, degrees).map(Some);
+    }
+    if config.coloring == Some(Exterior::Distance) {
+        return render_distance(&iteration, view, config).map(Some);
+    }
+    render_escapes(&iteration, view, config).map(Some)
+}
+
+/// Render `view` with the settings in `config`, and return its pixels.
+fn render_view(view: &View, config: &Config) -> Result<Bitmap, Error> {
+    render_view_as(view, config, None)
+}
+
+/// Render `view` as `render_view` does, but if `gray` is given, paint
+/// escape-time images as grayscale or RGB as it says, whatever colors they turn
+/// out to use.
+fn render_view_as(view: &View, config: &Config, gray: Option<bool>) -> Result<Bitmap, Error> {
+    let limit = config.iterations();
+    if let Some(script) = try!(resolve_script(config)) {
+        return render_script(view, config, &script);
+    }
+    if config.mode.unwrap_or(Mode::Escape) == Mode::Escape
+        || try!(resolve_quat_julia(config)).is_some()
+    {
+        let coloring = try!(resolve_coloring(config));
+        let samples = try!(render_samples(view, config)).unwrap();
+        let gray = gray.unwrap_or_else(|| coloring.is_gray(&samples));
+        return Ok(paint_as(&samples, view.bounds.0, limit, &coloring, gray));
+    }
+    let mode = config.mode.unwrap_or(Mode::Escape);
+    if mode != Mode::Escape && config.depth.unwrap_or(8) != 8 {
+        return Err(Error::usage("only escape-time images can be drawn with a bit depth \
+                                 other than 8"));
+    }
+    match mode {
+        Mode::Escape => unreachable!(),
+        Mode::Buddhabrot => {
+            let set = try!(resolve_set(config));
+            if let Set::Julia(_) = set {
+                return Err(Error::usage("Buddhabrot images can only be drawn for \
+                                         the Mandelbrot set"));
+            }
+            let expression = try!(resolve_expression(config));
+            let steps = try!(resolve_steps(config, expression.as_ref()));
+            let iteration = Iteration { set: set, steps: &steps, radius: config.escape_radius() };
+            let draw = |limits: &[u32]| buddhabrot(view, config, &iteration, limits);
+            Ok(match config.channel_limits {
+                Some((red, green, blue)) => {
+                    let channels = draw(&[red, green, blue]);
+                    Bitmap {
+                        pixels: buddhabrot::colorize(&channels[0], &channels[1], &channels[2]),
+                        color: ColorType::RGB(8),
+                        gamma: None,
+                        profile: None
+                    }
+                }
+                None => Bitmap {
+                    pixels: buddhabrot::grayscale(&draw(&[limit])[0]),
+                    color: ColorType::Gray(8),
+                    gamma: None,
+                    profile: None
+                }
+            })
+        }
+        Mode::Inverse => {
+            let c = try!(resolve_inverse(config));
+            let transform = view.transform();
+            let mut rng = random::Rng::new(config.seed.unwrap_or(0), 0);
+            let pixels = view.bounds.0 * view.bounds.1;
+            // Draw the outline in black, like the set in an escape-time render.
+            let mut shades = vec![255; pixels];
+            inverse::outline(c, pixels as u64 * config.samples() as u64, &mut rng, |z| {
+                if let Some((column, row)) =
+                    point_to_pixel(&transform, view.bounds, (z.re, z.im))
+                {
+                    shades[row * view.bounds.0 + column] = 0;
+                }
+            });
+            Ok(Bitmap {
+                pixels: shades,
+                color: ColorType::Gray(8),
+                gamma: None,
+                profile: None
+            })
+        }
+        Mode::Newton => {
+            let polynomial = try!(resolve_polynomial(config));
+            let roots = polynomial.roots();
+            let outcomes = render_parallel(view.bounds, &view.transform(), config.threads(),
+                                           |point| polynomial.converge(&roots, point, limit));
+            Ok(Bitmap {
+                pixels: newton::colorize(&outcomes, roots.len()),
+                color: ColorType::RGB(8),
+                gamma: None,
+                profile: None
+            })
+        }
+    }
+}
+
+#[macro_use]
+extern crate clap;
+
+mod cli;
+
+use clap::ArgMatches;
+use config::{Blend, ColorSpace, Config, Exterior, ExrChannel, Formula, Interior, Mode};
+use std::cmp;
+use std::f64;
+use std::fmt;
+use std::env;
+use std::mem;
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+/// Parse the value of the argument `name` in `matches` with `parse`, if it is
+/// present. `what` describes the argument, for use in error messages.
+fn parse_arg<T, F>(matches: &ArgMatches, name: &str, what: &str, parse: F)
+    -> Result<Option<T>, Error>
+    where F: Fn(&str) -> Result<T, String>
+{
+    match matches.value_of(name) {
+        None => Ok(None),
+        Some(s) => match parse(s) {
+            Ok(value) => Ok(Some(value)),
+            Err(why) => Err(Error::parse(format!("error parsing {} '{}': {}", what, s, why)))
+        }
+    }
+}
+
+/// Parse the value of the environment variable `name` with `parse`, returning
+/// `None` if the variable is unset or empty. The `what` argument describes the
+/// value, for error messages.
+fn parse_env<T, F>(name: &str, what: &str, parse: F) -> Result<Option<T>, Error>
+    where F: Fn(&str) -> Result<T, String>
+{
+    match env::var(name) {
+        Err(env::VarError::NotPresent) => Ok(None),
+        Err(env::VarError::NotUnicode(_)) =>
+            Err(Error::parse(format!("environment variable {} is not valid Unicode", name))),
+        Ok(ref s) if s.is_empty() => Ok(None),
+        Ok(s) => match parse(&s) {
+            Ok(value) => Ok(Some(value)),
+            Err(why) => Err(Error::parse(format!("error parsing {} '{}' from {}: {}",
+                                                 what, s, name, why)))
+        }
+    }
+}
+
+#[test]
+fn test_parse_env() {
+    env::set_var("MANDEL_TEST_PARSE_ENV", "12");
+    assert_eq!(parse_env("MANDEL_TEST_PARSE_ENV", "number", parse_number::<u32>),
+               Ok(Some(12)));
+    assert!(parse_env("MANDEL_TEST_PARSE_ENV", "set", parse_set).is_err());
+    env::set_var("MANDEL_TEST_PARSE_ENV", "");
+    assert_eq!(parse_env("MANDEL_TEST_PARSE_ENV", "number", parse_number::<u32>),
+               Ok(None));
+    env::remove_var("MANDEL_TEST_PARSE_ENV");
+    assert_eq!(parse_env("MANDEL_TEST_PARSE_ENV", "number", parse_number::<u32>),
+               Ok(None));
+}
+
+/// Parse `s` as the name of a set to draw.
+fn parse_set(s: &str) -> Result<config::SetKind, String> {
+    match s.trim() {
+        "mandelbrot" => Ok(config::SetKind::Mandelbrot),
+        "julia" => Ok(config::SetKind::Julia),
+        _ => Err("expected 'mandelbrot' or 'julia'".to_string())
+    }
+}
+
+/// Parse `s` as the name of a rendering mode.
+fn parse_mode(s: &str) -> Result<Mode, String> {
+    match s.trim() {
+        "escape" => Ok(Mode::Escape),
+        "newton" => Ok(Mode::Newton),
+        "buddhabrot" => Ok(Mode::Buddhabrot),
+        "inverse" => Ok(Mode::Inverse),
+        _ => Err("expected 'escape', 'newton', 'buddhabrot', or 'inverse'".to_string())
+    }
+}
+
+/// Parse `s` as a transfer function: `linear`, `sqrt`, `log`, or `power:K`,
+/// for some positive exponent `K`.
+fn parse_transfer(s: &str) -> Result<coloring::Transfer, String> {
+    let s = s.trim();
+    if s.starts_with("power:") {
+        let exponent: f64 = try!(parse_number(&s["power:".len()..]));
+        if !(exponent > 0.0 && exponent.is_finite()) {
+            return Err("the exponent must be positive".to_string());
+        }
+        return Ok(coloring::Transfer::Power(exponent));
+    }
+    match s {
+        "linear" => Ok(coloring::Transfer::Linear),
+        "sqrt" => Ok(coloring::Transfer::Sqrt),
+        "log" => Ok(coloring::Transfer::Log),
+        _ => Err("expected 'linear', 'sqrt', 'log', or 'power:K'".to_string())
+    }
+}
+
+#[test]
+fn test_parse_transfer() {
+    assert_eq!(parse_transfer("linear"), Ok(coloring::Transfer::Linear));
+    assert_eq!(parse_transfer(" log "), Ok(coloring::Transfer::Log));
+    assert_eq!(parse_transfer("power:2.5"), Ok(coloring::Transfer::Power(2.5)));
+    assert!(parse_transfer("power:0").is_err());
+    assert!(parse_transfer("power:").is_err());
+    assert!(parse_transfer("cube").is_err());
+}
+
+/// Parse `s` as the encoding of a palette's colors: `srgb`, or a positive
+/// number giving the exponent of a plain power law.
+fn parse_gamma(s: &str) -> Result<coloring::Gamma, String> {
+    let s = s.trim();
+    if s == "srgb" {
+        return Ok(coloring::Gamma::Srgb);
+    }
+    let gamma: f64 = try!(parse_number(s).map_err(|_| "expected 'srgb' or a number".to_string()));
+    if !(gamma > 0.0 && gamma.is_finite()) {
+        return Err("the gamma must be positive".to_string());
+    }
+    Ok(coloring::Gamma::Power(gamma))
+}
+
+#[test]
+fn test_parse_gamma() {
+    assert_eq!(parse_gamma("srgb"), Ok(coloring::Gamma::Srgb));
+    assert_eq!(parse_gamma(" 2.2"), Ok(coloring::Gamma::Power(2.2)));
+    assert!(parse_gamma("0").is_err());
+    assert!(parse_gamma("linear").is_err());
+}
+
+/// Parse `s` as a pair of percentiles, `LOW,HIGH`, with `LOW` less than `HIGH`,
+/// both from 0 to 100.
+fn parse_percentiles(s: &str) -> Result<(f64, f64), String> {
+    let (low, high) = try!(parse_pair::<f64>(s, ','));
+    try!(check_percentiles((low, high)));
+    Ok((low, high))
+}
+
+/// Check that `low` is less than `high`, and that both are from 0 to 100.
+fn check_percentiles((low, high): (f64, f64)) -> Result<(), String> {
+    if !(0.0 <= low && low < high && high <= 100.0) {
+        return Err("expected two percentiles from 0 to 100, the first less than the \
+                    second".to_string());
+    }
+    Ok(())
+}
+
+#[test]
+fn test_parse_percentiles() {
+    assert_eq!(parse_percentiles("1,99"), Ok((1.0, 99.0)));
+    assert_eq!(parse_percentiles("0,100"), Ok((0.0, 100.0)));
+    assert!(parse_percentiles("99,1").is_err());
+    assert!(parse_percentiles("5,101").is_err());
+    assert!(parse_percentiles("5").is_err());
+}
+
+/// Parse `s` as the name of a corner of the image.
+fn parse_corner(s: &str) -> Result<config::Corner, String> {
+    match s.trim() {
+        "top-left" => Ok(config::Corner::TopLeft),
+        "top-right" => Ok(config::Corner::TopRight),
+        "bottom-left" => Ok(config::Corner::BottomLeft),
+        "bottom-right" => Ok(config::Corner::BottomRight),
+        _ => Err("expected 'top-left', 'top-right', 'bottom-left', or 'bottom-right'"
+                 .to_string())
+    }
+}
+
+/// Parse `s` as the name of an image file format.
+fn parse_format(s: &str) -> Result<config::Format, String> {
+    match s.trim() {
+        "png" => Ok(config::Format::Png),
+        "jpeg" | "jpg" => Ok(config::Format::Jpeg),
+        "tiff" | "tif" => Ok(config::Format::Tiff),
+        "webp" => Ok(config::Format::Webp),
+        "avif" => Ok(config::Format::Avif),
+        "qoi" => Ok(config::Format::Qoi),
+        "pgm" => Ok(config::Format::Pgm),
+        "ppm" => Ok(config::Format::Ppm),
+        "farbfeld" | "ff" => Ok(config::Format::Farbfeld),
+        "bmp" => Ok(config::Format::Bmp),
+        "dzi" => Ok(config::Format::Dzi),
+        _ => Err("expected 'png', 'jpeg', 'tiff', 'webp', 'avif', 'qoi', 'pgm', 'ppm', \
+                  'farbfeld', 'bmp', or 'dzi'".to_string())
+    }
+}
+
+/// Parse `s` as the name of a way to color points that never escape.
+fn parse_interior(s: &str) -> Result<Interior, String> {
+    match s.trim() {
+        "black" => Ok(Interior::Black),
+        "magnitude" => Ok(Interior::Magnitude),
+        "closest" => Ok(Interior::Closest),
+        "average" => Ok(Interior::Average),
+        "period" => Ok(Interior::Period),
+        "transparent" => Ok(Interior::Transparent),
+        _ => Err("expected 'black', 'magnitude', 'closest', 'average', 'period', or \
+                  'transparent'".to_string())
+    }
+}
+
+/// Parse `s` as an orbit trap: `point:Z` for the point `Z`, `line:Z,ANGLE` for
+/// the line through `Z` at `ANGLE` degrees from the real axis,
+/// `circle:Z,RADIUS`, or `stalks`, for both axes. Each `Z` is a complex
+/// number, as for `parse_complex`.
+fn parse_trap(s: &str) -> Result<Trap, String> {
+    if s.trim() == "stalks" {
+        return Ok(Trap::Stalks);
+    }
+    let (shape, rest) = try!(parse_pair::<String>(s, ':'));
+    // Split off the last number, which may follow a point written as a pair.
+    let last = |rest: &str| -> Result<(Complex<f64>, f64), String> {
+        let comma = try!(rest.rfind(',').ok_or_else(|| "expected a point and a number"));
+        let (re, im) = try!(parse_complex(&rest[..comma]));
+        Ok((Complex { re: re, im: im }, try!(parse_number(rest[comma + 1..].trim()))))
+    };
+    match shape.trim() {
+        "point" => {
+            let (re, im) = try!(parse_complex(&rest));
+            Ok(Trap::Point(Complex { re: re, im: im }))
+        }
+        "line" => {
+            let (point, degrees) = try!(last(&rest));
+            Ok(Trap::line(point, degrees))
+        }
+        "circle" => {
+            let (center, radius) = try!(last(&rest));
+            if !(radius > 0.0) {
+                return Err("the radius must be positive".to_string());
+            }
+            Ok(Trap::Circle { center: center, radius: radius })
+        }
+        shape => Err(format!("expected 'point', 'line', 'circle', or 'stalks', not '{}'",
+                             shape))
+    }
+}
+
+#[test]
+fn test_parse_trap() {
+    let origin = Complex { re: 0.0, im: 0.0 };
+    assert_eq!(parse_trap("point:0,0"), Ok(Trap::Point(origin)));
+    assert_eq!(parse_trap("point:1-2i"), Ok(Trap::Point(Complex { re: 1.0, im: -2.0 })));
+    assert_eq!(parse_trap("line:0,0,0"), Ok(Trap::line(origin, 0.0)));
+    assert_eq!(parse_trap("line:0.5i,30"), Ok(Trap::line(Complex { re: 0.0, im: 0.5 }, 30.0)));
+    assert_eq!(parse_trap("circle:-1,0,0.25"),
+               Ok(Trap::Circle { center: Complex { re: -1.0, im: 0.0 }, radius: 0.25 }));
+    assert!(parse_trap("circle:0,0,0").is_err());
+    assert!(parse_trap("circle:0,0").is_err());
+    assert!(parse_trap("square:0,0").is_err());
+    assert!(parse_trap("point").is_err());
+    assert_eq!(parse_trap(" stalks"), Ok(Trap::Stalks));
+}
+
+/// Parse `s` as a way to blend an orbit trap's colors with escape times'.
+fn parse_blend(s: &str) -> Result<Blend, String> {
+    match s.trim() {
+        "replace" => Ok(Blend::Replace),
+        "multiply" => Ok(Blend::Multiply),
+        "minimum" => Ok(Blend::Minimum),
+        _ => Err("expected 'replace', 'multiply', or 'minimum'".to_string())
+    }
+}
+
+/// Parse `s` as the name of a color space to blend gradients in.
+fn parse_color_space(s: &str) -> Result<ColorSpace, String> {
+    match s.trim() {
+        "rgb" => Ok(ColorSpace::Rgb),
+        "hsv" => Ok(ColorSpace::Hsv),
+        "lab" => Ok(ColorSpace::Lab),
+        _ => Err("expected 'rgb', 'hsv', or 'lab'".to_string())
+    }
+}
+
+/// Parse `s` as the name of a way to color points whose orbits escape.
+fn parse_exterior(s: &str) -> Result<Exterior, String> {
+    match s.trim() {
+        "escape" => Ok(Exterior::Escape),
+        "tia" => Ok(Exterior::Tia),
+        "stripes" => Ok(Exterior::Stripes),
+        "curvature" => Ok(Exterior::Curvature),
+        "binary" => Ok(Exterior::Binary),
+        "angle" => Ok(Exterior::Angle),
+        "distance" => Ok(Exterior::Distance),
+        "atom" => Ok(Exterior::Atom),
+        _ => Err("expected 'escape', 'tia', 'stripes', 'curvature', 'binary', 'angle', \
+                  'distance', or 'atom'".to_string())
+    }
+}
+
+/// Parse `s` as a flag's value, `true` or `false`.
+fn parse_flag(s: &str) -> Result<bool, String> {
+    s.trim().parse().map_err(|_| "expected 'true' or 'false'".to_string())
+}
+
+/// Parse `s` as the iteration limits for the red, green, and blue channels of
+/// a Nebulabrot, separated by commas.
+fn parse_channel_limits(s: &str) -> Result<(u32, u32, u32), String> {
+    let limits: Vec<u32> = try!(s.split(',').map(parse_positive).collect());
+    match limits[..] {
+        [red, green, blue] => Ok((red, green, blue)),
+        _ => Err("expected three limits, for red, green, and blue".to_string())
+    }
+}
+
+#[test]
+fn test_parse_channel_limits() {
+    assert_eq!(parse_channel_limits("5000, 500,50"), Ok((5000, 500, 50)));
+    assert!(parse_channel_limits("5000,500").is_err());
+    assert!(parse_channel_limits("5000,0,50").is_err());
+}
+
+/// Parse `s` as a polynomial's coefficients, highest degree first, separated
+/// by commas, like `1,0,0,-1` for z^3 - 1. Complex coefficients must be
+/// written like `0.5-2i`, not as pairs.
+fn parse_polynomial(s: &str) -> Result<Vec<(f64, f64)>, String> {
+    s.split(',').map(parse_complex).collect()
+}
+
+#[test]
+fn test_parse_polynomial() {
+    assert_eq!(parse_polynomial("1, 0, -1"),
+               Ok(vec![(1.0, 0.0), (0.0, 0.0), (-1.0, 0.0)]));
+    assert_eq!(parse_polynomial("2i,0.5-1i"), Ok(vec![(0.0, 2.0), (0.5, -1.0)]));
+    assert!(parse_polynomial("1,,2").is_err());
+}
+
+/// Parse `s` as the name of a formula to iterate.
+fn parse_formula(s: &str) -> Result<Formula, String> {
+    match s.trim() {
+        "standard" => Ok(Formula::Standard),
+        "burning-ship" => Ok(Formula::BurningShip),
+        "tricorn" => Ok(Formula::Tricorn),
+        "celtic" => Ok(Formula::Celtic),
+        "perpendicular" => Ok(Formula::Perpendicular),
+        "perpendicular-burning-ship" => Ok(Formula::PerpendicularBurningShip),
+        "heart" => Ok(Formula::Heart),
+        "phoenix" => Ok(Formula::Phoenix),
+        "magnet1" => Ok(Formula::Magnet1),
+        "magnet2" => Ok(Formula::Magnet2),
+        "lambda" => Ok(Formula::Lambda),
+        "nova" => Ok(Formula::Nova),
+        "quat-julia" => Ok(Formula::QuatJulia),
+        "collatz" => Ok(Formula::Collatz),
+        "spider" => Ok(Formula::Spider),
+        "manowar" => Ok(Formula::Manowar),
+        _ => Err("expected 'standard', 'burning-ship', 'tricorn', 'celtic', \
+                  'perpendicular', 'perpendicular-burning-ship', 'heart', \
+                  'phoenix', 'magnet1', 'magnet2', 'lambda', 'nova', \
+                  'quat-julia', 'collatz', 'spider', or 'manowar'".to_string())
+    }
+}
+
+/// Return the name of `formula`, as `parse_formula` accepts it.
+fn formula_name(formula: Formula) -> &'static str {
+    match formula {
+        Formula::Standard => "standard",
+        Formula::BurningShip => "burning-ship",
+        Formula::Tricorn => "tricorn",
+        Formula::Celtic => "celtic",
+        Formula::Perpendicular => "perpendicular",
+        Formula::PerpendicularBurningShip => "perpendicular-burning-ship",
+        Formula::Heart => "heart",
+        Formula::Phoenix => "phoenix",
+        Formula::Magnet1 => "magnet1",
+        Formula::Magnet2 => "magnet2",
+        Formula::Lambda => "lambda",
+        Formula::Nova => "nova",
+        Formula::QuatJulia => "quat-julia",
+        Formula::Collatz => "collatz",
+        Formula::Spider => "spider",
+        Formula::Manowar => "manowar"
+    }
+}
+
+/// Parse `s` as a slice through quaternion space, like `k=0.1,l=0`, giving the
+/// `j` and `k` parts, called `k` and `l`, of the points the image shows.
+/// Either may be left out, and is then zero.
+fn parse_slice(s: &str) -> Result<(f64, f64), String> {
+    let mut slice = (None, None);
+    for part in s.split(',') {
+        let (name, value) = try!(parse_pair::<String>(part, '='));
+        let value = try!(parse_number(&value));
+        let component = match name.trim() {
+            "k" => &mut slice.0,
+            "l" => &mut slice.1,
+            name => return Err(format!("expected 'k' or 'l', not '{}'", name))
+        };
+        if component.is_some() {
+            return Err(format!("'{}' is given twice", name.trim()));
+        }
+        *component = Some(value);
+    }
+    Ok((slice.0.unwrap_or(0.0), slice.1.unwrap_or(0.0)))
+}
+
+#[test]
+fn test_parse_slice() {
+    assert_eq!(parse_slice("k=0.1,l=0.0"), Ok((0.1, 0.0)));
+    assert_eq!(parse_slice("l=-0.5, k=2"), Ok((2.0, -0.5)));
+    assert_eq!(parse_slice("l=0.25"), Ok((0.0, 0.25)));
+    assert!(parse_slice("j=0.1").is_err());
+    assert!(parse_slice("k=0.1,k=0.2").is_err());
+    assert!(parse_slice("k").is_err());
+}
+
+/// Parse `s` as a hybrid: a list of formulas separated by commas, applied in
+/// turn.
+fn parse_hybrid(s: &str) -> Result<Vec<Formula>, String> {
+    s.split(',').map(parse_formula).collect()
+}
+
+#[test]
+fn test_parse_hybrid() {
+    assert_eq!(parse_hybrid("standard, standard,burning-ship"),
+               Ok(vec![Formula::Standard, Formula::Standard, Formula::BurningShip]));
+    assert_eq!(parse_hybrid("tricorn"), Ok(vec![Formula::Tricorn]));
+    assert!(parse_hybrid("standard,,tricorn").is_err());
+    assert_eq!(formula_name(Formula::BurningShip), "burning-ship");
+}
+
+/// Parse `s` as a list of channels to add to an OpenEXR image, separated by
+/// commas.
+fn parse_exr_channels(s: &str) -> Result<Vec<ExrChannel>, String> {
+    s.split(',').map(|channel| {
+        match channel.trim() {
+            "distance" => Ok(ExrChannel::Distance),
+            "magnitude" => Ok(ExrChannel::Magnitude),
+            _ => Err("expected 'distance' or 'magnitude'".to_string())
+        }
+    }).collect()
+}
+
+#[test]
+fn test_parse_exr_channels() {
+    assert_eq!(parse_exr_channels("magnitude, distance"),
+               Ok(vec![ExrChannel::Magnitude, ExrChannel::Distance]));
+    assert!(parse_exr_channels("distance,").is_err());
+}
+
+/// Parse `s` as a number greater than zero.
+fn parse_positive<T: FromStr + PartialOrd + Default>(s: &str) -> Result<T, String> {
+    let value = try!(parse_number::<T>(s.trim()));
+    if value > T::default() {
+        Ok(value)
+    } else {
+        Err("must be greater than zero".to_string())
+    }
+}
+
+/// Sort a size as returned by `parse_size` into the `size`, `width`, and
+/// `height` fields of a `Config`: a complete size goes in `size`, and if one
+/// dimension is missing, the other goes in `width` or `height`.
+fn split_size(size: Option<(Option<usize>, Option<usize>)>)
+              -> (Option<(usize, usize)>, Option<usize>, Option<usize>)
+{
+    match size {
+        Some((Some(width), Some(height))) => (Some((width, height)), None, None),
+        Some((width, height)) => (None, width, height),
+        None => (None, None, None)
+    }
+}
+
+/// Return the settings given by the arguments in `matches`. The names of the
+/// region arguments all start with `prefix`, so `animate` can use this for
+/// both its start and end views.
+fn config_from_matches(matches: &ArgMatches, prefix: &str) -> Result<Config, Error> {
+    let point = |name: &str, what: &str| {
+        parse_arg(matches, &format!("{}{}", prefix, name), what, parse_complex)
+    };
+
+    let range = |name: &str, what: &str| {
+        parse_arg(matches, &format!("{}{}", prefix, name), what, parse_range)
+    };
+
+    let (size, width, height) =
+        split_size(try!(parse_arg(matches, "size", "image dimensions", parse_size)));
+
+    // Ranges along the axes are just another way to give the corners.
+    let (upper_left, lower_right) =
+        match (try!(range("re", "real range")), try!(range("im", "imaginary range"))) {
+            (Some((left, right)), Some((bottom, top))) => {
+                (Some((left, top)), Some((right, bottom)))
+            }
+            _ => (try!(point("upper-left", "upper left corner point")),
+                  try!(point("lower-right", "lower right corner point")))
+        };
+
+    Ok(Config {
+        output: matches.value_of("output").map(str::to_string),
+        output_dir: None,
+        output_normals: matches.value_of("output-normals").map(str::to_string),
+        output_data: matches.value_of("output-data").map(str::to_string),
+        output_raw: matches.value_of("output-raw").map(str::to_string),
+        output_npy: matches.value_of("output-npy").map(str::to_string),
+        npy_smooth: if matches.is_present("npy-smooth") { Some(true) } else { None },
+        output_csv: matches.value_of("output-csv").map(str::to_string),
+        csv_step: try!(parse_arg(matches, "csv-step", "CSV step", parse_positive)),
+        output_exr: matches.value_of("output-exr").map(str::to_string),
+        output_hdr: matches.value_of("output-hdr").map(str::to_string),
+        exr_channels: try!(parse_arg(matches, "exr-channels", "OpenEXR channels",
+                                     parse_exr_channels)),
+        format: try!(parse_arg(matches, "format", "image format", parse_format)),
+        quality: try!(parse_arg(matches, "quality", "quality", parse_number)),
+        tile_format: try!(parse_arg(matches, "tile-format", "tile format", parse_format)),
+        tile_size: try!(parse_arg(matches, "tile-size", "tile size", parse_positive)),
+        tile_overlap: try!(parse_arg(matches, "tile-overlap", "tile overlap", parse_number)),
+        size: size,
+        width: width,
+        height: height,
+        upper_left: upper_left,
+        lower_right: lower_right,
+        center: try!(point("center", "center point")),
+        zoom: try!(parse_arg(matches, &format!("{}zoom", prefix), "zoom factor",
+                             parse_positive)),
+        rotate: try!(parse_arg(matches, &format!("{}rotate", prefix), "rotation",
+                               parse_number)),
+        location: matches.value_of(format!("{}location", prefix)).map(str::to_string),
+        bookmarks: matches.value_of("bookmarks").map(str::to_string),
+        set: try!(parse_arg(matches, "set", "set", parse_set)),
+        c: try!(parse_arg(matches, "c", "value for c", parse_complex)),
+        mode: try!(parse_arg(matches, "mode", "mode", parse_mode)),
+        polynomial: try!(parse_arg(matches, "polynomial", "polynomial", parse_polynomial)),
+        formula: try!(parse_arg(matches, "formula", "formula", parse_formula)),
+        hybrid: try!(parse_arg(matches, "hybrid", "hybrid", parse_hybrid)),
+        formula_expr: matches.value_of("formula-expr").map(str::to_string),
+        script: matches.value_of("script").map(str::to_string),
+        power: try!(parse_arg(matches, "power", "power", parse_number)),
+        phoenix_p: try!(parse_arg(matches, "phoenix-p", "value for p", parse_complex)),
+        relaxation: try!(parse_arg(matches, "relaxation", "relaxation", parse_complex)),
+        morph_to: try!(parse_arg(matches, "morph-to", "formula", parse_formula)),
+        morph: try!(parse_arg(matches, &format!("{}morph", prefix), "morph", parse_number)),
+        slice: try!(parse_arg(matches, "slice", "slice", parse_slice)),
+        palette: matches.value_of("palette").map(|s| s.to_string()),
+        palette_file: matches.value_of("palette-file").map(|s| s.to_string()),
+        gradient: matches.value_of("gradient").map(|s| s.to_string()),
+        gradient_space: try!(parse_arg(matches, "gradient-space", "color space",
+                                       parse_color_space)),
+        transfer: matches.value_of("transfer").map(|s| s.to_string()),
+        gamma: matches.value_of("gamma").map(|s| s.to_string()),
+        icc_profile: matches.value_of("icc-profile").map(|s| s.to_string()),
+        depth: try!(parse_arg(matches, "depth", "bit depth", parse_number)),
+        coloring: try!(parse_arg(matches, "coloring", "coloring method", parse_exterior)),
+        stripe_density: try!(parse_arg(matches, "stripe-density", "stripe density",
+                                       parse_number)),
+        distance_thickness: try!(parse_arg(matches, "distance-thickness",
+                                           "distance thickness", parse_positive)),
+        light_angle: try!(parse_arg(matches, "light-angle", "light angle", parse_number)),
+        interior: try!(parse_arg(matches, "interior", "interior coloring", parse_interior)),
+        trap: matches.value_of("trap").map(|s| s.to_string()),
+        trap_blend: try!(parse_arg(matches, "trap-blend", "trap blend", parse_blend)),
+        equalize: if matches.is_present("equalize") { Some(true) } else { None },
+        auto_expose: try!(parse_arg(matches, "auto-expose", "percentiles", parse_percentiles)),
+        legend: try!(parse_arg(matches, "legend", "legend corner", parse_corner)),
+        dither: if matches.is_present("dither") { Some(true) } else { None },
+        palette_period: try!(parse_arg(matches, "palette-period", "palette period",
+                                       parse_positive)),
+        palette_offset: try!(parse_arg(matches, "palette-offset", "palette offset",
+                                       parse_number)),
+        z0: try!(parse_arg(matches, "z0", "starting value", parse_complex)),
+        samples: try!(parse_arg(matches, "samples", "sample count", parse_positive)),
+        channel_limits: try!(parse_arg(matches, "channel-limits", "channel limits",
+                                       parse_channel_limits)),
+        anti: if matches.is_present("anti") { Some(true) } else { None },
+        iterations: try!(parse_arg(matches, "iterations", "iteration limit",
+                                   parse_positive)),
+        escape_radius: try!(parse_arg(matches, "escape-radius", "escape radius",
+                                      parse_positive)),
+        threads: try!(parse_arg(matches, "threads", "thread count", parse_positive)),
+        seed: try!(parse_arg(matches, "seed", "seed", parse_number)),
+        force: if matches.is_present("force") { Some(true) } else { None }
+    })
+}
+
+/// Return the settings given by `MANDEL_...` environment variables. There is a
+/// variable for each setting a configuration file can hold, named after its key
+/// in capitals: `MANDEL_THREADS`, `MANDEL_OUTPUT_DIR`, and so on.
+fn config_from_env() -> Result<Config, Error> {
+    let string = |name: &str| parse_env(name, "", |s| Ok(s.to_string()));
+    let point = |name: &str, what: &str| parse_env(name, what, parse_complex);
+    let (size, width, height) =
+        split_size(try!(parse_env("MANDEL_SIZE", "image dimensions", parse_size)));
+
+    Ok(Config {
+        output: try!(string("MANDEL_OUTPUT")),
+        output_dir: try!(string("MANDEL_OUTPUT_DIR")),
+        output_normals: try!(string("MANDEL_OUTPUT_NORMALS")),
+        output_data: try!(string("MANDEL_OUTPUT_DATA")),
+        output_raw: try!(string("MANDEL_OUTPUT_RAW")),
+        output_npy: try!(string("MANDEL_OUTPUT_NPY")),
+        npy_smooth: try!(parse_env("MANDEL_NPY_SMOOTH", "flag", parse_flag)),
+        output_csv: try!(string("MANDEL_OUTPUT_CSV")),
+        csv_step: try!(parse_env("MANDEL_CSV_STEP", "CSV step", parse_positive)),
+        output_exr: try!(string("MANDEL_OUTPUT_EXR")),
+        output_hdr: try!(string("MANDEL_OUTPUT_HDR")),
+        exr_channels: try!(parse_env("MANDEL_EXR_CHANNELS", "OpenEXR channels",
+                                     parse_exr_channels)),
+        format: try!(parse_env("MANDEL_FORMAT", "image format", parse_format)),
+        quality: try!(parse_env("MANDEL_QUALITY", "quality", parse_number)),
+        tile_format: try!(parse_env("MANDEL_TILE_FORMAT", "tile format", parse_format)),
+        tile_size: try!(parse_env("MANDEL_TILE_SIZE", "tile size", parse_positive)),
+        tile_overlap: try!(parse_env("MANDEL_TILE_OVERLAP", "tile overlap", parse_number)),
+        size: size,
+        width: match width {
+            Some(_) => width,
+            None => try!(parse_env("MANDEL_WIDTH", "image width", parse_positive))
+        },
+        height: match height {
+            Some(_) => height,
+            None => try!(parse_env("MANDEL_HEIGHT", "image height", parse_positive))
+        },
+        upper_left: try!(point("MANDEL_UPPER_LEFT", "upper left corner point")),
+        lower_right: try!(point("MANDEL_LOWER_RIGHT", "lower right corner point")),
+        center: try!(point("MANDEL_CENTER", "center point")),
+        zoom: try!(parse_env("MANDEL_ZOOM", "zoom factor", parse_positive)),
+        rotate: try!(parse_env("MANDEL_ROTATE", "rotation", parse_number)),
+        location: try!(string("MANDEL_LOCATION")),
+        bookmarks: try!(string("MANDEL_BOOKMARKS")),
+        set: try!(parse_env("MANDEL_SET", "set", parse_set)),
+        c: try!(point("MANDEL_C", "value for c")),
+        mode: try!(parse_env("MANDEL_MODE", "mode", parse_mode)),
+        polynomial: try!(parse_env("MANDEL_POLYNOMIAL", "polynomial", parse_polynomial)),
+        formula: try!(parse_env("MANDEL_FORMULA", "formula", parse_formula)),
+        hybrid: try!(parse_env("MANDEL_HYBRID", "hybrid", parse_hybrid)),
+        formula_expr: try!(string("MANDEL_FORMULA_EXPR")),
+        script: try!(string("MANDEL_SCRIPT")),
+        power: try!(parse_env("MANDEL_POWER", "power", parse_number)),
+        phoenix_p: try!(point("MANDEL_PHOENIX_P", "value for p")),
+        relaxation: try!(point("MANDEL_RELAXATION", "relaxation")),
+        morph_to: try!(parse_env("MANDEL_MORPH_TO", "formula", parse_formula)),
+        morph: try!(parse_env("MANDEL_MORPH", "morph", parse_number)),
+        slice: try!(parse_env("MANDEL_SLICE", "slice", parse_slice)),
+        palette: try!(string("MANDEL_PALETTE")),
+        palette_file: try!(string("MANDEL_PALETTE_FILE")),
+        gradient: try!(string("MANDEL_GRADIENT")),
+        gradient_space: try!(parse_env("MANDEL_GRADIENT_SPACE", "color space",
+                                       parse_color_space)),
+        transfer: try!(string("MANDEL_TRANSFER")),
+        gamma: try!(string("MANDEL_GAMMA")),
+        icc_profile: try!(string("MANDEL_ICC_PROFILE")),
+        depth: try!(parse_env("MANDEL_DEPTH", "bit depth", parse_number)),
+        coloring: try!(parse_env("MANDEL_COLORING", "coloring method", parse_exterior)),
+        stripe_density: try!(parse_env("MANDEL_STRIPE_DENSITY", "stripe density",
+                                       parse_number)),
+        distance_thickness: try!(parse_env("MANDEL_DISTANCE_THICKNESS", "distance thickness",
+                                           parse_positive)),
+        light_angle: try!(parse_env("MANDEL_LIGHT_ANGLE", "light angle", parse_number)),
+        interior: try!(parse_env("MANDEL_INTERIOR", "interior coloring", parse_interior)),
+        trap: try!(string("MANDEL_TRAP")),
+        trap_blend: try!(parse_env("MANDEL_TRAP_BLEND", "trap blend", parse_blend)),
+        equalize: try!(parse_env("MANDEL_EQUALIZE", "flag", parse_flag)),
+        auto_expose: try!(parse_env("MANDEL_AUTO_EXPOSE", "percentiles", parse_percentiles)),
+        legend: try!(parse_env("MANDEL_LEGEND", "legend corner", parse_corner)),
+        dither: try!(parse_env("MANDEL_DITHER", "flag", parse_flag)),
+        palette_period: try!(parse_env("MANDEL_PALETTE_PERIOD", "palette period",
+                                       parse_positive)),
+        palette_offset: try!(parse_env("MANDEL_PALETTE_OFFSET", "palette offset",
+                                       parse_number)),
+        z0: try!(point("MANDEL_Z0", "starting value")),
+        samples: try!(parse_env("MANDEL_SAMPLES", "sample count", parse_positive)),
+        channel_limits: try!(parse_env("MANDEL_CHANNEL_LIMITS", "channel limits",
+                                       parse_channel_limits)),
+        anti: try!(parse_env("MANDEL_ANTI", "flag", parse_flag)),
+        iterations: try!(parse_env("MANDEL_ITERATIONS", "iteration limit", parse_positive)),
+        escape_radius: try!(parse_env("MANDEL_ESCAPE_RADIUS", "escape radius",
+                                      parse_positive)),
+        threads: try!(parse_env("MANDEL_THREADS", "thread count", parse_positive)),
+        seed: try!(parse_env("MANDEL_SEED", "seed", parse_number)),
+        force: try!(parse_env("MANDEL_FORCE", "flag", parse_flag))
+    })
+}
+
+/// Return true if `arg`, which may name an entry in a file as `FILE:ENTRY`,
+/// names a file whose name ends with `extension`.
+fn arg_path_has_extension(arg: &str, extension: &str) -> bool {
+    arg.ends_with(extension) || arg.rsplitn(2, ':').nth(1).map_or(false, |path| {
+        path.ends_with(extension)
+    })
+}
+
+#[test]
+fn test_arg_path_has_extension() {
+    assert!(arg_path_has_extension("gradients.ugr:Sunset", ".ugr"));
+    assert!(arg_path_has_extension("gradients.ugr", ".ugr"));
+    assert!(!arg_path_has_extension("classic.map", ".ugr"));
+    assert!(!arg_path_has_extension("ugr:classic.map", ".ugr"));
+}
+
+/// Split `arg`, which names an entry in a parameter file as `FILE:ENTRY`, into
+/// the file's path and the entry's name.
+fn parse_entry_arg(arg: &str) -> Result<(&Path, &str), Error> {
+    let mut parts = arg.rsplitn(2, ':');
+    let name = parts.next().unwrap();
+    match parts.next() {
+        Some(path) if !path.is_empty() && !name.is_empty() => Ok((Path::new(path), name)),
+        _ => Err(Error::parse(format!("error parsing parameter file entry '{}': \
+                                       expected FILE:ENTRY", arg)))
+    }
+}
+
+/// Return the settings given by the arguments in `matches`, layered over those
+/// from the environment, then over those imported with `--import-par`,
+/// `--import-kfr`, or `--import-upr`, and finally over those from the file
+/// named by `--config`, if any.
+fn settings_from_matches(matches: &ArgMatches) -> Result<Config, Error> {
+    let reading = |what: &str, path: &Path| {
+        let context = format!("error reading {} '{}'", what, path.display());
+        move |e| Error::from_io(&context, e)
+    };
+
+    let mut flags = try!(config_from_matches(matches, ""));
+    flags = flags.merge(try!(config_from_env()));
+    if let Some(code) = matches.value_of("share") {
+        flags = flags.merge(try!(share::decode(code).map_err(|why| {
+            Error::parse(format!("error parsing share code '{}': {}", code, why))
+        })));
+    }
+    if let Some(arg) = matches.value_of("import-par") {
+        let (path, name) = try!(parse_entry_arg(arg));
+        flags = flags.merge(try!(par::load(path, name)
+                                 .map_err(reading("parameter file", path))));
+    }
+    if let Some(arg) = matches.value_of("import-upr") {
+        let (path, name) = try!(parse_entry_arg(arg));
+        flags = flags.merge(try!(upr::load(path, name)
+                                 .map_err(reading("parameter file", path))));
+    }
+    if let Some(path) = matches.value_of("import-kfr") {
+        let path = Path::new(path);
+        flags = flags.merge(try!(kfr::load(path)
+                                 .map_err(reading("location file", path))).config());
+    }
+    match matches.value_of("config") {
+        Some(path) => {
+            let path = Path::new(path);
+            let file = try!(config::load(path)
+                            .map_err(reading("configuration file", path)));
+            flags = flags.merge(file);
+        }
+        None => ()
+    }
+    try!(check_config(&flags));
+    Ok(flags)
+}
+
+/// Check that the settings in `config` that must be positive are, and that
+/// its auto exposure percentiles make sense. The command line and environment
+/// check each value as they parse it, but configuration files, JSON settings,
+/// and imported parameter files don't, so every merged `Config` comes through
+/// here before we use it.
+fn check_config(config: &Config) -> Result<(), Error> {
+    fn positive<T>(value: Option<T>, what: &str) -> Result<(), Error>
+        where T: PartialOrd + Default + fmt::Display
+    {
+        match value {
+            Some(value) if !(value > T::default()) => {
+                Err(Error::parse(format!("bad {} {}: must be greater than zero", what, value)))
+            }
+            _ => Ok(())
+        }
+    }
+
+    try!(positive(config.threads, "thread count"));
+    try!(positive(config.iterations, "iteration limit"));
+    try!(positive(config.zoom, "zoom factor"));
+    try!(positive(config.samples, "sample count"));
+    try!(positive(config.escape_radius, "escape radius"));
+    try!(positive(config.distance_thickness, "distance thickness"));
+    try!(positive(config.width, "image width"));
+    try!(positive(config.height, "image height"));
+    try!(positive(config.size.map(|size| size.0), "image width"));
+    try!(positive(config.size.map(|size| size.1), "image height"));
+    try!(positive(config.tile_size, "tile size"));
+    try!(positive(config.csv_step, "CSV step"));
+    try!(positive(config.palette_period, "palette period"));
+    if let Some(percentiles) = config.auto_expose {
+        try!(check_percentiles(percentiles).map_err(|why| {
+            Error::parse(format!("bad auto exposure percentiles {:?}: {}", percentiles, why))
+        }));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_check_config() {
+    assert!(check_config(&Config::default()).is_ok());
+    let good = Config {
+        threads: Some(4),
+        iterations: Some(100),
+        zoom: Some(2.0),
+        size: Some((40, 30)),
+        csv_step: Some(2),
+        auto_expose: Some((1.0, 99.0)),
+        .. Config::default()
+    };
+    assert!(check_config(&good).is_ok());
+    let zeros = [
+        Config { threads: Some(0), .. good.clone() },
+        Config { iterations: Some(0), .. good.clone() },
+        Config { zoom: Some(0.0), .. good.clone() },
+        Config { samples: Some(0), .. good.clone() },
+        Config { escape_radius: Some(0.0), .. good.clone() },
+        Config { distance_thickness: Some(0.0), .. good.clone() },
+        Config { width: Some(0), .. good.clone() },
+        Config { height: Some(0), .. good.clone() },
+        Config { size: Some((0, 0)), .. good.clone() },
+        Config { tile_size: Some(0), .. good.clone() },
+        Config { csv_step: Some(0), .. good.clone() },
+        Config { palette_period: Some(0), .. good.clone() },
+        Config { auto_expose: Some((99.0, 1.0)), .. good.clone() },
+        Config { auto_expose: Some((1.0, 120.0)), .. good.clone() },
+        Config { escape_radius: Some(f64::NAN), .. good.clone() }
+    ];
+    for config in &zeros {
+        assert!(check_config(config).is_err(), "{:?} passed", config);
+    }
+
+    // Zeros in a configuration file are caught once it's merged with the
+    // flags that don't override them.
+    let file = config::parse("threads = 0\niterations = 0\nsize = [0, 0]\n").unwrap();
+    let flags = Config { output: Some("a.png".to_string()), .. Config::default() };
+    assert!(check_config(&flags.merge(file)).is_err());
+}
+
+/// Report `error`, and exit with the status its kind calls for.
+fn fail(error: Error) -> ! {
+    writeln!(io::stderr(), "error: {}", error).unwrap();
+    process::exit(error.kind.exit_status())
+}
+
+/// Report the command-line error `error`, and exit. Requests for help or the
+/// version number come through here too; they aren't really errors, and clap
+/// knows how to handle them.
+fn fail_usage(error: clap::Error) -> ! {
+    if !error.use_stderr() {
+        error.exit();
+    }
+    writeln!(io::stderr(), "{}", error.message).unwrap();
+    process::exit(error::Kind::Usage.exit_status())
+}
+
+/// Return the region of the complex plane that `config` describes, looking up
+/// named locations in its bookmarks file. If a bookmarked location records an
+/// iteration limit, and `config` doesn't have one of its own, fill it in.
+fn resolve_region(config: &mut Config) -> Result<Region, Error> {
+    if let Some(name) = config.location.clone() {
+        let path = config.bookmarks.clone()
+            .unwrap_or(bookmarks::DEFAULT_PATH.to_string());
+        let location = try!(bookmarks::find(Path::new(&path), &name).map_err(|e| {
+            let context = format!("error looking up bookmarked location '{}'", name);
+            match e.kind() {
+                // The bookmarks file has no such location.
+                io::ErrorKind::NotFound => Error::usage(format!("{}: {}", context, e)),
+                _ => Error::from_io(&context, e)
+            }
+        }));
+        config.iterations = config.iterations.or(location.iterations);
+        return Ok(Region::CenterZoom(location.center, location.zoom));
+    }
+
+    if let Some(center) = config.center {
+        return Ok(Region::CenterZoom(center, config.zoom.unwrap_or(1.0)));
+    }
+
+    match (config.upper_left, config.lower_right) {
+        (Some(upper_left), Some(lower_right)) => Ok(Region::Corners(upper_left, lower_right)),
+        _ => Err(Error::usage("no region was given (use --upper-left and --lower-right, \
+                               --center, or --location)"))
+    }
+}
+
+/// Return the view that `config` describes.
+///
+/// If `config` gives only one of the image's dimensions, work out the other
+/// from the region's aspect ratio. If it gives both, and they don't match the
+/// region's aspect ratio, the image will be stretched; warn about that.
+fn resolve_view(config: &mut Config) -> Result<View, Error> {
+    let region = try!(resolve_region(config));
+    let bounds = match (config.size, config.width, config.height) {
+        (Some(size), _, _) => size,
+        (None, None, None) => return Err(Error::usage("no image size was given (use --size)")),
+        (None, width, height) => try!(region.complete_bounds(width, height)
+                                      .map_err(Error::usage))
+    };
+
+    let view = View { rotation: config.rotate.unwrap_or(0.0), .. region.view(bounds) };
+    let stretch = view.pixel_aspect_ratio();
+    if (stretch - 1.0).abs() > 0.01 {
+        writeln!(io::stderr(),
+                 "warning: image size {}x{} doesn't match the region's aspect ratio; \
+                  each pixel covers {} times as much width as height",
+                 bounds.0, bounds.1, stretch)
+            .unwrap();
+    }
+
+    Ok(view)
+}
+
+/// Check that `view` isn't zoomed in so deeply that `f64` can't tell its pixels
+/// apart. If it is, return an error, unless `config` says to force the render,
+/// in which case just print a warning.
+fn check_precision(view: &View, config: &Config) -> Result<(), Error> {
+    if !view.exceeds_precision() {
+        return Ok(());
+    }
+
+    let (width, height) = view.pixel_size();
+    let message = format!("at zoom {:e}, adjacent pixels are only {:e} apart, but \
+                           64-bit floating point can only distinguish points {:e} \
+                           apart there; the image would come out blocky",
+                          view.zoom(), width.min(height), view.precision());
+    if config.force.unwrap_or(false) {
+        writeln!(io::stderr(), "warning: {}", message).unwrap();
+        Ok(())
+    } else {
+        Err(Error::usage(format!("{} (use --force to render anyway)", message)))
+    }
+}
+
+/// Return the seed for random sampling that `config` calls for. If it doesn't
+/// give one, choose one at random, store it in `config`, and print it to the
+/// standard error stream, so that the user can reproduce the render.
+fn resolve_seed(config: &mut Config) -> u64 {
+    if config.seed.is_none() {
+        let seed = random::entropy_seed();
+        writeln!(io::stderr(), "seed: {}", seed).unwrap();
+        config.seed = Some(seed);
+    }
+    config.seed.unwrap()
+}
+
+/// Render the image that `config` describes, and write it to its output file.
+/// Return the name of the file written.
+fn render_config(mut config: Config) -> Result<String, Error> {
+    try!(check_config(&config));
+    let output = try!(config.output_path()
+                      .ok_or(Error::usage("no output file was given (use --output)")));
+    let encoding = try!(resolve_encoding(&config, &output));
+    let view = try!(resolve_view(&mut config));
+    try!(check_precision(&view, &config));
+    resolve_seed(&mut config);
+
+    // Each orbit is followed once for the image, once more for iteration
+    // counts, and once more for smooth counts, however many files each of
+    // those goes to. Make sure they can all be drawn before drawing anything.
+    let smooth_npy = config.npy_smooth.unwrap_or(false);
+    let counts_option = if config.raw_path().is_some() {
+        Some("--output-raw")
+    } else if config.npy_path().is_some() && !smooth_npy {
+        Some("--output-npy")
+    } else {
+        None
+    };
+    if let Some(option) = counts_option {
+        try!(check_counts(&config, option));
+    }
+    if config.csv_path().is_some() {
+        try!(check_counts(&config, "--output-csv"));
+    }
+    // Only the OpenEXR file has room for extra channels.
+    let extra = match config.exr_path() {
+        Some(_) => config.exr_channels.clone().unwrap_or_default(),
+        None => vec![]
+    };
+    let field = config.exr_path().is_some() || config.hdr_path().is_some()
+        || (config.npy_path().is_some() && smooth_npy);
+    if field {
+        try!(check_field(&config, &extra));
+    }
+
+    let bitmap = match config.data_path() {
+        Some(data) => {
+            let coloring = try!(resolve_coloring(&config));
+            let samples = try!(try!(render_samples(&view, &config)).ok_or_else(|| {
+                Error::usage("only escape-time images can be saved with --output-data")
+            }));
+            let samples = samples::Samples {
+                bounds: view.bounds,
+                limit: config.iterations(),
+                samples: samples
+            };
+            try!(samples::save(Path::new(&data), &samples).map_err(|e| {
+                Error::from_io(&format!("error writing sample file '{}'", data), e)
+            }));
+            paint(&samples.samples, view.bounds.0, samples.limit, &coloring)
+        }
+        None => try!(render_view(&view, &config))
+    };
+    try!(write_bitmap(&output, &bitmap, view.bounds, encoding).map_err(|e| {
+        Error::from_io(&format!("error writing image file '{}'", output), e)
+    }));
+    if let Some(normals) = config.normals_path() {
+        let encoding = try!(resolve_encoding(&config, &normals));
+        let bitmap = try!(render_normals(&view, &config));
+        try!(write_bitmap(&normals, &bitmap, view.bounds, encoding).map_err(|e| {
+            Error::from_io(&format!("error writing image file '{}'", normals), e)
+        }));
+    }
+    let counts = match counts_option {
+        Some(option) => Some(raw::Counts {
+            view: view,
+            limit: config.iterations(),
+            counts: try!(render_counts(view.bounds, &view.transform(), &config, option))
+        }),
+        None => None
+    };
+    let field = if field { Some(try!(render_field(&view, &config, &extra))) } else { None };
+    if let Some(raw) = config.raw_path() {
+        try!(raw::save(Path::new(&raw), counts.as_ref().unwrap()).map_err(|e| {
+            Error::from_io(&format!("error writing iteration count file '{}'", raw), e)
+        }));
+    }
+    if let Some(npy) = config.npy_path() {
+        let array = match field {
+            Some(ref channels) if smooth_npy => npy::Array::F32(&channels[0].1),
+            _ => npy::Array::U32(&counts.as_ref().unwrap().counts)
+        };
+        try!(npy::save(Path::new(&npy), view.bounds, array).map_err(|e| {
+            Error::from_io(&format!("error writing NumPy file '{}'", npy), e)
+        }));
+    }
+    if let Some(csv) = config.csv_path() {
+        // Sample every `step`th pixel each way, starting from the upper left:
+        // from the counts already drawn, if there are any, or else by drawing
+        // just the pixels we need.
+        let step = config.csv_step.unwrap_or(1);
+        let grid = ((view.bounds.0 + step - 1) / step, (view.bounds.1 + step - 1) / step);
+        let transform = view.transform();
+        let sampled = match counts {
+            Some(ref counts) => (0..grid.0 * grid.1).map(|i| {
+                counts.counts[(i / grid.0 * view.bounds.0 + i % grid.0) * step]
+            }).collect(),
+            None => {
+                let sampled = Transform {
+                    origin: transform.origin,
+                    column: (transform.column.0 * step as f64, transform.column.1 * step as f64),
+                    row: (transform.row.0 * step as f64, transform.row.1 * step as f64)
+                };
+                try!(render_counts(grid, &sampled, &config, "--output-csv"))
+            }
+        };
+        try!(csv::save(Path::new(&csv), grid, &sampled,
+                       |column, row| (column * step, row * step),
+                       |pixel| pixel_to_point(&transform, pixel)).map_err(|e| {
+            Error::from_io(&format!("error writing CSV file '{}'", csv), e)
+        }));
+    }
+    if let Some(exr) = config.exr_path() {
+        let channels: Vec<(&str, &[f32])> = field.as_ref().unwrap().iter()
+            .map(|&(name, ref values)| (name, &values[..]))
+            .collect();
+        try!(exr::save(Path::new(&exr), view.bounds, &channels).map_err(|e| {
+            Error::from_io(&format!("error writing OpenEXR file '{}'", exr), e)
+        }));
+    }
+    if let Some(hdr) = config.hdr_path() {
+        let channels = field.as_ref().unwrap();
+        try!(hdr::save(Path::new(&hdr), view.bounds, &channels[0].1).map_err(|e| {
+            Error::from_io(&format!("error writing Radiance HDR file '{}'", hdr), e)
+        }));
+    }
+    Ok(output)
+}
+
+/// Carry out the `render` subcommand.
+fn render_command(matches: &ArgMatches) -> Result<(), Error> {
+    let mut config = try!(settings_from_matches(matches));
+
+    if matches.is_present("stdin-json") {
+        let stdin = io::stdin();
+        let params = try!(config::read_json(stdin.lock()).map_err(|e| {
+            Error::from_io("error reading settings from standard input", e)
+        }));
+        config = config.merge(params);
+        try!(check_config(&config));
+
+        // With no output file given, write the image to standard output, so
+        // that a program driving us can get the whole result through pipes.
+        if config.output.is_none() {
+            config.output = Some("-".to_string());
+        }
+    }
+
+    if let Some(path) = matches.value_of("watch") {
+        return watch(config, Path::new(path));
+    }
+
+    for config in try!(region_configs(matches, config)) {
+        if matches.is_present("dry-run") {
+            try!(dry_run(config));
+        } else {
+            try!(render_config(config));
+        }
+    }
+    Ok(())
+}
+
+/// Return a `Config` for each `--region` and `--output` pair in `matches`,
+/// with the other settings taken from `config`. If there are no `--region`
+/// arguments, just return `config`.
+fn region_configs(matches: &ArgMatches, config: Config) -> Result<Vec<Config>, Error> {
+    let regions: Vec<&str> = match matches.values_of("region") {
+        Some(regions) => regions.collect(),
+        None => return Ok(vec![config])
+    };
+    let outputs: Vec<&str> = matches.values_of("output")
+        .map(|outputs| outputs.collect())
+        .unwrap_or_else(Vec::new);
+    if regions.len() != outputs.len() {
+        return Err(Error::usage(format!("{} regions were given, but {} output files; \
+                                         each --region needs its own --output",
+                                        regions.len(), outputs.len())));
+    }
+
+    regions.into_iter().zip(outputs).map(|(region, output)| {
+        let (upper_left, lower_right) = try!(parse_region(region).map_err(|why| {
+            Error::parse(format!("error parsing region '{}': {}", region, why))
+        }));
+        let crop = Config {
+            output: Some(output.to_string()),
+            upper_left: Some(upper_left),
+            lower_right: Some(lower_right),
+            .. Config::default()
+        };
+        Ok(crop.merge(config.clone()))
+    }).collect()
+}
+
+extern crate notify;
+
+use notify::{DebouncedEvent, RecursiveMode, Watcher};
+use std::sync::mpsc::channel;
+use std::time::Duration;
+
+/// Render the settings in `flags`, layered over those in the configuration
+/// file at `path`, and then do so again each time the file changes, forever.
+///
+/// Errors reading the file or rendering are reported, but don't stop us: the
+/// user is presumably in the middle of editing the file, and will fix it.
+fn watch(flags: Config, path: &Path) -> Result<(), Error> {
+    let mut seed = None;
+    let mut render_once = || {
+        let start = Instant::now();
+        let result = config::load(path)
+            .map_err(|e| {
+                let context = format!("error reading configuration file '{}'", path.display());
+                Error::from_io(&context, e)
+            })
+            .and_then(|file| render_config(watched_config(&flags, file, &mut seed)));
+        match result {
+            Ok(output) => {
+                let elapsed = start.elapsed();
+                println!("wrote {} in {}.{:03}s", output,
+                         elapsed.as_secs(), elapsed.subsec_nanos() / 1_000_000);
+            }
+            Err(error) => writeln!(io::stderr(), "failed: {}", error).unwrap()
+        }
+    };
+
+    // Many editors save a file by writing a new one and renaming it over the
+    // old, so watch the directory, not the file itself.
+    let (tx, rx) = channel();
+    let mut watcher = try!(notify::watcher(tx, Duration::from_millis(200)).map_err(|e| {
+        Error::io(format!("error starting file watcher: {}", e))
+    }));
+    let dir = match path.parent() {
+        Some(dir) if dir != Path::new("") => dir,
+        _ => Path::new(".")
+    };
+    try!(watcher.watch(dir, RecursiveMode::NonRecursive).map_err(|e| {
+        Error::io(format!("error watching '{}': {}", dir.display(), e))
+    }));
+    let name = path.file_name();
+
+    render_once();
+    for event in rx.iter() {
+        let changed = match event {
+            DebouncedEvent::Create(ref p) |
+            DebouncedEvent::Write(ref p) |
+            DebouncedEvent::Rename(_, ref p) => p.file_name() == name,
+            _ => false
+        };
+        if changed {
+            render_once();
+        }
+    }
+    Err(Error::io("file watcher stopped unexpectedly"))
+}
+
+/// Return the settings for rendering the watched configuration file `file`,
+/// under the command-line settings `flags`. If neither gives a seed, use the
+/// one chosen for the first render, in `seed`, so that only the user's edits
+/// change the image; if there is no first render yet, choose one and store it
+/// there.
+fn watched_config(flags: &Config, file: Config, seed: &mut Option<u64>) -> Config {
+    let mut config = flags.clone().merge(file);
+    if config.seed.is_none() {
+        config.seed = *seed;
+        *seed = Some(resolve_seed(&mut config));
+    }
+    config
+}
+
+#[test]
+fn test_watched_config() {
+    let mut seed = None;
+    let chosen = watched_config(&Config::default(), Config::default(), &mut seed).seed;
+    assert!(chosen.is_some());
+    assert_eq!(seed, chosen);
+    assert_eq!(watched_config(&Config::default(), Config::default(), &mut seed).seed, chosen);
+
+    // A seed in the file or on the command line wins, without disturbing the
+    // one chosen for renders that give none.
+    let file = Config { seed: Some(1), .. Config::default() };
+    assert_eq!(watched_config(&Config::default(), file.clone(), &mut seed).seed, Some(1));
+    let flags = Config { seed: Some(2), .. Config::default() };
+    assert_eq!(watched_config(&flags, file, &mut seed).seed, Some(2));
+    assert_eq!(seed, chosen);
+}
+
+/// The most pixels `estimate_render_time` will render to time its probe.
+const PROBE_PIXELS: usize = 10_000;
+
+/// Estimate how many seconds rendering `view` with `config` will take, by
+/// timing a render of the same view at a lower resolution and scaling up.
+fn estimate_render_time(view: &View, config: &Config) -> Result<f64, Error> {
+    let pixels = view.bounds.0 * view.bounds.1;
+    let scale = (PROBE_PIXELS as f64 / pixels as f64).sqrt().min(1.0);
+    let probe = View {
+        bounds: (cmp::max(1, (view.bounds.0 as f64 * scale) as usize),
+                 cmp::max(1, (view.bounds.1 as f64 * scale) as usize)),
+        .. *view
+    };
+
+    let start = Instant::now();
+    try!(render_view(&probe, config));
+    let elapsed = start.elapsed();
+    let seconds = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9;
+    Ok(seconds * pixels as f64 / (probe.bounds.0 * probe.bounds.1) as f64)
+}
+
+/// Check the settings in `config`, and describe the render they call for,
+/// without actually carrying it out.
+fn dry_run(mut config: Config) -> Result<(), Error> {
+    let view = try!(resolve_view(&mut config));
+    try!(check_precision(&view, &config));
+    // Hold each pixel's iteration count and color, or the root it
+    // converged to and its color.
+    let pixel_bytes = match config.mode.unwrap_or(Mode::Escape) {
+        Mode::Escape => {
+            try!(resolve_set(&config));
+            try!(resolve_steps(&config, try!(resolve_expression(&config)).as_ref()));
+            try!(resolve_script(&config));
+            let coloring = try!(resolve_coloring(&config));
+            let channels = if coloring.palette.is_gray() { 1 } else { 3 };
+            mem::size_of::<u32>() + channels * coloring.depth as usize / 8
+        }
+        Mode::Newton => {
+            try!(resolve_polynomial(&config));
+            mem::size_of::<Option<(usize, u32)>>() + 3
+        }
+        Mode::Buddhabrot => {
+            try!(resolve_set(&config));
+            try!(resolve_steps(&config, try!(resolve_expression(&config)).as_ref()));
+            // Each thread keeps its own histograms, and then we total them.
+            let channels = if config.channel_limits.is_some() { 3 } else { 1 };
+            channels * (mem::size_of::<u32>() * (config.threads() + 1) + 1)
+        }
+        Mode::Inverse => {
+            try!(resolve_inverse(&config));
+            mem::size_of::<u8>()
+        }
+    };
+    let pixels = view.bounds.0 * view.bounds.1;
+    let bytes = pixels * pixel_bytes;
+
+    println!("output:       {}", config.output_path().unwrap_or_else(|| "(none)".to_string()));
+    if let Some(normals) = config.normals_path() {
+        println!("normals:      {}", normals);
+    }
+    if let Some(data) = config.data_path() {
+        println!("samples:      {}", data);
+    }
+    if let Some(raw) = config.raw_path() {
+        println!("counts:       {}", raw);
+    }
+    if let Some(npy) = config.npy_path() {
+        println!("NumPy:        {}", npy);
+    }
+    if let Some(csv) = config.csv_path() {
+        println!("CSV:          {}", csv);
+    }
+    if let Some(exr) = config.exr_path() {
+        println!("OpenEXR:      {}", exr);
+    }
+    if let Some(hdr) = config.hdr_path() {
+        println!("Radiance HDR: {}", hdr);
+    }
+    print_view(&view);
+    println!("iterations:   {}", config.iterations());
+    println!("memory:       {:.1} MiB", bytes as f64 / (1024.0 * 1024.0));
+    println!("render time:  about {:.1}s on {} threads",
+             try!(estimate_render_time(&view, &config)), config.threads());
+    Ok(())
+}
+
+/// Carry out the `animate` subcommand.
+fn animate_command(matches: &ArgMatches) -> Result<(), Error> {
+    let mut config = try!(settings_from_matches(matches));
+    let start = try!(resolve_view(&mut config));
+    resolve_seed(&mut config);
+    // The final frame must be the same size as the first, even if that means
+    // stretching it. Unless told otherwise, keep the rotation steady.
+    let mut to_config = try!(config_from_matches(matches, "to-"));
+    let end = View {
+        rotation: to_config.rotate.unwrap_or(start.rotation),
+        .. try!(resolve_region(&mut to_config)).view(start.bounds)
+    };
+    // Interpolated views are never deeper than the deeper of the two ends.
+    try!(check_precision(&start, &config));
+    try!(check_precision(&end, &config));
+    let frames = value_t!(matches, "frames", usize).unwrap_or_else(|e| fail_usage(e));
+    let delay = try!(parse_arg(matches, "frame-delay", "frame delay", parse_positive))
+        .unwrap_or(DEFAULT_FRAME_DELAY);
+
+    // Either assemble the frames into an animated PNG or GIF, or write each to
+    // a file of its own. Frames are named for their format, so the extension
+    // can't choose it.
+    let mut animation = None;
+    let prefix = config.output_path();
+    let extension = config.format.unwrap_or(config::Format::Png).extension();
+    let encoding = if let Some(path) = matches.value_of("apng") {
+        if config.format.unwrap_or(config::Format::Png) != config::Format::Png {
+            return Err(Error::usage("animated PNG frames can only be PNG images"));
+        }
+        if frames == 0 {
+            return Err(Error::usage("an animated PNG needs at least one frame"));
+        }
+        let file = try!(File::create(path).map_err(|e| {
+            Error::from_io(&format!("error creating animated PNG file '{}'", path), e)
+        }));
+        let apng = apng::Animation::new(file, frames as u32, delay);
+        animation = Some((Animation::Png(apng), format!("animated PNG file '{}'", path)));
+        Encoding::Png
+    } else if let Some(path) = matches.value_of("gif") {
+        if config.depth.unwrap_or(8) != 8 {
+            return Err(Error::usage("GIF images can only have a bit depth of 8"));
+        }
+        if config.interior == Some(Interior::Transparent) {
+            return Err(Error::usage("GIF animations can't be transparent"));
+        }
+        let file = try!(File::create(path).map_err(|e| {
+            Error::from_io(&format!("error creating GIF file '{}'", path), e)
+        }));
+        // GIF counts delays in hundredths of a second.
+        let hundredths = ((delay as u32 + 5) / 10) as u16;
+        let gif = gif::Animation::new(io::BufWriter::new(file), start.bounds, hundredths,
+                                      matches.is_present("gif-dither"),
+                                      matches.is_present("gif-global-palette"));
+        animation = Some((Animation::Gif(gif), format!("GIF file '{}'", path)));
+        Encoding::Png
+    } else if let Some(arguments) = matches.value_of("ffmpeg") {
+        if config.depth.unwrap_or(8) != 8 {
+            return Err(Error::usage("frames for ffmpeg can only have a bit depth of 8"));
+        }
+        if config.interior == Some(Interior::Transparent) {
+            return Err(Error::usage("frames for ffmpeg can't be transparent"));
+        }
+        let pipe = try!(ffmpeg::Pipe::start(arguments, start.bounds, delay).map_err(|e| {
+            Error::from_io("error starting ffmpeg", e)
+        }));
+        animation = Some((Animation::Video(pipe), "frames to ffmpeg".to_string()));
+        Encoding::Png
+    } else {
+        if matches.is_present("frame-delay") {
+            return Err(Error::usage("--frame-delay only applies to --apng, --gif, and \
+                                     --ffmpeg"));
+        }
+        let prefix = prefix.as_ref().unwrap();
+        try!(resolve_encoding(&config, &format!("{}.{}", prefix, extension)))
+    };
+
+    // Morph from one blend to the other, if asked.
+    let morphs = to_config.morph.map(|end| (config.morph().map_or(0.0, |(_, t)| t), end));
+
+    // Every frame must have the same number of channels, whatever colors it
+    // turns out to use.
+    let gray = try!(resolve_coloring(&config)).palette.is_gray()
+        && config.interior != Some(Interior::Period);
+
+    for frame in 0..frames {
+        let t = if frames > 1 { frame as f64 / (frames - 1) as f64 } else { 0.0 };
+        let view = interpolate_view(&start, &end, t);
+        if let Some((from, to)) = morphs {
+            config.morph = Some(from + (to - from) * t);
+        }
+        let bitmap = try!(render_view_as(&view, &config, Some(gray)));
+        if let Some((ref mut animation, ref what)) = animation {
+            let added = match *animation {
+                Animation::Png(ref mut apng) => {
+                    let mut png = Vec::new();
+                    encode_png(&mut png, &bitmap, view.bounds).and_then(|_| apng.add(&png))
+                }
+                Animation::Gif(ref mut gif) => {
+                    rgba_pixels(&bitmap, "GIF").and_then(|pixels| {
+                        let pixels: Vec<[u8; 3]> = pixels.iter()
+                            .map(|pixel| [pixel[0], pixel[1], pixel[2]])
+                            .collect();
+                        gif.add(&pixels)
+                    })
+                }
+                Animation::Video(ref mut pipe) => {
+                    rgba_pixels(&bitmap, "video").and_then(|pixels| {
+                        let rgb: Vec<u8> = pixels.iter()
+                            .flat_map(|pixel| pixel[..3].iter().cloned())
+                            .collect();
+                        pipe.add(&rgb)
+                    })
+                }
+            };
+            try!(added.map_err(|e| {
+                Error::from_io(&format!("error writing {}", what), e)
+            }));
+            continue;
+        }
+        let filename = format!("{}{:04}.{}", prefix.as_ref().unwrap(), frame, extension);
+        try!(write_bitmap(&filename, &bitmap, view.bounds, encoding).map_err(|e| {
+            Error::from_io(&format!("error writing image file '{}'", filename), e)
+        }));
+    }
+    if let Some((animation, what)) = animation {
+        let finished = match animation {
+            Animation::Png(apng) => apng.finish(),
+            Animation::Gif(gif) => gif.finish(),
+            Animation::Video(pipe) => pipe.finish()
+        };
+        try!(finished.map_err(|e| {
+            Error::from_io(&format!("error writing {}", what), e)
+        }));
+    }
+    Ok(())
+}
+
+/// An animation file the `animate` subcommand is assembling its frames into.
+enum Animation {
+    Png(apng::Animation<File>),
+    Gif(gif::Animation<io::BufWriter<File>>),
+    Video(ffmpeg::Pipe)
+}
+
+/// How long to show each frame of an animated PNG, GIF, or video, in
+/// milliseconds, unless told otherwise: 25 frames a second.
+const DEFAULT_FRAME_DELAY: u16 = 40;
+
+/// Carry out the `atlas` subcommand. The region is of the parameter plane, and
+/// the grid's cells are its pixels: each shows the Julia set for the `c` at its
+/// center. If no region is given, use one around the Mandelbrot set.
+fn atlas_command(matches: &ArgMatches) -> Result<(), Error> {
+    let mut config = try!(settings_from_matches(matches));
+    if !config.has_region() {
+        config.upper_left = Some((-2.0, 1.25));
+        config.lower_right = Some((0.5, -1.25));
+    }
+    let output = try!(config.output_path()
+                      .ok_or(Error::usage("no output file was given (use --output)")));
+    let grid = try!(parse_arg(matches, "grid", "grid size", |s| {
+        match parse_pair::<usize>(s, 'x') {
+            Ok((columns, rows)) if columns > 0 && rows > 0 => Ok((columns, rows)),
+            Ok(_) => Err("the grid must have at least one row and column".to_string()),
+            Err(why) => Err(why)
+        }
+    })).unwrap();
+    let cell: usize = try!(parse_arg(matches, "cell-size", "cell size", parse_positive))
+        .unwrap();
+    let encoding = try!(resolve_encoding(&config, &output));
+    config.size = Some(grid);
+    let grid_view = try!(resolve_view(&mut config));
+
+    let expression = try!(resolve_expression(&config));
+    let steps = try!(resolve_steps(&config, expression.as_ref()));
+    let limit = config.iterations();
+    let coloring = try!(resolve_coloring(&config));
+    if coloring.legend.is_some() {
+        return Err(Error::usage("atlases can't be drawn with a legend"));
+    }
+    // Every cell must have the same number of channels, whatever colors it
+    // turns out to use.
+    let gray = coloring.palette.is_gray() && config.interior != Some(Interior::Period);
+    let channels = if gray { 1 } else { 3 } + if coloring.transparent { 1 } else { 0 };
+    let pixel_bytes = channels * coloring.depth as usize / 8;
+    let cell_view = View::from_center_zoom((cell, cell), (0.0, 0.0), 1.0);
+    let width = grid.0 * cell;
+    let mut pixels = vec![0; width * grid.1 * cell * pixel_bytes];
+
+    let transform = grid_view.transform();
+    for row in 0..grid.1 {
+        for column in 0..grid.0 {
+            let (x, y) = (column as f64 + 0.5, row as f64 + 0.5);
+            let c = Complex {
+                re: transform.origin.0 + x * transform.column.0 + y * transform.row.0,
+                im: transform.origin.1 + x * transform.column.1 + y * transform.row.1
+            };
+            let iteration = Iteration {
+                set: Set::Julia(c),
+                steps: &steps,
+                radius: config.escape_radius()
+            };
+            let line_bytes = cell * pixel_bytes;
+            let samples = try!(render_escapes(&iteration, &cell_view, &config));
+            let painted = paint_as(&samples, cell, limit, &coloring, gray);
+            for (y, line) in painted.pixels.chunks(line_bytes).enumerate() {
+                let start = ((row * cell + y) * width + column * cell) * pixel_bytes;
+                pixels[start..start + line_bytes].copy_from_slice(line);
+            }
+        }
+    }
+
+    let color = color_type(gray, coloring.transparent, coloring.depth);
+    let bitmap = Bitmap {
+        pixels: pixels,
+        color: color,
+        gamma: coloring.gamma,
+        profile: coloring.profile.clone()
+    };
+    write_bitmap(&output, &bitmap, (width, grid.1 * cell), encoding).map_err(|e| {
+        Error::from_io(&format!("error writing image file '{}'", output), e)
+    })
+}
+
+/// Carry out the `tiles` subcommand, writing a web map's tiles under the
+/// output directory. The region is the whole map, the single tile at level
+/// zero; if none is given, use one around the Mandelbrot set.
+fn tiles_command(matches: &ArgMatches) -> Result<(), Error> {
+    let mut config = try!(settings_from_matches(matches));
+    if !config.has_region() {
+        config.center = Some((-0.5, 0.0));
+    }
+    let directory = try!(config.output_path()
+                         .ok_or(Error::usage("no output directory was given (use --output)")));
+    let (low, high) = try!(parse_arg(matches, "levels", "zoom levels", parse_levels)).unwrap();
+    let size = config.tile_size.unwrap_or(xyz::DEFAULT_TILE_SIZE);
+    let encoding = try!(resolve_tile_encoding(&config));
+    let extension = if encoding == Encoding::Png { "png" } else { "jpg" };
+
+    // Each tile is colored on its own, so nothing may depend on what the rest
+    // of the map looks like.
+    if config.equalize == Some(true) || config.auto_expose.is_some() {
+        return Err(Error::usage("web map tiles can't be drawn with --equalize or \
+                                 --auto-expose"));
+    }
+    if config.legend.is_some() {
+        return Err(Error::usage("web map tiles can't be drawn with a legend"));
+    }
+    config.size = Some((size, size));
+    let map = try!(resolve_view(&mut config));
+    try!(check_precision(&xyz::Tile { level: high, column: 0, row: 0 }.view(&map), &config));
+    resolve_seed(&mut config);
+
+    // Render whole tiles in parallel, rather than each tile's rows, and report
+    // the first failure.
+    let tile_config = Config { threads: Some(1), .. config.clone() };
+    let tiles = xyz::count(low, high);
+    let next_tile = AtomicUsize::new(0);
+    let failure = Mutex::new(None);
+    crossbeam::scope(|scope| {
+        for _ in 0..config.threads() {
+            scope.spawn(|| {
+                loop {
+                    if failure.lock().unwrap().is_some() {
+                        break;
+                    }
+                    let index = next_tile.fetch_add(1, Ordering::SeqCst) as u64;
+                    if index >= tiles {
+                        break;
+                    }
+                    let tile = xyz::tile(low, high, index).unwrap();
+                    let written = write_map_tile(&directory, tile, &map, &tile_config, encoding,
+                                                 extension);
+                    if let Err(error) = written {
+                        failure.lock().unwrap().get_or_insert(error);
+                        break;
+                    }
+                }
+            });
+        }
+    });
+    match failure.into_inner().unwrap() {
+        Some(error) => Err(error),
+        None => Ok(())
+    }
+}
+
+/// Render `tile` of `map` as `config` says, and write it under `directory`,
+/// encoded as `encoding`, with the extension `extension`.
+fn write_map_tile(directory: &str, tile: xyz::Tile, map: &View, config: &Config,
+                  encoding: Encoding, extension: &str)
+    -> Result<(), Error>
+{
+    let path = Path::new(directory).join(tile.path(extension));
+    let filename = path.to_string_lossy().into_owned();
+    let bitmap = try!(render_view(&tile.view(map), config));
+    try!(std::fs::create_dir_all(path.parent().unwrap()).and_then(|_| {
+        write_bitmap(&filename, &bitmap, map.bounds, encoding)
+    }).map_err(|e| Error::from_io(&format!("error writing image file '{}'", filename), e)));
+    Ok(())
+}
+
+/// Carry out the `colorize` subcommand, painting the samples saved by
+/// `render --output-data` with the palette and transfer settings given now.
+fn colorize_command(matches: &ArgMatches) -> Result<(), Error> {
+    let config = try!(settings_from_matches(matches));
+    let output = try!(config.output_path()
+                      .ok_or(Error::usage("no output file was given (use --output)")));
+    let encoding = try!(resolve_encoding(&config, &output));
+    let coloring = try!(resolve_coloring(&config));
+    let input = matches.value_of("input").unwrap();
+    let samples = try!(load_samples(Path::new(input)).map_err(|e| {
+        Error::from_io(&format!("error reading sample file '{}'", input), e)
+    }));
+    let bitmap = paint(&samples.samples, samples.bounds.0, samples.limit, &coloring);
+    write_bitmap(&output, &bitmap, samples.bounds, encoding).map_err(|e| {
+        Error::from_io(&format!("error writing image file '{}'", output), e)
+    })
+}
+
+/// Read the samples in the file at `path`, written by either `render
+/// --output-data` or `render --output-raw`.
+fn load_samples(path: &Path) -> io::Result<samples::Samples> {
+    let mut input = io::BufReader::new(try!(File::open(path)));
+    if try!(input.fill_buf()).starts_with(raw::MAGIC) {
+        let counts = try!(raw::read(input));
+        return Ok(samples::Samples {
+            bounds: counts.view.bounds,
+            limit: counts.limit,
+            samples: coloring::counts(&counts.counts)
+        });
+    }
+    samples::read(input)
+}
+
+/// Carry out the `palette` subcommand.
+fn palette_command(matches: &ArgMatches) -> Result<(), Error> {
+    match matches.subcommand() {
+        ("preview", Some(matches)) => {
+            let config = try!(settings_from_matches(matches));
+            let output = try!(config.output_path()
+                              .ok_or(Error::usage("no output file was given (use --output)")));
+            let strip = try!(parse_arg(matches, "strip", "strip size", |s| {
+                match parse_pair::<usize>(s, 'x') {
+                    Ok((width, height)) if width > 0 && height > 0 => Ok((width, height)),
+                    Ok(_) => Err("the strip must be at least one pixel each way".to_string()),
+                    Err(why) => Err(why)
+                }
+            })).unwrap();
+            let encoding = try!(resolve_encoding(&config, &output));
+            let coloring = try!(resolve_coloring(&config));
+            let limit = config.iterations();
+            let samples = preview_samples(strip, limit);
+            let bitmap = paint(&samples, strip.0, limit, &coloring);
+            write_bitmap(&output, &bitmap, strip, encoding).map_err(|e| {
+                Error::from_io(&format!("error writing image file '{}'", output), e)
+            })
+        }
+        _ => unreachable!()
+    }
+}
+
+/// Return the samples for a palette preview strip whose dimensions are given
+/// by `bounds`: in each column, the escape time the same fraction of the way
+/// from zero to `limit`.
+fn preview_samples(bounds: (usize, usize), limit: u32) -> Vec<Sample> {
+    let row: Vec<Sample> = (0..bounds.0).map(|column| {
+        Sample::Count((column as u64 * limit as u64 / bounds.0 as u64) as u32)
+    }).collect();
+    (0..bounds.1).flat_map(|_| row.iter().cloned()).collect()
+}
+
+#[test]
+fn test_preview_samples() {
+    assert_eq!(preview_samples((4, 2), 100),
+               coloring::counts(&[0, 25, 50, 75, 0, 25, 50, 75]));
+    assert_eq!(preview_samples((3, 1), 2), coloring::counts(&[0, 0, 1]));
+}
+
+/// Carry out the `lyapunov` subcommand. The region is a rectangle of growth
+/// rates, with `a` along the real axis and `b` along the imaginary axis; if
+/// none is given, use the one where the most interesting structure lies.
+fn lyapunov_command(matches: &ArgMatches) -> Result<(), Error> {
+    let mut config = try!(settings_from_matches(matches));
+    if !config.has_region() {
+        config.upper_left = Some((2.0, 4.0));
+        config.lower_right = Some((4.0, 2.0));
+    }
+    let output = try!(config.output_path()
+                      .ok_or(Error::usage("no output file was given (use --output)")));
+    let encoding = try!(resolve_encoding(&config, &output));
+    let sequence = try!(lyapunov::parse_sequence(matches.value_of("sequence").unwrap())
+                        .map_err(|why| Error::parse(format!("error parsing sequence: {}", why))));
+    let view = try!(resolve_view(&mut config));
+    let iterations = config.iterations();
+
+    let exponents = render_parallel(view.bounds, &view.transform(), config.threads(),
+                                    |point| {
+        lyapunov::exponent(&sequence, point.re, point.im, iterations)
+    });
+    let bitmap = Bitmap {
+        pixels: lyapunov::colorize(&exponents),
+        color: ColorType::RGB(8),
+        gamma: None,
+        profile: None
+    };
+    write_bitmap(&output, &bitmap, view.bounds, encoding).map_err(|e| {
+        Error::from_io(&format!("error writing image file '{}'", output), e)
+    })
+}
+
+/// Carry out the `info` subcommand.
+fn info_command(matches: &ArgMatches) -> Result<(), Error> {
+    let mut config = try!(settings_from_matches(matches));
+    print_view(&try!(resolve_view(&mut config)));
+    Ok(())
+}
+
+/// Print a description of `view`: its size, where it lies on the complex
+/// plane, and how much of the plane each pixel covers.
+fn print_view(view: &View) {
+    let (width, height) = view.extent();
+    let center = view.center();
+
+    println!("image size:   {}x{} pixels", view.bounds.0, view.bounds.1);
+    println!("upper left:   {},{}", view.upper_left.0, view.upper_left.1);
+    println!("lower right:  {},{}", view.lower_right.0, view.lower_right.1);
+    println!("center:       {},{}", center.0, center.1);
+    println!("extent:       {} x {}", width, height);
+    println!("pixel size:   {} x {}",
+             width / view.bounds.0 as f64, height / view.bounds.1 as f64);
+    println!("zoom:         {}", view.zoom());
+    if view.rotation != 0.0 {
+        println!("rotation:     {} degrees", view.rotation);
+    }
+    if view.exceeds_precision() {
+        println!("precision:    insufficient; pixels are closer together than \
+                  64-bit floating point can distinguish");
+    }
+}
+
+/// Carry out the `locate` subcommand, printing the point on the complex plane
+/// at each pixel given with `--pixel`, and the pixel holding each point given
+/// with `--point`.
+fn locate_command(matches: &ArgMatches) -> Result<(), Error> {
+    let mut config = try!(settings_from_matches(matches));
+    let view = try!(resolve_view(&mut config));
+    let transform = view.transform();
+
+    for arg in matches.values_of("pixel").into_iter().flat_map(|values| values) {
+        let (column, row) = try!(parse_pair(arg, ',').map_err(|why| {
+            Error::parse(format!("error parsing pixel '{}': {}", arg, why))
+        }));
+        if column >= view.bounds.0 || row >= view.bounds.1 {
+            return Err(Error::usage(format!("pixel {},{} is outside the {}x{} image",
+                                            column, row, view.bounds.0, view.bounds.1)));
+        }
+        let point = pixel_to_point(&transform, (column, row));
+        println!("pixel {},{} is at {},{}", column, row, point.0, point.1);
+    }
+
+    for arg in matches.values_of("point").into_iter().flat_map(|values| values) {
+        let point = try!(parse_complex(arg).map_err(|why| {
+            Error::parse(format!("error parsing point '{}': {}", arg, why))
+        }));
+        match point_to_pixel(&transform, view.bounds, point) {
+            Some((column, row)) => {
+                println!("point {},{} is in pixel {},{}", point.0, point.1, column, row)
+            }
+            None => println!("point {},{} is outside the image", point.0, point.1)
+        }
+    }
+    Ok(())
+}
+
+/// Carry out the `bookmark` subcommand.
+fn bookmark_command(matches: &ArgMatches) -> Result<(), Error> {
+    let path = |matches: &ArgMatches| {
+        PathBuf::from(matches.value_of("bookmarks").unwrap_or(bookmarks::DEFAULT_PATH))
+    };
+    let load = |path: &Path| {
+        bookmarks::load(path).map_err(|e| {
+            Error::from_io(&format!("error reading bookmarks file '{}'", path.display()), e)
+        })
+    };
+
+    match matches.subcommand() {
+        ("add", Some(matches)) => {
+            let mut config = try!(config_from_matches(matches, ""));
+            let (center, zoom) = try!(resolve_region(&mut config)).center_zoom();
+            let path = path(matches);
+
+            let mut bookmarks = try!(load(&path));
+            bookmarks.insert(matches.value_of("name").unwrap().to_string(),
+                             bookmarks::Location {
+                                 center: center,
+                                 zoom: zoom,
+                                 iterations: config.iterations
+                             });
+            bookmarks::save(&path, &bookmarks).map_err(|e| {
+                Error::from_io(&format!("error writing bookmarks file '{}'", path.display()), e)
+            })
+        }
+        ("list", Some(matches)) => {
+            let bookmarks = try!(load(&path(matches)));
+            for (name, location) in &bookmarks {
+                print!("{}: center {},{} zoom {}",
+                       name, location.center.0, location.center.1, location.zoom);
+                if let Some(iterations) = location.iterations {
+                    print!(" iterations {}", iterations);
+                }
+                println!();
+            }
+            Ok(())
+        }
+        _ => unreachable!()
+    }
+}
+
+/// Carry out the `share` subcommand.
+fn share_command(matches: &ArgMatches) -> Result<(), Error> {
+    match matches.subcommand() {
+        ("encode", Some(matches)) => {
+            let mut config = try!(settings_from_matches(matches));
+            let (center, zoom) = try!(resolve_region(&mut config)).center_zoom();
+            config.center = Some(center);
+            config.zoom = Some(zoom);
+            let code = try!(share::encode(&config).map_err(Error::usage));
+            println!("{}", code);
+            Ok(())
+        }
+        ("decode", Some(matches)) => {
+            let code = matches.value_of("code").unwrap();
+            let config = try!(share::decode(code).map_err(|why| {
+                Error::parse(format!("error parsing share code '{}': {}", code, why))
+            }));
+            // Print the settings as arguments, ready to paste into a command.
+            let (center, zoom) = (config.center.unwrap(), config.zoom.unwrap());
+            print!("--center {},{} --zoom {}", center.0, center.1, zoom);
+            if let Some(rotate) = config.rotate {
+                print!(" --rotate {}", rotate);
+            }
+            if let Some(iterations) = config.iterations {
+                print!(" --iterations {}", iterations);
+            }
+            if let Some(c) = config.c {
+                print!(" --set julia --c {},{}", c.0, c.1);
+            }
+            if let Some(formula) = config.formula {
+                print!(" --formula {}", formula_name(formula));
+            }
+            if let Some(ref hybrid) = config.hybrid {
+                let names: Vec<&str> = hybrid.iter().map(|&f| formula_name(f)).collect();
+                print!(" --hybrid {}", names.join(","));
+            }
+            if let Some(p) = config.phoenix_p {
+                print!(" --phoenix-p {},{}", p.0, p.1);
+            }
+            if let Some(relaxation) = config.relaxation {
+                print!(" --relaxation {},{}", relaxation.0, relaxation.1);
+            }
+            if let Some(slice) = config.slice {
+                print!(" --slice k={},l={}", slice.0, slice.1);
+            }
+            if let Some((formula, t)) = config.morph() {
+                print!(" --morph-to {} --morph {}", formula_name(formula), t);
+            }
+            if let Some(z0) = config.z0 {
+                print!(" --z0 {},{}", z0.0, z0.1);
+            }
+            if let Some(power) = config.power {
+                print!(" --power {}", power);
+            }
+            println!();
+            Ok(())
+        }
+        _ => unreachable!()
+    }
+}
+
+/// Return the settings for the batch job `job`, layered over `defaults`.
+fn job_config(job: &batch::Job, defaults: &Config) -> Result<Config, Error> {
+    let config = match *job {
+        batch::Job::Settings(ref config) => config.clone(),
+        batch::Job::Arguments(ref args) => {
+            let argv = ["mandelbrot", "render"].iter()
+                .map(|s| s.to_string())
+                .chain(args.iter().cloned());
+            let matches = try!(cli::build().get_matches_from_safe(argv)
+                               .map_err(|e| Error::usage(e.message)));
+            let render = matches.subcommand().1.unwrap();
+            if render.is_present("region") {
+                return Err(Error::usage("--region can't be used in job files; \
+                                         give each crop its own line"));
+            }
+            try!(settings_from_matches(render))
+        }
+    };
+    Ok(config.merge(defaults.clone()))
+}
+
+/// Carry out the `batch` subcommand.
+fn batch_command(matches: &ArgMatches) -> Result<(), Error> {
+    let parallel = matches.value_of("parallel").unwrap();
+    let workers: usize = try!(parse_positive(parallel).map_err(|why| {
+        Error::usage(format!("bad number of jobs to render at once '{}': {}", parallel, why))
+    }));
+    let path = matches.value_of("jobs").unwrap();
+    let jobs = try!(batch::load(Path::new(path)).map_err(|e| {
+        Error::from_io(&format!("error reading job file '{}'", path), e)
+    }));
+    let defaults = Config {
+        bookmarks: matches.value_of("bookmarks").map(str::to_string),
+        .. Config::default()
+    }.merge(try!(config_from_env()));
+
+    let next_job = AtomicUsize::new(0);
+    let failures = AtomicUsize::new(0);
+    crossbeam::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                loop {
+                    let index = next_job.fetch_add(1, Ordering::SeqCst);
+                    let &(ref label, ref job) = match jobs.get(index) {
+                        Some(job) => job,
+                        None => break
+                    };
+
+                    let start = Instant::now();
+                    match job_config(job, &defaults).and_then(render_config) {
+                        Ok(output) => {
+                            let elapsed = start.elapsed();
+                            println!("{}: wrote {} in {}.{:03}s", label, output,
+                                     elapsed.as_secs(), elapsed.subsec_nanos() / 1_000_000);
+                        }
+                        Err(error) => {
+                            println!("{}: failed: {}", label, error);
+                            failures.fetch_add(1, Ordering::SeqCst);
+                        }
+                    }
+                }
+            });
+        }
+    });
+
+    let failures = failures.load(Ordering::SeqCst);
+    println!("{} of {} jobs succeeded", jobs.len() - failures, jobs.len());
+    if failures > 0 {
+        // Each failure has been reported already; just say that there were
+        // some.
+        process::exit(1);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_batch_parallel() {
+    let error = run_args(&["batch", "-p", "0", "no-such-jobs.txt"]).unwrap_err();
+    assert_eq!(error.kind, error::Kind::Usage);
+    assert!(run_args(&["batch", "-p", "many", "no-such-jobs.txt"]).is_err());
+}
+
+/// Carry out the subcommand `matches` asks for.
+fn run(matches: &ArgMatches) -> Result<(), Error> {
+    match matches.subcommand() {
+        ("render", Some(matches)) => render_command(matches),
+        ("animate", Some(matches)) => animate_command(matches),
+        ("info", Some(matches)) => info_command(matches),
+        ("locate", Some(matches)) => locate_command(matches),
+        ("atlas", Some(matches)) => atlas_command(matches),
+        ("tiles", Some(matches)) => tiles_command(matches),
+        ("lyapunov", Some(matches)) => lyapunov_command(matches),
+        ("colorize", Some(matches)) => colorize_command(matches),
+        ("bookmark", Some(matches)) => bookmark_command(matches),
+        ("share", Some(matches)) => share_command(matches),
+        ("palette", Some(matches)) => palette_command(matches),
+        ("batch", Some(matches)) => batch_command(matches),
+        _ => unreachable!()
+    }
+}
+
+/// Run the command line `args`, as the tests' own invocation of the program.
+#[cfg(test)]
+fn run_args(args: &[&str]) -> Result<(), Error> {
+    let mut argv = vec!["mandelbrot"];
+    argv.extend_from_slice(args);
+    run(&cli::build().get_matches_from_safe(argv).unwrap())
+}
+
+/// Return the path of a new, empty directory for the test `name` to write
+/// files in.
+#[cfg(test)]
+fn test_directory(name: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("mandelbrot-test-{}-{}", process::id(), name));
+    let _ = std::fs::remove_dir_all(&path);
+    std::fs::create_dir_all(&path).unwrap();
+    path
+}
+
+#[test]
+fn test_colorize() {
+    let directory = test_directory("colorize");
+    let file = |name: &str| directory.join(name).to_string_lossy().into_owned();
+    run_args(&["render", "-o", &file("rendered.png"), "--output-data", &file("mandel.smp"),
+               "--size", "40x30", "--center=-0.5,0", "--zoom", "1", "--palette", "fire",
+               "--transfer", "sqrt", "--seed", "1"]).unwrap();
+    run_args(&["colorize", &file("mandel.smp"), "-o", &file("colorized.png"),
+               "--palette", "fire", "--transfer", "sqrt"]).unwrap();
+    let read = |name: &str| std::fs::read(file(name)).unwrap();
+    assert_eq!(read("colorized.png"), read("rendered.png"));
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn test_colorize_raw() {
+    let directory = test_directory("colorize-raw");
+    let file = |name: &str| directory.join(name).to_string_lossy().into_owned();
+    run_args(&["render", "-o", &file("rendered.png"), "--output-raw", &file("mandel.raw"),
+               "--size", "40x30", "--center=-0.5,0", "--zoom", "1", "--palette", "fire",
+               "--seed", "1"]).unwrap();
+    run_args(&["colorize", &file("mandel.raw"), "-o", &file("colorized.png"),
+               "--palette", "fire"]).unwrap();
+    let read = |name: &str| std::fs::read(file(name)).unwrap();
+    assert_eq!(read("colorized.png"), read("rendered.png"));
+
+    // Crafted headers: the largest possible limit mustn't size anything, and
+    // an image with no pixels is refused.
+    let mut bytes = read("mandel.raw");
+    bytes[56..60].copy_from_slice(b"\xff\xff\xff\xff");
+    std::fs::write(file("limit.raw"), &bytes).unwrap();
+    run_args(&["colorize", &file("limit.raw"), "-o", &file("limit.png"), "--equalize"]).unwrap();
+    let mut empty = bytes[..60].to_vec();
+    empty[8..12].copy_from_slice(b"\0\0\0\0");
+    std::fs::write(file("empty.raw"), &empty).unwrap();
+    assert!(run_args(&["colorize", &file("empty.raw"), "-o", &file("empty.png")]).is_err());
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+fn main() {
+    let matches = cli::build().get_matches_safe().unwrap_or_else(|e| fail_usage(e));
+    if let Err(error) = run(&matches) {
+        fail(error);
+    }
 }
@@ -0,0 +1,163 @@
+//! Newton fractals: the basins of attraction of Newton's method.
+//!
+//! Starting from each pixel's point, we repeatedly apply Newton's method for
+//! finding a root of a polynomial `p`, taking `z` to `z - p(z)/p'(z)`, until
+//! `z` comes close to one of `p`'s roots. Each root gets its own color, and
+//! pixels are shaded by how quickly they got there. Points that converge to no
+//! root within the iteration limit are black.
+
+use num::Complex;
+
+/// How close to a root `z` must come for us to deem it converged.
+const TOLERANCE: f64 = 1e-6;
+
+/// A polynomial with complex coefficients.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Polynomial {
+    /// The coefficients, highest degree first.
+    coefficients: Vec<Complex<f64>>
+}
+
+impl Polynomial {
+    /// Return the polynomial with the given coefficients, highest degree first.
+    /// Leading zeros are dropped; the result must have degree at least one.
+    pub fn new(coefficients: &[(f64, f64)]) -> Result<Polynomial, String> {
+        let coefficients: Vec<Complex<f64>> = coefficients.iter()
+            .skip_while(|&&(re, im)| re == 0.0 && im == 0.0)
+            .map(|&(re, im)| Complex { re: re, im: im })
+            .collect();
+        if coefficients.len() < 2 {
+            return Err("the polynomial must have degree at least one".to_string());
+        }
+        Ok(Polynomial { coefficients: coefficients })
+    }
+
+    /// Return the value of this polynomial and of its derivative at `z`.
+    fn evaluate(&self, z: Complex<f64>) -> (Complex<f64>, Complex<f64>) {
+        // Horner's rule, applied to the polynomial and its derivative at once.
+        let zero = Complex { re: 0.0, im: 0.0 };
+        self.coefficients.iter().fold((zero, zero), |(p, dp), &a| (p * z + a, dp * z + p))
+    }
+
+    /// Return the roots of this polynomial, found by the Durand-Kerner method.
+    pub fn roots(&self) -> Vec<Complex<f64>> {
+        let degree = self.coefficients.len() - 1;
+        let leading = self.coefficients[0];
+        let monic = |z: Complex<f64>| self.evaluate(z).0 / leading;
+
+        // Start from powers of a number that is neither real nor a root of
+        // unity, as the method requires.
+        let seed = Complex { re: 0.4, im: 0.9 };
+        let mut roots: Vec<Complex<f64>> = (0..degree)
+            .scan(Complex { re: 1.0, im: 0.0 }, |power, _| {
+                *power = *power * seed;
+                Some(*power)
+            })
+            .collect();
+        for _ in 0..500 {
+            let mut largest_change = 0.0_f64;
+            for i in 0..degree {
+                let denominator = (0..degree)
+                    .filter(|&j| j != i)
+                    .fold(Complex { re: 1.0, im: 0.0 }, |d, j| d * (roots[i] - roots[j]));
+                let change = monic(roots[i]) / denominator;
+                roots[i] = roots[i] - change;
+                largest_change = largest_change.max(change.norm_sqr());
+            }
+            if largest_change < TOLERANCE * TOLERANCE * 1e-6 {
+                break;
+            }
+        }
+        roots
+    }
+
+    /// Apply Newton's method starting from `z`. If it comes within `TOLERANCE`
+    /// of one of `roots` in fewer than `limit` steps, return that root's index
+    /// in `roots` and the number of steps taken. Otherwise, return `None`.
+    pub fn converge(&self, roots: &[Complex<f64>], z: Complex<f64>, limit: u32)
+        -> Option<(usize, u32)>
+    {
+        let mut z = z;
+        for i in 0..limit {
+            let near = |&root: &Complex<f64>| (z - root).norm_sqr() < TOLERANCE * TOLERANCE;
+            if let Some(root) = roots.iter().position(near) {
+                return Some((root, i));
+            }
+            let (p, dp) = self.evaluate(z);
+            if dp.norm_sqr() == 0.0 {
+                return None;
+            }
+            z = z - p / dp;
+        }
+        None
+    }
+}
+
+/// Return the RGB color for hue `hue`, in turns, at full saturation and the
+/// given `value`, both between zero and one.
+fn hue_to_rgb(hue: f64, value: f64) -> [u8; 3] {
+    let sector = (hue.fract() * 6.0).floor();
+    let f = hue.fract() * 6.0 - sector;
+    let (r, g, b) = match sector as u32 {
+        0 => (1.0, f, 0.0),
+        1 => (1.0 - f, 1.0, 0.0),
+        2 => (0.0, 1.0, f),
+        3 => (0.0, 1.0 - f, 1.0),
+        4 => (f, 0.0, 1.0),
+        _ => (1.0, 0.0, 1.0 - f)
+    };
+    let channel = |x: f64| (x * value * 255.0).round() as u8;
+    [channel(r), channel(g), channel(b)]
+}
+
+/// How much each step of Newton's method darkens a pixel's color.
+const SHADE_PER_STEP: f64 = 0.94;
+
+/// Convert the outcomes of `converge` for each pixel into RGB pixels. There are
+/// `roots` roots in all, each of which gets its own hue, evenly spaced around
+/// the color wheel.
+pub fn colorize(outcomes: &[Option<(usize, u32)>], roots: usize) -> Vec<u8> {
+    let mut pixels = Vec::with_capacity(outcomes.len() * 3);
+    for outcome in outcomes {
+        let color = match *outcome {
+            Some((root, steps)) => {
+                hue_to_rgb(root as f64 / roots as f64, SHADE_PER_STEP.powi(steps as i32))
+            }
+            None => [0, 0, 0]
+        };
+        pixels.extend_from_slice(&color);
+    }
+    pixels
+}
+
+#[test]
+fn test_roots() {
+    // z^3 - 1 has the cube roots of unity as its roots.
+    let cubic = Polynomial::new(&[(1.0, 0.0), (0.0, 0.0), (0.0, 0.0), (-1.0, 0.0)]).unwrap();
+    let mut roots = cubic.roots();
+    roots.sort_by(|a, b| a.im.partial_cmp(&b.im).unwrap());
+    let expected = [(-0.5, -0.75_f64.sqrt()), (1.0, 0.0), (-0.5, 0.75_f64.sqrt())];
+    for (root, &(re, im)) in roots.iter().zip(expected.iter()) {
+        assert!((*root - Complex { re: re, im: im }).norm_sqr() < 1e-20);
+    }
+
+    assert!(Polynomial::new(&[(0.0, 0.0), (3.0, 0.0)]).is_err());
+}
+
+#[test]
+fn test_converge() {
+    // z^2 - 1: points in the right half-plane go to 1, and the left to -1.
+    let quadratic = Polynomial::new(&[(1.0, 0.0), (0.0, 0.0), (-1.0, 0.0)]).unwrap();
+    let roots = [Complex { re: 1.0, im: 0.0 }, Complex { re: -1.0, im: 0.0 }];
+    assert_eq!(quadratic.converge(&roots, Complex { re: 1.0, im: 0.0 }, 10), Some((0, 0)));
+    assert_eq!(quadratic.converge(&roots, Complex { re: -3.0, im: 1.0 }, 50).map(|o| o.0),
+               Some(1));
+    // The imaginary axis is the boundary; there, the iteration never settles.
+    assert_eq!(quadratic.converge(&roots, Complex { re: 0.0, im: 0.5 }, 50), None);
+}
+
+#[test]
+fn test_colorize() {
+    assert_eq!(colorize(&[Some((0, 0)), Some((1, 0)), Some((2, 0)), None], 3),
+               vec![255, 0, 0, 0, 255, 0, 0, 0, 255, 0, 0, 0]);
+}
@@ -0,0 +1,85 @@
+//! Writing NumPy `.npy` files.
+//!
+//! A `.npy` file holds a single array, which Python programs can read with
+//! `numpy.load`, without any parsing of their own. It starts with the magic
+//! string `\x93NUMPY`, a version number, and the length of a header; the
+//! header is a Python dictionary literal giving the type of the array's
+//! elements, their order, and the array's shape, padded with spaces to align
+//! the data that follows. We write two-dimensional arrays, indexed by row and
+//! then column, so `array[y, x]` is the pixel in row `y` and column `x`.
+
+use bytes::le32;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// The magic string every `.npy` file starts with, followed by the format
+/// version, 1.0.
+const MAGIC: &'static [u8; 8] = b"\x93NUMPY\x01\x00";
+
+/// The elements of an array to write, row by row from the top.
+#[derive(Clone, Copy, Debug)]
+pub enum Array<'a> {
+    /// Unsigned 32-bit integers, like iteration counts.
+    U32(&'a [u32]),
+
+    /// 32-bit floating-point numbers, like smooth iteration counts.
+    F32(&'a [f32])
+}
+
+/// Write `array`, whose dimensions are given by `bounds`, to `output` as a
+/// `.npy` file.
+pub fn write<W: Write>(output: W, bounds: (usize, usize), array: Array) -> io::Result<()> {
+    let descr = match array {
+        Array::U32(_) => "<u4",
+        Array::F32(_) => "<f4"
+    };
+    let mut header = format!("{{'descr': '{}', 'fortran_order': False, 'shape': ({}, {}), }}",
+                             descr, bounds.1, bounds.0);
+    // Pad with spaces and a final newline so that the data starts at a
+    // multiple of 64 bytes.
+    while (MAGIC.len() + 2 + header.len() + 1) % 64 != 0 {
+        header.push(' ');
+    }
+    header.push('\n');
+
+    let mut output = BufWriter::new(output);
+    try!(output.write_all(MAGIC));
+    try!(output.write_all(&[header.len() as u8, (header.len() >> 8) as u8]));
+    try!(output.write_all(header.as_bytes()));
+    match array {
+        Array::U32(values) => {
+            for &value in values {
+                try!(output.write_all(&le32(value)));
+            }
+        }
+        Array::F32(values) => {
+            for &value in values {
+                try!(output.write_all(&le32(value.to_bits())));
+            }
+        }
+    }
+    output.flush()
+}
+
+/// Write `array` to the file at `path`, as `write` does.
+pub fn save(path: &Path, bounds: (usize, usize), array: Array) -> io::Result<()> {
+    write(try!(File::create(path)), bounds, array)
+}
+
+#[test]
+fn test_write() {
+    let mut npy = Vec::new();
+    write(&mut npy, (3, 1), Array::U32(&[1, 2, 256])).unwrap();
+    assert_eq!(npy.len(), 128 + 12);
+    assert_eq!(&npy[..10], b"\x93NUMPY\x01\x00\x76\x00");
+    assert!(npy[10..128].starts_with(b"{'descr': '<u4', 'fortran_order': False, \
+                                      'shape': (1, 3), }  "));
+    assert_eq!(npy[127], b'\n');
+    assert_eq!(&npy[128..], b"\x01\0\0\0\x02\0\0\0\0\x01\0\0");
+
+    let mut npy = Vec::new();
+    write(&mut npy, (1, 2), Array::F32(&[1.0, -2.5])).unwrap();
+    assert!(npy[10..].starts_with(b"{'descr': '<f4'"));
+    assert_eq!(&npy[npy.len() - 8..], b"\0\0\x80\x3f\0\0\x20\xc0");
+}
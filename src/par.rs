@@ -0,0 +1,189 @@
+//! Reading FractInt `.par` parameter files.
+//!
+//! A `.par` file holds any number of named entries, each a list of `key=value`
+//! settings between braces:
+//!
+//! ```text
+//! Seahorse       { ; A classic
+//!   reset=2004 type=mandel center-mag=-0.743/0.131/500
+//!   maxiter=1000 inside=0
+//!   }
+//! ```
+//!
+//! Text after a `;` is a comment, and a `\` at the end of a line continues the
+//! value onto the next. We only understand the settings that describe what to
+//! draw: `type` (which must be `mandel` or `julia`), `center-mag` or
+//! `corners`, `maxiter`, and, for Julia sets, `params`. Everything else, like
+//! coloring, is ignored.
+
+use std::fs::File;
+use std::io::{self, ErrorKind, Read};
+use std::path::Path;
+
+use config::{Config, SetKind};
+
+/// Return the text of the entry named `name` in `text`, with comments removed
+/// and continued lines joined.
+fn entry_text(text: &str, name: &str) -> Option<String> {
+    let mut body: Option<String> = None;
+    for line in text.lines() {
+        let mut line = match line.find(';') {
+            Some(i) => &line[..i],
+            None => line
+        };
+
+        if body.is_none() {
+            // Look for the line that opens the entry we want.
+            let i = match line.find('{') {
+                Some(i) => i,
+                None => continue
+            };
+            if !line[..i].trim().eq_ignore_ascii_case(name) {
+                continue;
+            }
+            body = Some(String::new());
+            line = &line[i + 1..];
+        }
+
+        let body = body.as_mut().unwrap();
+        let (line, done) = match line.find('}') {
+            Some(j) => (&line[..j], true),
+            None => (line, false)
+        };
+        if body.ends_with('\\') {
+            body.pop();
+        } else {
+            body.push(' ');
+        }
+        body.push_str(line.trim());
+        if done {
+            return Some(body.clone());
+        }
+    }
+    None
+}
+
+/// Parse a `/`-separated list of numbers, as used in `.par` values.
+fn parse_numbers(key: &str, value: &str) -> Result<Vec<f64>, String> {
+    value.split('/')
+        .map(|n| n.trim().parse::<f64>().map_err(|_| {
+            format!("bad number '{}' in '{}' setting", n, key)
+        }))
+        .collect()
+}
+
+/// Return the settings in the entry named `name` in `text`, the contents of a
+/// `.par` file. Entry names are compared without regard to case, as FractInt
+/// does.
+pub fn parse(text: &str, name: &str) -> Result<Config, String> {
+    let body = try!(entry_text(text, name).ok_or_else(|| {
+        format!("no entry named '{}'", name)
+    }));
+
+    let mut config = Config::default();
+    let mut params = None;
+    for setting in body.split_whitespace() {
+        let (key, value) = match setting.find('=') {
+            Some(i) => (&setting[..i], &setting[i + 1..]),
+            None => continue
+        };
+        match key.to_lowercase().as_str() {
+            "type" => {
+                config.set = Some(match value.to_lowercase().as_str() {
+                    "mandel" | "mandelfp" => SetKind::Mandelbrot,
+                    "julia" | "juliafp" => SetKind::Julia,
+                    _ => return Err(format!("unsupported fractal type '{}'", value))
+                });
+            }
+            "center-mag" => {
+                let numbers = try!(parse_numbers(key, value));
+                if numbers.len() < 3 {
+                    return Err("'center-mag' setting needs a center and magnification"
+                               .to_string());
+                }
+                // At magnification 1, FractInt shows one unit of the complex
+                // plane above and below the center. Our zoom 1 shows two.
+                config.center = Some((numbers[0], numbers[1]));
+                config.zoom = Some(numbers[2] * 2.0);
+            }
+            "corners" => {
+                let numbers = try!(parse_numbers(key, value));
+                if numbers.len() < 4 {
+                    return Err("'corners' setting needs four numbers".to_string());
+                }
+                config.upper_left = Some((numbers[0], numbers[3]));
+                config.lower_right = Some((numbers[1], numbers[2]));
+            }
+            "maxiter" => {
+                config.iterations = Some(try!(value.parse().map_err(|_| {
+                    format!("bad iteration limit '{}'", value)
+                })));
+            }
+            "params" => {
+                params = Some(try!(parse_numbers(key, value)));
+            }
+            _ => ()
+        }
+    }
+
+    if config.set == Some(SetKind::Julia) {
+        match params {
+            Some(ref p) if p.len() >= 2 => config.c = Some((p[0], p[1])),
+            _ => return Err("Julia set entry has no 'params' setting for c".to_string())
+        }
+    }
+
+    if !config.has_region() {
+        return Err(format!("entry '{}' has no 'center-mag' or 'corners' setting", name));
+    }
+
+    Ok(config)
+}
+
+/// Read the entry named `name` from the `.par` file at `path`.
+pub fn load(path: &Path, name: &str) -> io::Result<Config> {
+    let mut text = String::new();
+    try!(try!(File::open(path)).read_to_string(&mut text));
+    parse(&text, name).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+}
+
+#[test]
+fn test_parse() {
+    let text = "Whole { ; the whole set\n\
+                \x20 reset=2004 type=mandel corners=-2.5/1.5/-1.5/1.5\n\
+                \x20 }\n\
+                \n\
+                SEAHORSE { reset=2004 type=mandel\n\
+                \x20 center-mag=-0.743/0.131/500 maxiter=10\\\n\
+                \x2000 inside=0 }\n\
+                Dendrite {\n\
+                \x20 type=julia center-mag=0/0/0.5 params=0/1\n\
+                \x20 }\n\
+                Ship { type=burning-ship center-mag=0/0/1 }\n";
+
+    assert_eq!(parse(text, "whole").unwrap(),
+               Config {
+                   set: Some(SetKind::Mandelbrot),
+                   upper_left: Some((-2.5, 1.5)),
+                   lower_right: Some((1.5, -1.5)),
+                   .. Config::default()
+               });
+    assert_eq!(parse(text, "Seahorse").unwrap(),
+               Config {
+                   set: Some(SetKind::Mandelbrot),
+                   center: Some((-0.743, 0.131)),
+                   zoom: Some(1000.0),
+                   iterations: Some(1000),
+                   .. Config::default()
+               });
+    assert_eq!(parse(text, "Dendrite").unwrap(),
+               Config {
+                   set: Some(SetKind::Julia),
+                   center: Some((0.0, 0.0)),
+                   zoom: Some(1.0),
+                   c: Some((0.0, 1.0)),
+                   .. Config::default()
+               });
+    assert!(parse(text, "Ship").is_err());
+    assert!(parse(text, "Missing").is_err());
+}
@@ -0,0 +1,63 @@
+//! Writing binary PGM and PPM files.
+//!
+//! These are the Netpbm formats: a short text header giving the format, the
+//! image's dimensions, and the greatest sample value, followed by the samples
+//! themselves, uncompressed. PGM holds gray images, and PPM RGB images. Samples
+//! are one byte each if the greatest value is below 256, and two bytes,
+//! big-endian, otherwise. Nearly anything can read them, including scripts
+//! with no image library at all.
+
+use std::io::{self, BufWriter, ErrorKind, Write};
+
+/// Write `pixels`, an image whose dimensions are given by `bounds`, to `output`
+/// as a PGM file if `gray` is true, or as a PPM file otherwise. Each pixel has
+/// `channels` samples of `depth` bits: one for gray, or three for RGB. Gray
+/// images can be written as PPM files, but RGB images can't be written as PGM
+/// files.
+pub fn write<W: Write>(output: W, pixels: &[u8], bounds: (usize, usize), channels: usize,
+                       depth: u8, gray: bool)
+    -> io::Result<()>
+{
+    match (channels, gray) {
+        (1, _) | (3, false) => (),
+        (3, true) => {
+            return Err(io::Error::new(ErrorKind::InvalidInput,
+                                      "the image has colors other than grays, so it can't be \
+                                       written as PGM; try PPM"));
+        }
+        _ => {
+            return Err(io::Error::new(ErrorKind::InvalidInput,
+                                      "PGM and PPM images can't be transparent"));
+        }
+    }
+
+    let mut output = BufWriter::new(output);
+    let maximum = (1_u32 << depth) - 1;
+    try!(write!(output, "{}\n{} {}\n{}\n", if gray { "P5" } else { "P6" },
+                bounds.0, bounds.1, maximum));
+    if channels == 3 || gray {
+        try!(output.write_all(pixels));
+    } else {
+        // Repeat each gray sample for red, green, and blue.
+        for sample in pixels.chunks(depth as usize / 8) {
+            for _ in 0..3 {
+                try!(output.write_all(sample));
+            }
+        }
+    }
+    output.flush()
+}
+
+#[test]
+fn test_write() {
+    let mut pgm = Vec::new();
+    write(&mut pgm, &[0, 128, 255, 7], (2, 2), 1, 8, true).unwrap();
+    assert_eq!(pgm, b"P5\n2 2\n255\n\x00\x80\xff\x07");
+
+    let mut ppm = Vec::new();
+    write(&mut ppm, &[0x12, 0x34], (1, 1), 1, 16, false).unwrap();
+    assert_eq!(ppm, b"P6\n1 1\n65535\n\x12\x34\x12\x34\x12\x34");
+
+    assert!(write(&mut Vec::new(), &[1, 2, 3], (1, 1), 3, 8, true).is_err());
+    assert!(write(&mut Vec::new(), &[1, 2], (1, 1), 2, 8, false).is_err());
+}
@@ -0,0 +1,84 @@
+//! Symbolic image sizes: video resolutions, and paper sizes at a given density.
+//!
+//! Video resolutions are named as usual: `720p`, `1080p`, `1440p`, `4k`, and
+//! `8k`. Paper sizes take a density in dots per inch, as in `a4@300dpi`; they
+//! are portrait unless `-landscape` follows the paper's name, as in
+//! `letter-landscape@150dpi`.
+
+/// Video resolutions, by name.
+const VIDEO: &'static [(&'static str, (usize, usize))] = &[
+    ("720p", (1280, 720)),
+    ("1080p", (1920, 1080)),
+    ("1440p", (2560, 1440)),
+    ("4k", (3840, 2160)),
+    ("8k", (7680, 4320)),
+];
+
+/// Paper sizes in millimeters, portrait, by name.
+const PAPER: &'static [(&'static str, (f64, f64))] = &[
+    ("a0", (841.0, 1189.0)),
+    ("a1", (594.0, 841.0)),
+    ("a2", (420.0, 594.0)),
+    ("a3", (297.0, 420.0)),
+    ("a4", (210.0, 297.0)),
+    ("a5", (148.0, 210.0)),
+    ("a6", (105.0, 148.0)),
+    ("letter", (215.9, 279.4)),
+    ("legal", (215.9, 355.6)),
+    ("tabloid", (279.4, 431.8)),
+];
+
+const MM_PER_INCH: f64 = 25.4;
+
+/// The suffix on a paper's name that asks for landscape orientation.
+const LANDSCAPE: &'static str = "-landscape";
+
+/// If `name` is the name of a preset size, return its dimensions in pixels. If
+/// it isn't, return `Ok(None)`. If it looks like a paper size but is malformed,
+/// return an error explaining why.
+pub fn lookup(name: &str) -> Result<Option<(usize, usize)>, String> {
+    let name = name.trim().to_lowercase();
+    if let Some(&(_, size)) = VIDEO.iter().find(|&&(n, _)| n == name) {
+        return Ok(Some(size));
+    }
+
+    let (paper, density) = match name.find('@') {
+        Some(index) => (&name[..index], &name[index + 1..]),
+        None => {
+            if PAPER.iter().any(|&(n, _)| n == name.trim_right_matches(LANDSCAPE)) {
+                return Err(format!("paper sizes need a density, as in {}@300dpi", name));
+            }
+            return Ok(None);
+        }
+    };
+
+    let (paper, landscape) = if paper.ends_with(LANDSCAPE) {
+        (&paper[..paper.len() - LANDSCAPE.len()], true)
+    } else {
+        (paper, false)
+    };
+    let &(_, (width, height)) = try!(PAPER.iter().find(|&&(n, _)| n == paper).ok_or_else(|| {
+        format!("unknown paper size '{}'", paper)
+    }));
+    let dpi: f64 = match density.trim_right_matches("dpi").parse() {
+        Ok(dpi) if dpi > 0.0 => dpi,
+        _ => return Err(format!("expected a density like 300dpi, not '{}'", density))
+    };
+
+    let pixels = |mm: f64| (mm / MM_PER_INCH * dpi).round() as usize;
+    let (width, height) = (pixels(width), pixels(height));
+    Ok(Some(if landscape { (height, width) } else { (width, height) }))
+}
+
+#[test]
+fn test_lookup() {
+    assert_eq!(lookup("1080p"), Ok(Some((1920, 1080))));
+    assert_eq!(lookup("4K"), Ok(Some((3840, 2160))));
+    assert_eq!(lookup("a4@300dpi"), Ok(Some((2480, 3508))));
+    assert_eq!(lookup("A4-landscape@300"), Ok(Some((3508, 2480))));
+    assert_eq!(lookup("letter@100dpi"), Ok(Some((850, 1100))));
+    assert_eq!(lookup("1000x750"), Ok(None));
+    assert!(lookup("a4").is_err());
+    assert!(lookup("b4@300dpi").is_err());
+    assert!(lookup("a4@lots").is_err());
+}
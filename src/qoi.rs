@@ -0,0 +1,107 @@
+//! Writing QOI ("Quite OK Image") files.
+//!
+//! QOI is a lossless format that compresses nearly as well as PNG, but far
+//! faster, which matters when writing hundreds of animation frames for some
+//! other program to assemble. Each pixel is coded as the most compact of: a
+//! continuation of a run of the previous pixel; a reference to a recently seen
+//! pixel, kept in a 64-entry table indexed by a hash; a small difference from
+//! the previous pixel; or the pixel itself.
+
+use bytes::be32;
+
+/// The tags of QOI's operations.
+const OP_INDEX: u8 = 0x00;
+const OP_DIFF: u8 = 0x40;
+const OP_LUMA: u8 = 0x80;
+const OP_RUN: u8 = 0xc0;
+const OP_RGB: u8 = 0xfe;
+const OP_RGBA: u8 = 0xff;
+
+/// The longest run a single operation can code.
+const MAX_RUN: usize = 62;
+
+/// Return `pixels`, an image whose dimensions are given by `bounds`, encoded as
+/// a QOI file. Each pixel is red, green, blue, and alpha; if `alpha` is false,
+/// the file says the image has only three channels, though it codes the same
+/// pixels.
+pub fn encode(pixels: &[[u8; 4]], bounds: (usize, usize), alpha: bool) -> Vec<u8> {
+    let mut qoi = b"qoif".to_vec();
+    qoi.extend_from_slice(&be32(bounds.0 as u32));
+    qoi.extend_from_slice(&be32(bounds.1 as u32));
+    qoi.push(if alpha { 4 } else { 3 });
+    qoi.push(0); // sRGB, with linear alpha
+
+    let mut seen = [[0_u8; 4]; 64];
+    let mut previous = [0, 0, 0, 255];
+    let mut run = 0;
+    for (i, &pixel) in pixels.iter().enumerate() {
+        if pixel == previous {
+            run += 1;
+            if run == MAX_RUN || i == pixels.len() - 1 {
+                qoi.push(OP_RUN | (run - 1) as u8);
+                run = 0;
+            }
+            continue;
+        }
+        if run > 0 {
+            qoi.push(OP_RUN | (run - 1) as u8);
+            run = 0;
+        }
+
+        let hash = hash(pixel);
+        if seen[hash] == pixel {
+            qoi.push(OP_INDEX | hash as u8);
+        } else if pixel[3] == previous[3] {
+            seen[hash] = pixel;
+            let difference = |channel: usize| pixel[channel].wrapping_sub(previous[channel]) as i8;
+            let (red, green, blue) = (difference(0), difference(1), difference(2));
+            let (red_green, blue_green) = (red.wrapping_sub(green), blue.wrapping_sub(green));
+            let small = |d: i8| d >= -2 && d <= 1;
+            let medium = |d: i8| d >= -8 && d <= 7;
+            if small(red) && small(green) && small(blue) {
+                qoi.push(OP_DIFF | ((red + 2) as u8) << 4 | ((green + 2) as u8) << 2 |
+                         (blue + 2) as u8);
+            } else if green >= -32 && green <= 31 && medium(red_green) && medium(blue_green) {
+                qoi.push(OP_LUMA | (green + 32) as u8);
+                qoi.push(((red_green + 8) as u8) << 4 | (blue_green + 8) as u8);
+            } else {
+                qoi.extend_from_slice(&[OP_RGB, pixel[0], pixel[1], pixel[2]]);
+            }
+        } else {
+            seen[hash] = pixel;
+            qoi.extend_from_slice(&[OP_RGBA, pixel[0], pixel[1], pixel[2], pixel[3]]);
+        }
+        previous = pixel;
+    }
+
+    qoi.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 1]);
+    qoi
+}
+
+/// Return the index of `pixel` in the table of recently seen pixels.
+fn hash(pixel: [u8; 4]) -> usize {
+    (pixel[0] as usize * 3 + pixel[1] as usize * 5 + pixel[2] as usize * 7 +
+     pixel[3] as usize * 11) % 64
+}
+
+#[test]
+fn test_encode() {
+    let black = [0, 0, 0, 255];
+    let pixels = [black, black, [1, 2, 3, 255], [1, 1, 2, 255], black, [1, 2, 3, 255],
+                  [9, 9, 9, 0]];
+    let qoi = encode(&pixels, (7, 1), true);
+    assert_eq!(&qoi[..14], b"qoif\0\0\0\x07\0\0\0\x01\x04\0");
+    assert_eq!(&qoi[14..],
+               &[OP_RUN | 1, // a run of two of the initial pixel
+                 OP_LUMA | 34, 0x79, // green up two, red one less, blue one more
+                 OP_DIFF | 2 << 4 | 1 << 2 | 1, // red the same, green and blue down one
+                 OP_DIFF | 1 << 4 | 1 << 2, // down one, one, and two
+                 OP_INDEX | 23, // seen before
+                 OP_RGBA, 9, 9, 9, 0,
+                 0, 0, 0, 0, 0, 0, 0, 1][..]);
+
+    // Runs are at most 62 long, and one reaching the end is finished.
+    let qoi = encode(&[black; 70], (70, 1), false);
+    assert_eq!(qoi[12], 3);
+    assert_eq!(&qoi[14..16], &[OP_RUN | 61, OP_RUN | 7]);
+}
@@ -0,0 +1,69 @@
+//! Quaternion arithmetic, for quaternion Julia sets.
+//!
+//! Quaternions extend the complex numbers with two more imaginary units, `j`
+//! and `k`, where `i^2 = j^2 = k^2 = ijk = -1`. Multiplication is associative
+//! but not commutative: `ij = k`, but `ji = -k`. Quaternions whose `j` and `k`
+//! parts are zero behave just like complex numbers.
+
+use std::ops::{Add, Mul};
+
+/// The quaternion `r + i i + j j + k k`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quaternion {
+    pub r: f64,
+    pub i: f64,
+    pub j: f64,
+    pub k: f64
+}
+
+impl Quaternion {
+    /// Return the square of this quaternion's absolute value.
+    pub fn norm_sqr(&self) -> f64 {
+        self.r * self.r + self.i * self.i + self.j * self.j + self.k * self.k
+    }
+}
+
+impl Add for Quaternion {
+    type Output = Quaternion;
+    fn add(self, other: Quaternion) -> Quaternion {
+        Quaternion {
+            r: self.r + other.r,
+            i: self.i + other.i,
+            j: self.j + other.j,
+            k: self.k + other.k
+        }
+    }
+}
+
+impl Mul for Quaternion {
+    type Output = Quaternion;
+    fn mul(self, other: Quaternion) -> Quaternion {
+        let (a, b) = (self, other);
+        Quaternion {
+            r: a.r * b.r - a.i * b.i - a.j * b.j - a.k * b.k,
+            i: a.r * b.i + a.i * b.r + a.j * b.k - a.k * b.j,
+            j: a.r * b.j - a.i * b.k + a.j * b.r + a.k * b.i,
+            k: a.r * b.k + a.i * b.j - a.j * b.i + a.k * b.r
+        }
+    }
+}
+
+#[test]
+fn test_quaternion() {
+    let q = |r, i, j, k| Quaternion { r: r, i: i, j: j, k: k };
+    let (one, i) = (q(1., 0., 0., 0.), q(0., 1., 0., 0.));
+    let (j, k) = (q(0., 0., 1., 0.), q(0., 0., 0., 1.));
+    let minus_one = q(-1., 0., 0., 0.);
+    assert_eq!(i * i, minus_one);
+    assert_eq!(j * j, minus_one);
+    assert_eq!(k * k, minus_one);
+    assert_eq!(i * j * k, minus_one);
+    assert_eq!(i * j, k);
+    assert_eq!(j * i, q(0., 0., 0., -1.));
+    assert_eq!(one * j, j);
+
+    // Without j and k parts, quaternions multiply like complex numbers.
+    assert_eq!(q(1., 2., 0., 0.) * q(3., -1., 0., 0.), q(5., 5., 0., 0.));
+    assert_eq!(q(1., 2., 3., 4.) + q(1., 1., 1., 1.), q(2., 3., 4., 5.));
+    assert_eq!(q(1., 2., 3., 4.).norm_sqr(), 30.0);
+}
@@ -0,0 +1,80 @@
+//! Reproducible pseudo-random numbers, for rendering modes that sample.
+//!
+//! Every stochastic feature draws its numbers from generators derived from a
+//! single seed, given with `--seed`. A render's output then depends only on
+//! its settings, not on how the work happened to be divided among threads: each
+//! unit of work, like a row of pixels, gets its own stream, chosen by number.
+//!
+//! The generator is SplitMix64. It is small, fast, and good enough for
+//! sampling, and since it's ours, a seed will produce the same image no matter
+//! what version of some library we're built against.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A pseudo-random number generator.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64
+}
+
+/// The increment SplitMix64 adds to its state at each step.
+const GOLDEN_GAMMA: u64 = 0x9e3779b97f4a7c15;
+
+/// Scramble the bits of `z`. This is SplitMix64's output function.
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+impl Rng {
+    /// Return a generator for stream number `stream` of the given `seed`.
+    /// Different streams of the same seed are independent.
+    pub fn new(seed: u64, stream: u64) -> Rng {
+        Rng { state: mix(seed ^ mix(stream.wrapping_add(GOLDEN_GAMMA))) }
+    }
+
+    /// Return the next 64 random bits.
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(GOLDEN_GAMMA);
+        mix(self.state)
+    }
+
+    /// Return a random number in the range `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        // Use the top 53 bits, as many as an f64's significand holds.
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Return a seed that will be different each time the program runs. The seed
+/// is less than 2^63, so that it can be written in a TOML file.
+pub fn entropy_seed() -> u64 {
+    // The standard library seeds each `RandomState` from the operating
+    // system's random number source; mix in the time for good measure.
+    let mut hasher = RandomState::new().build_hasher();
+    if let Ok(now) = SystemTime::now().duration_since(UNIX_EPOCH) {
+        hasher.write_u64(now.as_secs());
+        hasher.write_u32(now.subsec_nanos());
+    }
+    hasher.finish() >> 1
+}
+
+#[test]
+fn test_rng() {
+    let mut a = Rng::new(42, 0);
+    let mut b = Rng::new(42, 0);
+    let mut c = Rng::new(42, 1);
+    let from_a: Vec<u64> = (0..10).map(|_| a.next_u64()).collect();
+    let from_b: Vec<u64> = (0..10).map(|_| b.next_u64()).collect();
+    let from_c: Vec<u64> = (0..10).map(|_| c.next_u64()).collect();
+    assert_eq!(from_a, from_b);
+    assert!(from_a != from_c);
+
+    for _ in 0..1000 {
+        let x = a.next_f64();
+        assert!(0.0 <= x && x < 1.0);
+    }
+}
@@ -0,0 +1,138 @@
+//! Saving raw iteration counts, for other programs to analyze.
+//!
+//! `render --output-data` saves whatever the coloring method needs, in a form
+//! only this program reads. `render --output-raw` saves something simpler:
+//! the number of iterations each pixel's orbit took to escape, or the
+//! iteration limit if it never did, along with enough about the view to tell
+//! which point each pixel stands for. The `colorize` subcommand can paint
+//! these files, too.
+//!
+//! The file starts with the eight bytes `MANDITR1`, then the image's width and
+//! height as four-byte little-endian integers; then the real and imaginary
+//! parts of the upper left and lower right corners of the region, and its
+//! rotation in degrees counterclockwise, as eight-byte little-endian IEEE
+//! doubles; and then the iteration limit, as a four-byte integer. Then come
+//! the counts, row by row from the top, each a four-byte little-endian
+//! integer.
+
+use bytes::{invalid, le32, le64, read32, read64};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use view::View;
+
+/// The bytes every iteration count file starts with.
+pub const MAGIC: &'static [u8; 8] = b"MANDITR1";
+
+/// An image's worth of iteration counts.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Counts {
+    /// The image's size, and the region of the plane it shows.
+    pub view: View,
+
+    /// The iteration limit the counts were computed with.
+    pub limit: u32,
+
+    /// The counts themselves, row by row from the top.
+    pub counts: Vec<u32>
+}
+
+/// Write `counts` to `output` in the format described above.
+pub fn write<W: Write>(output: W, counts: &Counts) -> io::Result<()> {
+    let mut output = BufWriter::new(output);
+    let view = &counts.view;
+    try!(output.write_all(MAGIC));
+    try!(output.write_all(&le32(view.bounds.0 as u32)));
+    try!(output.write_all(&le32(view.bounds.1 as u32)));
+    for &value in &[view.upper_left.0, view.upper_left.1, view.lower_right.0,
+                    view.lower_right.1, view.rotation] {
+        try!(output.write_all(&le64(value.to_bits())));
+    }
+    try!(output.write_all(&le32(counts.limit)));
+    for &count in &counts.counts {
+        try!(output.write_all(&le32(count)));
+    }
+    output.flush()
+}
+
+/// Read counts written by `write` from `input`.
+pub fn read<R: Read>(input: R) -> io::Result<Counts> {
+    let mut input = BufReader::new(input);
+    let mut magic = [0; 8];
+    try!(input.read_exact(&mut magic));
+    if &magic != MAGIC {
+        return Err(invalid("not an iteration count file"));
+    }
+    let width = try!(read32(&mut input)) as usize;
+    let height = try!(read32(&mut input)) as usize;
+    let mut region = [0.0; 5];
+    for value in &mut region {
+        *value = f64::from_bits(try!(read64(&mut input)));
+    }
+    let limit = try!(read32(&mut input));
+    if width == 0 || height == 0 {
+        return Err(invalid("image has no pixels"));
+    }
+
+    // Don't trust the header's dimensions to size the vector: a damaged file
+    // would run out of counts long before filling it.
+    let pixels = try!(width.checked_mul(height).ok_or_else(|| invalid("image is too large")));
+    let mut counts = Vec::new();
+    for _ in 0..pixels {
+        counts.push(try!(read32(&mut input)));
+    }
+    if try!(input.read(&mut [0])) != 0 {
+        return Err(invalid("extra data after the last count"));
+    }
+    Ok(Counts {
+        view: View {
+            bounds: (width, height),
+            upper_left: (region[0], region[1]),
+            lower_right: (region[2], region[3]),
+            rotation: region[4]
+        },
+        limit: limit,
+        counts: counts
+    })
+}
+
+/// Write `counts` to the file at `path`.
+pub fn save(path: &Path, counts: &Counts) -> io::Result<()> {
+    write(try!(File::create(path)), counts)
+}
+
+#[test]
+fn test_round_trip() {
+    let counts = Counts {
+        view: View {
+            bounds: (2, 2),
+            upper_left: (-2.0, 1.5),
+            lower_right: (1.0, -1.5),
+            rotation: 30.0
+        },
+        limit: 255,
+        counts: vec![1, 2, 255, 70000]
+    };
+    let mut bytes = Vec::new();
+    write(&mut bytes, &counts).unwrap();
+    assert_eq!(bytes.len(), 8 + 8 + 40 + 4 + 16);
+    assert_eq!(&bytes[..16], b"MANDITR1\x02\0\0\0\x02\0\0\0");
+    assert_eq!(&bytes[56..60], b"\xff\0\0\0");
+    assert_eq!(&bytes[72..], b"\x70\x11\x01\0");
+    assert_eq!(read(&bytes[..]).unwrap(), counts);
+
+    assert!(read(&bytes[..bytes.len() - 1]).is_err());
+    bytes.push(0);
+    assert!(read(&bytes[..]).is_err());
+    assert!(read(&b"MANDSMP1\0\0\0\0\0\0\0\0"[..]).is_err());
+
+    // A header claiming an enormous image, with nothing after it.
+    let mut huge = b"MANDITR1\xff\xff\xff\xff\xff\xff\xff\xff".to_vec();
+    huge.extend_from_slice(&[0; 44]);
+    assert!(read(&huge[..]).is_err());
+
+    // A header claiming no pixels at all.
+    let mut empty = bytes[..60].to_vec();
+    empty[12..16].copy_from_slice(b"\0\0\0\0");
+    assert!(read(&empty[..]).is_err());
+}
@@ -0,0 +1,166 @@
+//! Saving what rendering found out about each point, for coloring later.
+//!
+//! Following orbits is the slow part of drawing an image; turning the results
+//! into colors takes a moment. So `render --output-data` can save the samples
+//! it computed to a file, and the `colorize` subcommand can paint them again
+//! with a different palette, transfer function, and so on, without following
+//! a single orbit.
+//!
+//! The file starts with the eight bytes `MANDSMP1`, then the image's width,
+//! height, and iteration limit, each as a four-byte little-endian integer.
+//! Then come the samples, row by row, each a tag byte saying what kind of
+//! sample it is, followed by its fields: counts and periods as four-byte
+//! little-endian integers, fractions as eight-byte little-endian IEEE doubles,
+//! and blends as a single byte.
+
+use bytes::{invalid, le32, le64, read32, read64};
+use coloring::Sample;
+use config::Blend;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// The bytes every sample file starts with.
+const MAGIC: &'static [u8; 8] = b"MANDSMP1";
+
+/// An image's worth of samples.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Samples {
+    /// The width and height of the image, in pixels.
+    pub bounds: (usize, usize),
+
+    /// The iteration limit the samples were computed with.
+    pub limit: u32,
+
+    /// The samples themselves, row by row from the top.
+    pub samples: Vec<Sample>
+}
+
+/// Write `samples` to `output` in the format described above.
+pub fn write<W: Write>(output: W, samples: &Samples) -> io::Result<()> {
+    let mut output = BufWriter::new(output);
+    try!(output.write_all(MAGIC));
+    try!(output.write_all(&le32(samples.bounds.0 as u32)));
+    try!(output.write_all(&le32(samples.bounds.1 as u32)));
+    try!(output.write_all(&le32(samples.limit)));
+    for sample in &samples.samples {
+        match *sample {
+            Sample::Count(count) => {
+                try!(output.write_all(&[0]));
+                try!(output.write_all(&le32(count)));
+            }
+            Sample::Fraction(fraction) => {
+                try!(output.write_all(&[1]));
+                try!(output.write_all(&le64(fraction.to_bits())));
+            }
+            Sample::Blend(count, fraction, blend) => {
+                try!(output.write_all(&[2]));
+                try!(output.write_all(&le32(count)));
+                try!(output.write_all(&le64(fraction.to_bits())));
+                try!(output.write_all(&[blend_code(blend)]));
+            }
+            Sample::Lit(count, brightness) => {
+                try!(output.write_all(&[3]));
+                try!(output.write_all(&le32(count)));
+                try!(output.write_all(&le64(brightness.to_bits())));
+            }
+            Sample::Period(period) => {
+                try!(output.write_all(&[4]));
+                try!(output.write_all(&le32(period)));
+            }
+        }
+    }
+    output.flush()
+}
+
+/// Read samples written by `write` from `input`.
+pub fn read<R: Read>(input: R) -> io::Result<Samples> {
+    let mut input = BufReader::new(input);
+    let mut magic = [0; 8];
+    try!(input.read_exact(&mut magic));
+    if &magic != MAGIC {
+        return Err(invalid("not a sample file"));
+    }
+    let width = try!(read32(&mut input)) as usize;
+    let height = try!(read32(&mut input)) as usize;
+    let limit = try!(read32(&mut input));
+    if width == 0 || height == 0 {
+        return Err(invalid("image has no pixels"));
+    }
+
+    // A damaged header could claim any size at all, so grow the vector as
+    // samples actually arrive.
+    let pixels = try!(width.checked_mul(height).ok_or_else(|| invalid("image is too large")));
+    let mut samples = Vec::new();
+    for _ in 0..pixels {
+        let mut tag = [0];
+        try!(input.read_exact(&mut tag));
+        samples.push(match tag[0] {
+            0 => Sample::Count(try!(read32(&mut input))),
+            1 => Sample::Fraction(f64::from_bits(try!(read64(&mut input)))),
+            2 => {
+                let count = try!(read32(&mut input));
+                let fraction = f64::from_bits(try!(read64(&mut input)));
+                let mut code = [0];
+                try!(input.read_exact(&mut code));
+                let blend = try!(blend_from_code(code[0])
+                                 .ok_or_else(|| invalid("unrecognized blend in sample")));
+                Sample::Blend(count, fraction, blend)
+            }
+            3 => {
+                let count = try!(read32(&mut input));
+                Sample::Lit(count, f64::from_bits(try!(read64(&mut input))))
+            }
+            4 => Sample::Period(try!(read32(&mut input))),
+            _ => return Err(invalid("unrecognized kind of sample"))
+        });
+    }
+    if try!(input.read(&mut [0])) != 0 {
+        return Err(invalid("extra data after the last sample"));
+    }
+    Ok(Samples { bounds: (width, height), limit: limit, samples: samples })
+}
+
+/// Write `samples` to the file at `path`.
+pub fn save(path: &Path, samples: &Samples) -> io::Result<()> {
+    write(try!(File::create(path)), samples)
+}
+
+fn blend_code(blend: Blend) -> u8 {
+    match blend {
+        Blend::Replace => 0,
+        Blend::Multiply => 1,
+        Blend::Minimum => 2
+    }
+}
+
+fn blend_from_code(code: u8) -> Option<Blend> {
+    match code {
+        0 => Some(Blend::Replace),
+        1 => Some(Blend::Multiply),
+        2 => Some(Blend::Minimum),
+        _ => None
+    }
+}
+
+#[test]
+fn test_round_trip() {
+    let samples = Samples {
+        bounds: (3, 2),
+        limit: 1000,
+        samples: vec![Sample::Count(17), Sample::Fraction(0.25),
+                      Sample::Blend(40, 0.5, Blend::Multiply), Sample::Lit(3, 0.875),
+                      Sample::Period(3), Sample::Fraction(1.0 / 3.0)]
+    };
+    let mut bytes = Vec::new();
+    write(&mut bytes, &samples).unwrap();
+    assert_eq!(&bytes[..12], b"MANDSMP1\x03\0\0\0");
+    assert_eq!(read(&bytes[..]).unwrap(), samples);
+
+    assert!(read(&bytes[..bytes.len() - 1]).is_err());
+    bytes.push(0);
+    assert!(read(&bytes[..]).is_err());
+    assert!(read(&b"MANDSMP2\0\0\0\0\0\0\0\0\0\0\0\0"[..]).is_err());
+    assert!(read(&b"MANDSMP1\xff\xff\xff\xff\xff\xff\xff\xff\0\0\0\0"[..]).is_err());
+    assert!(read(&b"MANDSMP1\x03\0\0\0\0\0\0\0\xff\xff\xff\xff"[..]).is_err());
+}
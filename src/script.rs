@@ -0,0 +1,198 @@
+//! Iteration and coloring defined by a Rhai script.
+//!
+//! `--script` names a file of Rhai code defining a function `iterate(z, c)`,
+//! which returns the point following `z` in the orbit for `c`. The script may
+//! also define `color(n, z)`, which returns the color of a pixel as an array of
+//! three numbers from 0 to 255, for red, green, and blue: `n` is the number of
+//! iterations the pixel's orbit took to escape, or -1 if it never did, and `z`
+//! is the orbit's last point. Without `color`, pixels are colored with the
+//! palette, as usual.
+//!
+//! Complex numbers in scripts have `re` and `im` properties, and are made with
+//! `complex(re, im)`. They support `+`, `-`, `*`, and `/`, both with each
+//! other and with ordinary numbers, and the functions `norm`, `norm_sqr`,
+//! `conj`, `exp`, `ln`, `sqrt`, `sin`, `cos`, `tan`, `sinh`, `cosh`, and
+//! `tanh`. For example:
+//!
+//! ```text
+//! fn iterate(z, c) { z * z * z + c }
+//! fn color(n, z) { if n < 0 { [0, 0, 0] } else { [n % 256, 128, 255 - n % 256] } }
+//! ```
+//!
+//! Scripts are much slower than the built-in formulas, but let you try out a
+//! new kind of fractal without rebuilding the program.
+
+use num::Complex;
+use rhai::{Array, Engine, Scope, AST, FLOAT, INT};
+use std::path::Path;
+
+/// The most operations a single call to a script's function may perform. Each
+/// call handles one step of one orbit, or one pixel's color, so anything near
+/// this is surely a script stuck in a loop, which would otherwise hang the
+/// render.
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// A compiled script, ready to run.
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    has_color: bool
+}
+
+/// Return an engine that knows how to work with complex numbers.
+fn engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.register_type_with_name::<Complex<f64>>("Complex");
+    engine.register_fn("complex", |re: FLOAT, im: FLOAT| Complex { re: re, im: im });
+    engine.register_get("re", |z: &mut Complex<f64>| z.re);
+    engine.register_get("im", |z: &mut Complex<f64>| z.im);
+
+    engine.register_fn("+", |a: Complex<f64>, b: Complex<f64>| a + b);
+    engine.register_fn("-", |a: Complex<f64>, b: Complex<f64>| a - b);
+    engine.register_fn("*", |a: Complex<f64>, b: Complex<f64>| a * b);
+    engine.register_fn("/", |a: Complex<f64>, b: Complex<f64>| a / b);
+    engine.register_fn("-", |a: Complex<f64>| -a);
+
+    // Mixing complex and real numbers.
+    let real = |x: FLOAT| Complex { re: x, im: 0.0 };
+    engine.register_fn("+", move |a: Complex<f64>, x: FLOAT| a + real(x));
+    engine.register_fn("+", move |x: FLOAT, a: Complex<f64>| real(x) + a);
+    engine.register_fn("-", move |a: Complex<f64>, x: FLOAT| a - real(x));
+    engine.register_fn("-", move |x: FLOAT, a: Complex<f64>| real(x) - a);
+    engine.register_fn("*", move |a: Complex<f64>, x: FLOAT| a * real(x));
+    engine.register_fn("*", move |x: FLOAT, a: Complex<f64>| real(x) * a);
+    engine.register_fn("/", move |a: Complex<f64>, x: FLOAT| a / real(x));
+    engine.register_fn("/", move |x: FLOAT, a: Complex<f64>| real(x) / a);
+
+    engine.register_fn("norm", |z: Complex<f64>| z.norm());
+    engine.register_fn("norm_sqr", |z: Complex<f64>| z.norm_sqr());
+    engine.register_fn("conj", |z: Complex<f64>| z.conj());
+    engine.register_fn("exp", |z: Complex<f64>| z.exp());
+    engine.register_fn("ln", |z: Complex<f64>| z.ln());
+    engine.register_fn("sqrt", |z: Complex<f64>| z.sqrt());
+    engine.register_fn("sin", |z: Complex<f64>| z.sin());
+    engine.register_fn("cos", |z: Complex<f64>| z.cos());
+    engine.register_fn("tan", |z: Complex<f64>| z.tan());
+    engine.register_fn("sinh", |z: Complex<f64>| z.sinh());
+    engine.register_fn("cosh", |z: Complex<f64>| z.cosh());
+    engine.register_fn("tanh", |z: Complex<f64>| z.tanh());
+    engine
+}
+
+impl Script {
+    /// Compile the script in the file at `path`.
+    pub fn load(path: &Path) -> Result<Script, String> {
+        let engine = engine();
+        let ast = try!(engine.compile_file(path.to_path_buf()).map_err(|e| e.to_string()));
+        let defines = |name: &str| ast.iter_functions().any(|f| f.name == name);
+        if !defines("iterate") {
+            return Err("the script doesn't define a function 'iterate(z, c)'".to_string());
+        }
+        let has_color = defines("color");
+        Ok(Script { engine: engine, ast: ast, has_color: has_color })
+    }
+
+    /// Return true if the script defines its own `color` function.
+    pub fn has_color(&self) -> bool {
+        self.has_color
+    }
+
+    /// Follow the orbit of `z` for `c` using the script's `iterate` function,
+    /// as `escapes` does with a built-in formula, and return the result
+    /// `escapes` would, along with the last point of the orbit.
+    pub fn escapes(&self, z: Complex<f64>, c: Complex<f64>, limit: u32, radius: f64)
+        -> Result<(Option<u32>, Complex<f64>), String>
+    {
+        let radius_sqr = radius * radius;
+        let mut z = z;
+        for i in 0..limit {
+            z = try!(self.engine.call_fn(&mut Scope::new(), &self.ast, "iterate", (z, c))
+                     .map_err(|e| e.to_string()));
+            if z.norm_sqr() > radius_sqr {
+                return Ok((Some(i), z));
+            }
+        }
+        Ok((None, z))
+    }
+
+    /// Return the color the script's `color` function gives for an orbit that
+    /// escaped after `count` iterations, or never did, ending at `z`.
+    pub fn color(&self, count: Option<u32>, z: Complex<f64>) -> Result<[u8; 3], String> {
+        let n = count.map_or(-1, |n| n as INT);
+        let color: Array = try!(self.engine.call_fn(&mut Scope::new(), &self.ast, "color", (n, z))
+                                .map_err(|e| e.to_string()));
+        if color.len() != 3 {
+            return Err(format!("'color' should return three numbers, not {}", color.len()));
+        }
+        let mut rgb = [0; 3];
+        for (channel, value) in rgb.iter_mut().zip(color.iter()) {
+            let value = try!(value.as_int().map_err(|_| {
+                "'color' should return whole numbers".to_string()
+            }));
+            *channel = value.max(0).min(255) as u8;
+        }
+        Ok(rgb)
+    }
+}
+
+/// Compile `source` as a script, by way of a temporary file named for `name`.
+#[cfg(test)]
+fn compile(name: &str, source: &str) -> Result<Script, String> {
+    let directory = ::test_directory(&format!("script-{}", name));
+    let path = directory.join("script.rhai");
+    ::std::fs::write(&path, source).unwrap();
+    let script = Script::load(&path);
+    ::std::fs::remove_dir_all(&directory).unwrap();
+    script
+}
+
+#[test]
+fn test_load() {
+    let script = compile("load", "fn iterate(z, c) { z * z + c }").unwrap();
+    assert!(!script.has_color());
+    assert!(compile("load-color", "fn iterate(z, c) { z }\nfn color(n, z) { [0, 0, 0] }")
+            .unwrap().has_color());
+    let missing = compile("load-missing", "fn color(n, z) { [0, 0, 0] }").err().unwrap();
+    assert!(missing.contains("'iterate(z, c)'"));
+    assert!(compile("load-syntax", "fn iterate(z, c) {").is_err());
+    assert!(Script::load(Path::new("/no/such/script.rhai")).is_err());
+}
+
+#[test]
+fn test_escapes() {
+    let script = compile("escapes", "fn iterate(z, c) { z * z + c }").unwrap();
+    let zero = Complex { re: 0.0, im: 0.0 };
+    assert_eq!(script.escapes(zero, zero, 100, 2.0).unwrap(), (None, zero));
+    assert_eq!(script.escapes(zero, Complex { re: 1.0, im: 0.0 }, 100, 2.0).unwrap(),
+               (Some(2), Complex { re: 5.0, im: 0.0 }));
+    assert_eq!(script.escapes(zero, Complex { re: 1.0, im: 0.0 }, 2, 2.0).unwrap(),
+               (None, Complex { re: 2.0, im: 0.0 }));
+
+    // A script that never returns runs out of operations instead of hanging.
+    let stuck = compile("escapes-stuck", "fn iterate(z, c) { loop { } }").unwrap();
+    assert!(stuck.escapes(zero, zero, 10, 2.0).is_err());
+    let wrong = compile("escapes-wrong", "fn iterate(z, c) { 1 }").unwrap();
+    assert!(wrong.escapes(zero, zero, 10, 2.0).is_err());
+}
+
+#[test]
+fn test_color() {
+    let z = Complex { re: 0.5, im: -0.5 };
+    let color = |body: &str| {
+        let source = format!("fn iterate(z, c) {{ z }}\nfn color(n, z) {{ {} }}", body);
+        compile("color", &source).unwrap().color(Some(7), z)
+    };
+    assert_eq!(color("[n, 1, 2]"), Ok([7, 1, 2]));
+    assert_eq!(color("[300, -5, 255]"), Ok([255, 0, 255]));
+    assert_eq!(color("if z.re > 0.0 && z.im < 0.0 { [1, 2, 3] } else { [0, 0, 0] }"),
+               Ok([1, 2, 3]));
+    assert!(color("[1, 2]").err().unwrap().contains("not 2"));
+    assert!(color("[1, 2, 3, 4]").is_err());
+    assert!(color("[1, 2.5, 3]").err().unwrap().contains("whole numbers"));
+    assert!(color("[1, \"two\", 3]").is_err());
+    assert!(color("7").is_err());
+
+    let never = compile("color-never", "fn iterate(z, c) { z }\nfn color(n, z) { [n, 0, 0] }");
+    assert_eq!(never.unwrap().color(None, z), Ok([0, 0, 0]));
+}
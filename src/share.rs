@@ -0,0 +1,431 @@
+//! Compact codes for sharing views.
+//!
+//! `mandelbrot share encode` packs a view's center, zoom, and rotation, along
+//! with the set drawn, the formula or hybrid and power, and the iteration
+//! limit, into a short string that fits easily in a chat message; giving that
+//! string to `--share` reproduces the view exactly, at whatever image size the
+//! recipient likes.
+//!
+//! A code is the URL-safe base64 encoding, without padding, of these bytes:
+//!
+//! - the format's version number: 2 if the code uses any of the second byte of
+//!   flags, or 1 otherwise;
+//! - a byte of flags, saying which of the optional fields below are present,
+//!   followed, in version 2, by a second such byte;
+//! - the center's real and imaginary parts, and the zoom, each a little-endian
+//!   `f64`;
+//! - the rotation in degrees, as an `f64`, if the view is rotated;
+//! - the iteration limit, as an unsigned LEB128 number, if one was given;
+//! - for a Julia set, the real and imaginary parts of `c`, as `f64` values;
+//! - the power to iterate with, as an `f64`, if it isn't 2;
+//! - the formula, as a byte, if one was given: 0 for the standard iteration,
+//!   1 for the Burning Ship, 2 for the Tricorn, 3 for the Phoenix, and 4 and 5
+//!   for Magnet types I and II, 6 for lambda, and 7, 8, and 9 for the Celtic,
+//!   Perpendicular, and Heart, 10 for the Nova, 11 for quaternion Julia
+//!   sets, 12 for the Collatz map, 13 and 14 for the Spider and Manowar, and
+//!   15 for the Perpendicular Burning Ship;
+//! - the Phoenix formula's `p`, as two `f64` values, if one was given;
+//! - the orbits' starting value `z0`, as two `f64` values, if one was given;
+//! - for a hybrid, the number of formulas in it, as a byte, followed by each
+//!   formula, as a byte;
+//! - the Nova formula's relaxation, as two `f64` values, if one was given;
+//! - a quaternion Julia set's slice, as two `f64` values, if one was given;
+//! - the formula to morph into, as a byte, followed by how far to morph, as an
+//!   `f64`, if there is a morph.
+//!
+//! Coloring isn't recorded; the recipient can choose their own palette. Only
+//! escape-time renders can be shared; Newton fractals can't, and nor can
+//! renders of formula expressions or scripts.
+
+use config::{Config, Formula, Mode, SetKind};
+
+/// The versions of the format: the original, with a single byte of flags, and
+/// the extension with a second byte, for codes that need it.
+const VERSION: u8 = 1;
+const VERSION_EXTENDED: u8 = 2;
+
+/// Flag bits saying which optional fields a code includes.
+const JULIA: u8 = 1;
+const ROTATED: u8 = 2;
+const ITERATIONS: u8 = 4;
+const POWER: u8 = 8;
+const FORMULA: u8 = 16;
+const PHOENIX_P: u8 = 32;
+const Z0: u8 = 64;
+const HYBRID: u8 = 128;
+
+/// Flag bits in the second byte of flags.
+const RELAXATION: u8 = 1;
+const SLICE: u8 = 2;
+const MORPH: u8 = 4;
+
+/// The URL-safe base64 alphabet, from RFC 4648.
+const ALPHABET: &'static [u8] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// Return the base64 encoding of `bytes`, without padding.
+fn to_base64(bytes: &[u8]) -> String {
+    let mut result = String::new();
+    for chunk in bytes.chunks(3) {
+        let bits = chunk.iter().enumerate()
+            .fold(0u32, |bits, (i, &byte)| bits | (byte as u32) << (16 - 8 * i));
+        for i in 0..chunk.len() + 1 {
+            result.push(ALPHABET[(bits >> (18 - 6 * i) & 63) as usize] as char);
+        }
+    }
+    result
+}
+
+/// Decode `text` as unpadded base64.
+fn from_base64(text: &str) -> Result<Vec<u8>, String> {
+    let mut result = Vec::new();
+    let (mut bits, mut count) = (0u32, 0);
+    for ch in text.chars() {
+        let digit = try!(ALPHABET.iter().position(|&a| a as char == ch).ok_or_else(|| {
+            format!("unexpected character '{}'", ch)
+        }));
+        bits = bits << 6 | digit as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            result.push((bits >> count) as u8);
+        }
+    }
+    Ok(result)
+}
+
+/// Append `value` to `bytes`, little-endian.
+fn push_f64(bytes: &mut Vec<u8>, value: f64) {
+    let bits = value.to_bits();
+    bytes.extend((0..8).map(|i| (bits >> (8 * i)) as u8));
+}
+
+/// Return the byte that stands for `formula` in a code.
+fn formula_code(formula: Formula) -> u8 {
+    match formula {
+        Formula::Standard => 0,
+        Formula::BurningShip => 1,
+        Formula::Tricorn => 2,
+        Formula::Phoenix => 3,
+        Formula::Magnet1 => 4,
+        Formula::Magnet2 => 5,
+        Formula::Lambda => 6,
+        Formula::Celtic => 7,
+        Formula::Perpendicular => 8,
+        Formula::Heart => 9,
+        Formula::Nova => 10,
+        Formula::QuatJulia => 11,
+        Formula::Collatz => 12,
+        Formula::Spider => 13,
+        Formula::Manowar => 14,
+        Formula::PerpendicularBurningShip => 15
+    }
+}
+
+/// Return the formula that `code` stands for.
+fn code_formula(code: u8) -> Result<Formula, String> {
+    Ok(match code {
+        0 => Formula::Standard,
+        1 => Formula::BurningShip,
+        2 => Formula::Tricorn,
+        3 => Formula::Phoenix,
+        4 => Formula::Magnet1,
+        5 => Formula::Magnet2,
+        6 => Formula::Lambda,
+        7 => Formula::Celtic,
+        8 => Formula::Perpendicular,
+        9 => Formula::Heart,
+        10 => Formula::Nova,
+        11 => Formula::QuatJulia,
+        12 => Formula::Collatz,
+        13 => Formula::Spider,
+        14 => Formula::Manowar,
+        15 => Formula::PerpendicularBurningShip,
+        n => return Err(format!("unknown formula number {}", n))
+    })
+}
+
+/// Return a code for the view that `config` describes. Its `center` and `zoom`
+/// must be present; a Julia set needs `c` as well.
+pub fn encode(config: &Config) -> Result<String, String> {
+    let (center, zoom) = match (config.center, config.zoom) {
+        (Some(center), Some(zoom)) => (center, zoom),
+        _ => return Err("a view's center and zoom are needed to share it".to_string())
+    };
+    if config.mode.unwrap_or(Mode::Escape) != Mode::Escape {
+        return Err("only escape-time renders can be shared".to_string());
+    }
+    if config.formula_expr.is_some() || config.script.is_some() {
+        return Err("renders of formula expressions and scripts can't be shared".to_string());
+    }
+    let c = match config.set {
+        Some(SetKind::Julia) => {
+            Some(try!(config.c.ok_or("a Julia set needs a value for c")))
+        }
+        _ => None
+    };
+    let rotate = config.rotate.and_then(|r| if r != 0.0 { Some(r) } else { None });
+    let power = config.power.and_then(|d| if d != 2.0 { Some(d) } else { None });
+
+    let mut flags = 0;
+    if c.is_some() { flags |= JULIA; }
+    if rotate.is_some() { flags |= ROTATED; }
+    if config.iterations.is_some() { flags |= ITERATIONS; }
+    if power.is_some() { flags |= POWER; }
+    if config.formula.is_some() { flags |= FORMULA; }
+    if config.phoenix_p.is_some() { flags |= PHOENIX_P; }
+    if config.z0.is_some() { flags |= Z0; }
+    if let Some(ref hybrid) = config.hybrid {
+        if hybrid.len() > u8::max_value() as usize {
+            return Err("a hybrid of more than 255 formulas can't be shared".to_string());
+        }
+        flags |= HYBRID;
+    }
+    let mut more_flags = 0;
+    if config.relaxation.is_some() { more_flags |= RELAXATION; }
+    if config.slice.is_some() { more_flags |= SLICE; }
+    if config.morph_to.is_some() { more_flags |= MORPH; }
+
+    let mut bytes = if more_flags != 0 {
+        vec![VERSION_EXTENDED, flags, more_flags]
+    } else {
+        vec![VERSION, flags]
+    };
+    push_f64(&mut bytes, center.0);
+    push_f64(&mut bytes, center.1);
+    push_f64(&mut bytes, zoom);
+    if let Some(rotate) = rotate {
+        push_f64(&mut bytes, rotate);
+    }
+    if let Some(mut iterations) = config.iterations {
+        while iterations >= 0x80 {
+            bytes.push(iterations as u8 | 0x80);
+            iterations >>= 7;
+        }
+        bytes.push(iterations as u8);
+    }
+    if let Some(c) = c {
+        push_f64(&mut bytes, c.0);
+        push_f64(&mut bytes, c.1);
+    }
+    if let Some(power) = power {
+        push_f64(&mut bytes, power);
+    }
+    if let Some(formula) = config.formula {
+        bytes.push(formula_code(formula));
+    }
+    if let Some(p) = config.phoenix_p {
+        push_f64(&mut bytes, p.0);
+        push_f64(&mut bytes, p.1);
+    }
+    if let Some(z0) = config.z0 {
+        push_f64(&mut bytes, z0.0);
+        push_f64(&mut bytes, z0.1);
+    }
+    if let Some(ref hybrid) = config.hybrid {
+        bytes.push(hybrid.len() as u8);
+        bytes.extend(hybrid.iter().map(|&formula| formula_code(formula)));
+    }
+    if let Some(relaxation) = config.relaxation {
+        push_f64(&mut bytes, relaxation.0);
+        push_f64(&mut bytes, relaxation.1);
+    }
+    if let Some(slice) = config.slice {
+        push_f64(&mut bytes, slice.0);
+        push_f64(&mut bytes, slice.1);
+    }
+    if let Some((formula, t)) = config.morph() {
+        bytes.push(formula_code(formula));
+        push_f64(&mut bytes, t);
+    }
+    Ok(to_base64(&bytes))
+}
+
+/// A cursor over the bytes of a code being decoded.
+struct Reader<'a> {
+    bytes: &'a [u8]
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Result<u8, String> {
+        match self.bytes.split_first() {
+            Some((&first, rest)) => {
+                self.bytes = rest;
+                Ok(first)
+            }
+            None => Err("code is too short".to_string())
+        }
+    }
+
+    fn f64(&mut self) -> Result<f64, String> {
+        let mut bits = 0u64;
+        for i in 0..8 {
+            bits |= (try!(self.byte()) as u64) << (8 * i);
+        }
+        Ok(f64::from_bits(bits))
+    }
+
+    fn leb128(&mut self) -> Result<u32, String> {
+        let mut value = 0u64;
+        for i in 0..5 {
+            let byte = try!(self.byte());
+            value |= ((byte & 0x7f) as u64) << (7 * i);
+            if byte & 0x80 == 0 {
+                if value > u32::max_value() as u64 {
+                    break;
+                }
+                return Ok(value as u32);
+            }
+        }
+        Err("iteration limit is too large".to_string())
+    }
+}
+
+/// Return the settings recorded in `code`, produced by `encode`.
+pub fn decode(code: &str) -> Result<Config, String> {
+    let bytes = try!(from_base64(code.trim()));
+    let mut reader = Reader { bytes: &bytes };
+    let version = try!(reader.byte());
+    if version != VERSION && version != VERSION_EXTENDED {
+        return Err(format!("code is in an unknown format, version {}", version));
+    }
+    let flags = try!(reader.byte());
+    let more_flags = if version == VERSION_EXTENDED { try!(reader.byte()) } else { 0 };
+
+    let mut config = Config::default();
+    config.center = Some((try!(reader.f64()), try!(reader.f64())));
+    config.zoom = Some(try!(reader.f64()));
+    if flags & ROTATED != 0 {
+        config.rotate = Some(try!(reader.f64()));
+    }
+    if flags & ITERATIONS != 0 {
+        config.iterations = Some(try!(reader.leb128()));
+    }
+    if flags & JULIA != 0 {
+        config.set = Some(SetKind::Julia);
+        config.c = Some((try!(reader.f64()), try!(reader.f64())));
+    } else {
+        config.set = Some(SetKind::Mandelbrot);
+    }
+    if flags & POWER != 0 {
+        config.power = Some(try!(reader.f64()));
+    }
+    if flags & FORMULA != 0 {
+        config.formula = Some(try!(code_formula(try!(reader.byte()))));
+    }
+    if flags & PHOENIX_P != 0 {
+        config.phoenix_p = Some((try!(reader.f64()), try!(reader.f64())));
+    }
+    if flags & Z0 != 0 {
+        config.z0 = Some((try!(reader.f64()), try!(reader.f64())));
+    }
+    if flags & HYBRID != 0 {
+        let count = try!(reader.byte());
+        let mut hybrid = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            hybrid.push(try!(code_formula(try!(reader.byte()))));
+        }
+        config.hybrid = Some(hybrid);
+    }
+    if more_flags & RELAXATION != 0 {
+        config.relaxation = Some((try!(reader.f64()), try!(reader.f64())));
+    }
+    if more_flags & SLICE != 0 {
+        config.slice = Some((try!(reader.f64()), try!(reader.f64())));
+    }
+    if more_flags & MORPH != 0 {
+        config.morph_to = Some(try!(code_formula(try!(reader.byte()))));
+        config.morph = Some(try!(reader.f64()));
+    }
+    if !reader.bytes.is_empty() {
+        return Err("code is too long".to_string());
+    }
+    Ok(config)
+}
+
+#[test]
+fn test_base64() {
+    assert_eq!(to_base64(b""), "");
+    assert_eq!(to_base64(b"f"), "Zg");
+    assert_eq!(to_base64(b"fo"), "Zm8");
+    assert_eq!(to_base64(b"foo"), "Zm9v");
+    assert_eq!(to_base64(&[0xfb, 0xff]), "-_8");
+    assert_eq!(from_base64("Zm9vYg"), Ok(b"foob".to_vec()));
+    assert!(from_base64("Zm9v+").is_err());
+}
+
+#[test]
+fn test_round_trip() {
+    let seahorse = Config {
+        center: Some((-0.743, 0.131)),
+        zoom: Some(1e3),
+        iterations: Some(1000),
+        set: Some(SetKind::Mandelbrot),
+        .. Config::default()
+    };
+    let code = encode(&seahorse).unwrap();
+    assert_eq!(decode(&code), Ok(seahorse));
+
+    let dragon = Config {
+        center: Some((0.0, 0.0)),
+        zoom: Some(1.0),
+        rotate: Some(-30.0),
+        set: Some(SetKind::Julia),
+        c: Some((-0.8, 0.156)),
+        power: Some(3.0),
+        formula: Some(Formula::Phoenix),
+        phoenix_p: Some((-0.5, 0.1)),
+        z0: Some((0.25, 0.0)),
+        .. Config::default()
+    };
+    assert_eq!(decode(&encode(&dragon).unwrap()), Ok(dragon));
+
+    let hybrid = Config {
+        center: Some((-1.75, 0.0)),
+        zoom: Some(20.0),
+        set: Some(SetKind::Mandelbrot),
+        hybrid: Some(vec![Formula::Standard, Formula::Standard, Formula::BurningShip]),
+        .. Config::default()
+    };
+    assert_eq!(decode(&encode(&hybrid).unwrap()), Ok(hybrid));
+
+    let nova = Config {
+        center: Some((0.0, 0.0)),
+        zoom: Some(1.0),
+        set: Some(SetKind::Mandelbrot),
+        formula: Some(Formula::Nova),
+        power: Some(3.0),
+        relaxation: Some((0.5, 0.25)),
+        .. Config::default()
+    };
+    let nova_code = encode(&nova).unwrap();
+    assert_eq!(from_base64(&nova_code).unwrap()[0], VERSION_EXTENDED);
+    assert_eq!(decode(&nova_code), Ok(nova));
+    assert_eq!(from_base64(&code).unwrap()[0], VERSION);
+
+    let quat = Config {
+        center: Some((0.0, 0.0)),
+        zoom: Some(1.0),
+        set: Some(SetKind::Julia),
+        c: Some((-0.2, 0.8)),
+        formula: Some(Formula::QuatJulia),
+        slice: Some((0.1, 0.0)),
+        .. Config::default()
+    };
+    assert_eq!(decode(&encode(&quat).unwrap()), Ok(quat));
+
+    let morph = Config {
+        center: Some((-0.5, 0.0)),
+        zoom: Some(1.0),
+        set: Some(SetKind::Mandelbrot),
+        morph_to: Some(Formula::BurningShip),
+        morph: Some(0.3),
+        .. Config::default()
+    };
+    assert_eq!(decode(&encode(&morph).unwrap()), Ok(morph));
+
+    assert!(encode(&Config::default()).is_err());
+    assert!(decode("").is_err());
+    assert!(decode(&code[..code.len() - 2]).is_err());
+    assert!(decode(&format!("{}AA", code)).is_err());
+}
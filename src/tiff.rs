@@ -0,0 +1,281 @@
+//! Writing TIFF and BigTIFF files.
+//!
+//! TIFF is the usual interchange format for print work, and unlike PNG, it can
+//! be read a piece at a time, which matters for gigapixel images. A TIFF file
+//! is a short header, the pixel data, and an "image file directory" listing
+//! tagged fields: the image's dimensions, how its samples are laid out, and
+//! where in the file its pixels are.
+//!
+//! Classic TIFF uses four-byte file offsets, so it can't hold more than 4 GiB.
+//! For images bigger than that, we write BigTIFF, which is the same format
+//! with eight-byte offsets, and divide the image into square tiles rather than
+//! strips of rows, so that readers can fetch any part of it without reading
+//! whole rows of a huge image.
+//!
+//! We write everything big-endian, which TIFF allows, so that 16-bit samples,
+//! which our bitmaps already hold big-endian, can be copied as they are. The
+//! pixels are stored uncompressed.
+
+use std::io::{self, BufWriter, Write};
+
+/// How many bytes of pixel data each strip of a classic TIFF file should hold,
+/// roughly.
+const STRIP_BYTES: usize = 64 * 1024;
+
+/// The width and height of each tile in a BigTIFF file.
+const TILE_SIZE: usize = 256;
+
+/// The types of values a field can hold, with their TIFF codes.
+const SHORT: u16 = 3;
+const LONG: u16 = 4;
+const RATIONAL: u16 = 5;
+const UNDEFINED: u16 = 7;
+const LONG8: u16 = 16;
+
+/// A field's value: its type, how many values it holds, and those values as
+/// big-endian bytes.
+struct Field {
+    tag: u16,
+    kind: u16,
+    count: u64,
+    bytes: Vec<u8>
+}
+
+impl Field {
+    fn shorts(tag: u16, values: &[u16]) -> Field {
+        let bytes = values.iter().flat_map(|&n| vec![(n >> 8) as u8, n as u8]).collect();
+        Field { tag: tag, kind: SHORT, count: values.len() as u64, bytes: bytes }
+    }
+
+    fn long(tag: u16, value: u32) -> Field {
+        Field { tag: tag, kind: LONG, count: 1, bytes: be(value as u64, 4) }
+    }
+
+    /// A list of offsets or byte counts, which are longs in classic TIFF, and
+    /// eight-byte longs in BigTIFF.
+    fn offsets(tag: u16, values: &[u64], big: bool) -> Field {
+        let (kind, size) = if big { (LONG8, 8) } else { (LONG, 4) };
+        let bytes = values.iter().flat_map(|&n| be(n, size)).collect();
+        Field { tag: tag, kind: kind, count: values.len() as u64, bytes: bytes }
+    }
+
+    fn rational(tag: u16, numerator: u32, denominator: u32) -> Field {
+        let mut bytes = be(numerator as u64, 4);
+        bytes.extend(be(denominator as u64, 4));
+        Field { tag: tag, kind: RATIONAL, count: 1, bytes: bytes }
+    }
+
+    fn undefined(tag: u16, bytes: &[u8]) -> Field {
+        Field { tag: tag, kind: UNDEFINED, count: bytes.len() as u64, bytes: bytes.to_vec() }
+    }
+}
+
+/// Write `pixels`, an image whose dimensions are given by `bounds`, to `output`
+/// as a TIFF file. Each pixel has `channels` samples of `depth` bits; one or
+/// two channels are gray, three or four are RGB, and an even number includes
+/// alpha. If `profile` is given, embed it as the image's ICC profile.
+///
+/// If the file would be too big for classic TIFF, write BigTIFF.
+pub fn write<W: Write>(output: W, pixels: &[u8], bounds: (usize, usize), channels: usize,
+                       depth: u8, profile: Option<&[u8]>)
+    -> io::Result<()>
+{
+    // Besides the pixels, there is a header, a directory, and a table of
+    // strips; a megabyte is plenty for those.
+    let overhead = (1 << 20) + profile.map_or(0, |profile| profile.len());
+    let big = (pixels.len() + overhead) as u64 > 0xffff_ffff;
+    encode(output, pixels, bounds, channels, depth, profile, big)
+}
+
+/// Write a TIFF file as `write` does, in BigTIFF's tiled form if `big` is true.
+fn encode<W: Write>(output: W, pixels: &[u8], bounds: (usize, usize), channels: usize,
+                    depth: u8, profile: Option<&[u8]>, big: bool)
+    -> io::Result<()>
+{
+    let mut output = BufWriter::new(output);
+    let pixel_bytes = channels * depth as usize / 8;
+    let row_bytes = bounds.0 * pixel_bytes;
+    let header_length = if big { 16 } else { 8 };
+
+    // Lay out the strips or tiles, one after another, right after the header.
+    let blocks = if big {
+        let tiles = ((bounds.0 + TILE_SIZE - 1) / TILE_SIZE) *
+            ((bounds.1 + TILE_SIZE - 1) / TILE_SIZE);
+        vec![TILE_SIZE * TILE_SIZE * pixel_bytes; tiles]
+    } else {
+        let rows = (STRIP_BYTES / row_bytes.max(1)).max(1);
+        (0..(bounds.1 + rows - 1) / rows)
+            .map(|strip| (bounds.1 - strip * rows).min(rows) * row_bytes)
+            .collect()
+    };
+    let mut offsets = Vec::with_capacity(blocks.len());
+    let mut end = header_length as u64;
+    for &length in &blocks {
+        offsets.push(end);
+        end += length as u64;
+    }
+    let counts: Vec<u64> = blocks.iter().map(|&length| length as u64).collect();
+
+    let samples = vec![depth as u16; channels];
+    let mut fields = vec![
+        Field::long(256, bounds.0 as u32),
+        Field::long(257, bounds.1 as u32),
+        Field::shorts(258, &samples),
+        Field::shorts(259, &[1]), // no compression
+        Field::shorts(262, &[if channels < 3 { 1 } else { 2 }]), // black is zero, or RGB
+        Field::shorts(277, &[channels as u16]),
+        Field::rational(282, 72, 1),
+        Field::rational(283, 72, 1),
+        Field::shorts(284, &[1]), // each pixel's samples together
+        Field::shorts(296, &[2]) // resolution in dots per inch
+    ];
+    if big {
+        fields.push(Field::shorts(322, &[TILE_SIZE as u16]));
+        fields.push(Field::shorts(323, &[TILE_SIZE as u16]));
+        fields.push(Field::offsets(324, &offsets, true));
+        fields.push(Field::offsets(325, &counts, true));
+    } else {
+        let rows = if blocks.is_empty() { 0 } else { blocks[0] / row_bytes.max(1) };
+        fields.push(Field::offsets(273, &offsets, false));
+        fields.push(Field::long(278, rows as u32));
+        fields.push(Field::offsets(279, &counts, false));
+    }
+    if channels % 2 == 0 {
+        fields.push(Field::shorts(338, &[2])); // unassociated alpha
+    }
+    if let Some(profile) = profile {
+        fields.push(Field::undefined(34675, profile));
+    }
+    fields.sort_by_key(|field| field.tag);
+
+    // The directory goes after the pixels, on a word boundary, and values too
+    // big to fit in its entries go after it.
+    let padding = end % 2;
+    let directory = end + padding;
+    let (word, count, entry) = if big { (8, 8, 20) } else { (4, 2, 12) };
+    let mut values_end = directory + (count + fields.len() * entry + word) as u64;
+
+    if big {
+        try!(output.write_all(b"MM\0\x2b\0\x08\0\0"));
+        try!(output.write_all(&be(directory, 8)));
+    } else {
+        try!(output.write_all(b"MM\0\x2a"));
+        try!(output.write_all(&be(directory, 4)));
+    }
+    if big {
+        try!(write_tiles(&mut output, pixels, bounds, pixel_bytes));
+    } else {
+        try!(output.write_all(pixels));
+    }
+    try!(output.write_all(&vec![0; padding as usize]));
+
+    try!(output.write_all(&be(fields.len() as u64, count)));
+    let mut values = Vec::new();
+    for field in &fields {
+        try!(output.write_all(&be(field.tag as u64, 2)));
+        try!(output.write_all(&be(field.kind as u64, 2)));
+        try!(output.write_all(&be(field.count, word)));
+        if field.bytes.len() <= word {
+            let mut inline = field.bytes.clone();
+            inline.resize(word, 0);
+            try!(output.write_all(&inline));
+        } else {
+            try!(output.write_all(&be(values_end, word)));
+            values.extend_from_slice(&field.bytes);
+            values_end += field.bytes.len() as u64;
+            if values.len() % 2 == 1 {
+                values.push(0);
+                values_end += 1;
+            }
+        }
+    }
+    try!(output.write_all(&vec![0; word])); // no further directories
+    try!(output.write_all(&values));
+    output.flush()
+}
+
+/// Write `pixels`, an image whose dimensions are given by `bounds`, to `output`
+/// as a series of tiles, left to right and top to bottom, padding those that
+/// hang off the right or bottom edges with zeros.
+fn write_tiles<W: Write>(output: &mut W, pixels: &[u8], bounds: (usize, usize),
+                         pixel_bytes: usize)
+    -> io::Result<()>
+{
+    let tile_row = TILE_SIZE * pixel_bytes;
+    let blank = vec![0; tile_row];
+    for tile_y in 0..(bounds.1 + TILE_SIZE - 1) / TILE_SIZE {
+        for tile_x in 0..(bounds.0 + TILE_SIZE - 1) / TILE_SIZE {
+            let (left, top) = (tile_x * TILE_SIZE, tile_y * TILE_SIZE);
+            let width = (bounds.0 - left).min(TILE_SIZE) * pixel_bytes;
+            for y in top..top + TILE_SIZE {
+                if y < bounds.1 {
+                    let start = (y * bounds.0 + left) * pixel_bytes;
+                    try!(output.write_all(&pixels[start..start + width]));
+                    try!(output.write_all(&blank[width..]));
+                } else {
+                    try!(output.write_all(&blank));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Return the low `size` bytes of `n`, big-endian.
+fn be(n: u64, size: usize) -> Vec<u8> {
+    (0..size).rev().map(|i| (n >> (8 * i)) as u8).collect()
+}
+
+#[cfg(test)]
+fn read(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0, |n, &byte| n << 8 | byte as u64)
+}
+
+#[test]
+fn test_classic() {
+    let pixels: Vec<u8> = (0..3 * 2 * 3).collect();
+    let mut tiff = Vec::new();
+    encode(&mut tiff, &pixels, (3, 2), 3, 8, None, false).unwrap();
+    assert_eq!(&tiff[..8], b"MM\0\x2a\0\0\0\x1a");
+    assert_eq!(&tiff[8..26], &pixels[..]);
+
+    // Thirteen fields, in order, with the width first; the directory ends with
+    // a zero offset, and the resolutions follow it.
+    assert_eq!(read(&tiff[26..28]), 13);
+    let entry = |i: usize| &tiff[28 + 12 * i..40 + 12 * i];
+    assert_eq!(entry(0), &[1, 0, 0, LONG as u8, 0, 0, 0, 1, 0, 0, 0, 3]);
+    let tags: Vec<u64> = (0..13).map(|i| read(&entry(i)[..2])).collect();
+    assert_eq!(tags, vec![256, 257, 258, 259, 262, 273, 277, 278, 279, 282, 283, 284, 296]);
+    assert_eq!(read(&entry(5)[8..]), 8); // the strip's offset
+    assert_eq!(read(&entry(8)[8..]), 18); // and length
+    let values = 28 + 12 * 13 + 4;
+    assert_eq!(read(&tiff[values - 4..values]), 0);
+    assert_eq!(read(&entry(2)[8..]), values as u64); // the three bits per sample
+    assert_eq!(&tiff[values..values + 6], &[0, 8, 0, 8, 0, 8]);
+    assert_eq!(tiff.len(), values + 6 + 8 + 8);
+}
+
+#[test]
+fn test_big() {
+    // A gray image with alpha, sixteen bits deep, with a profile.
+    let pixels: Vec<u8> = (0..8).collect();
+    let mut tiff = Vec::new();
+    encode(&mut tiff, &pixels, (2, 1), 2, 16, Some(b"profile"), true).unwrap();
+    assert_eq!(&tiff[..8], b"MM\0\x2b\0\x08\0\0");
+
+    // One tile, its first row holding the pixels and then zeros.
+    let tile = TILE_SIZE * TILE_SIZE * 4;
+    let directory = 16 + tile;
+    assert_eq!(read(&tiff[8..16]), directory as u64);
+    assert_eq!(&tiff[16..24], &pixels[..]);
+    assert!(tiff[24..directory].iter().all(|&byte| byte == 0));
+
+    let entry = |i: usize| &tiff[directory + 8 + 20 * i..directory + 28 + 20 * i];
+    assert_eq!(read(&tiff[directory..directory + 8]), 16);
+    let tags: Vec<u64> = (0..16).map(|i| read(&entry(i)[..2])).collect();
+    assert_eq!(tags, vec![256, 257, 258, 259, 262, 277, 282, 283, 284, 296,
+                          322, 323, 324, 325, 338, 34675]);
+    assert_eq!(&entry(12)[2..], &[0, 16, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 16]);
+    assert_eq!(read(&entry(13)[12..]), tile as u64);
+    assert_eq!(&entry(15)[12..], b"profile\0");
+}
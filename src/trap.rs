@@ -0,0 +1,71 @@
+//! Orbit traps.
+//!
+//! An orbit trap is a shape in the complex plane that orbits can pass near.
+//! Coloring each point by how close its orbit came to the trap, rather than by
+//! how long it took to escape, traces copies of the trap's shape throughout
+//! the set and around it, distorted by the iteration: a point trap makes
+//! glowing spots, a line trap ribbons, and a circle trap rings.
+//!
+//! Clifford Pickover's "stalks" use both coordinate axes as the trap. Blended
+//! with the escape-time colors, rather than replacing them, they sprout thin
+//! stems from the set's edges.
+//!
+//! The closest approach counts every point the orbit visits after its start,
+//! whether or not it eventually escapes.
+
+use num::Complex;
+
+/// A shape to measure orbits' distance from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Trap {
+    /// A single point.
+    Point(Complex<f64>),
+
+    /// The line through `point`, running in the direction of the unit vector
+    /// `direction`.
+    Line { point: Complex<f64>, direction: Complex<f64> },
+
+    /// The circle with the given center and radius.
+    Circle { center: Complex<f64>, radius: f64 },
+
+    /// The real and imaginary axes together.
+    Stalks
+}
+
+impl Trap {
+    /// Return the line through `point` at an angle of `degrees`
+    /// counterclockwise from the real axis.
+    pub fn line(point: Complex<f64>, degrees: f64) -> Trap {
+        let radians = degrees.to_radians();
+        Trap::Line { point: point, direction: Complex { re: radians.cos(), im: radians.sin() } }
+    }
+
+    /// Return the distance from `z` to the nearest point of this trap.
+    pub fn distance(&self, z: Complex<f64>) -> f64 {
+        match *self {
+            Trap::Point(point) => (z - point).norm(),
+            Trap::Line { point, direction } => {
+                let offset = z - point;
+                (offset.re * direction.im - offset.im * direction.re).abs()
+            }
+            Trap::Circle { center, radius } => ((z - center).norm() - radius).abs(),
+            Trap::Stalks => z.re.abs().min(z.im.abs())
+        }
+    }
+}
+
+#[test]
+fn test_distance() {
+    let close = |a: f64, b: f64| (a - b).abs() < 1e-12;
+    let z = Complex { re: 3.0, im: 4.0 };
+    let origin = Complex { re: 0.0, im: 0.0 };
+    assert!(close(Trap::Point(origin).distance(z), 5.0));
+    assert!(close(Trap::Point(Complex { re: 3.0, im: 3.0 }).distance(z), 1.0));
+    assert!(close(Trap::line(origin, 0.0).distance(z), 4.0));
+    assert!(close(Trap::line(origin, 90.0).distance(z), 3.0));
+    assert!(close(Trap::line(Complex { re: 0.0, im: 1.0 }, 45.0).distance(z), 0.0));
+    assert!(close(Trap::Circle { center: origin, radius: 2.0 }.distance(z), 3.0));
+    assert!(close(Trap::Circle { center: origin, radius: 7.0 }.distance(z), 2.0));
+    assert!(close(Trap::Stalks.distance(z), 3.0));
+    assert!(close(Trap::Stalks.distance(Complex { re: -5.0, im: 0.5 }), 0.5));
+}
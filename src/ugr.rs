@@ -0,0 +1,126 @@
+//! Reading Ultra Fractal `.ugr` gradient files.
+//!
+//! A `.ugr` file holds any number of named gradients, laid out like the
+//! entries of a `.upr` parameter file:
+//!
+//! ```text
+//! Sunset {
+//! gradient:
+//!   title="Sunset" smooth=no index=0 color=8388608
+//!   index=120 color=33023 index=260 color=16777215
+//! opacity:
+//!   smooth=no index=0 opacity=255
+//! }
+//! ```
+//!
+//! Each `index` places a color along the gradient, which Ultra Fractal divides
+//! into 400 steps; the `color` following it is a decimal number holding blue,
+//! green, and red bytes, from most significant to least. The gradient loops
+//! around, blending from its last color back to its first, so the stretch
+//! before the first index and after the last blends between those two.
+//!
+//! We blend the colors in RGB, and ignore the opacity section.
+
+use std::fs::File;
+use std::io::{self, ErrorKind, Read};
+use std::path::Path;
+
+use coloring::Palette;
+use config::ColorSpace;
+use gradient;
+use upr;
+
+/// The number of steps Ultra Fractal divides a gradient into.
+const STEPS: f64 = 400.0;
+
+/// Return the palette for the gradient named `name` in `text`, the contents
+/// of a `.ugr` file.
+pub fn parse(text: &str, name: &str) -> Result<Palette, String> {
+    let mut stops = Vec::new();
+    let mut index = None;
+    let mut section = "";
+    for line in try!(upr::entry(text, name)) {
+        let trimmed = line.trim();
+        if trimmed.ends_with(':') && !trimmed.contains('=') {
+            section = &trimmed[..trimmed.len() - 1];
+            continue;
+        }
+        if section != "gradient" {
+            continue;
+        }
+        for (key, value) in upr::settings(line) {
+            match key {
+                "index" => {
+                    index = Some(try!(value.parse::<i32>().map_err(|_| {
+                        format!("bad gradient index '{}'", value)
+                    })));
+                }
+                "color" => {
+                    let color: u32 = try!(value.parse().map_err(|_| {
+                        format!("bad gradient color '{}'", value)
+                    }));
+                    let index = try!(index.take().ok_or_else(|| {
+                        format!("gradient color '{}' has no index", value)
+                    }));
+                    stops.push((index as f64 / STEPS,
+                                [color as u8, (color >> 8) as u8, (color >> 16) as u8]));
+                }
+                _ => ()
+            }
+        }
+    }
+
+    if stops.is_empty() {
+        return Err(format!("gradient '{}' has no colors", name));
+    }
+    stops.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+    // Wrap the last color around before the first, and the first after the
+    // last, so the gradient loops.
+    let (first, last) = (stops[0], stops[stops.len() - 1]);
+    stops.insert(0, (last.0 - 1.0, last.1));
+    stops.push((first.0 + 1.0, first.1));
+    Ok(gradient::palette(&stops, ColorSpace::Rgb))
+}
+
+/// Read the gradient named `name` from the `.ugr` file at `path`.
+pub fn load(path: &Path, name: &str) -> io::Result<Palette> {
+    let mut text = String::new();
+    try!(try!(File::open(path)).read_to_string(&mut text));
+    parse(&text, name).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+}
+
+#[test]
+fn test_parse() {
+    let text = "Sunset {\n\
+                gradient:\n\
+                \x20 title=\"Sunset\" smooth=no index=0 color=255\n\
+                \x20 index=200 color=16711680\n\
+                opacity:\n\
+                \x20 smooth=no index=0 opacity=255\n\
+                }\n\
+                \n\
+                Dusk {\n\
+                gradient:\n\
+                \x20 index=100 color=65280 index=300 color=0\n\
+                }\n\
+                \n\
+                Empty {\n\
+                gradient:\n\
+                \x20 title=\"Empty\"\n\
+                }\n";
+
+    // Red at the start and end, and blue halfway.
+    let sunset = parse(text, "Sunset").unwrap();
+    assert_eq!(sunset.at(0, 4), [255, 0, 0]);
+    assert_eq!(sunset, gradient::palette(&[(0.0, [255, 0, 0]), (0.5, [0, 0, 255]),
+                                           (1.0, [255, 0, 0])],
+                                         ColorSpace::Rgb));
+
+    // Before index 100, the gradient blends from black, wrapping around.
+    let dusk = parse(text, "Dusk").unwrap();
+    assert_eq!(dusk.at(0, 4), [0, 128, 0]);
+
+    assert!(parse(text, "Empty").is_err());
+    assert!(parse(text, "Missing").is_err());
+}
@@ -0,0 +1,200 @@
+//! Reading Ultra Fractal `.upr` parameter files.
+//!
+//! A `.upr` file holds any number of named entries. Each entry is divided into
+//! sections, introduced by a line holding a section name and a colon, and each
+//! section holds `key=value` settings:
+//!
+//! ```text
+//! Seahorse {
+//! fractal:
+//!   title="Seahorse" width=640 height=480 layers=1
+//! layer:
+//!   method=multipass caption="Background" opacity=100
+//! mapping:
+//!   center=-0.743/0.131 magn=375 angle=0
+//! formula:
+//!   maxiter=1000 filename="Standard.ufm" entry="Mandelbrot"
+//! inside:
+//!   transfer=none
+//! }
+//! ```
+//!
+//! We take the view from the `mapping` section, and the iteration limit from
+//! the `formula` section. The formula must be Ultra Fractal's standard
+//! `Mandelbrot` or `Julia`; for the latter, its `p_seed` parameter gives `c`.
+//! Everything else, like layering and coloring, is ignored.
+
+use std::fs::File;
+use std::io::{self, ErrorKind, Read};
+use std::path::Path;
+
+use config::{Config, SetKind};
+
+/// Split `line` into `key=value` settings. Values may be quoted, in which case
+/// they may contain spaces; the quotes are removed.
+///
+/// Ultra Fractal's other files, like `.ugr` gradients, are laid out the same
+/// way, so this and `entry` serve for them too.
+pub fn settings(line: &str) -> Vec<(&str, &str)> {
+    let mut result = Vec::new();
+    let mut rest = line.trim_left();
+    while let Some(eq) = rest.find('=') {
+        let key = rest[..eq].trim();
+        let after = &rest[eq + 1..];
+        let (value, next) = if after.starts_with('"') {
+            match after[1..].find('"') {
+                Some(end) => (&after[1..end + 1], &after[end + 2..]),
+                None => (&after[1..], "")
+            }
+        } else {
+            match after.find(char::is_whitespace) {
+                Some(end) => (&after[..end], &after[end..]),
+                None => (after, "")
+            }
+        };
+        result.push((key, value));
+        rest = next.trim_left();
+    }
+    result
+}
+
+/// Parse a `/`-separated pair of numbers, as used in `.upr` values.
+fn parse_pair(key: &str, value: &str) -> Result<(f64, f64), String> {
+    let mut parts = value.split('/').map(|n| n.trim().parse::<f64>());
+    match (parts.next(), parts.next()) {
+        (Some(Ok(x)), Some(Ok(y))) => Ok((x, y)),
+        _ => Err(format!("bad '{}' setting: '{}'", key, value))
+    }
+}
+
+/// Return the lines of the entry named `name` in `text`, between its opening
+/// line and its closing brace.
+pub fn entry<'a>(text: &'a str, name: &str) -> Result<Vec<&'a str>, String> {
+    let mut lines = text.lines()
+        .skip_while(|line| {
+            match line.find('{') {
+                Some(i) => line[..i].trim() != name,
+                None => true
+            }
+        });
+    if lines.next().is_none() {
+        return Err(format!("no entry named '{}'", name));
+    }
+    Ok(lines.take_while(|line| line.trim() != "}").collect())
+}
+
+/// Return the settings in the entry named `name` in `text`, the contents of a
+/// `.upr` file.
+pub fn parse(text: &str, name: &str) -> Result<Config, String> {
+    let lines = try!(entry(text, name));
+    let mut config = Config::default();
+    let mut formula = None;
+    let mut seed = None;
+    let mut section = "";
+    for line in lines {
+        let trimmed = line.trim();
+        if trimmed.ends_with(':') && !trimmed.contains('=') {
+            section = &trimmed[..trimmed.len() - 1];
+            continue;
+        }
+
+        for (key, value) in settings(line) {
+            match (section, key) {
+                ("mapping", "center") => config.center = Some(try!(parse_pair(key, value))),
+                ("mapping", "magn") => {
+                    let magn: f64 = try!(value.parse().map_err(|_| {
+                        format!("bad magnification '{}'", value)
+                    }));
+                    // At magnification 1, Ultra Fractal shows three units of
+                    // the complex plane vertically. Our zoom 1 shows four.
+                    config.zoom = Some(magn * 4.0 / 3.0);
+                }
+                ("formula", "maxiter") => {
+                    config.iterations = Some(try!(value.parse().map_err(|_| {
+                        format!("bad iteration limit '{}'", value)
+                    })));
+                }
+                ("formula", "entry") => formula = Some(value),
+                ("formula", "p_seed") => seed = Some(try!(parse_pair(key, value))),
+                _ => ()
+            }
+        }
+    }
+
+    match formula {
+        Some("Mandelbrot") | None => config.set = Some(SetKind::Mandelbrot),
+        Some("Julia") => {
+            config.set = Some(SetKind::Julia);
+            config.c = Some(try!(seed.ok_or("Julia formula has no 'p_seed' setting for c")));
+        }
+        Some(other) => return Err(format!("unsupported formula '{}'", other))
+    }
+
+    if config.center.is_none() || config.zoom.is_none() {
+        return Err(format!("entry '{}' has no 'center' and 'magn' settings", name));
+    }
+
+    Ok(config)
+}
+
+/// Read the entry named `name` from the `.upr` file at `path`.
+pub fn load(path: &Path, name: &str) -> io::Result<Config> {
+    let mut text = String::new();
+    try!(try!(File::open(path)).read_to_string(&mut text));
+    parse(&text, name).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+}
+
+#[test]
+fn test_settings() {
+    assert_eq!(settings("  title=\"Two words\" width=640 empty=\"\" height=480"),
+               vec![("title", "Two words"), ("width", "640"), ("empty", ""),
+                    ("height", "480")]);
+    assert_eq!(settings(""), vec![]);
+}
+
+#[test]
+fn test_parse() {
+    let text = "Seahorse {\n\
+                fractal:\n\
+                \x20 title=\"Seahorse\" width=640 height=480 layers=1\n\
+                mapping:\n\
+                \x20 center=-0.743/0.131 magn=375 angle=0\n\
+                formula:\n\
+                \x20 maxiter=1000 filename=\"Standard.ufm\" entry=\"Mandelbrot\"\n\
+                outside:\n\
+                \x20 entry=\"Smooth\" filename=\"Standard.ucl\"\n\
+                }\n\
+                \n\
+                Dragon {\n\
+                mapping:\n\
+                \x20 center=0/0 magn=0.75\n\
+                formula:\n\
+                \x20 entry=\"Julia\" p_seed=-0.8/0.156\n\
+                }\n\
+                \n\
+                Nova {\n\
+                mapping:\n\
+                \x20 center=0/0 magn=1\n\
+                formula:\n\
+                \x20 entry=\"Nova\"\n\
+                }\n";
+
+    assert_eq!(parse(text, "Seahorse").unwrap(),
+               Config {
+                   set: Some(SetKind::Mandelbrot),
+                   center: Some((-0.743, 0.131)),
+                   zoom: Some(500.0),
+                   iterations: Some(1000),
+                   .. Config::default()
+               });
+    assert_eq!(parse(text, "Dragon").unwrap(),
+               Config {
+                   set: Some(SetKind::Julia),
+                   center: Some((0.0, 0.0)),
+                   zoom: Some(1.0),
+                   c: Some((-0.8, 0.156)),
+                   .. Config::default()
+               });
+    assert!(parse(text, "Nova").is_err());
+    assert!(parse(text, "Missing").is_err());
+}
@@ -0,0 +1,341 @@
+//! Rectangles of the complex plane, and the images that show them.
+
+/// An image size, together with the rectangle of the complex plane it covers.
+///
+/// The rectangle may be rotated about its center by `rotation` degrees,
+/// counterclockwise. The corners are those of the rectangle before rotation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct View {
+    pub bounds: (usize, usize),
+    pub upper_left: (f64, f64),
+    pub lower_right: (f64, f64),
+    pub rotation: f64
+}
+
+/// An affine map from pixel coordinates to points on the complex plane. The
+/// pixel in column `c` and row `r` maps to `origin + c * column + r * row`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub origin: (f64, f64),
+    pub column: (f64, f64),
+    pub row: (f64, f64)
+}
+
+impl Transform {
+    /// Return the transform for the part of the image starting at row `top`,
+    /// treating that row as row zero.
+    pub fn from_row(&self, top: usize) -> Transform {
+        Transform {
+            origin: (self.origin.0 + top as f64 * self.row.0,
+                     self.origin.1 + top as f64 * self.row.1),
+            .. *self
+        }
+    }
+}
+
+/// The width of the complex plane visible along the shorter side of the image
+/// at a zoom of 1. This is enough to show the entire Mandelbrot set.
+pub const UNZOOMED_SPAN: f64 = 4.0;
+
+/// How many distinct `f64` values we want between the coordinates of adjacent
+/// pixels. With fewer, rounding error in the pixels' coordinates is visible,
+/// and then amplified by the iteration.
+pub const PRECISION_MARGIN: f64 = 8.0;
+
+impl View {
+    /// Return the view of size `bounds` centered on `center` and magnified by
+    /// `zoom`.
+    ///
+    /// At a zoom of 1, the shorter side of the image spans `UNZOOMED_SPAN`
+    /// units of the complex plane; doubling the zoom halves that. Pixels are
+    /// always square, so the longer side's span follows from the image's aspect
+    /// ratio.
+    pub fn from_center_zoom(bounds: (usize, usize), center: (f64, f64), zoom: f64)
+        -> View
+    {
+        let pixel_size = UNZOOMED_SPAN / (zoom * bounds.0.min(bounds.1) as f64);
+        let half_width = bounds.0 as f64 * pixel_size / 2.0;
+        let half_height = bounds.1 as f64 * pixel_size / 2.0;
+        View {
+            bounds: bounds,
+            upper_left: (center.0 - half_width, center.1 + half_height),
+            lower_right: (center.0 + half_width, center.1 - half_height),
+            rotation: 0.0
+        }
+    }
+
+    /// Return the point at the center of this view.
+    pub fn center(&self) -> (f64, f64) {
+        ((self.upper_left.0 + self.lower_right.0) / 2.0,
+         (self.upper_left.1 + self.lower_right.1) / 2.0)
+    }
+
+    /// Return the width and height of the area this view covers on the complex
+    /// plane.
+    pub fn extent(&self) -> (f64, f64) {
+        (self.lower_right.0 - self.upper_left.0,
+         self.upper_left.1 - self.lower_right.1)
+    }
+
+    /// Return the ratio of the width of the area each pixel covers to its
+    /// height. This is 1 unless the image's aspect ratio differs from that of
+    /// the area it covers, stretching the image.
+    pub fn pixel_aspect_ratio(&self) -> f64 {
+        let (width, height) = self.extent();
+        (width / self.bounds.0 as f64) / (height / self.bounds.1 as f64)
+    }
+
+    /// Return the width and height of the area each pixel covers on the complex
+    /// plane.
+    pub fn pixel_size(&self) -> (f64, f64) {
+        let (width, height) = self.extent();
+        (width / self.bounds.0 as f64, height / self.bounds.1 as f64)
+    }
+
+    /// Return the transform from this view's pixel coordinates to points on
+    /// the complex plane.
+    pub fn transform(&self) -> Transform {
+        let (width, height) = self.pixel_size();
+        if self.rotation == 0.0 {
+            return Transform {
+                origin: self.upper_left,
+                column: (width, 0.0),
+                row: (0.0, -height)
+            };
+        }
+
+        let center = self.center();
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let rotate = |x: f64, y: f64| (x * cos - y * sin, x * sin + y * cos);
+        let corner = rotate(self.upper_left.0 - center.0, self.upper_left.1 - center.1);
+        Transform {
+            origin: (center.0 + corner.0, center.1 + corner.1),
+            column: rotate(width, 0.0),
+            row: rotate(0.0, -height)
+        }
+    }
+
+    /// Return the spacing of representable `f64` values near this view's
+    /// coordinates: the smallest difference between two points in the view
+    /// that we can count on an `f64` to represent.
+    pub fn precision(&self) -> f64 {
+        let magnitude = [self.upper_left.0, self.upper_left.1,
+                         self.lower_right.0, self.lower_right.1]
+            .iter()
+            .fold(0.0_f64, |max, x| max.max(x.abs()));
+        magnitude * ::std::f64::EPSILON
+    }
+
+    /// Return true if adjacent pixels of this view are too close together on
+    /// the complex plane for an `f64` to tell them apart reliably. Such a view
+    /// renders as blocks and smears rather than detail.
+    pub fn exceeds_precision(&self) -> bool {
+        let (width, height) = self.pixel_size();
+        width.min(height) < self.precision() * PRECISION_MARGIN
+    }
+
+    /// Return this view's magnification, in the sense of `from_center_zoom`.
+    /// If the pixels aren't square, use the larger of their two dimensions.
+    pub fn zoom(&self) -> f64 {
+        let (width, height) = self.extent();
+        let pixel_size = (width / self.bounds.0 as f64)
+            .max(height / self.bounds.1 as f64);
+        UNZOOMED_SPAN / (pixel_size * self.bounds.0.min(self.bounds.1) as f64)
+    }
+}
+
+#[test]
+fn test_transform() {
+    let view = View { bounds: (4, 2), upper_left: (-2.0, 1.0), lower_right: (2.0, -1.0),
+                      rotation: 0.0 };
+    assert_eq!(view.transform(),
+               Transform { origin: (-2.0, 1.0), column: (1.0, 0.0), row: (0.0, -1.0) });
+    assert_eq!(view.transform().from_row(1).origin, (-2.0, 0.0));
+
+    // A quarter turn counterclockwise puts the upper left corner at the lower
+    // left, and makes columns run upwards.
+    let turned = View { rotation: 90.0, .. view }.transform();
+    let close = |a: (f64, f64), b: (f64, f64)| {
+        (a.0 - b.0).abs() < 1e-12 && (a.1 - b.1).abs() < 1e-12
+    };
+    assert!(close(turned.origin, (-1.0, -2.0)));
+    assert!(close(turned.column, (0.0, 1.0)));
+    assert!(close(turned.row, (1.0, 0.0)));
+}
+
+#[test]
+fn test_exceeds_precision() {
+    let shallow = View::from_center_zoom((1000, 750), (-0.743, 0.131), 1e6);
+    assert!(!shallow.exceeds_precision());
+    let deep = View::from_center_zoom((1000, 750), (-0.743, 0.131), 3.2e13);
+    assert!(deep.exceeds_precision());
+
+    // Near the origin, the same zoom is fine.
+    let small = View::from_center_zoom((1000, 750), (1e-6, 1e-6), 3.2e13);
+    assert!(!small.exceeds_precision());
+}
+
+#[test]
+fn test_from_center_zoom() {
+    let view = View::from_center_zoom((200, 100), (-0.5, 0.0), 1.0);
+    assert_eq!(view, View { bounds: (200, 100),
+                            upper_left: (-4.5, 2.0), lower_right: (3.5, -2.0),
+                            rotation: 0.0 });
+    assert_eq!(view.center(), (-0.5, 0.0));
+    assert_eq!(view.zoom(), 1.0);
+
+    let view = View::from_center_zoom((100, 400), (1.0, 1.0), 4.0);
+    assert_eq!(view, View { bounds: (100, 400),
+                            upper_left: (0.5, 3.0), lower_right: (1.5, -1.0),
+                            rotation: 0.0 });
+    assert_eq!(view.zoom(), 4.0);
+}
+
+/// An area of the complex plane, as the user might describe it, independent of
+/// the size of the image that will show it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Region {
+    /// The points at the image's upper left and lower right corners.
+    Corners((f64, f64), (f64, f64)),
+
+    /// The point at the image's center, and its magnification, in the sense of
+    /// `View::from_center_zoom`.
+    CenterZoom((f64, f64), f64)
+}
+
+impl Region {
+    /// Return a `View` of this region in an image of size `bounds`.
+    pub fn view(&self, bounds: (usize, usize)) -> View {
+        match *self {
+            Region::Corners(upper_left, lower_right) => View {
+                bounds: bounds,
+                upper_left: upper_left,
+                lower_right: lower_right,
+                rotation: 0.0
+            },
+            Region::CenterZoom(center, zoom) =>
+                View::from_center_zoom(bounds, center, zoom)
+        }
+    }
+
+    /// Return image dimensions for this region, given at most one of the width
+    /// and height. Choose the missing dimension so that the pixels are square.
+    ///
+    /// A region given by center and zoom has no aspect ratio of its own, so
+    /// both dimensions must be given for it.
+    pub fn complete_bounds(&self, width: Option<usize>, height: Option<usize>)
+        -> Result<(usize, usize), String>
+    {
+        let (upper_left, lower_right) = match *self {
+            Region::Corners(upper_left, lower_right) => (upper_left, lower_right),
+            Region::CenterZoom(..) => {
+                return Err("a view given by its center needs both image \
+                            dimensions".to_string());
+            }
+        };
+
+        let aspect = (lower_right.0 - upper_left.0) / (upper_left.1 - lower_right.1);
+        if !(aspect > 0.0 && aspect.is_finite()) {
+            return Err("the region's corners don't describe a rectangle".to_string());
+        }
+        match (width, height) {
+            (Some(width), Some(height)) => Ok((width, height)),
+            (Some(width), None) =>
+                Ok((width, ((width as f64 / aspect).round() as usize).max(1))),
+            (None, Some(height)) =>
+                Ok((((height as f64 * aspect).round() as usize).max(1), height)),
+            (None, None) => Err("no image size was given".to_string())
+        }
+    }
+
+    /// Return the center and magnification of this region. For a region given
+    /// by its corners, choose the magnification that fits the shorter of its
+    /// two sides.
+    pub fn center_zoom(&self) -> ((f64, f64), f64) {
+        match *self {
+            Region::Corners(upper_left, lower_right) => {
+                let center = ((upper_left.0 + lower_right.0) / 2.0,
+                              (upper_left.1 + lower_right.1) / 2.0);
+                let span = (lower_right.0 - upper_left.0)
+                    .min(upper_left.1 - lower_right.1);
+                (center, UNZOOMED_SPAN / span)
+            }
+            Region::CenterZoom(center, zoom) => (center, zoom)
+        }
+    }
+}
+
+#[test]
+fn test_region() {
+    let corners = Region::Corners((-2.0, 1.0), (1.0, -1.0));
+    assert_eq!(corners.center_zoom(), ((-0.5, 0.0), 2.0));
+    assert_eq!(corners.view((30, 20)),
+               View { bounds: (30, 20),
+                      upper_left: (-2.0, 1.0), lower_right: (1.0, -1.0),
+                      rotation: 0.0 });
+    assert_eq!(Region::CenterZoom((-0.5, 0.0), 2.0).view((30, 20)),
+               View { bounds: (30, 20),
+                      upper_left: (-2.0, 1.0), lower_right: (1.0, -1.0),
+                      rotation: 0.0 });
+}
+
+/// Return the view `t` of the way through a zoom from `start` to `end`, where
+/// `t` runs from `0.0` to `1.0`. Both views must have the same bounds.
+///
+/// The width of the view is interpolated geometrically, so that each frame of
+/// an animation zooms in (or out) by the same factor. The center moves in
+/// proportion to the change in width, so that the one point that ends up at
+/// the same place in both `start` and `end` stays put throughout. The rotation
+/// changes at a steady rate.
+pub fn interpolate_view(start: &View, end: &View, t: f64) -> View {
+    let (start_width, start_height) = start.extent();
+    let (end_width, end_height) = end.extent();
+    let (start_center, end_center) = (start.center(), end.center());
+
+    let width = start_width * (end_width / start_width).powf(t);
+    let height = start_height * (end_height / start_height).powf(t);
+    let progress = if start_width == end_width {
+        t
+    } else {
+        (start_width - width) / (start_width - end_width)
+    };
+    let center = (start_center.0 + (end_center.0 - start_center.0) * progress,
+                  start_center.1 + (end_center.1 - start_center.1) * progress);
+
+    View {
+        bounds: start.bounds,
+        upper_left: (center.0 - width / 2.0, center.1 + height / 2.0),
+        lower_right: (center.0 + width / 2.0, center.1 - height / 2.0),
+        rotation: start.rotation + (end.rotation - start.rotation) * t
+    }
+}
+
+#[test]
+fn test_interpolate_view() {
+    // Zoom out by a factor of nine, keeping the lower right corner fixed.
+    let start = View { bounds: (100, 100),
+                       upper_left: (0.0, 1.0), lower_right: (1.0, 0.0),
+                       rotation: 0.0 };
+    let end = View { bounds: (100, 100),
+                     upper_left: (-8.0, 9.0), lower_right: (1.0, 0.0),
+                     rotation: 0.0 };
+    assert_eq!(interpolate_view(&start, &end, 0.0), start);
+    assert_eq!(interpolate_view(&start, &end, 1.0), end);
+    assert_eq!(interpolate_view(&start, &end, 0.5),
+               View { bounds: (100, 100),
+                      upper_left: (-2.0, 3.0), lower_right: (1.0, 0.0),
+                      rotation: 0.0 });
+}
+
+#[test]
+fn test_complete_bounds() {
+    let corners = Region::Corners((-2.0, 1.0), (1.0, -1.0));
+    assert_eq!(corners.complete_bounds(Some(300), None), Ok((300, 200)));
+    assert_eq!(corners.complete_bounds(None, Some(100)), Ok((150, 100)));
+    assert_eq!(corners.complete_bounds(Some(3), Some(4)), Ok((3, 4)));
+    assert!(Region::CenterZoom((0.0, 0.0), 1.0).complete_bounds(Some(300), None).is_err());
+    assert!(Region::Corners((1.0, 1.0), (-1.0, -1.0)).complete_bounds(Some(300), None).is_err());
+
+    let view = corners.view((300, 100));
+    assert_eq!(view.pixel_aspect_ratio(), 0.5);
+}
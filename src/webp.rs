@@ -0,0 +1,473 @@
+//! Writing WebP files.
+//!
+//! We write WebP's lossless format, VP8L. The pixels pass through two of its
+//! transforms: "subtract green", which takes each pixel's green from its red
+//! and blue, since the three tend to move together; and a predictor, which
+//! replaces each pixel with its difference from the one to its left, so that
+//! smooth gradients become runs of small, repeated values. Runs of identical
+//! values become references back to the previous pixel, and everything else
+//! is Huffman-coded, with a separate code for each channel.
+//!
+//! We don't have an encoder for WebP's lossy format, VP8. Instead, for
+//! qualities below 100, we round off the low bits of each color channel
+//! before encoding, as libwebp's "near-lossless" mode does: the result is a
+//! lossless WebP file of a slightly different image, which compresses better
+//! because it has fewer distinct colors and longer runs.
+//!
+//! If the image has an ICC profile, we use WebP's extended format, which adds
+//! a header chunk and a chunk holding the profile.
+
+use bytes::le32;
+use std::io::{self, ErrorKind};
+
+/// The greatest width or height a WebP image can have.
+const MAX_DIMENSION: usize = 16384;
+
+/// The order in which a Huffman code's code length code lengths are written.
+const CODE_LENGTH_ORDER: [usize; 19] = [17, 18, 0, 1, 2, 3, 4, 5, 16, 6, 7, 8, 9, 10, 11,
+                                        12, 13, 14, 15];
+
+/// The number of symbols in the green channel's alphabet: the 256 values, and
+/// the 24 prefixes of backward reference lengths.
+const GREEN_SYMBOLS: usize = 256 + 24;
+
+/// The number of symbols in the alphabet of backward reference distances.
+const DISTANCE_SYMBOLS: usize = 40;
+
+/// The base two logarithm of the size of the blocks the predictor transform
+/// chooses a mode for; we use the largest the format allows, since we use the
+/// same mode everywhere.
+const PREDICTOR_BITS: u32 = 9;
+
+/// The predictor mode that predicts each pixel from the one to its left.
+const PREDICT_LEFT: usize = 1;
+
+/// The code backward references use for a distance of one pixel to the left.
+const LEFT: usize = 2;
+
+/// The shortest run of repeated pixels worth a backward reference, and the
+/// longest run one reference can cover.
+const MIN_RUN: usize = 3;
+const MAX_RUN: usize = 4096;
+
+/// Return `pixels`, an image whose dimensions are given by `bounds`, encoded as
+/// a WebP file. Each pixel is red, green, blue, and alpha. At a `quality` of
+/// 100, the file holds exactly these pixels; below that, their colors are
+/// rounded to make the file smaller. If `profile` is given, embed it as the
+/// image's ICC profile.
+pub fn encode(pixels: &[[u8; 4]], bounds: (usize, usize), quality: u8, profile: Option<&[u8]>)
+    -> io::Result<Vec<u8>>
+{
+    if bounds.0 > MAX_DIMENSION || bounds.1 > MAX_DIMENSION {
+        return Err(io::Error::new(ErrorKind::InvalidInput,
+                                  format!("WebP images can be at most {} pixels wide or high",
+                                          MAX_DIMENSION)));
+    }
+    let alpha = pixels.iter().any(|pixel| pixel[3] != 255);
+
+    let mut bits = Bits::new();
+    bits.put(0x2f, 8); // the VP8L signature
+    bits.put(bounds.0 as u32 - 1, 14);
+    bits.put(bounds.1 as u32 - 1, 14);
+    bits.put(alpha as u32, 1);
+    bits.put(0, 3); // version
+
+    // The decoder undoes the transforms in the reverse of the order they are
+    // listed, so we apply them in that order.
+    let round = near_lossless_bits(quality);
+    let mut pixels: Vec<[u8; 4]> = pixels.iter().map(|pixel| {
+        let (red, green, blue) = (quantize(pixel[0], round), quantize(pixel[1], round),
+                                  quantize(pixel[2], round));
+        [red.wrapping_sub(green), green, blue.wrapping_sub(green), pixel[3]]
+    }).collect();
+    bits.put(1, 1);
+    bits.put(2, 2); // subtract green
+
+    bits.put(1, 1);
+    bits.put(0, 2); // predictor
+    bits.put(PREDICTOR_BITS - 2, 3);
+    let blocks = |n: usize| (n + (1 << PREDICTOR_BITS) - 1) >> PREDICTOR_BITS;
+    let modes = vec![[0, PREDICT_LEFT as u8, 0, 0]; blocks(bounds.0) * blocks(bounds.1)];
+    bits.put(0, 1); // no color cache
+    write_image(&mut bits, &tokenize(&modes));
+    predict_left(&mut pixels, bounds);
+    bits.put(0, 1); // no more transforms
+
+    bits.put(0, 1); // no color cache
+    bits.put(0, 1); // the same codes for the whole image
+    write_image(&mut bits, &tokenize(&pixels));
+    let vp8l = bits.finish();
+
+    let mut webp = b"RIFF\0\0\0\0WEBP".to_vec();
+    if let Some(profile) = profile {
+        let mut header = vec![0x20 | if alpha { 0x10 } else { 0 }, 0, 0, 0];
+        header.extend_from_slice(&le32(bounds.0 as u32 - 1)[..3]);
+        header.extend_from_slice(&le32(bounds.1 as u32 - 1)[..3]);
+        chunk(&mut webp, b"VP8X", &header);
+        chunk(&mut webp, b"ICCP", profile);
+    }
+    chunk(&mut webp, b"VP8L", &vp8l);
+    let size = le32(webp.len() as u32 - 8);
+    webp[4..8].copy_from_slice(&size);
+    Ok(webp)
+}
+
+/// Append a RIFF chunk of type `kind` holding `data` to `webp`.
+fn chunk(webp: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    webp.extend_from_slice(kind);
+    webp.extend_from_slice(&le32(data.len() as u32));
+    webp.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        webp.push(0);
+    }
+}
+
+/// Return the number of low bits to round off each color channel at `quality`.
+fn near_lossless_bits(quality: u8) -> u32 {
+    (100 - quality.min(100) as u32 + 19) / 20
+}
+
+/// Round `value` to the nearest multiple of `2^bits` that fits in a byte.
+fn quantize(value: u8, bits: u32) -> u8 {
+    if bits == 0 {
+        return value;
+    }
+    let rounded = (value as u32 + (1 << (bits - 1))) >> bits << bits;
+    rounded.min(255 >> bits << bits) as u8
+}
+
+/// Replace each of `pixels`, an image whose dimensions are given by `bounds`,
+/// with its difference from the prediction of the predictor transform, with
+/// every block using the mode `PREDICT_LEFT`. Along the edges, the format
+/// fixes the predictions: opaque black for the top left pixel, the pixel to
+/// the left for the rest of the top row, and the pixel above for the rest of
+/// the left column.
+fn predict_left(pixels: &mut [[u8; 4]], bounds: (usize, usize)) {
+    let original = pixels.to_vec();
+    for (i, pixel) in pixels.iter_mut().enumerate() {
+        let prediction = if i == 0 {
+            [0, 0, 0, 255]
+        } else if i % bounds.0 == 0 {
+            original[i - bounds.0]
+        } else {
+            original[i - 1]
+        };
+        for channel in 0..4 {
+            pixel[channel] = pixel[channel].wrapping_sub(prediction[channel]);
+        }
+    }
+}
+
+/// An element of an image's coded form: a pixel, or a run of pixels copied
+/// from the one before.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Token {
+    Pixel([u8; 4]),
+    Repeat(usize)
+}
+
+/// Return `pixels` as tokens, using `Repeat` for runs of identical pixels.
+fn tokenize(pixels: &[[u8; 4]]) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < pixels.len() {
+        if i > 0 {
+            let run = pixels[i..].iter().take(MAX_RUN)
+                .take_while(|&&pixel| pixel == pixels[i - 1])
+                .count();
+            if run >= MIN_RUN {
+                tokens.push(Token::Repeat(run));
+                i += run;
+                continue;
+            }
+        }
+        tokens.push(Token::Pixel(pixels[i]));
+        i += 1;
+    }
+    tokens
+}
+
+/// Write `tokens` to `bits` as an entropy-coded image: Huffman codes for the
+/// green, red, blue, alpha, and distance alphabets, followed by the tokens
+/// themselves. The caller must already have said whether the image uses a
+/// color cache, and for the main image, whether it uses several sets of codes.
+fn write_image(bits: &mut Bits, tokens: &[Token]) {
+    let mut counts = [vec![0; GREEN_SYMBOLS], vec![0; 256], vec![0; 256], vec![0; 256],
+                      vec![0; DISTANCE_SYMBOLS]];
+    for &token in tokens {
+        match token {
+            Token::Pixel(pixel) => {
+                counts[0][pixel[1] as usize] += 1;
+                counts[1][pixel[0] as usize] += 1;
+                counts[2][pixel[2] as usize] += 1;
+                counts[3][pixel[3] as usize] += 1;
+            }
+            Token::Repeat(length) => {
+                counts[0][256 + prefix(length).0] += 1;
+                counts[4][prefix(LEFT).0] += 1;
+            }
+        }
+    }
+    let codes: Vec<Code> = counts.iter().map(|counts| Code::new(counts, 15)).collect();
+    for code in &codes {
+        code.write(bits);
+    }
+
+    let distance = prefix(LEFT);
+    for &token in tokens {
+        match token {
+            Token::Pixel(pixel) => {
+                codes[0].put(bits, pixel[1] as usize);
+                codes[1].put(bits, pixel[0] as usize);
+                codes[2].put(bits, pixel[2] as usize);
+                codes[3].put(bits, pixel[3] as usize);
+            }
+            Token::Repeat(length) => {
+                let (symbol, extra_bits, extra) = prefix(length);
+                codes[0].put(bits, 256 + symbol);
+                bits.put(extra, extra_bits);
+                codes[4].put(bits, distance.0);
+                bits.put(distance.2, distance.1);
+            }
+        }
+    }
+}
+
+/// Return the prefix symbol for a backward reference length or distance code
+/// of `value`, along with the number of extra bits that follow it and their
+/// value.
+fn prefix(value: usize) -> (usize, u32, u32) {
+    if value <= 4 {
+        return (value - 1, 0, 0);
+    }
+    let value = value - 1;
+    let highest = 63 - (value as u64).leading_zeros() as usize;
+    let second = (value >> (highest - 1)) & 1;
+    let extra_bits = highest - 1;
+    let offset = (2 + second) << extra_bits;
+    (2 * highest + second, extra_bits as u32, (value - offset) as u32)
+}
+
+/// A Huffman code for an alphabet.
+struct Code {
+    /// The length of each symbol's code, or zero if it is never used.
+    lengths: Vec<u8>,
+
+    /// Each symbol's code, with its bits reversed, since the format packs
+    /// codes into bytes starting with the first bit of the code.
+    codes: Vec<u32>,
+
+    /// The number of symbols used. A code with just one symbol takes no bits
+    /// to write.
+    used: usize
+}
+
+impl Code {
+    /// Return a code for symbols that occur as many times as `counts` says,
+    /// with no code longer than `limit` bits.
+    fn new(counts: &[u32], limit: u8) -> Code {
+        let lengths = code_lengths(counts, limit);
+        // Codes of each length are consecutive, following on from the codes
+        // one bit shorter.
+        let mut counts = [0_u32; 16];
+        for &length in lengths.iter().filter(|&&length| length > 0) {
+            counts[length as usize] += 1;
+        }
+        let mut next = [0_u32; 16];
+        let mut code = 0;
+        for (next, &shorter) in next[1..].iter_mut().zip(&counts[..15]) {
+            code = (code + shorter) << 1;
+            *next = code;
+        }
+        let codes = lengths.iter().map(|&length| {
+            if length == 0 {
+                return 0;
+            }
+            let code = next[length as usize];
+            next[length as usize] += 1;
+            (0..length).fold(0, |reversed, bit| reversed << 1 | (code >> bit) & 1)
+        }).collect();
+        let used = lengths.iter().filter(|&&length| length > 0).count();
+        Code { lengths: lengths, codes: codes, used: used }
+    }
+
+    /// Write `symbol` to `bits`.
+    fn put(&self, bits: &mut Bits, symbol: usize) {
+        if self.used > 1 {
+            bits.put(self.codes[symbol], self.lengths[symbol] as u32);
+        }
+    }
+
+    /// Write a description of this code to `bits`, from which the decoder can
+    /// rebuild it.
+    fn write(&self, bits: &mut Bits) {
+        let symbols: Vec<usize> = (0..self.lengths.len())
+            .filter(|&symbol| self.lengths[symbol] > 0)
+            .collect();
+        if symbols.len() <= 2 && symbols.iter().all(|&symbol| symbol < 256) {
+            // A "simple" code: one or two symbols, listed outright. A code
+            // that is never used still needs a symbol.
+            let first = symbols.first().cloned().unwrap_or(0);
+            bits.put(1, 1);
+            bits.put(symbols.len().max(1) as u32 - 1, 1);
+            if first < 2 {
+                bits.put(0, 1);
+                bits.put(first as u32, 1);
+            } else {
+                bits.put(1, 1);
+                bits.put(first as u32, 8);
+            }
+            if let Some(&second) = symbols.get(1) {
+                bits.put(second as u32, 8);
+            }
+            return;
+        }
+
+        // A "normal" code: the code lengths, themselves Huffman-coded, with
+        // the code for them written first.
+        let mut counts = [0; 19];
+        for &length in &self.lengths {
+            counts[length as usize] += 1;
+        }
+        let length_code = Code::new(&counts, 7);
+        let written = CODE_LENGTH_ORDER.iter()
+            .rposition(|&length| length_code.lengths[length] > 0)
+            .map_or(0, |last| last + 1)
+            .max(4);
+        bits.put(0, 1);
+        bits.put(written as u32 - 4, 4);
+        for &length in &CODE_LENGTH_ORDER[..written] {
+            bits.put(length_code.lengths[length] as u32, 3);
+        }
+        bits.put(0, 1); // every symbol's length follows
+        for &length in &self.lengths {
+            length_code.put(bits, length as usize);
+        }
+    }
+}
+
+/// Return the lengths of the codes in a Huffman code for symbols that occur as
+/// many times as `counts` says, with no code longer than `limit` bits. If only
+/// one symbol occurs, its code has length one.
+fn code_lengths(counts: &[u32], limit: u8) -> Vec<u8> {
+    let mut counts = counts.to_vec();
+    loop {
+        let mut lengths = vec![0_u8; counts.len()];
+        let mut trees: Vec<(u64, Vec<usize>)> = counts.iter().enumerate()
+            .filter(|&(_, &count)| count > 0)
+            .map(|(symbol, &count)| (count as u64, vec![symbol]))
+            .collect();
+        if trees.len() == 1 {
+            lengths[trees[0].1[0]] = 1;
+        }
+        // Join the two least common trees until only one remains, making the
+        // codes of the symbols in both one bit longer.
+        while trees.len() > 1 {
+            trees.sort_by_key(|tree| tree.0);
+            let (count_a, symbols_a) = trees.remove(0);
+            let (count_b, symbols_b) = trees.remove(0);
+            let mut symbols = symbols_a;
+            symbols.extend(symbols_b);
+            for &symbol in &symbols {
+                lengths[symbol] += 1;
+            }
+            trees.push((count_a + count_b, symbols));
+        }
+        if lengths.iter().all(|&length| length <= limit) {
+            return lengths;
+        }
+        // Some codes are too long. Flatten the distribution and try again;
+        // eventually all counts are equal, and the tree is balanced.
+        for count in &mut counts {
+            *count = (*count + 1) / 2;
+        }
+    }
+}
+
+/// A stream of bits, packed into bytes starting with the least significant
+/// bit.
+struct Bits {
+    bytes: Vec<u8>,
+    buffer: u64,
+    count: u32
+}
+
+impl Bits {
+    fn new() -> Bits {
+        Bits { bytes: Vec::new(), buffer: 0, count: 0 }
+    }
+
+    /// Append the low `count` bits of `value`.
+    fn put(&mut self, value: u32, count: u32) {
+        self.buffer |= (value as u64 & ((1 << count) - 1)) << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+#[test]
+fn test_prefix() {
+    assert_eq!(prefix(1), (0, 0, 0));
+    assert_eq!(prefix(4), (3, 0, 0));
+    assert_eq!(prefix(5), (4, 1, 0));
+    assert_eq!(prefix(6), (4, 1, 1));
+    assert_eq!(prefix(7), (5, 1, 0));
+    assert_eq!(prefix(9), (6, 2, 0));
+    assert_eq!(prefix(4096), (23, 10, 1023));
+}
+
+#[test]
+fn test_code() {
+    let code = Code::new(&[10, 1, 1, 0, 2], 15);
+    assert_eq!(code.lengths, vec![1, 3, 3, 0, 2]);
+    // Canonically, 0, 110, 111, and 10, reversed.
+    assert_eq!(code.codes, vec![0b0, 0b011, 0b111, 0, 0b01]);
+
+    // Limiting the length flattens the code.
+    let counts: Vec<u32> = (0..8).map(|i| 1 << i).collect();
+    assert_eq!(code_lengths(&counts, 15), vec![7, 7, 6, 5, 4, 3, 2, 1]);
+    assert!(code_lengths(&counts, 4).iter().all(|&length| length <= 4));
+    assert_eq!(code_lengths(&[0, 5, 0], 7), vec![0, 1, 0]);
+}
+
+#[test]
+fn test_tokenize() {
+    let (a, b) = ([1, 2, 3, 4], [5, 6, 7, 8]);
+    assert_eq!(tokenize(&[a, a, a, a, b, b, b, b, a, a, a]),
+               vec![Token::Pixel(a), Token::Repeat(3), Token::Pixel(b), Token::Repeat(3),
+                    Token::Pixel(a), Token::Pixel(a), Token::Pixel(a)]);
+}
+
+#[test]
+fn test_encode() {
+    assert_eq!(quantize(200, 0), 200);
+    assert_eq!(quantize(201, 2), 200);
+    assert_eq!(quantize(254, 2), 252);
+    assert_eq!(near_lossless_bits(100), 0);
+    assert_eq!(near_lossless_bits(90), 1);
+
+    let pixels = vec![[255, 0, 0, 255]; 6];
+    let webp = encode(&pixels, (3, 2), 100, None).unwrap();
+    assert_eq!(&webp[..4], b"RIFF");
+    assert_eq!(&webp[4..8], &le32(webp.len() as u32 - 8));
+    assert_eq!(&webp[8..16], b"WEBPVP8L");
+    // The signature, then the width and height less one, 14 bits each.
+    assert_eq!(&webp[20..25], &[0x2f, 2, 0x40, 0, 0]);
+
+    let webp = encode(&pixels, (3, 2), 100, Some(b"profile")).unwrap();
+    assert_eq!(&webp[12..30], b"VP8X\x0a\0\0\0\x20\0\0\0\x02\0\0\x01\0\0");
+    assert_eq!(&webp[30..46], b"ICCP\x07\0\0\0profile\0");
+
+    assert!(encode(&[[0; 4]; 16385], (16385, 1), 100, None).is_err());
+}
@@ -0,0 +1,125 @@
+//! Laying out tiles for web maps.
+//!
+//! Web map libraries like Leaflet and OpenLayers show "slippy maps" assembled
+//! from square tiles fetched by zoom level, column, and row, from URLs like
+//! `https://example.com/{z}/{x}/{y}.png`. At zoom level zero, a single tile
+//! covers the whole map; each level above doubles the number of tiles across
+//! and down, each covering a quarter of the area of a tile in the level below.
+//! Columns count from the left and rows from the top.
+//!
+//! Here, the whole map is a view of the complex plane, and each tile is a
+//! view of its own, computed independently of the others, so a static web
+//! host can serve the tree of files just as it is.
+
+use view::View;
+
+/// The usual width and height of a tile.
+pub const DEFAULT_TILE_SIZE: usize = 256;
+
+/// The deepest zoom level we can lay out. Deeper levels have more tiles than
+/// we can count.
+pub const MAX_LEVEL: u32 = 30;
+
+/// A tile of a web map.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tile {
+    pub level: u32,
+    pub column: usize,
+    pub row: usize
+}
+
+impl Tile {
+    /// Return the view this tile shows of `map`, the view of the whole map at
+    /// level zero. The tile has the same size and rotation as `map`.
+    pub fn view(&self, map: &View) -> View {
+        // Find the tile's center on the plane, in pixels of the whole map, and
+        // take the tile's corners around that, before rotation.
+        let across = (1_u64 << self.level) as f64;
+        let transform = map.transform();
+        let x = (self.column as f64 + 0.5) * map.bounds.0 as f64 / across;
+        let y = (self.row as f64 + 0.5) * map.bounds.1 as f64 / across;
+        let center = (transform.origin.0 + x * transform.column.0 + y * transform.row.0,
+                      transform.origin.1 + x * transform.column.1 + y * transform.row.1);
+        let extent = map.extent();
+        let (half_width, half_height) = (extent.0 / across / 2.0, extent.1 / across / 2.0);
+        View {
+            bounds: map.bounds,
+            upper_left: (center.0 - half_width, center.1 + half_height),
+            lower_right: (center.0 + half_width, center.1 - half_height),
+            rotation: map.rotation
+        }
+    }
+
+    /// Return the path of this tile's file, relative to the top of the tree,
+    /// with the extension `extension`.
+    pub fn path(&self, extension: &str) -> String {
+        format!("{}/{}/{}.{}", self.level, self.column, self.row, extension)
+    }
+}
+
+/// Return the number of tiles in the levels from `low` to `high`, inclusive.
+pub fn count(low: u32, high: u32) -> u64 {
+    (low..high + 1).map(|level| 1_u64 << (2 * level)).sum()
+}
+
+/// Return the tile numbered `index` among the levels from `low` to `high`,
+/// counting from the first level's upper left tile, row by row, and then
+/// through each level above it. Return `None` if there is no such tile.
+pub fn tile(low: u32, high: u32, index: u64) -> Option<Tile> {
+    let mut index = index;
+    for level in low..high + 1 {
+        let across = 1_u64 << level;
+        if index < across * across {
+            return Some(Tile {
+                level: level,
+                column: (index % across) as usize,
+                row: (index / across) as usize
+            });
+        }
+        index -= across * across;
+    }
+    None
+}
+
+#[test]
+fn test_count() {
+    assert_eq!(count(0, 0), 1);
+    assert_eq!(count(0, 2), 1 + 4 + 16);
+    assert_eq!(count(3, 3), 64);
+    assert_eq!(count(MAX_LEVEL, MAX_LEVEL), 1 << 60);
+}
+
+#[test]
+fn test_tile() {
+    assert_eq!(tile(0, 1, 0), Some(Tile { level: 0, column: 0, row: 0 }));
+    assert_eq!(tile(0, 1, 1), Some(Tile { level: 1, column: 0, row: 0 }));
+    assert_eq!(tile(0, 1, 2), Some(Tile { level: 1, column: 1, row: 0 }));
+    assert_eq!(tile(0, 1, 4), Some(Tile { level: 1, column: 1, row: 1 }));
+    assert_eq!(tile(0, 1, 5), None);
+    assert_eq!(tile(2, 2, 6), Some(Tile { level: 2, column: 2, row: 1 }));
+    assert_eq!(Tile { level: 2, column: 2, row: 1 }.path("png"), "2/2/1.png");
+}
+
+#[test]
+fn test_view() {
+    let map = View {
+        bounds: (256, 256),
+        upper_left: (-2.0, 2.0),
+        lower_right: (2.0, -2.0),
+        rotation: 0.0
+    };
+    assert_eq!(Tile { level: 0, column: 0, row: 0 }.view(&map), map);
+    assert_eq!(Tile { level: 1, column: 1, row: 0 }.view(&map),
+               View { upper_left: (0.0, 2.0), lower_right: (2.0, 0.0), .. map });
+    assert_eq!(Tile { level: 2, column: 0, row: 3 }.view(&map),
+               View { upper_left: (-2.0, -1.0), lower_right: (-1.0, -2.0), .. map });
+
+    // A rotated map's tiles are rotated too, and their corners land on the
+    // map's.
+    let map = View { rotation: 90.0, .. map };
+    let tile = Tile { level: 1, column: 0, row: 0 }.view(&map);
+    let (map, tile) = (map.transform(), tile.transform());
+    assert!((map.origin.0 - tile.origin.0).abs() < 1e-12);
+    assert!((map.origin.1 - tile.origin.1).abs() < 1e-12);
+    assert!((map.column.0 - tile.column.0 * 2.0).abs() < 1e-12);
+}